@@ -0,0 +1,586 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::git;
+use crate::gitignore;
+use crate::hooks;
+use crate::identity;
+use crate::layout::{self, LayoutKind};
+use crate::lfs;
+use crate::manifest::{self, Manifest};
+use crate::perms;
+use crate::repo::{self, LinkMode};
+use crate::scan;
+use crate::ui::{self, message_box};
+use crate::verify;
+
+/// Options accepted by `dfl add`.
+#[derive(Debug, Default)]
+pub struct AddOptions {
+    /// Package to store entries in when the repository uses the package layout.
+    pub package: Option<String>,
+    /// Commit message to use instead of the generated one.
+    pub message: Option<String>,
+    /// Leave the changes uncommitted so several adds can go into one manual commit.
+    pub no_commit: bool,
+    /// Pick unmanaged dotfiles from a checklist instead of passing paths.
+    pub interactive: bool,
+    /// Repository path to store the entry at, e.g. `bashrc` for `~/.bashrc`.
+    pub store_as: Option<String>,
+    /// Gitignore-style patterns of paths to leave out when adding a directory.
+    pub exclude: Vec<String>,
+    /// Push right after committing, as `auto_push` in the config does.
+    pub push: bool,
+    /// Store large binary files with git-lfs without asking.
+    pub lfs: bool,
+    /// Commit even if something looks like a secret.
+    pub allow_secrets: bool,
+}
+
+/// Dotfiles in the home directory that are worth offering in the interactive picker.
+const COMMON_DOTFILES: &[&str] = &[
+    ".bashrc", ".bash_profile", ".bash_aliases", ".profile", ".zshrc", ".zprofile", ".zshenv", ".inputrc",
+    ".gitconfig", ".gitignore_global", ".vimrc", ".tmux.conf", ".editorconfig", ".npmrc", ".wgetrc", ".curlrc", ".ssh/config",
+];
+
+/// Handles the 'add' command.
+/// It moves each file or directory into the repository, creates a symlink back,
+/// and commits everything that was added in a single commit.
+pub fn handle_add_command(patterns: &[String], options: &AddOptions) -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let dfl_path = repo::dfl_path()?;
+
+    if options.lfs && lfs::version().is_none() {
+        return Err(errors::error(Code::InvalidArgument, "'--lfs' needs git-lfs, which is not installed. Install it (e.g. 'apt install git-lfs' or 'brew install git-lfs') first."));
+    }
+    if !dfl_path.exists() || !git::git_dir(&dfl_path).exists() {
+        message_box("Setting up", &format!("Initializing dotfiles repository in {} for you.", repo::display(&dfl_path)));
+        crate::init::handle_init_command(None, &identity::Identity::default(), false, false)?;
+    }
+
+    let picked;
+    let patterns = if options.interactive {
+        picked = pick_unmanaged(&dfl_path, &home_dir)?;
+        if picked.is_empty() {
+            ui::say("Nothing selected.");
+            return Ok(());
+        }
+        &picked
+    } else {
+        patterns
+    };
+
+    // Each path is added independently so one bad path does not stop the rest;
+    // everything that succeeded still goes into one commit.
+    let mut manifest = manifest::load(&dfl_path)?;
+    let mut added = Vec::new();
+    let mut skipped = Vec::new();
+    let mut failed: Vec<(String, io::Error)> = Vec::new();
+    let mut sources = Vec::new();
+    for pattern in patterns {
+        match expand_pattern(pattern, &home_dir) {
+            Ok(matches) => sources.extend(matches),
+            Err(e) => failed.push((pattern.clone(), e)),
+        }
+    }
+
+    // The hook sees every path at once and can veto the whole add. It runs inside
+    // the repository, so relative paths are made absolute first.
+    let absolute = sources.iter().map(std::path::absolute).collect::<io::Result<Vec<_>>>()?;
+    hooks::check(&dfl_path, "pre-add", &absolute)?;
+    if !options.no_commit && !options.allow_secrets {
+        scan::check(&dfl_path, &home_dir, &sources)?;
+    }
+    for source_path in &sources {
+        match add_entry(&dfl_path, &home_dir, &mut manifest, source_path, options) {
+            Ok(Some(stored_path)) => added.push(stored_path),
+            Ok(None) => skipped.push(source_path.display().to_string()),
+            Err(e) => failed.push((source_path.display().to_string(), e)),
+        }
+    }
+
+    if added.is_empty() {
+        return finish(&added, &skipped, failed, false);
+    }
+
+    lfs::offer(&dfl_path, &added, options.lfs)?;
+    if added.iter().any(|stored| dfl_path.join(stored).is_dir()) {
+        gitignore::ensure_defaults(&dfl_path)?;
+    }
+
+    if options.no_commit {
+        verify::record_checksums(&dfl_path)?;
+        ui::say(&format!("Skipped committing. Review with 'git -C {} status' and run 'dfl commit' when you are ready.", repo::display(&dfl_path)));
+        return finish(&added, &skipped, failed, false);
+    }
+
+    // Automatically stage and commit the change
+    ui::say("Automatically committing changes...");
+    if let Err(e) = git::add_all(&dfl_path) {
+        return Err(errors::error(Code::GitFailed, format!("Error staging changes: {}", e)));
+    }
+    let message = match &options.message {
+        Some(message) => message.clone(),
+        None => {
+            let stats = git::staged_diffstat(&dfl_path).map_err(|e| errors::error(Code::GitFailed, format!("Error comparing the changes: {}", e)))?;
+            let (action, files, default) = commit_message(&added, &stats);
+            git::commit_message(&action, &files, default)?
+        }
+    };
+    if let Err(e) = git::commit(&dfl_path, &message) {
+        return Err(errors::error(Code::GitFailed, format!("Error committing changes: {}", e)));
+    }
+    ui::say("✅ Changes committed.");
+
+    verify::record_checksums(&dfl_path)?;
+    let pushed = (options.push || config::load()?.auto_push) && crate::remote::push_after_commit(&dfl_path)?;
+
+    if added.len() == 1 && skipped.is_empty() && failed.is_empty() {
+        message_box("Success", "Dotfile added and linked successfully!");
+        if !pushed {
+            ui::say("Remember to add a remote and 'dfl push' to sync your changes.");
+        }
+        return Ok(());
+    }
+    finish(&added, &skipped, failed, pushed)
+}
+
+/// Stores `source` in the repository at `dfl_path` and deploys it back in its place,
+/// as 'add' does for each path, without scanning or committing. Returns the
+/// repository-relative path it is stored at, or `None` if it was already managed.
+pub fn store(dfl_path: &Path, source: &Path, options: &AddOptions) -> io::Result<Option<PathBuf>> {
+    let mut manifest = manifest::load(dfl_path)?;
+    add_entry(dfl_path, &repo::home_dir()?, &mut manifest, source, options)
+}
+
+/// Offers the common dotfiles in `$HOME` and everything in `~/.config` that dfl
+/// does not manage yet as a checklist, and returns the chosen paths.
+fn pick_unmanaged(dfl_path: &Path, home_dir: &Path) -> io::Result<Vec<String>> {
+    let mut candidates: Vec<PathBuf> = COMMON_DOTFILES.iter().map(|name| home_dir.join(name)).collect();
+    if let Ok(config_dir) = fs::read_dir(home_dir.join(".config")) {
+        let mut children = config_dir.map(|entry| entry.map(|e| e.path())).collect::<io::Result<Vec<PathBuf>>>()?;
+        children.sort();
+        candidates.extend(children);
+    }
+
+    let managed = repo::managed_entries(dfl_path)?;
+    candidates.retain(|path| {
+        fs::symlink_metadata(path).is_ok() && !repo::links_into_repo(path, dfl_path) && !managed.iter().any(|e| path.starts_with(&e.target) || e.target.starts_with(path))
+    });
+    if candidates.is_empty() {
+        ui::say("No unmanaged dotfiles found in your home directory or ~/.config.");
+        return Ok(Vec::new());
+    }
+
+    let labels: Vec<String> = candidates.iter().map(|path| format!("~/{}", path.strip_prefix(home_dir).unwrap_or(path).display())).collect();
+    let chosen = dialoguer::MultiSelect::new()
+        .with_prompt("Select dotfiles to add (space to toggle, enter to confirm)")
+        .items(&labels)
+        .interact_opt()
+        .map_err(|e| errors::error(Code::InvalidArgument, format!("The interactive picker needs a terminal: {}", e)))?
+        .unwrap_or_default();
+    Ok(chosen.into_iter().map(|index| candidates[index].to_string_lossy().into_owned()).collect())
+}
+
+/// Prints a per-path report and turns any failures into the command's result.
+/// The reminder to push is left out when `pushed` says the commit already was.
+fn finish(added: &[PathBuf], skipped: &[String], mut failed: Vec<(String, io::Error)>, pushed: bool) -> io::Result<()> {
+    if added.len() + skipped.len() + failed.len() > 1 {
+        ui::say("\nSummary:");
+        for path in added {
+            ui::say(&format!("  ✅ {}", path.display()));
+        }
+        for path in skipped {
+            ui::say(&format!("  ➖ {} (already managed)", path));
+        }
+        for (path, e) in &failed {
+            ui::say(&format!("  ❌ {}: {}", path, e));
+        }
+    }
+
+    if failed.is_empty() {
+        if !added.is_empty() {
+            message_box("Success", &format!("{} dotfile(s) added and linked successfully!", added.len()));
+            if !pushed {
+                ui::say("Remember to add a remote and 'dfl push' to sync your changes.");
+            }
+        }
+        return Ok(());
+    }
+
+    // A strict-mode warning keeps its own error so the process exits with the strict code.
+    if let Some(index) = failed.iter().position(|(_, e)| ui::is_strict_violation(e)) {
+        return Err(failed.swap_remove(index).1);
+    }
+    if failed.len() == 1 && added.is_empty() && skipped.is_empty() {
+        return Err(failed.remove(0).1);
+    }
+    Err(io::Error::other(format!("{} of {} path(s) could not be added.", failed.len(), added.len() + skipped.len() + failed.len())))
+}
+
+/// How many changed files a commit message lists before summing up the rest.
+const LISTED_FILES: usize = 20;
+
+/// Names what a commit of `added` entries does, from what is actually staged:
+/// the new entries are added, and other files that were edited, deleted, or
+/// created in the repository since the last commit are updated, removed, or
+/// added too. Returns the action and the files for `commit_template`, and the
+/// built-in message, whose body sums up the lines each file gains and loses.
+pub fn commit_message(added: &[PathBuf], stats: &[git::FileStat]) -> (String, Vec<PathBuf>, String) {
+    let (mut adds, mut updates, mut removes) = (added.to_vec(), Vec::new(), Vec::new());
+    for stat in stats {
+        let path = PathBuf::from(&stat.path);
+        if added.iter().any(|entry| path.starts_with(entry)) || repo::is_reserved(&path) {
+            continue;
+        }
+        match stat.status {
+            'A' => adds.push(path),
+            'D' => removes.push(path),
+            _ => updates.push(path),
+        }
+    }
+
+    let name = |paths: &[PathBuf], noun: &str| match paths {
+        [single] => single.display().to_string(),
+        _ => format!("{} {}", paths.len(), noun),
+    };
+    let mut actions = Vec::new();
+    let mut phrases = Vec::new();
+    for (verb, paths, noun) in [("add", &adds, "dotfiles"), ("update", &updates, "files"), ("remove", &removes, "files")] {
+        if !paths.is_empty() {
+            actions.push(verb);
+            phrases.push(format!("{} {}", verb, name(paths, noun)));
+        }
+    }
+    let phrases = match phrases.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        Some(_) => phrases.concat(),
+        // Only dfl's own files changed, such as dfl.toml.
+        None => "update repository settings".to_string(),
+    };
+    let prefix = if adds.is_empty() { "chore" } else { "feat" };
+    let subject = format!("{}: {}{}", prefix, phrases.get(..1).unwrap_or_default().to_uppercase(), phrases.get(1..).unwrap_or_default());
+
+    let (insertions, deletions) = stats.iter().filter_map(|stat| stat.lines).fold((0, 0), |(i, d), (added, removed)| (i + added, d + removed));
+    let mut body = vec![format!("{} file(s) changed, {} insertion(s)(+), {} deletion(s)(-)", stats.len(), insertions, deletions)];
+    for stat in stats.iter().take(LISTED_FILES) {
+        let state = match stat.status {
+            'A' => "new, ",
+            'D' => "deleted, ",
+            _ => "",
+        };
+        match stat.lines {
+            Some((added, removed)) => body.push(format!("- {} | {}+{} -{}", stat.path, state, added, removed)),
+            None => body.push(format!("- {} | {}binary", stat.path, state)),
+        }
+    }
+    if stats.len() > LISTED_FILES {
+        body.push(format!("- and {} more", stats.len() - LISTED_FILES));
+    }
+
+    let files = [adds, updates, removes].concat();
+    (actions.join(" and "), files, format!("{}\n\n{}", subject, body.join("\n")))
+}
+
+/// Expands `~` and glob patterns such as `~/.bash*` into the matching paths, so
+/// patterns behave the same whether or not the calling shell already expanded them.
+/// Like a shell, `*` does not match a leading dot unless the pattern spells it out.
+pub fn expand_pattern(pattern: &str, home_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let expanded = match pattern.strip_prefix("~/") {
+        Some(rest) => home_dir.join(rest).to_string_lossy().into_owned(),
+        None if pattern == "~" => home_dir.to_string_lossy().into_owned(),
+        None => pattern.to_string(),
+    };
+
+    if !expanded.contains(['*', '?', '[']) {
+        return Ok(vec![PathBuf::from(expanded)]);
+    }
+
+    let matches: Vec<PathBuf> = glob::glob(&expanded)
+        .map_err(|e| errors::error(Code::InvalidArgument, format!("Invalid pattern '{}': {}", pattern, e)))?
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .map_err(|e| io::Error::other(format!("Error expanding '{}': {}", pattern, e)))?
+        .into_iter()
+        .filter(|path| !hidden_by_wildcard(Path::new(&expanded), path))
+        .collect();
+
+    if matches.is_empty() {
+        return Err(errors::error(Code::SourceMissing, format!("No files match '{}'.", pattern)));
+    }
+    ui::say(&format!("Pattern '{}' matched {} path(s).", pattern, matches.len()));
+    Ok(matches)
+}
+
+/// Returns true if a wildcard component of `pattern` matched a dot-file in `path`.
+fn hidden_by_wildcard(pattern: &Path, path: &Path) -> bool {
+    let pattern_components: Vec<_> = pattern.components().collect();
+    let path_components: Vec<_> = path.components().collect();
+    // `**` can span several components; leave those matches alone.
+    if pattern_components.len() != path_components.len() {
+        return false;
+    }
+    pattern_components.iter().zip(&path_components).any(|(p, m)| {
+        m.as_os_str().to_string_lossy().starts_with('.') && !p.as_os_str().to_string_lossy().starts_with('.')
+    })
+}
+
+/// Refuses paths whose move would nest the repository or its entries inside each other.
+fn check_containment(dfl_path: &Path, source_path: &Path) -> io::Result<()> {
+    let absolute = std::path::absolute(source_path)?;
+    let file_path = source_path.display();
+    let error = |message: String| Err(errors::error(Code::ManagedPath, message));
+
+    if dfl_path.starts_with(&absolute) {
+        return error(format!("'{}' contains the dfl repository itself and cannot be added.", file_path));
+    }
+    let entries = repo::managed_entries(dfl_path)?;
+    if let Some(entry) = entries.iter().find(|e| absolute.starts_with(&e.target) && absolute != e.target) {
+        return error(format!("'{}' is inside '{}', which dfl already manages. Edit it in place; changes are stored in the repository already.", file_path, entry.target.display()));
+    }
+    let canonical_repo = fs::canonicalize(dfl_path)?;
+    if absolute.starts_with(dfl_path) || fs::canonicalize(&absolute)?.starts_with(&canonical_repo) {
+        return error(format!("'{}' is inside the dfl repository. Add the original path in your home directory instead.", file_path));
+    }
+    if let Some(entry) = entries.iter().find(|e| e.target.starts_with(&absolute)) {
+        return error(format!("'{}' contains '{}', which dfl already manages. Add the other files inside it individually.", file_path, entry.target.display()));
+    }
+    Ok(())
+}
+
+/// Moves one file or directory into the repository and links it back.
+/// Returns the repository-relative path it was stored at, or `None` if it is already managed.
+fn add_entry(dfl_path: &Path, home_dir: &Path, manifest: &mut Manifest, source_path: &Path, options: &AddOptions) -> io::Result<Option<PathBuf>> {
+    let file_path = source_path.display();
+    ui::say(&format!("Adding file or directory: {}", file_path));
+
+    // Moving a managed symlink would store a link to itself; treat it as done instead.
+    if repo::links_into_repo(source_path, dfl_path) {
+        ui::say(&format!("➖ '{}' is already managed by dfl.", file_path));
+        return Ok(None);
+    }
+
+    if !source_path.exists() {
+        return Err(errors::error(Code::SourceMissing, format!("Source path '{}' does not exist.", file_path)));
+    }
+    let absolute = std::path::absolute(source_path)?;
+    if repo::managed_entries(dfl_path)?.iter().any(|e| !e.mode.is_symlink() && e.target == absolute) {
+        ui::say(&format!("➖ '{}' is already managed by dfl as a copy or hard link.", file_path));
+        return Ok(None);
+    }
+    check_containment(dfl_path, source_path)?;
+
+    let layout = manifest.layout.layout();
+    let home_relative = layout::home_relative(source_path, home_dir)?;
+    let (stored_path, mapped_target) = if let Some(name) = options.store_as.as_deref() {
+        // An explicit repository name is always deployed through a path mapping in dfl.toml.
+        let name = Path::new(name);
+        if name.as_os_str().is_empty() || !name.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(errors::error(Code::InvalidArgument, format!("'{}' is not a valid repository path; use a relative path such as 'bashrc'.", name.display())));
+        }
+        let target = match &home_relative {
+            Some(relative) => Path::new("~").join(relative),
+            None => std::path::absolute(source_path)?,
+        };
+        (name.to_path_buf(), Some(target))
+    } else {
+        match home_relative {
+            Some(target_path) => {
+                if layout.kind() == LayoutKind::Flat && target_path.components().count() > 1 {
+                    ui::warn(&format!("The flat layout stores '{}' by file name only, so 'sync' will link it at '~/{}'. Run 'dfl layout convert home' to keep nested paths.", file_path, target_path.file_name().unwrap_or_default().to_string_lossy()))?;
+                }
+                (layout.store_path(&target_path, options.package.as_deref())?, None)
+            }
+            // Layouts mirror `$HOME`, so anything else is deployed through a path mapping in dfl.toml.
+            None => {
+                let (stored_path, target) = repo::mapped_paths(&std::path::absolute(source_path)?, home_dir);
+                ui::say(&format!("'{}' is outside your home directory; recording its location in {}.", file_path, manifest::MANIFEST_FILE));
+                (stored_path, Some(target))
+            }
+        }
+    };
+    layout::check_store_path(&stored_path)?;
+    let destination_path = dfl_path.join(&stored_path);
+
+    // Never overwrite something already stored at the same place in the repository.
+    if fs::symlink_metadata(&destination_path).is_ok() {
+        return Err(errors::error(Code::AlreadyInRepo, format!("'{}' would be stored at '{}', but something is already stored there. Rename or remove one of them first.", file_path, destination_path.display())));
+    }
+
+    let is_dir = source_path.is_dir();
+    if is_dir {
+        // Git would record a nested repository as an opaque gitlink and silently drop its contents.
+        if let Some(nested) = repo::find_nested_git_dir(source_path)? {
+            return Err(errors::error(Code::NestedRepository, format!("'{}' contains a Git repository at '{}'. Remove it or add the files individually.", file_path, nested.display())));
+        }
+    }
+
+    let mode = manifest.mode_for(&stored_path);
+    if mode == LinkMode::Hardlink && !fsutil::same_device(source_path, dfl_path)? {
+        return Err(errors::error(Code::CrossDevice, format!("'{}' is on a different filesystem than {}, so it cannot be hard-linked. Use mode = \"copy\" for it instead.", file_path, dfl_path.display())));
+    }
+
+    // Excluded paths stay where they are, so the directory is moved piece by piece
+    // and stays a real directory that `sync` links into file by file.
+    let rules = exclude_rules(source_path, manifest, options)?;
+    let partial = is_dir && contains_excluded(source_path, &rules)?;
+    if partial && mode != LinkMode::Symlink {
+        ui::warn(&format!("Exclude patterns only apply to symlinked entries; adding all of '{}' as a {}.", file_path, if mode == LinkMode::Copy { "copy" } else { "hard link" }))?;
+    }
+
+    // Move the file or directory into the repository
+    if let Some(parent) = destination_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if partial && mode == LinkMode::Symlink {
+        add_partially(source_path, &destination_path, &rules)?;
+    } else {
+        move_and_deploy(source_path, &destination_path, mode)?;
+    }
+
+    // Git only keeps the executable bit, so modes such as 0600 are recorded in dfl.toml.
+    let mut changed = perms::record(dfl_path, manifest, &stored_path)?;
+    if let Some(target) = mapped_target {
+        manifest.paths.insert(stored_path.clone(), target);
+        changed = true;
+    } else if layout.kind() != LayoutKind::Flat {
+        layout.register(manifest, &stored_path);
+        changed = true;
+    }
+    if changed {
+        manifest::save(dfl_path, manifest)?;
+    }
+
+    Ok(Some(stored_path))
+}
+
+/// Moves a whole file or directory into the repository at `destination` and deploys
+/// it back at `source` according to `mode`. If deploying fails, the original is
+/// moved back so nothing is lost.
+fn move_and_deploy(source: &Path, destination: &Path, mode: LinkMode) -> io::Result<()> {
+    let source_is_dir = source.is_dir();
+    if let Err(e) = fsutil::move_path(source, destination) {
+        return Err(errors::error(Code::MoveFailed, format!("Error moving file: {}", e)));
+    }
+    if source_is_dir {
+        let file_count = repo::files_under(destination)?.len();
+        ui::say(&format!("✅ Moved directory to repository ({} files): {:?}", file_count, destination));
+    } else {
+        ui::say(&format!("✅ Moved file to repository: {:?}", destination));
+    }
+
+    match mode {
+        // Copy and hardlink modes put the original back in place as a copy or hard links.
+        LinkMode::Copy | LinkMode::Hardlink => {
+            let deployed = if mode == LinkMode::Copy {
+                fsutil::copy_recursive(destination, source)
+            } else {
+                fsutil::hardlink_recursive(destination, source)
+            };
+            if let Err(e) = deployed {
+                let _ = fsutil::remove_path(source);
+                let _ = fsutil::move_path(destination, source);
+                return Err(errors::error(Code::MoveFailed, format!("Error deploying '{}' again: {}. Original file has been restored.", source.display(), e)));
+            }
+            let how = if mode == LinkMode::Copy { "a copy" } else { "hard links" };
+            ui::say(&format!("✅ Deployed {} at: {:?}", how, source));
+        }
+        // Templates are only ever written in the repository, never added.
+        LinkMode::Symlink | LinkMode::Template => {
+            // Create a symbolic link
+            if let Err(e) = fsutil::symlink_to(destination, source, config::load()?.relative_links) {
+                // If symlink creation fails, move the original file back to prevent data loss
+                let _ = fsutil::move_path(destination, source);
+                return Err(errors::error(Code::SymlinkFailed, format!("Error creating symlink: {}. Original file has been restored.", e)));
+            }
+            ui::say(&format!("✅ Created symlink at: {:?}", source));
+        }
+    }
+    Ok(())
+}
+
+/// Builds the exclude rules for a directory added at `source` from `--exclude`,
+/// the `exclude` list in `dfl.toml`, and the one in the user configuration.
+/// Patterns are relative to the directory.
+fn exclude_rules(source: &Path, manifest: &Manifest, options: &AddOptions) -> io::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(source);
+    for pattern in options.exclude.iter().chain(&manifest.exclude).chain(&config::load()?.exclude) {
+        builder.add_line(None, pattern).map_err(|e| errors::error(Code::InvalidArgument, format!("'{}' is not a valid exclude pattern: {}", pattern, e)))?;
+    }
+    builder.build().map_err(|e| errors::error(Code::InvalidArgument, format!("Invalid exclude patterns: {}", e)))
+}
+
+/// Returns true if anything below `dir` matches the exclude rules.
+fn contains_excluded(dir: &Path, rules: &Gitignore) -> io::Result<bool> {
+    if rules.is_empty() {
+        return Ok(false);
+    }
+    for child in fs::read_dir(dir)? {
+        let path = child?.path();
+        let is_dir = fs::symlink_metadata(&path)?.is_dir();
+        if rules.matched(&path, is_dir).is_ignore() || (is_dir && contains_excluded(&path, rules)?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Moves everything below `source` that the exclude rules do not match into the
+/// repository at `destination`, leaving excluded paths and the directories holding
+/// them in place, then links each moved piece back. Any failure moves every piece
+/// back, so the directory ends up as it was.
+fn add_partially(source: &Path, destination: &Path, rules: &Gitignore) -> io::Result<()> {
+    let mut moved = Vec::new();
+    let mut excluded = Vec::new();
+    let result = move_except(source, destination, rules, &mut moved, &mut excluded).and_then(|()| {
+        let relative = config::load()?.relative_links;
+        moved.iter().try_for_each(|(original, stored)| fsutil::symlink_to(stored, original, relative))
+    });
+    if let Err(e) = result {
+        let restored = moved.iter().rev().all(|(original, stored)| {
+            // Whatever is at the original path now is the link created for it.
+            if fs::symlink_metadata(original).is_ok() {
+                let _ = fs::remove_file(original);
+            }
+            fsutil::move_path(stored, original).is_ok()
+        });
+        if restored {
+            let _ = fs::remove_dir_all(destination);
+        }
+        return Err(errors::error(Code::MoveFailed, format!("Error adding '{}' without its excluded paths: {}. Original files have been restored.", source.display(), e)));
+    }
+
+    let file_count = repo::files_under(destination)?.len();
+    ui::say(&format!("✅ Moved directory to repository ({} files, {} excluded): {:?}", file_count, excluded.len(), destination));
+    for path in &excluded {
+        ui::say(&format!("  ➖ Excluded '{}'", path.display()));
+    }
+    ui::say(&format!("✅ Linked {} piece(s) back into: {:?}", moved.len(), source));
+    Ok(())
+}
+
+/// Moves the parts of `source` that are not excluded to `destination`, recording
+/// each moved `(original, stored)` pair and each excluded path.
+fn move_except(source: &Path, destination: &Path, rules: &Gitignore, moved: &mut Vec<(PathBuf, PathBuf)>, excluded: &mut Vec<PathBuf>) -> io::Result<()> {
+    fs::create_dir_all(destination)?;
+    let mut children = fs::read_dir(source)?.map(|child| child.map(|child| child.path())).collect::<io::Result<Vec<_>>>()?;
+    children.sort();
+    for path in children {
+        let is_dir = fs::symlink_metadata(&path)?.is_dir();
+        let stored = destination.join(path.file_name().unwrap_or_default());
+        if rules.matched(&path, is_dir).is_ignore() {
+            excluded.push(path);
+        } else if is_dir && contains_excluded(&path, rules)? {
+            move_except(&path, &stored, rules, moved, excluded)?;
+        } else {
+            fsutil::move_path(&path, &stored)?;
+            moved.push((path, stored));
+        }
+    }
+    Ok(())
+}
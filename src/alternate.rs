@@ -0,0 +1,118 @@
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::config;
+use crate::errors::{self, Code};
+
+/// Separates a stored name from the conditions it is deployed under, as in
+/// `.gitconfig##os.darwin`. Several conditions are joined with commas and must all hold.
+pub const SEPARATOR: &str = "##";
+
+/// Splits a file name into the name it is deployed as and its conditions, if any.
+fn split(name: &OsStr) -> Option<(&str, &str)> {
+    name.to_str()?.split_once(SEPARATOR)
+}
+
+/// Returns true if the current operating system is `os`. `darwin` is accepted for
+/// macOS, and `bsd` matches every BSD.
+fn is_os(os: &str) -> bool {
+    let current = std::env::consts::OS;
+    match os {
+        "darwin" => current == "macos",
+        "bsd" => current.ends_with("bsd") || current == "dragonfly",
+        _ => current == os,
+    }
+}
+
+/// What conditions are checked against, gathered once per run.
+struct Machine {
+    hostname: String,
+    tags: Vec<String>,
+}
+
+static MACHINE: OnceLock<Machine> = OnceLock::new();
+
+fn machine() -> io::Result<&'static Machine> {
+    if let Some(machine) = MACHINE.get() {
+        return Ok(machine);
+    }
+    let machine = Machine { hostname: hostname(), tags: config::load()?.tags };
+    Ok(MACHINE.get_or_init(|| machine))
+}
+
+/// Returns the name of this machine as reported by the system.
+pub fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for its whole length, and a truncated name is
+    // still cut at the last byte below.
+    if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+        return String::new();
+    }
+    let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..end]).into_owned()
+}
+
+/// Returns true if this machine is called `name`, either by its full name or by
+/// the part before the first dot, so `laptop` matches `laptop.example.com`.
+fn is_host(name: &str, hostname: &str) -> bool {
+    hostname.eq_ignore_ascii_case(name) || hostname.split('.').next().is_some_and(|short| short.eq_ignore_ascii_case(name))
+}
+
+/// Evaluates a comma-separated list of conditions such as `os.linux,tag.work`.
+/// `name` says where they come from in errors, such as `'.gitconfig##tag.work'`.
+pub fn holds(conditions: &str, name: &str) -> io::Result<bool> {
+    for condition in conditions.split(',') {
+        let holds = match condition.split_once('.') {
+            Some(("os", os)) => is_os(os),
+            Some(("hostname", host)) => is_host(host, &machine()?.hostname),
+            Some(("tag", tag)) => machine()?.tags.iter().any(|t| t == tag),
+            _ => {
+                return Err(errors::error(
+                    Code::InvalidConfig,
+                    format!("'{}' in {} is not a known condition. Use os.<name>, hostname.<name>, or tag.<name>, e.g. '##os.darwin'.", condition, name),
+                ));
+            }
+        };
+        if !holds {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns true if every conditional component of a repository-relative path
+/// applies to this machine. Paths without conditions always apply.
+pub fn applies(stored: &Path) -> io::Result<bool> {
+    for component in stored.components() {
+        if let Some((_, conditions)) = split(component.as_os_str())
+            && !holds(conditions, &format!("'{}'", stored.display()))?
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns the path with the conditions removed from every component, which is
+/// where a conditional entry is deployed.
+pub fn strip(path: &Path) -> PathBuf {
+    path.components()
+        .map(|component| match component {
+            Component::Normal(name) => split(name).map_or(name, |(base, _)| OsStr::new(base)),
+            other => other.as_os_str(),
+        })
+        .collect()
+}
+
+/// Counts the conditions in a path, so that a more specific alternate such as
+/// `.gitconfig##os.darwin` wins over a plain `.gitconfig`.
+pub fn specificity(stored: &Path) -> usize {
+    stored.components().filter_map(|component| split(component.as_os_str())).map(|(_, conditions)| conditions.split(',').count()).sum()
+}
+
+/// Returns true if a file name carries conditions.
+pub fn is_conditional(name: &OsStr) -> bool {
+    split(name).is_some()
+}
@@ -0,0 +1,247 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::layout;
+use crate::repo;
+use crate::state;
+use crate::ui;
+
+/// Where older versions of dfl kept backups, relative to the home directory.
+pub const LEGACY_DIR: &str = ".dfl-backups";
+
+/// Returns the directory holding the files `sync` moved out of the way,
+/// `$XDG_STATE_HOME/dfl/backups`, or `~/.dfl-backups` where an older dfl made them.
+pub fn backups_dir() -> io::Result<PathBuf> {
+    Ok(repo::current_or_legacy(state::state_dirs()?.0.join("backups"), repo::home_dir()?.join(LEGACY_DIR)))
+}
+
+/// Directory below the backups directory that mirrors targets outside `$HOME`.
+const OUTSIDE_HOME: &str = "ROOT";
+
+/// Returns the directory holding every backup of `target`. It mirrors the target's
+/// path relative to `$HOME`, or its absolute path below `ROOT/` for targets outside it.
+fn backup_dir_for(target: &Path) -> io::Result<PathBuf> {
+    let home_dir = repo::home_dir()?;
+    let relative = match layout::home_relative(target, &home_dir)? {
+        Some(relative) => relative,
+        None => Path::new(OUTSIDE_HOME).join(std::path::absolute(target)?.strip_prefix("/").map_err(io::Error::other)?),
+    };
+    Ok(backups_dir()?.join(relative))
+}
+
+/// Returns true for the timestamped names backups are stored under.
+fn is_backup_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() >= 20 && bytes[..4].iter().all(u8::is_ascii_digit) && bytes[10] == b'T' && bytes[19] == b'Z'
+}
+
+/// Formats the current UTC time as `2024-05-01T09-30-00Z`, which sorts chronologically
+/// and is safe to use as a file name everywhere.
+pub fn timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}T{:02}-{:02}-{:02}Z", year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60)
+}
+
+/// Moves `target` into a new timestamped backup and prunes old backups beyond the
+/// configured retention. Returns the path of the new backup.
+pub fn create(target: &Path) -> io::Result<PathBuf> {
+    let backup = reserve(target)?;
+    fsutil::move_path(target, &backup)?;
+    prune(target)?;
+    Ok(backup)
+}
+
+/// Returns an unused timestamped path for a new backup of `target`, creating its
+/// directory. The caller puts the backup there and then calls `prune`.
+pub fn reserve(target: &Path) -> io::Result<PathBuf> {
+    let dir = backup_dir_for(target)?;
+    fs::create_dir_all(&dir)?;
+
+    let stamp = timestamp();
+    let mut backup = dir.join(&stamp);
+    let mut attempt = 1;
+    while fs::symlink_metadata(&backup).is_ok() {
+        attempt += 1;
+        backup = dir.join(format!("{}-{}", stamp, attempt));
+    }
+    Ok(backup)
+}
+
+/// Removes the oldest backups of `target` beyond the configured retention.
+pub fn prune(target: &Path) -> io::Result<()> {
+    let keep = config::load()?.backup_retention;
+    if keep > 0 {
+        let backups = list(target)?;
+        for old in backups.iter().take(backups.len().saturating_sub(keep)) {
+            fsutil::remove_path(old)?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the backups of `target`, oldest first.
+pub fn list(target: &Path) -> io::Result<Vec<PathBuf>> {
+    let dir = backup_dir_for(target)?;
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut backups = entries.map(|entry| entry.map(|e| e.path())).collect::<io::Result<Vec<PathBuf>>>()?;
+    backups.retain(|path| path.file_name().is_some_and(|name| is_backup_name(&name.to_string_lossy())));
+    backups.sort();
+    Ok(backups)
+}
+
+/// Collects every backed-up path below `dir` as `(target, backups)` pairs.
+fn collect(dir: &Path, relative: &Path, found: &mut Vec<(PathBuf, Vec<PathBuf>)>) -> io::Result<()> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if is_backup_name(&name) {
+            backups.push(entry.path());
+        } else if entry.file_type()?.is_dir() {
+            collect(&entry.path(), &relative.join(&name), found)?;
+        }
+    }
+    if !backups.is_empty() {
+        backups.sort();
+        found.push((relative.to_path_buf(), backups));
+    }
+    Ok(())
+}
+
+/// Handles the 'backups' command.
+/// It lists the backups `sync` made, for one path or for every path that has any.
+pub fn handle_backups_command(path: Option<&str>) -> io::Result<()> {
+    let dir = backups_dir()?;
+    let mut found = Vec::new();
+    match path {
+        Some(path) => {
+            let target = repo::expand_home(Path::new(path), &repo::home_dir()?);
+            let backups = list(&target)?;
+            if backups.is_empty() {
+                return Err(errors::error(Code::InvalidArgument, format!("There are no backups of '{}'.", path)));
+            }
+            found.push((PathBuf::from(path), backups));
+        }
+        None if dir.exists() => {
+            collect(&dir, Path::new(""), &mut found)?;
+            for (target, _) in &mut found {
+                *target = match target.strip_prefix(OUTSIDE_HOME) {
+                    Ok(absolute) => Path::new("/").join(absolute),
+                    Err(_) => Path::new("~").join(&target),
+                };
+            }
+        }
+        None => {}
+    }
+
+    if found.is_empty() {
+        println!("No backups yet. 'dfl sync' stores files it replaces in {}.", dir.display());
+        return Ok(());
+    }
+    for (target, backups) in &found {
+        println!("{}", target.display());
+        for backup in backups.iter().rev() {
+            println!("  {}", backup.file_name().unwrap_or_default().to_string_lossy());
+        }
+    }
+    println!("\nBackups are stored in {}.", dir.display());
+    Ok(())
+}
+
+/// Moves the backup set aside at `staging` to `target`, first removing dfl's symlink
+/// there or backing up anything else in the way.
+fn put_in_place(staging: &Path, target: &Path, dfl_path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(target).is_ok() {
+        if repo::links_into_repo(target, dfl_path) {
+            fs::remove_file(target)?;
+        } else {
+            let replaced = create(target)?;
+            ui::say(&format!("Backed up the current '{}' to '{}'.", repo::display(target), replaced.display()));
+        }
+    }
+    fsutil::move_path(staging, target)
+}
+
+/// Handles the 'restore-backup' command.
+/// It puts a backup of `path` back in place, replacing dfl's symlink. The newest
+/// backup is used unless one is named or picked interactively. A path that is
+/// still managed is marked as conflicted so `sync` does not back it up again.
+pub fn handle_restore_backup_command(path: &str, name: Option<&str>) -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let dfl_path = repo::dfl_path()?;
+    let target = std::path::absolute(repo::expand_home(Path::new(path), &home_dir))?;
+
+    let mut backups = list(&target)?;
+    backups.reverse();
+    if backups.is_empty() {
+        return Err(errors::error(Code::InvalidArgument, format!("There are no backups of '{}'. Run 'dfl backups' to see what is available.", path)));
+    }
+    let names: Vec<String> = backups.iter().map(|b| b.file_name().unwrap_or_default().to_string_lossy().into_owned()).collect();
+    let index = match name {
+        Some(name) => names
+            .iter()
+            .position(|n| n == name)
+            .ok_or_else(|| errors::error(Code::InvalidArgument, format!("No backup named '{}' for '{}'. Run 'dfl backups {}' to list them.", name, path, path)))?,
+        None if backups.len() > 1 && ui::interactive() => dialoguer::Select::new()
+            .with_prompt(format!("Restore which backup of '{}'?", path))
+            .items(&names)
+            .default(0)
+            .interact()
+            .map_err(|e| errors::error(Code::InvalidArgument, format!("Could not read your answer: {}", e)))?,
+        None => 0,
+    };
+    let chosen = &backups[index];
+
+    // Set the chosen backup aside first, so backing up what is in the way cannot prune it.
+    // The name is unique to this run, so what an interrupted restore left behind is never overwritten.
+    let mut staging = chosen.with_file_name(format!(".restoring-{}-{}", names[index], std::process::id()));
+    let mut attempt = 1;
+    while fs::symlink_metadata(&staging).is_ok() {
+        attempt += 1;
+        staging = chosen.with_file_name(format!(".restoring-{}-{}-{}", names[index], std::process::id(), attempt));
+    }
+    fs::rename(chosen, &staging)?;
+    if let Err(e) = put_in_place(&staging, &target, &dfl_path) {
+        // Put the backup back under its own name, so 'dfl backups' still lists it.
+        if fs::symlink_metadata(&staging).is_ok()
+            && let Err(undo) = fs::rename(&staging, chosen)
+        {
+            return Err(errors::error(Code::MoveFailed, format!("Could not restore '{}': {}
+The backup is kept at '{}' ({}); move it back to '{}' by hand.", path, e, staging.display(), undo, chosen.display())));
+        }
+        return Err(errors::error(Code::MoveFailed, format!("Could not restore '{}': {}
+The backup is still available; run 'dfl backups {}' to see it.", path, e, path)));
+    }
+    ui::say(&format!("✅ Restored '{}' from the backup taken at {}.", path, names[index]));
+
+    if repo::managed_entries(&dfl_path)?.iter().any(|entry| entry.target == target) {
+        let mut state = state::load_repo(&dfl_path)?;
+        state.restored.insert(target.clone());
+        state::save_repo(&dfl_path, &state)?;
+        ui::say(&format!("'{}' is still managed by dfl and is now marked as conflicted; 'dfl sync' leaves it alone.", path));
+        ui::say("Run 'dfl sync --force' to link the repository version again.");
+    } else {
+        ui::say(&format!("'{}' is not managed by dfl.", path));
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::add::{self, AddOptions};
+use crate::backup;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::hooks;
+use crate::list::{self, ListedEntry};
+use crate::repo;
+use crate::scan;
+use crate::ui::{self, Tone, message_box};
+
+/// Commands that work on symlinks or on files stored in the repository, which a bare
+/// repository has neither of.
+pub const UNSUPPORTED: &[&str] = &["layout", "unfold", "fold", "prune", "perms", "verify", "sparse", "ignore", "mirror", "machine", "merge-from", "promote", "remove", "mv-entry"];
+
+/// Returns true if the repository at `dfl_path` is a bare one tracking files in place in the
+/// home directory, instead of a repository holding the files behind symlinks.
+pub fn in_use(dfl_path: &Path) -> bool {
+    dfl_path.join("HEAD").is_file() && !dfl_path.join(".git").exists()
+}
+
+/// Returns the directory the bare repository at `dfl_path` tracks files in, or
+/// `None` if it is not one.
+pub fn work_tree(dfl_path: &Path) -> Option<PathBuf> {
+    if !in_use(dfl_path) {
+        return None;
+    }
+    git2::Config::open(&dfl_path.join("config")).ok()?.get_path("core.worktree").ok()
+}
+
+/// Returns an error naming the command if it has no meaning in bare mode.
+pub fn check_supported(dfl_path: &Path, command: &str) -> io::Result<()> {
+    if in_use(dfl_path) && UNSUPPORTED.contains(&command) {
+        return Err(errors::error(Code::InvalidArgument, format!("'{}' works on symlinked dotfiles, and {} is a bare repository tracking files in place.", command, repo::display(dfl_path))));
+    }
+    Ok(())
+}
+
+/// Moves the git directory of the repository just created or cloned in `staging`
+/// to `dfl_path` and points it at the home directory. Untracked files are hidden
+/// from `git status`, as they are the rest of the home directory.
+fn adopt(staging: &Path, dfl_path: &Path, home_dir: &Path) -> io::Result<()> {
+    fs::rename(staging.join(".git"), dfl_path)?;
+    fs::remove_dir_all(staging)?;
+    git::set_config(dfl_path, "core.bare", "false")?;
+    git::set_config(dfl_path, "core.worktree", &home_dir.to_string_lossy())?;
+    git::set_config(dfl_path, "status.showUntrackedFiles", "no")
+}
+
+/// Returns the directory a repository for bare mode is set up in before it moves to `dfl_path`.
+fn staging(dfl_path: &Path) -> io::Result<PathBuf> {
+    let staging = dfl_path.with_extension("new");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    Ok(staging)
+}
+
+/// Creates an empty bare repository at `dfl_path` tracking files in `home_dir`.
+pub fn init(dfl_path: &Path, home_dir: &Path) -> io::Result<()> {
+    let staging = staging(dfl_path)?;
+    fs::create_dir_all(&staging)?;
+    git::init(&staging)?;
+    adopt(&staging, dfl_path, home_dir)
+}
+
+/// Clones `url` as a bare repository at `dfl_path` and checks its files out in
+/// place in `home_dir`. Files already there are moved to the backups first.
+pub fn clone(dfl_path: &Path, home_dir: &Path, url: &str, depth: Option<u32>, filter: Option<&str>) -> io::Result<()> {
+    let staging = staging(dfl_path)?;
+    git::clone(&staging, url, depth, filter)?;
+    adopt(&staging, dfl_path, home_dir)?;
+
+    let mut backed_up = 0;
+    for file in git::tracked_files(dfl_path)? {
+        let target = home_dir.join(&file);
+        if fs::symlink_metadata(&target).is_ok() {
+            backup::create(&target)?;
+            backed_up += 1;
+        }
+    }
+    if backed_up > 0 {
+        ui::say(&format!("✅ Moved {} existing file(s) to {} to make room.", backed_up, backup::backups_dir()?.display()));
+    }
+    git::checkout_head(dfl_path)
+}
+
+/// Handles the 'add' command in bare mode.
+/// It starts tracking each file or directory where it is, without moving it or
+/// creating a symlink, and commits everything that was added in a single commit.
+pub fn handle_add_command(patterns: &[String], options: &AddOptions) -> io::Result<()> {
+    if options.package.is_some() || options.store_as.is_some() || options.interactive || options.lfs || !options.exclude.is_empty() {
+        return Err(errors::error(Code::InvalidArgument, "'--package', '--as', '--exclude', '--interactive', and '--lfs' decide how files are stored in the repository, and a bare repository tracks them in place."));
+    }
+    let home_dir = repo::home_dir()?;
+    let dfl_path = repo::dfl_path()?;
+
+    let mut sources = Vec::new();
+    for pattern in patterns {
+        sources.extend(add::expand_pattern(pattern, &home_dir)?);
+    }
+    // Paths relative to the home directory, the absolute paths for hooks and the
+    // secret scan, and the paths git did not track before.
+    let (mut added, mut absolutes, mut new) = (Vec::new(), Vec::new(), Vec::new());
+    let tracked = git::tracked_files(&dfl_path)?;
+    for source in &sources {
+        let absolute = std::path::absolute(source)?;
+        let Ok(relative) = absolute.strip_prefix(&home_dir) else {
+            return Err(errors::error(Code::InvalidArgument, format!("{} is outside your home directory, which a bare repository tracks.", absolute.display())));
+        };
+        if fs::symlink_metadata(&absolute).is_err() {
+            return Err(errors::error(Code::InvalidArgument, format!("{} does not exist.", absolute.display())));
+        }
+        if !tracked.iter().any(|file| Path::new(file).starts_with(relative)) {
+            new.push(relative.to_path_buf());
+        }
+        added.push(relative.to_string_lossy().into_owned());
+        absolutes.push(absolute);
+    }
+
+    hooks::check(&dfl_path, "pre-add", &absolutes)?;
+    if !options.no_commit && !options.allow_secrets {
+        // Changes to tracked files are scanned as uncommitted lines.
+        let untracked: Vec<PathBuf> = new.iter().map(|relative| home_dir.join(relative)).collect();
+        scan::check(&dfl_path, &home_dir, &untracked)?;
+    }
+    git::add_paths(&dfl_path, &added).map_err(|e| errors::error(Code::GitFailed, format!("Error staging changes: {}", e)))?;
+    for relative in &added {
+        ui::say(&format!("✅ Tracking ~/{} in place.", relative));
+    }
+    if options.no_commit {
+        ui::say("Skipped committing. Run 'dfl commit' when you are ready.");
+        return Ok(());
+    }
+
+    let stats = git::staged_diffstat(&dfl_path).map_err(|e| errors::error(Code::GitFailed, format!("Error comparing the changes: {}", e)))?;
+    if stats.is_empty() {
+        ui::say("Nothing to commit: these files match the last commit.");
+        return Ok(());
+    }
+    let message = match &options.message {
+        Some(message) => message.clone(),
+        None => {
+            let (action, files, default) = add::commit_message(&new, &stats);
+            git::commit_message(&action, &files, default)?
+        }
+    };
+    git::commit(&dfl_path, &message).map_err(|e| errors::error(Code::GitFailed, format!("Error committing changes: {}", e)))?;
+    ui::say("✅ Changes committed.");
+    if options.push || config::load()?.auto_push {
+        crate::remote::push_after_commit(&dfl_path)?;
+    }
+    Ok(())
+}
+
+/// Handles the 'sync' command in bare mode.
+/// Tracked files already live in the home directory, so nothing is linked; files
+/// deleted from it are checked out again, as sync would link them again.
+pub fn handle_sync_command(dfl_path: &Path) -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let Some(head) = git::head(dfl_path)? else {
+        ui::say("Nothing to sync yet. Add a dotfile first.");
+        return Ok(());
+    };
+    let files = git::tracked_files(dfl_path)?;
+    let mut restored = 0;
+    for file in &files {
+        if fs::symlink_metadata(home_dir.join(file)).is_err() {
+            git::checkout_path(dfl_path, &head, file).map_err(|e| errors::error(Code::GitFailed, format!("Error restoring ~/{}: {}", file, e)))?;
+            ui::say(&format!("✅ Restored ~/{}", file));
+            restored += 1;
+        }
+    }
+    message_box("Sync Complete", &format!("{} file(s) tracked in place, {} restored.", files.len(), restored));
+    Ok(())
+}
+
+/// Handles the 'list' command in bare mode.
+/// It lists the tracked files and marks the ones changed or deleted since the last commit,
+/// or prints them as JSON with `json`.
+pub fn handle_list_command(dfl_path: &Path, json: bool) -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let files = git::tracked_files(dfl_path)?;
+    if json {
+        let changed = git::local_changes(dfl_path)?;
+        let entries = files.iter().map(|file| ListedEntry {
+            target: home_dir.join(file).display().to_string(),
+            stored: file.clone(),
+            package: None,
+            mode: None,
+            status: if fs::symlink_metadata(home_dir.join(file)).is_err() { "deleted" } else if changed.contains(file) { "modified" } else { "tracked" },
+            links_to: None,
+            restored: false,
+        });
+        return list::print_listing(dfl_path, "bare".to_string(), entries.collect());
+    }
+    if files.is_empty() {
+        println!("No dotfiles are tracked yet. Add one with 'dfl add <path>'.");
+        return Ok(());
+    }
+    let changed = git::local_changes(dfl_path)?;
+    let width = files.iter().map(|file| file.chars().count()).max().unwrap_or(0) + 2;
+    for file in &files {
+        let (tone, state) = if fs::symlink_metadata(home_dir.join(file)).is_err() {
+            (Tone::Broken, "deleted")
+        } else if changed.contains(file) {
+            (Tone::Conflict, "modified")
+        } else {
+            (Tone::Ok, "ok")
+        };
+        println!("{} {:width$}  {}", ui::mark(tone), format!("~/{}", file), ui::paint(tone, state));
+    }
+    Ok(())
+}
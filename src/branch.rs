@@ -0,0 +1,78 @@
+use std::io;
+use std::path::Path;
+
+use crate::conflict;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::hooks;
+use crate::manifest;
+use crate::repo;
+use crate::ui;
+use crate::verify;
+
+/// Prints every local branch, marking the current one and showing what it tracks.
+fn list(dfl_path: &Path) -> io::Result<()> {
+    let current = git::branch(dfl_path).ok();
+    for branch in git::branches(dfl_path)? {
+        let marker = if current.as_deref() == Some(branch.as_str()) { "*" } else { " " };
+        match git::upstream(dfl_path, &branch)? {
+            Some((remote, remote_branch)) => println!("{} {} -> {}/{}", marker, branch, remote, remote_branch),
+            None => println!("{} {}", marker, branch),
+        }
+    }
+    Ok(())
+}
+
+/// Settles the home directory after the checked-out files changed, as `pull` does.
+/// Links already point into the repository, so only new entries need a sync.
+pub fn after_checkout(dfl_path: &Path) -> io::Result<()> {
+    let changed = verify::record_checksums(dfl_path)?;
+    hooks::run_reloads(&manifest::load(dfl_path)?, &repo::home_dir()?, &changed)?;
+    ui::say("Run 'dfl sync' to link anything that is new on this branch.");
+    Ok(())
+}
+
+/// What the 'branch' command does.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum BranchCommand {
+    /// Lists the local branches and what they track (the default).
+    List,
+    /// Creates a branch at the current commit and switches to it.
+    New { name: String },
+    /// Switches to another branch.
+    Switch { name: String },
+    /// Merges a branch into the current one.
+    Merge { name: String },
+}
+
+/// Handles the 'branch' command.
+/// `list` shows the local branches, `new` creates a branch at the current commit and
+/// switches to it, `switch` checks out another branch, and `merge` merges a branch
+/// into the current one.
+pub fn handle_branch_command(command: Option<BranchCommand>) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let failed = |action: &str, e: io::Error| errors::error(errors::code_or(&e, Code::GitFailed), format!("Error {}: {}", action, e));
+    match command.unwrap_or(BranchCommand::List) {
+        BranchCommand::List => list(&dfl_path),
+        BranchCommand::New { name } => {
+            git::create_branch(&dfl_path, &name).map_err(|e| failed("creating the branch", e))?;
+            git::switch(&dfl_path, &name).map_err(|e| failed("switching branches", e))?;
+            ui::say(&format!("✅ Created branch '{}' and switched to it. 'dfl push' publishes it.", name));
+            Ok(())
+        }
+        BranchCommand::Switch { name } => {
+            git::switch(&dfl_path, &name).map_err(|e| failed("switching branches", e))?;
+            ui::say(&format!("✅ Switched to branch '{}'.", name));
+            after_checkout(&dfl_path)
+        }
+        BranchCommand::Merge { name } => {
+            git::merge(&dfl_path, &name, conflict::merge_resolver()).map_err(|e| failed("merging", e))?;
+            ui::say(&format!("✅ Merged '{}' into the current branch.", name));
+            after_checkout(&dfl_path)
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::io;
+use std::path::Path;
+
+use crate::add;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::repo;
+use crate::scan;
+use crate::verify;
+
+/// Options accepted by `dfl commit`.
+#[derive(Debug, Default)]
+pub struct CommitOptions {
+    /// Fold the changes into the last commit instead of making a new one.
+    pub amend: bool,
+    /// Replaces the generated message, or with `amend`, the last commit's message.
+    pub message: Option<String>,
+    /// Push right after committing, as `auto_push` in the config does.
+    pub push: bool,
+    /// Commit even if a changed line looks like a secret.
+    pub allow_secrets: bool,
+}
+
+/// Returns an error if the last commit is already on the remote branch the
+/// current branch tracks, where replacing it would need a force push.
+fn check_unpushed(dfl_path: &Path, head: &str) -> io::Result<()> {
+    let branch = git::branch(dfl_path)?;
+    let Some(published) = git::upstream_head(dfl_path, &branch)? else {
+        return Ok(());
+    };
+    if git::count_commits(dfl_path, Some(&published), head)? == 0 {
+        let remote = git::upstream(dfl_path, &branch)?.map(|(remote, _)| remote).unwrap_or_else(|| "origin".to_string());
+        return Err(errors::error(Code::InvalidArgument, format!("The last commit was already pushed to {}, and other machines may have pulled it. Run 'dfl commit' without --amend to commit the fix on its own.", remote)));
+    }
+    Ok(())
+}
+
+/// Handles the 'commit' command.
+/// It commits the changes made to managed files since the last commit, with a
+/// message naming them. With `amend`, they are folded into the last commit
+/// instead, as long as it was not pushed yet, to fix a typo without a commit of its own.
+pub fn handle_commit_command(options: &CommitOptions) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+    let head = git::head(&dfl_path)?;
+    if options.amend {
+        let Some(head) = &head else {
+            return Err(errors::error(Code::InvalidArgument, "There is no commit to amend yet. Add a dotfile first."));
+        };
+        check_unpushed(&dfl_path, head)?;
+    }
+    if !options.allow_secrets {
+        scan::check(&dfl_path, &repo::home_dir()?, &[])?;
+    }
+
+    git::add_all(&dfl_path).map_err(|e| errors::error(Code::GitFailed, format!("Error staging changes: {}", e)))?;
+    let stats = git::staged_diffstat(&dfl_path).map_err(|e| errors::error(Code::GitFailed, format!("Error comparing the changes: {}", e)))?;
+    if stats.is_empty() && (!options.amend || options.message.is_none()) {
+        println!("Nothing to commit: your managed files match the last commit.");
+        return Ok(());
+    }
+
+    if options.amend {
+        git::amend(&dfl_path, options.message.as_deref()).map_err(|e| errors::error(Code::GitFailed, format!("Error amending the last commit: {}", e)))?;
+        match stats.len() {
+            0 => println!("✅ Reworded the last commit."),
+            count => println!("✅ Folded {} changed file(s) into the last commit.", count),
+        }
+    } else {
+        let message = match &options.message {
+            Some(message) => message.clone(),
+            None => {
+                let (action, files, default) = add::commit_message(&[], &stats);
+                git::commit_message(&action, &files, default)?
+            }
+        };
+        git::commit(&dfl_path, &message).map_err(|e| errors::error(Code::GitFailed, format!("Error committing changes: {}", e)))?;
+        println!("✅ Committed {} changed file(s).", stats.len());
+    }
+
+    verify::record_checksums(&dfl_path)?;
+    if options.push || config::load()?.auto_push {
+        crate::remote::push_after_commit(&dfl_path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{self, Code};
+use crate::git::Backend;
+use crate::repo::{self, LinkMode};
+use crate::ui::{self, ColorChoice, Theme};
+
+/// User configuration, read from `~/.config/dfl/config.toml`.
+/// Every field has a default so a missing file or key is never an error.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Print one-time tips after commands.
+    pub hints: bool,
+    /// Treat every warning as an error, as if `--strict` were passed.
+    pub strict: bool,
+    /// How many backups `sync` keeps per file in the backups directory; 0 keeps all of them.
+    pub backup_retention: usize,
+    /// Create symlinks relative to their own directory instead of absolute ones.
+    pub relative_links: bool,
+    /// Classes this machine belongs to, such as `work` or `personal`, which pick
+    /// variants named with `##tag.<name>`.
+    pub tags: Vec<String>,
+    /// Command that prints the secret `{name}` for `{{secret "NAME"}}` in templates,
+    /// such as `pass show dotfiles/{name}`.
+    pub secret_command: String,
+    /// Commands that print individual secrets, keyed by secret name.
+    pub secrets: BTreeMap<String, String>,
+    /// Whether `pull` and `up` replay local commits on top of the remote ones
+    /// instead of creating a merge commit.
+    pub pull_rebase: bool,
+    /// Command that opens a file with conflict markers for editing when a pull
+    /// conflicts, such as `code --wait`. `$VISUAL` or `$EDITOR` is used when empty.
+    pub merge_tool: String,
+    /// Sign every commit dfl makes, as `commit.gpgsign` does for git.
+    pub sign_commits: bool,
+    /// GPG key ID or SSH public key file to sign with, instead of git's `user.signingkey`.
+    pub signing_key: String,
+    /// Subject of the commits dfl makes, such as
+    /// `chore(dotfiles): {action} {files} on {hostname}`; built-in messages when empty.
+    pub commit_template: String,
+    /// Push to the remote right after `add` and other commands commit.
+    pub auto_push: bool,
+    /// Whether git operations use the embedded library or the `git` command.
+    pub git_backend: Backend,
+    /// Size in KiB from which `add` offers to store a binary file with git-lfs.
+    pub lfs_threshold_kb: u64,
+    /// Size in KiB above which the pre-commit hook refuses a file not stored with
+    /// git-lfs; 0 allows any size.
+    pub max_file_kb: u64,
+    /// Where the repository lives; a leading `~/` expands to the home directory.
+    /// `$XDG_DATA_HOME/dfl/repo` when empty, or `~/.dfl` where an older dfl put it.
+    pub repo_path: String,
+    /// How this machine deploys entries that `dfl.toml` gives no mode, such as
+    /// `copy` where symlinks do not work; the repository decides when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_mode: Option<LinkMode>,
+    /// Branch `init` starts the repository on; git's `init.defaultBranch` when empty.
+    pub default_branch: String,
+    /// Install git hooks that check commits and sync after pulls made with git.
+    pub git_hooks: bool,
+    /// Gitignore-style patterns that `add` leaves out of every directory it adds on
+    /// this machine, on top of the `exclude` list in `dfl.toml`.
+    pub exclude: Vec<String>,
+    /// When to color output, as if `--color` were passed.
+    pub color: ColorChoice,
+    /// Colors of entries that are in place, in conflict, and broken.
+    pub theme: Theme,
+    /// Repositories other than the default one, keyed by the name `--profile` picks them by.
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A repository of its own, such as one for work dotfiles kept apart from personal ones.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Profile {
+    /// Where the repository lives; a leading `~/` expands to the home directory.
+    pub repo_path: String,
+    /// URL `clone` fetches the repository from when none is given, and `init` adds as `origin`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub remote: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            hints: true,
+            strict: false,
+            backup_retention: 5,
+            relative_links: false,
+            tags: Vec::new(),
+            secret_command: String::new(),
+            secrets: BTreeMap::new(),
+            pull_rebase: true,
+            merge_tool: String::new(),
+            sign_commits: false,
+            signing_key: String::new(),
+            commit_template: String::new(),
+            auto_push: false,
+            git_backend: Backend::default(),
+            lfs_threshold_kb: 512,
+            max_file_kb: 10240,
+            repo_path: String::new(),
+            link_mode: None,
+            default_branch: String::new(),
+            git_hooks: true,
+            exclude: Vec::new(),
+            color: ColorChoice::Auto,
+            theme: Theme::default(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// Returns the directory of the user configuration by the XDG base directories,
+/// `$XDG_CONFIG_HOME/dfl`, and where an older dfl kept it, the system's own
+/// configuration directory. They only differ where the XDG variable overrides the
+/// system's convention, such as on macOS.
+pub fn config_dirs() -> io::Result<(PathBuf, PathBuf)> {
+    let legacy = dirs::config_dir().ok_or_else(|| errors::error(Code::NoHomeDir, "Could not find config directory"))?.join("dfl");
+    let current = repo::xdg_dir("XDG_CONFIG_HOME", || None).map_or_else(|| legacy.clone(), |dir| dir.join("dfl"));
+    Ok((current, legacy))
+}
+
+/// Returns the path of the user configuration file.
+pub fn config_path() -> io::Result<PathBuf> {
+    let (current, legacy) = config_dirs()?;
+    Ok(repo::current_or_legacy(current, legacy).join("config.toml"))
+}
+
+/// Loads the user configuration, falling back to defaults when the file does not exist.
+pub fn load() -> io::Result<Config> {
+    let path = config_path()?;
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    toml::from_str(&contents).map_err(|e| errors::error(Code::InvalidConfig, format!("Error parsing {}: {}", path.display(), e)))
+}
+
+/// Returns the location of the repository named by `repo_path`, or the default
+/// one when it is empty. Relative paths are taken from the home directory.
+pub fn repo_path(config: &Config) -> io::Result<PathBuf> {
+    if config.repo_path.is_empty() {
+        return repo::default_dfl_path();
+    }
+    let home_dir = repo::home_dir()?;
+    Ok(home_dir.join(repo::expand_home(Path::new(&config.repo_path), &home_dir)))
+}
+
+/// Returns the keys and values set in the configuration file itself, without defaults.
+fn load_file() -> io::Result<toml::Table> {
+    let path = config_path()?;
+    if !path.exists() {
+        return Ok(toml::Table::new());
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    toml::from_str(&contents).map_err(|e| errors::error(Code::InvalidConfig, format!("Error parsing {}: {}", path.display(), e)))
+}
+
+/// Parses a value given on the command line as TOML, such as `true`, `5`, or
+/// `["work"]`, or takes it as a string if it is not valid TOML.
+fn parse_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+/// Writes `table` as the configuration file once it passes for a valid configuration.
+/// Comments in the file are not kept.
+fn save_file(table: toml::Table) -> io::Result<()> {
+    let path = config_path()?;
+    let contents = toml::to_string(&table).map_err(io::Error::other)?;
+    toml::from_str::<Config>(&contents).map_err(|e| errors::error(Code::InvalidConfig, format!("Not saving {}: {}", path.display(), e)))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
+
+/// Removes `key` from the configuration file, so it has its default again.
+/// Returns false if the file did not set it.
+pub fn unset(key: &str) -> io::Result<bool> {
+    let mut table = load_file()?;
+    if table.remove(key).is_none() {
+        return Ok(false);
+    }
+    save_file(table)?;
+    Ok(true)
+}
+
+/// What the 'config' command does.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum ConfigCommand {
+    /// Lists the settings in effect (the default).
+    List,
+    /// Shows one setting.
+    Get { key: String },
+    /// Changes one setting; the value is read as TOML, or as text if it is not valid TOML.
+    Set { key: String, value: String },
+    /// Puts one setting back to its default.
+    Unset { key: String },
+    /// Prints where the configuration file is.
+    Path,
+}
+
+/// Handles the 'config' command.
+/// It lists the settings in effect, shows or changes one of them in the
+/// configuration file, or prints where that file is.
+pub fn handle_config_command(command: Option<ConfigCommand>) -> io::Result<()> {
+    match command.unwrap_or(ConfigCommand::List) {
+        ConfigCommand::List => {
+            print!("{}", toml::to_string(&load()?).map_err(io::Error::other)?);
+            Ok(())
+        }
+        ConfigCommand::Get { key } => {
+            let settings = toml::Table::try_from(load()?).map_err(io::Error::other)?;
+            match settings.get(&key) {
+                Some(toml::Value::String(value)) => println!("{}", value),
+                Some(value) => println!("{}", value),
+                None if key == "link_mode" => println!("(not set)"),
+                None => return Err(errors::error(Code::InvalidArgument, format!("Unknown setting: {}. Run 'dfl config list' to see them all.", key))),
+            }
+            Ok(())
+        }
+        ConfigCommand::Set { key, value } => {
+            let mut table = load_file()?;
+            table.insert(key.clone(), parse_value(&value));
+            // A misspelled key would be ignored when loading, so it is refused here.
+            let contents = toml::to_string(&table).map_err(io::Error::other)?;
+            let settings = toml::from_str::<Config>(&contents).ok().and_then(|config| toml::Table::try_from(config).ok()).unwrap_or_default();
+            if !settings.is_empty() && !settings.contains_key(&key) {
+                return Err(errors::error(Code::InvalidArgument, format!("Unknown setting: {}. Run 'dfl config list' to see them all.", key)));
+            }
+            save_file(table)?;
+            ui::say(&format!("✅ Set {} in {}.", key, config_path()?.display()));
+            Ok(())
+        }
+        ConfigCommand::Unset { key } => {
+            if !unset(&key)? {
+                ui::say(&format!("{} is not set in {}; it already has its default.", key, config_path()?.display()));
+                return Ok(());
+            }
+            ui::say(&format!("✅ {} is back to its default.", key));
+            Ok(())
+        }
+        ConfigCommand::Path => {
+            println!("{}", config_path()?.display());
+            Ok(())
+        }
+    }
+}
+
+/// Template variables that belong to this machine, keyed by name.
+pub type Values = toml::Table;
+
+/// Returns the path of the machine-local template values file.
+pub fn values_path() -> io::Result<PathBuf> {
+    Ok(config_path()?.with_file_name("values.toml"))
+}
+
+/// Loads the machine-local template values, which are empty when the file does not exist.
+pub fn load_values() -> io::Result<Values> {
+    let path = values_path()?;
+    if !path.exists() {
+        return Ok(Values::new());
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    toml::from_str(&contents).map_err(|e| errors::error(Code::InvalidConfig, format!("Error parsing {}: {}", path.display(), e)))
+}
+
+/// Writes the machine-local template values.
+pub fn save_values(values: &Values) -> io::Result<()> {
+    let path = values_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let contents = toml::to_string(values).map_err(io::Error::other)?;
+    fs::write(path, contents)
+}
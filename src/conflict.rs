@@ -0,0 +1,175 @@
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use duct::cmd;
+
+use crate::commit::{self, CommitOptions};
+use crate::config;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::git::{self, Conflict};
+use crate::repo;
+use crate::ui;
+
+/// How a conflict between a local file and the repository version is settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Back up the local file and link the repository version.
+    UseRepo,
+    /// Copy the local file into the repository, then link it.
+    KeepLocal,
+    /// Leave the local file alone and do not link this entry.
+    Skip,
+}
+
+const CHOICES: &[&str] = &[
+    "Use the repository version (back up the local file)",
+    "Keep the local version (copy it into the repository)",
+    "Show the differences",
+    "Skip this file",
+    "Use the repository version for all remaining conflicts",
+    "Keep the local version for all remaining conflicts",
+    "Skip all remaining conflicts",
+];
+
+/// Asks how to settle a conflict between `local` and the repository's `stored`
+/// version. Once an answer for all remaining conflicts is given, it is kept in
+/// `remembered` and returned without asking again.
+pub fn resolve(stored: &Path, local: &Path, remembered: &Cell<Option<Resolution>>) -> io::Result<Resolution> {
+    if let Some(resolution) = remembered.get() {
+        return Ok(resolution);
+    }
+
+    println!("\n'{}' differs from the version in the repository.", local.display());
+    loop {
+        let choice = dialoguer::Select::new()
+            .with_prompt("What should dfl do?")
+            .items(CHOICES)
+            .default(0)
+            .interact()
+            .map_err(|e| errors::error(Code::InvalidArgument, format!("Could not read your answer: {}", e)))?;
+        let (resolution, for_all) = match choice {
+            0 => (Resolution::UseRepo, false),
+            1 => (Resolution::KeepLocal, false),
+            2 => {
+                // git exits non-zero whenever the files differ, which is expected here.
+                cmd!("git", "--no-pager", "diff", "--no-index", "--", stored, local).unchecked().run()?;
+                continue;
+            }
+            3 => (Resolution::Skip, false),
+            4 => (Resolution::UseRepo, true),
+            5 => (Resolution::KeepLocal, true),
+            _ => (Resolution::Skip, true),
+        };
+        if for_all {
+            remembered.set(Some(resolution));
+        }
+        return Ok(resolution);
+    }
+}
+
+const MERGE_CHOICES: &[&str] = &["Take the incoming version", "Keep the local version", "Edit it in a merge tool", "Show the differences"];
+
+/// Returns the resolver for conflicts during a pull or merge: asking about each
+/// file in a terminal, and nothing otherwise, so the pull stops without changes.
+pub fn merge_resolver() -> Option<git::Resolver> {
+    ui::interactive().then_some(resolve_merge as git::Resolver)
+}
+
+/// Asks how to settle a file changed on both sides of a merge, and returns what it
+/// should contain, or `None` to delete it.
+fn resolve_merge(conflict: &Conflict) -> io::Result<Option<Vec<u8>>> {
+    match (&conflict.ours, &conflict.theirs) {
+        (None, _) => println!("\n'{}' was deleted locally but changed in the incoming commits.", conflict.path),
+        (_, None) => println!("\n'{}' was changed locally but deleted in the incoming commits.", conflict.path),
+        _ => println!("\n'{}' was changed both locally and in the incoming commits.", conflict.path),
+    }
+    // Both versions may hold secrets, so no other user may read them or plant files in their place.
+    let scratch = fsutil::PrivateDir::create("dfl-merge")?;
+    let name = Path::new(&conflict.path).file_name().unwrap_or_default();
+    let mut marked = conflict.marked.clone();
+    let resolved = loop {
+        let choice = dialoguer::Select::new()
+            .with_prompt("What should dfl do?")
+            .items(MERGE_CHOICES)
+            .default(0)
+            .interact()
+            .map_err(|e| errors::error(Code::InvalidArgument, format!("Could not read your answer: {}", e)))?;
+        match choice {
+            0 => break conflict.theirs.clone(),
+            1 => break conflict.ours.clone(),
+            2 => {
+                let file = scratch.write(name, &marked)?;
+                let config = config::load()?;
+                let tool = [config.merge_tool, std::env::var("VISUAL").unwrap_or_default(), std::env::var("EDITOR").unwrap_or_default()].into_iter().find(|tool| !tool.is_empty()).unwrap_or_else(|| "vi".to_string());
+                let status = cmd!("sh", "-c", format!("{} \"$1\"", tool), "sh", &file).unchecked().run()?.status;
+                marked = fs::read(&file)?;
+                if !status.success() {
+                    println!("The merge tool exited with {}; pick again.", status);
+                } else if String::from_utf8_lossy(&marked).lines().any(|line| line.starts_with("<<<<<<<") || line.starts_with(">>>>>>>")) {
+                    println!("The file still has conflict markers; edit it again or pick a version.");
+                } else {
+                    break Some(marked);
+                }
+            }
+            _ => {
+                let local = scratch.write("local", conflict.ours.as_deref().unwrap_or_default())?;
+                let incoming = scratch.write("incoming", conflict.theirs.as_deref().unwrap_or_default())?;
+                // git exits non-zero whenever the files differ, which is expected here.
+                cmd!("git", "--no-pager", "diff", "--no-index", "--", &local, &incoming).unchecked().run()?;
+            }
+        }
+    };
+    ui::say(&format!("✅ Settled '{}'", conflict.path));
+    Ok(resolved)
+}
+
+/// What a pull does with uncommitted changes in the repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalChanges {
+    /// Commit them first, so the pull merges them like any other commit.
+    Commit,
+    /// Set them aside while pulling and put them back afterwards.
+    Stash,
+    /// Stop without pulling.
+    Abort,
+}
+
+const LOCAL_CHANGE_CHOICES: &[&str] = &["Commit them first, then pull", "Set them aside and put them back after pulling", "Stop without pulling"];
+
+/// Settles uncommitted changes before a pull, as `choice` says or, without one, as
+/// the user picks in a terminal. Outside a terminal they are set aside, which the
+/// pull then does. Stopping is an error, so scripts see that nothing was pulled.
+pub fn settle_local_changes(dfl_path: &Path, choice: Option<LocalChanges>) -> io::Result<()> {
+    let changes = git::local_changes(dfl_path)?;
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let choice = match choice {
+        Some(choice) => choice,
+        None if ui::interactive() => {
+            println!("You have uncommitted changes in {}:", repo::display(dfl_path));
+            for path in &changes {
+                println!("  {}", path);
+            }
+            let picked = dialoguer::Select::new()
+                .with_prompt("What should dfl do with them?")
+                .items(LOCAL_CHANGE_CHOICES)
+                .default(0)
+                .interact()
+                .map_err(|e| errors::error(Code::InvalidArgument, format!("Could not read your answer: {}", e)))?;
+            [LocalChanges::Commit, LocalChanges::Stash, LocalChanges::Abort][picked]
+        }
+        None => LocalChanges::Stash,
+    };
+    match choice {
+        LocalChanges::Commit => commit::handle_commit_command(&CommitOptions::default()),
+        LocalChanges::Stash => Ok(()),
+        LocalChanges::Abort => Err(errors::error(
+            Code::PullFailed,
+            format!("Not pulling: {} file(s) in {} have uncommitted changes. Commit them with 'dfl commit', or pull with --stash to set them aside while pulling.", changes.len(), repo::display(dfl_path)),
+        )),
+    }
+}
@@ -0,0 +1,90 @@
+use std::env;
+use std::io;
+use std::path::Path;
+
+use duct::cmd;
+
+use crate::errors::{self, Code};
+use crate::git;
+use crate::ui;
+
+/// Environment variable an HTTPS access token is read from, for scripts and CI.
+pub const TOKEN_VAR: &str = "DFL_GIT_TOKEN";
+
+/// Credential helper that answers with the token from `TOKEN_VAR` when it is set
+/// and stays silent otherwise, so the next helper gets its turn.
+const TOKEN_HELPER: &str = "!f() { test \"$1\" = get && test -n \"$DFL_GIT_TOKEN\" && printf 'username=dfl\\npassword=%s\\n' \"$DFL_GIT_TOKEN\"; }; f";
+
+/// Returns true for remotes reached over HTTP(S), which log in with a username and password or token.
+fn is_https(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// Returns the credential helper that keeps logins in the system keyring, if it is installed.
+fn keyring_helper() -> Option<&'static str> {
+    let helper = if cfg!(target_os = "macos") { "osxkeychain" } else { "libsecret" };
+    let exec_path = cmd!("git", "--exec-path").stderr_null().read().ok()?;
+    let program = format!("git-credential-{}", helper);
+    let mut dirs = env::split_paths(&env::var_os("PATH").unwrap_or_default()).collect::<Vec<_>>();
+    dirs.push(exec_path.trim().into());
+    dirs.iter().any(|dir| dir.join(&program).is_file()).then_some(helper)
+}
+
+/// Hands `token` to the configured credential helper to keep for `url`.
+fn store_token(dfl_path: &Path, url: &str, token: &str) -> io::Result<()> {
+    cmd!("git", "credential", "approve")
+        .dir(dfl_path)
+        .stdin_bytes(format!("url={}\nusername=dfl\npassword={}\n\n", url, token))
+        .run()
+        .map(drop)
+        .map_err(|e| errors::error(Code::GitFailed, format!("Error storing the token with git, which is needed for this: {}", e)))
+}
+
+/// Sets up how git logs in to an HTTPS remote of the repository. The token in
+/// `DFL_GIT_TOKEN` is always offered first. `helper` picks the credential helper
+/// that keeps logins; without one, the system keyring is used if nothing else is
+/// configured. With `store`, a token is read from `DFL_GIT_TOKEN` or asked for and
+/// kept by that helper. SSH and local remotes are left alone.
+pub fn configure(dfl_path: &Path, url: &str, helper: Option<&str>, store: bool) -> io::Result<()> {
+    if !is_https(url) {
+        if helper.is_some() || store {
+            return Err(errors::error(Code::InvalidArgument, "--credential-helper and --token only apply to HTTPS remotes; SSH remotes log in with keys."));
+        }
+        return Ok(());
+    }
+
+    // Keyed by the full URL, which both git and dfl's built-in git check before any other helper.
+    git::set_config(dfl_path, &format!("credential.{}.helper", url), TOKEN_HELPER)?;
+    match helper {
+        Some(helper) => {
+            git::set_config(dfl_path, "credential.helper", helper)?;
+            println!("✅ HTTPS logins are kept by the '{}' credential helper.", helper);
+        }
+        None if git::get_config(dfl_path, "credential.helper")?.is_none() => match keyring_helper() {
+            Some(helper) => {
+                git::set_config(dfl_path, "credential.helper", helper)?;
+                println!("✅ HTTPS logins are kept in the system keyring ('{}' credential helper).", helper);
+            }
+            None => println!("No git credential helper is set up, so HTTPS logins are not remembered. Set {} for scripts, or pass --credential-helper.", TOKEN_VAR),
+        },
+        None => {}
+    }
+    if !store {
+        return Ok(());
+    }
+
+    if git::get_config(dfl_path, "credential.helper")?.is_none() {
+        return Err(errors::error(Code::InvalidArgument, format!("There is no credential helper to keep the token in. Pass --credential-helper, or set {} wherever dfl runs.", TOKEN_VAR)));
+    }
+    let token = match env::var(TOKEN_VAR).ok().filter(|token| !token.trim().is_empty()) {
+        Some(token) => token,
+        None if ui::interactive() => dialoguer::Password::new()
+            .with_prompt("Access token")
+            .interact()
+            .map_err(|e| errors::error(Code::InvalidArgument, format!("The token prompt needs a terminal: {}", e)))?,
+        None => return Err(errors::error(Code::InvalidArgument, format!("--token needs a terminal to ask for the token, or the token in {}.", TOKEN_VAR))),
+    };
+    store_token(dfl_path, url, token.trim())?;
+    ui::say(&format!("✅ Stored the token for {}.", url));
+    Ok(())
+}
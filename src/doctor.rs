@@ -0,0 +1,132 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::{MetadataExt, symlink};
+
+use duct::cmd;
+
+use crate::alternate;
+use crate::config;
+use crate::fsutil;
+use crate::git;
+use crate::githooks;
+use crate::lfs;
+use crate::manifest;
+use crate::repo;
+use crate::signing;
+
+/// Collects the outcome of each check so the summary can count them.
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    failures: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: &str) {
+        println!("  ✅ {}", message);
+    }
+
+    fn warn(&mut self, message: &str) {
+        self.warnings += 1;
+        println!("  ⚠️  {}", message);
+    }
+
+    fn fail(&mut self, message: &str) {
+        self.failures += 1;
+        println!("  ❌ {}", message);
+    }
+}
+
+/// Handles the 'doctor' command.
+/// It checks the environment dfl depends on and reports anything that would make it misbehave.
+pub fn handle_doctor_command() -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let dfl_path = repo::dfl_path()?;
+    let mut report = Report::default();
+
+    println!("Checking your dfl setup...\n");
+
+    // Only the command backend needs git installed; plugins are still cloned with it.
+    let backend = git::backend(&dfl_path)?;
+    match cmd!("git", "--version").stdout_capture().stderr_null().read() {
+        Ok(version) => report.ok(&format!("git is installed ({})", version.trim())),
+        Err(_) if backend == git::Backend::Command => report.fail("git is not installed or not on PATH; git_backend = \"command\" needs it to commit, push, and pull."),
+        Err(_) => report.warn("git is not installed or not on PATH; dfl uses its built-in git support, but plugins cannot be installed."),
+    }
+
+    if git::git_dir(&dfl_path).exists() {
+        let layout = manifest::load(&dfl_path)?.layout;
+        report.ok(&format!("Repository found at {} ({} layout)", dfl_path.display(), layout));
+        if dfl_path == home_dir.join(repo::LEGACY_DIR) {
+            report.warn(&format!("The repository is in ~/.dfl, where an older dfl created it; run 'dfl migrate' to move it to {}.", repo::display(&repo::xdg_repo_path()?)));
+        }
+
+        // Hooks from an older dfl, or for a binary that moved, are rewritten on the spot.
+        if config::load()?.git_hooks {
+            let updated = githooks::install(&dfl_path)?;
+            for (name, state) in githooks::states(&dfl_path)? {
+                match state {
+                    githooks::HookState::Foreign => report.warn(&format!("The {} hook in {} is your own, so commits and pulls made with git skip dfl's checks.", name, repo::display(&dfl_path))),
+                    _ if updated.contains(&name) => report.ok(&format!("Installed dfl's {} git hook", name)),
+                    _ => report.ok(&format!("dfl's {} git hook is installed", name)),
+                }
+            }
+        }
+    } else {
+        report.fail(&format!("No repository at {}; run 'dfl init' or clone your dotfiles there.", dfl_path.display()));
+    }
+
+    // Files stored with git-lfs are only pointers without it.
+    if lfs::in_use(&dfl_path) {
+        match lfs::version() {
+            Some(version) => report.ok(&format!("git-lfs is installed ({})", version)),
+            None => report.fail(&format!("{} stores files with git-lfs, but git-lfs is not installed; those files would be checked out as pointers.", lfs::ATTRIBUTES_FILE)),
+        }
+    }
+
+    match signing::check(&dfl_path) {
+        Ok(Some(key)) => report.ok(&format!("Commit signing works with the {}", key)),
+        Ok(None) => {}
+        Err(e) => report.fail(&format!("Commit signing is enabled but does not work: {}", e)),
+    }
+
+    // The names that '##hostname.<name>' and '##tag.<name>' variants are matched against.
+    let tags = config::load()?.tags;
+    let tags = if tags.is_empty() { "none".to_string() } else { tags.join(", ") };
+    report.ok(&format!("Machine: hostname '{}', os '{}', tags: {}", alternate::hostname(), std::env::consts::OS, tags));
+
+    let home_fs = fsutil::fs_info(&home_dir)?;
+    let repo_fs = fsutil::fs_info(&dfl_path)?;
+    for (label, info) in [("Home directory", &home_fs), ("Repository", &repo_fs)] {
+        if info.is_network {
+            report.warn(&format!("{} is on a network filesystem ({}); dfl copies instead of renaming there.", label, info.name));
+        } else {
+            report.ok(&format!("{} filesystem: {}", label, info.name));
+        }
+    }
+
+    if dfl_path.exists() && fs::metadata(&home_dir)?.dev() != fs::metadata(&dfl_path)?.dev() {
+        report.warn("The repository is on a different device than your home directory; adding files there copies them.");
+    }
+
+    // Some SMB servers and FAT-formatted drives cannot hold symlinks at all.
+    let probe = home_dir.join(format!(".dfl-doctor-{}", std::process::id()));
+    match symlink(&dfl_path, &probe) {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            report.ok("Symlinks can be created in your home directory");
+        }
+        Err(e) => report.fail(&format!("Cannot create symlinks in your home directory: {}", e)),
+    }
+
+    println!();
+    if report.failures > 0 {
+        return Err(io::Error::other(format!("doctor found {} problem(s) and {} warning(s).", report.failures, report.warnings)));
+    }
+    if report.warnings > 0 {
+        println!("No problems found, but {} warning(s) need your attention.", report.warnings);
+    } else {
+        println!("✅ Everything looks good.");
+    }
+    Ok(())
+}
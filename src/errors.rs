@@ -0,0 +1,464 @@
+use std::fmt;
+use std::io;
+
+use crate::repo;
+use crate::ui;
+
+/// Stable identifiers for the errors dfl reports. The numbers are printed as
+/// `DFL-0001` and must never be reused for a different meaning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Code {
+    NotInitialized = 1,
+    NoHomeDir = 2,
+    SourceMissing = 3,
+    AlreadyInRepo = 4,
+    // No longer raised since paths outside $HOME go under [paths]; kept so the number is never reused.
+    OutsideHome = 5,
+    ReservedPath = 6,
+    NestedRepository = 7,
+    GitFailed = 8,
+    NoRemote = 9,
+    PushFailed = 10,
+    PullFailed = 11,
+    MoveFailed = 12,
+    SymlinkFailed = 13,
+    InvalidConfig = 14,
+    StrictWarning = 15,
+    VerificationFailed = 16,
+    LayoutCollision = 17,
+    InvalidArgument = 18,
+    UpdateFailed = 19,
+    InitFailed = 20,
+    RemoteExists = 21,
+    ManagedPath = 22,
+    CrossDevice = 23,
+    HookFailed = 24,
+    TemplateFailed = 25,
+    HostingFailed = 26,
+    SshAuthFailed = 27,
+    MirrorFailed = 28,
+    SecretDetected = 29,
+    FileTooLarge = 30,
+    ProfileFailed = 31,
+    MergeConflict = 32,
+    ExternalFailed = 33,
+    MigrateFailed = 34,
+    Locked = 35,
+    StashLeftOver = 36,
+}
+
+/// The extended explanation shown by `dfl explain-error`. `{repo}` in the text
+/// stands for where the repository is, which is filled in when it is shown.
+struct Explanation {
+    code: Code,
+    title: &'static str,
+    explanation: &'static str,
+    causes: &'static [&'static str],
+    remediation: &'static [&'static str],
+}
+
+const DATABASE: &[Explanation] = &[
+    Explanation {
+        code: Code::NotInitialized,
+        title: "dfl repository not found",
+        explanation: "The command needs the dotfiles repository at ~/.local/share/dfl/repo (or ~/.dfl, where an older dfl created it), but it does not exist or is not a Git repository.",
+        causes: &["dfl has never been set up on this machine.", "The repository was moved or deleted."],
+        remediation: &["Run 'dfl init' to create a new repository.", "Or clone an existing one: 'dfl clone <url>'."],
+    },
+    Explanation {
+        code: Code::NoHomeDir,
+        title: "Home directory not found",
+        explanation: "dfl could not determine your home directory, which it needs to locate the repository and deploy links.",
+        causes: &["The HOME environment variable is unset or empty.", "dfl is running as a system user without a home directory."],
+        remediation: &["Set HOME to your home directory before running dfl."],
+    },
+    Explanation {
+        code: Code::SourceMissing,
+        title: "Path to add does not exist",
+        explanation: "The file or directory passed to 'dfl add' could not be found.",
+        causes: &["A typo in the path.", "A relative path resolved against a different working directory than expected."],
+        remediation: &["Check the path with 'ls -la <path>'.", "Pass an absolute path such as ~/.bashrc."],
+    },
+    Explanation {
+        code: Code::AlreadyInRepo,
+        title: "Entry already exists in the repository",
+        explanation: "Something is already stored where the new entry would go inside the repository, and dfl never overwrites it.",
+        causes: &["The dotfile was added before.", "Two different dotfiles map to the same place in the repository layout."],
+        remediation: &["Run 'dfl list' to see what is stored there.", "Move or remove the existing entry, or switch to a layout that keeps paths apart ('dfl layout convert home')."],
+    },
+    Explanation {
+        code: Code::OutsideHome,
+        title: "Path is outside the home directory (retired)",
+        explanation: "Older versions of dfl raised this error when adding a path outside $HOME. dfl no longer raises it: such paths are recorded under [paths] in dfl.toml instead.",
+        causes: &["The message came from a dfl older than the [paths] table."],
+        remediation: &["Update dfl and add the path again."],
+    },
+    Explanation {
+        code: Code::ReservedPath,
+        title: "Path is reserved by dfl",
+        explanation: "The entry would be stored at a name that dfl or Git use for their own files at the repository root.",
+        causes: &["Adding a file named dfl.toml or .git directly from your home directory."],
+        remediation: &["Rename the file before adding it."],
+    },
+    Explanation {
+        code: Code::NestedRepository,
+        title: "Directory contains a Git repository",
+        explanation: "Git records a nested repository as a single opaque link and does not store its files, so they would silently be missing on other machines.",
+        causes: &["Adding a configuration directory that was itself cloned from GitHub, such as an editor config."],
+        remediation: &["Remove the nested .git directory if you no longer need its history.", "Or add the files inside it individually."],
+    },
+    Explanation {
+        code: Code::GitFailed,
+        title: "A Git command failed",
+        explanation: "dfl runs git to initialize, stage, and commit changes in the dfl repository, and one of those commands exited with an error.",
+        causes: &["git is not installed or not on PATH.", "The repository is in the middle of a merge or rebase.", "There was nothing to commit."],
+        remediation: &["Run 'git -C {repo} status' to inspect the repository.", "Install git if it is missing."],
+    },
+    Explanation {
+        code: Code::NoRemote,
+        title: "No remote configured",
+        explanation: "Pushing needs a remote named 'origin', but the repository does not have one.",
+        causes: &["'dfl remote add' has not been run on this machine."],
+        remediation: &["Run 'dfl remote add <url>' with the URL of an empty repository on your Git host."],
+    },
+    Explanation {
+        code: Code::PushFailed,
+        title: "Push to the remote failed",
+        explanation: "git push exited with an error, so your commits have not reached the remote yet.",
+        causes: &["No network connection.", "Missing SSH key or credentials for the remote.", "The remote has commits you have not pulled yet."],
+        remediation: &["Check connectivity and credentials with 'git -C {repo} ls-remote origin'.", "For an HTTPS remote, set DFL_GIT_TOKEN or store a token with 'dfl remote add --token <url>'.", "Run 'dfl pull' first if the remote is ahead."],
+    },
+    Explanation {
+        code: Code::PullFailed,
+        title: "Pull from the remote failed",
+        explanation: "git pull exited with an error, so the local repository was not updated.",
+        causes: &["No network connection.", "Local uncommitted changes conflict with incoming ones.", "The local branch has no upstream."],
+        remediation: &["Run 'git -C {repo} status' to see local changes.", "Commit or stash them, then pull again."],
+    },
+    Explanation {
+        code: Code::MoveFailed,
+        title: "Could not move the file into the repository",
+        explanation: "dfl moves a dotfile into the repository before linking it back, and that move failed. The original file was left in place.",
+        causes: &["Missing write permission on the file's directory.", "The file is on a different filesystem and copying it failed, for example because the disk is full."],
+        remediation: &["Check permissions with 'ls -ld' on both directories.", "Copy the file into {repo} manually and run 'dfl sync'."],
+    },
+    Explanation {
+        code: Code::SymlinkFailed,
+        title: "Could not create a symlink",
+        explanation: "Creating the link from your home directory into the repository failed.",
+        causes: &["The filesystem does not support symlinks.", "Missing write permission on the target directory."],
+        remediation: &["Check permissions on the directory that should contain the link."],
+    },
+    Explanation {
+        code: Code::InvalidConfig,
+        title: "Configuration file could not be read",
+        explanation: "A TOML file dfl reads (the user config, the repository manifest, or its state file) is not valid.",
+        causes: &["A hand edit introduced a syntax error.", "A value has the wrong type, for example a string where a boolean is expected."],
+        remediation: &["Open the file named in the error and fix the reported line."],
+    },
+    Explanation {
+        code: Code::StrictWarning,
+        title: "Warning treated as an error",
+        explanation: "Strict mode is enabled, so a warning that would normally let the command continue made it fail instead. The process exits with code 3.",
+        causes: &["'--strict' was passed or 'strict = true' is set in the config.", "The command ran into something it would otherwise work around, such as backing up a file."],
+        remediation: &["Fix the condition described by the warning.", "Or run the command without --strict to accept the fallback behavior."],
+    },
+    Explanation {
+        code: Code::VerificationFailed,
+        title: "Verification found problems",
+        explanation: "'dfl verify' found links that are missing or point elsewhere, or files whose contents no longer match their recorded checksums.",
+        causes: &["A program replaced a symlink with a regular file.", "A file was edited outside of dfl.", "New changes were pulled but not synced yet."],
+        remediation: &["Run 'dfl sync' to restore links.", "Run 'dfl verify --update' to accept intended changes."],
+    },
+    Explanation {
+        code: Code::LayoutCollision,
+        title: "Layout conversion would overwrite an entry",
+        explanation: "Converting the repository layout would place two entries at the same path, so nothing was moved.",
+        causes: &["Converting to the flat layout with two dotfiles that share a file name."],
+        remediation: &["Remove or rename one of the colliding entries, or pick a different layout."],
+    },
+    Explanation {
+        code: Code::InvalidArgument,
+        title: "Invalid argument",
+        explanation: "A command was given an argument or option it does not understand.",
+        causes: &["A typo in a subcommand or option.", "A command from an external dfl-<name> executable that is not installed or not on PATH.", "An option that needs a value was given none."],
+        remediation: &["Run 'dfl --help' to see the accepted arguments."],
+    },
+    Explanation {
+        code: Code::UpdateFailed,
+        title: "Self-update failed",
+        explanation: "dfl could not download, verify, or install the latest release. The installed binary was not changed.",
+        causes: &["No network connection or GitHub is unreachable.", "The release has no artifact for this platform.", "The downloaded archive did not match its checksum."],
+        remediation: &["Try again later, or download the release manually from GitHub."],
+    },
+    Explanation {
+        code: Code::InitFailed,
+        title: "Could not create the repository",
+        explanation: "'dfl init' could not create the repository directory, ~/.local/share/dfl/repo unless repo_path or DFL_DIR names another.",
+        causes: &["Missing write permission on the home directory or ~/.local/share.", "A file (not a directory) already exists where the repository goes."],
+        remediation: &["Check 'ls -ld' on the directory and its parents, and fix the permissions or move the file out of the way."],
+    },
+    Explanation {
+        code: Code::RemoteExists,
+        title: "Remote already points elsewhere",
+        explanation: "'dfl remote create' made a repository for a remote name that already exists with a different URL, and dfl does not replace it unasked.",
+        causes: &["The repository is already connected to another Git host.", "The command was run a second time for a different host."],
+        remediation: &["Pass --name <other> to add the new repository as an additional remote.", "Or run 'dfl remote set-url <url>' to point the remote at it."],
+    },
+    Explanation {
+        code: Code::ManagedPath,
+        title: "Path overlaps the repository or a managed entry",
+        explanation: "The path is inside the dfl repository, inside a directory dfl already manages, or contains one of them. Moving it would store the repository or a managed entry inside itself.",
+        causes: &["Adding a file by its path inside {repo}.", "Adding a file below a directory such as ~/.config/nvim that was added as a whole.", "Adding a parent directory such as ~/.config that contains managed entries."],
+        remediation: &["Edit files inside a managed directory in place; they are already stored in the repository.", "Add the other files in a parent directory individually instead of the whole directory."],
+    },
+    Explanation {
+        code: Code::CrossDevice,
+        title: "Hard link across filesystems",
+        explanation: "The entry uses mode = \"hardlink\", but the dfl repository and the target are on different filesystems, and a hard link cannot span two of them.",
+        causes: &["$HOME or the target directory is a separate mount from {repo}.", "The repository lives on an external or network drive."],
+        remediation: &["Set the entry to mode = \"copy\" or \"symlink\" in the [modes] table of dfl.toml.", "Or move the repository onto the same filesystem as the target."],
+    },
+    Explanation {
+        code: Code::HookFailed,
+        title: "Hook rejected the operation",
+        explanation: "A pre-sync or pre-add script in {repo}/hooks exited with a non-zero status, or could not be run, so dfl stopped before changing anything.",
+        causes: &["The hook found a problem it was written to catch, such as a secret in a file being added.", "The hook is not executable or its interpreter is missing."],
+        remediation: &["Read the hook's output shown with the error and fix what it reports.", "Run the hook by hand to debug it, or make it executable with chmod +x."],
+    },
+    Explanation {
+        code: Code::TemplateFailed,
+        title: "Template could not be rendered",
+        explanation: "A file ending in .tmpl in the dfl repository is rendered with Handlebars before it is deployed, and rendering it failed.",
+        causes: &["The template uses a variable that is not defined in ~/.config/dfl/values.toml, the [values] table of dfl.toml, or built in, and dfl could not ask for it because it was not run in a terminal.", "The template has a syntax error, such as an unclosed {{#if}} block."],
+        remediation: &["Run 'dfl sync' in a terminal to be asked for the value, define it in ~/.config/dfl/values.toml, or guard it with {{#if name}}...{{/if}}.", "Fix the line named in the error message and run 'dfl sync' again."],
+    },
+    Explanation {
+        code: Code::HostingFailed,
+        title: "Git host refused to create the repository",
+        explanation: "'dfl remote create' asks the Git host's API to create a repository for your dotfiles, and the host answered with an error or could not be reached.",
+        causes: &["The access token is wrong, expired, or lacks the scope to create repositories.", "A repository with that name already exists on your account.", "The --url of a self-hosted instance is wrong, curl is not installed, or there is no network connection."],
+        remediation: &["Create a new token that may create repositories ('repo' on GitHub, 'api' on GitLab, 'write:repository' on Gitea) and pass it in the environment variable named in the error.", "Pass another repository name, or connect the existing one with 'dfl remote add <url>'."],
+    },
+    Explanation {
+        code: Code::SshAuthFailed,
+        title: "SSH login to the remote refused",
+        explanation: "Before the first push to an SSH remote, dfl checks that the host accepts your login, and the host refused it.",
+        causes: &["You have no SSH key, or its public key was never added to your account or the repository on the host.", "The key has a passphrase and is not loaded in ssh-agent.", "A deploy key was added without write access."],
+        remediation: &["Run 'dfl push' in a terminal: dfl offers to generate a key, shows the public key, and waits until the host accepts it.", "Load a key with a passphrase with 'ssh-add', or switch the remote to HTTPS with 'dfl remote add --force <https-url>'."],
+    },
+    Explanation {
+        code: Code::MirrorFailed,
+        title: "Could not copy to or from a mirror",
+        explanation: "Mirrors in dfl.toml receive a copy of the repository on every push, with rsync or as a git bundle on S3, and machines cloned from a mirror download it again before each pull. Copying failed.",
+        causes: &["rsync, the aws CLI, or git is not installed.", "The SSH host or bucket cannot be reached, or the login was refused.", "The S3 credentials of the aws CLI do not allow writing or reading the bucket."],
+        remediation: &["Try the copy by hand, e.g. 'rsync -az {repo}/.git/ user@host:/path/' or 'aws s3 ls s3://bucket/'.", "Pass --endpoint with the address of S3-compatible storage other than AWS."],
+    },
+    Explanation {
+        code: Code::SecretDetected,
+        title: "Something that looks like a secret was about to be committed",
+        explanation: "Before committing, dfl checks the new and changed lines for access tokens, AWS credentials, private keys, and .netrc passwords, since a pushed secret has to be revoked. Nothing was changed.",
+        causes: &["A token is exported in a shell startup file such as .zshrc.", "A private key or a credentials file is being added.", "A line only looks like a secret, such as a long example value."],
+        remediation: &["Move the secret out of the file, e.g. into a file dfl does not manage that the dotfile sources, or into a template with {{secret \"NAME\"}}.", "If it is not a secret, add the comment 'dfl:allow-secret' to the line, or pass --allow-secrets."],
+    },
+    Explanation {
+        code: Code::FileTooLarge,
+        title: "A file is too large to commit",
+        explanation: "The pre-commit hook dfl installs in the dfl repository refuses files above max_file_kb in the dfl config (10 MiB by default) that git-lfs does not store, since every clone downloads them forever. Nothing was committed.",
+        causes: &["A wallpaper, font, or other binary was copied into a managed directory.", "A cache or log file grew inside a managed directory."],
+        remediation: &["Store it with git-lfs: 'dfl add --lfs <path>'.", "Keep it out of the repository with 'dfl ignore add <pattern>' and 'git -C {repo} rm --cached <file>'.", "Raise max_file_kb in ~/.config/dfl/config.toml, or set it to 0 to allow any size."],
+    },
+    Explanation {
+        code: Code::ProfileFailed,
+        title: "A command failed for some profiles",
+        explanation: "With --all-profiles, dfl runs the command once for each profile in ~/.config/dfl/config.toml and the default repository. It went on with the other profiles after one failed, and each failure was reported above the summary.",
+        causes: &["The repository of a profile has not been created or cloned yet.", "The command failed for that repository for any of its usual reasons."],
+        remediation: &["Read the error printed under the profile's name and run 'dfl explain-error' with its code.", "Run the command again for that profile alone with 'dfl --profile <name> <command>'."],
+    },
+    Explanation {
+        code: Code::MergeConflict,
+        title: "Changes conflict with local commits",
+        explanation: "A pull, merge, or machine promotion brought in changes to lines your local commits also changed, and they could not be settled. Nothing was changed.",
+        causes: &["The same dotfile was edited on two machines.", "dfl ran outside a terminal, where it cannot ask how to settle each file."],
+        remediation: &["Run the command again in a terminal to settle each file.", "Or merge with git in {repo} and run 'dfl sync' afterwards."],
+    },
+    Explanation {
+        code: Code::ExternalFailed,
+        title: "External command could not be started",
+        explanation: "dfl found a dfl-<name> executable on PATH for a command it does not have itself, but could not start it.",
+        causes: &["The file is a script whose interpreter, named on its #! line, is not installed.", "The file was built for a different system."],
+        remediation: &["Run the dfl-<name> executable directly to see why it does not start.", "Reinstall the plugin, or remove it from PATH."],
+    },
+    Explanation {
+        code: Code::MigrateFailed,
+        title: "Could not move to the XDG base directories",
+        explanation: "'dfl migrate' moves the repository from ~/.dfl to $XDG_DATA_HOME/dfl/repo (~/.local/share/dfl/repo by default), the backups to $XDG_STATE_HOME/dfl/backups, and the configuration and state to their XDG directories, and points the symlinks into ~/.dfl at the new place. It stopped at the step named in the message; what it moved before stays moved.",
+        causes: &["A repository already exists at the new place, e.g. from a 'dfl clone' made by a newer dfl.", "The new place is not writable, or a symlink could not be replaced."],
+        remediation: &["Move or remove the repository that is in the way, then run 'dfl migrate' again.", "Run 'dfl doctor' to check the links, and 'dfl sync' to fix any left pointing at ~/.dfl."],
+    },
+    Explanation {
+        code: Code::Locked,
+        title: "Another dfl operation is in progress",
+        explanation: "Commands that change the repository or the links lock the repository while they run, so two of them never interleave and leave it half changed. Another dfl process held the lock, and this one stopped before changing anything.",
+        causes: &["A sync from cron, a systemd timer, or the shell plugin ran at the same time.", "A slow push or pull in another terminal has not finished."],
+        remediation: &["Run the command again once the other one has finished.", "Pass --wait to start as soon as the lock is released, e.g. in scheduled jobs."],
+    },
+    Explanation {
+        code: Code::StashLeftOver,
+        title: "A previous sync left replaced files behind",
+        explanation: "While it runs, sync keeps each file it replaces in a stash directory inside the repository's git directory, so a failed sync can put it back. A sync that was interrupted, or whose rollback failed, left files there, and they may be the only copy. The next sync does not start until they are dealt with.",
+        causes: &["dfl was killed or the machine went down during a sync.", "A sync failed and some files could not be put back, e.g. because a directory was no longer writable."],
+        remediation: &["Read the manifest file in the directory named in the message: each line gives a kept file and where it came from.", "Move back the files you want, delete the directory, and run 'dfl sync' again."],
+    },
+];
+
+impl Code {
+    /// Returns the printable identifier, e.g. `DFL-0001`.
+    pub fn id(self) -> String {
+        format!("DFL-{:04}", self as u16)
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id())
+    }
+}
+
+/// Process exit code for arguments or settings dfl cannot use, as clap uses for its own.
+pub const EXIT_USAGE: i32 = 2;
+/// Process exit code when the repository has not been created or cloned yet.
+pub const EXIT_NOT_INITIALIZED: i32 = 4;
+/// Process exit code when changes clash, such as pulled commits with local ones.
+pub const EXIT_CONFLICT: i32 = 5;
+/// Process exit code when a remote, the git host, or a mirror could not be talked to.
+pub const EXIT_NETWORK: i32 = 6;
+/// Process exit code when git failed on the repository itself.
+pub const EXIT_GIT: i32 = 7;
+
+/// An error dfl reports, by what a script running dfl can do about it. Each kind
+/// exits with its own code; the `Code` inside says exactly what went wrong.
+/// It travels inside an `io::Error`, which `error` creates.
+#[derive(Debug, thiserror::Error)]
+pub enum DflError {
+    /// Arguments or settings dfl cannot use.
+    #[error("{message}")]
+    Usage { code: Code, message: String },
+    /// The repository has not been created or cloned yet.
+    #[error("{message}")]
+    NotInitialized { code: Code, message: String },
+    /// Changes clash, such as pulled commits with local ones, or a new entry with one already stored.
+    #[error("{message}")]
+    Conflict { code: Code, message: String },
+    /// A remote, the git host, or a mirror could not be talked to.
+    #[error("{message}")]
+    Network { code: Code, message: String },
+    /// Git failed on the repository itself.
+    #[error("{message}")]
+    Git { code: Code, message: String },
+    /// Any other failure.
+    #[error("{message}")]
+    Other { code: Code, message: String },
+}
+
+impl DflError {
+    /// Creates the error of the kind `code` belongs to.
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        let message = message.into();
+        match code {
+            Code::InvalidArgument | Code::InvalidConfig => DflError::Usage { code, message },
+            Code::NotInitialized => DflError::NotInitialized { code, message },
+            Code::MergeConflict | Code::AlreadyInRepo | Code::LayoutCollision | Code::RemoteExists | Code::MigrateFailed | Code::Locked => DflError::Conflict { code, message },
+            Code::PushFailed | Code::PullFailed | Code::SshAuthFailed | Code::HostingFailed | Code::MirrorFailed | Code::UpdateFailed => DflError::Network { code, message },
+            Code::GitFailed | Code::InitFailed => DflError::Git { code, message },
+            _ => DflError::Other { code, message },
+        }
+    }
+
+    /// Returns the stable code of the error.
+    pub fn code(&self) -> Code {
+        match self {
+            DflError::Usage { code, .. } | DflError::NotInitialized { code, .. } | DflError::Conflict { code, .. } | DflError::Network { code, .. } | DflError::Git { code, .. } | DflError::Other { code, .. } => *code,
+        }
+    }
+
+    /// Returns the process exit code for the kind of error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DflError::Usage { .. } => EXIT_USAGE,
+            DflError::NotInitialized { .. } => EXIT_NOT_INITIALIZED,
+            DflError::Conflict { .. } => EXIT_CONFLICT,
+            DflError::Network { .. } => EXIT_NETWORK,
+            DflError::Git { .. } => EXIT_GIT,
+            DflError::Other { .. } => 1,
+        }
+    }
+}
+
+/// Creates an error with the given code.
+pub fn error(code: Code, message: impl Into<String>) -> io::Error {
+    io::Error::other(DflError::new(code, message))
+}
+
+/// Returns the dfl error inside `err`, if it is one.
+pub fn dfl_error(err: &io::Error) -> Option<&DflError> {
+    err.get_ref().and_then(|inner| inner.downcast_ref::<DflError>())
+}
+
+/// Returns the code attached to an error, if any.
+pub fn code_of(err: &io::Error) -> Option<Code> {
+    if ui::is_strict_violation(err) {
+        return Some(Code::StrictWarning);
+    }
+    dfl_error(err).map(DflError::code)
+}
+
+/// Returns the code attached to `err`, or `code` if it has none, so an error can be
+/// given more context without hiding what kind it is.
+pub fn code_or(err: &io::Error, code: Code) -> Code {
+    code_of(err).unwrap_or(code)
+}
+
+/// Returns the process exit code for `err`.
+pub fn exit_code(err: &io::Error) -> i32 {
+    if ui::is_strict_violation(err) {
+        return ui::EXIT_STRICT;
+    }
+    dfl_error(err).map_or(1, DflError::exit_code)
+}
+
+/// Handles the 'explain-error' command.
+/// It prints the extended explanation of an error code, or lists every code.
+pub fn handle_explain_error_command(query: Option<&str>) -> io::Result<()> {
+    let Some(query) = query else {
+        println!("Known error codes:");
+        for entry in DATABASE {
+            println!("  {}  {}", entry.code, entry.title);
+        }
+        println!("\nRun 'dfl explain-error <code>' for details.");
+        return Ok(());
+    };
+
+    // Accept "DFL-0007", "dfl-7", and plain "7".
+    let number = query.to_ascii_uppercase().trim_start_matches("DFL-").parse::<u16>().ok();
+    let entry = DATABASE
+        .iter()
+        .find(|e| Some(e.code as u16) == number)
+        .ok_or_else(|| error(Code::InvalidArgument, format!("Unknown error code '{}'. Run 'dfl explain-error' to list all codes.", query)))?;
+
+    // Commands in the text should run as they are printed, so they name this machine's repository.
+    let repo_path = repo::dfl_path().map_or_else(|_| "<repository>".to_string(), |path| repo::display(&path));
+    let fill = |text: &str| text.replace("{repo}", &repo_path);
+    println!("{}: {}\n", entry.code, entry.title);
+    println!("{}\n", fill(entry.explanation));
+    println!("Likely causes:");
+    for cause in entry.causes {
+        println!("  - {}", fill(cause));
+    }
+    println!("\nHow to fix it:");
+    for step in entry.remediation {
+        println!("  - {}", fill(step));
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::env;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use duct::cmd;
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::repo;
+
+/// Prefix of the executables that add commands to dfl: `dfl foo` runs `dfl-foo`.
+pub const PREFIX: &str = "dfl-";
+
+/// Returns the executable that provides the command `name`, the first `dfl-<name>`
+/// on `PATH` that can be run.
+pub fn find(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(format!("{}{}", PREFIX, name)))
+        .find(|candidate| candidate.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0))
+}
+
+/// Returns the number of single-character edits that turn `a` into `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Runs the external command `name` with `args`, in the current directory and with
+/// the terminal dfl has. It finds the repository through `DFL_DIR`, the user
+/// configuration through `DFL_CONFIG`, the profile in use, if any, through
+/// `DFL_PROFILE`, and dfl itself through `DFL`. Returns the exit code of the command;
+/// a command that does not exist is an error that suggests the closest of `known`.
+pub fn run(name: &str, args: &[String], known: &[&str]) -> io::Result<i32> {
+    let Some(program) = find(name) else {
+        let closest = known.iter().filter(|command| distance(name, command) <= 2).min_by_key(|command| distance(name, command));
+        let hint = closest.map(|command| format!(" Did you mean '{}'?", command)).unwrap_or_default();
+        return Err(errors::error(Code::InvalidArgument, format!("'{}' is not a dfl command, and there is no {}{} on your PATH.{} Run 'dfl --help' to see the commands.", name, PREFIX, name, hint)));
+    };
+    tracing::info!("Running {} for 'dfl {}'.", program.display(), name);
+    let output = cmd(&program, args)
+        .env(repo::DIR_VARIABLE, repo::dfl_path()?)
+        .env("DFL_CONFIG", config::config_path()?)
+        .env("DFL", env::current_exe()?)
+        .unchecked()
+        .run()
+        .map_err(|e| errors::error(Code::ExternalFailed, format!("Could not run {}: {}", program.display(), e)))?;
+    // Killed by a signal: exit as a shell reports it, 128 plus the signal number.
+    Ok(output.status.code().unwrap_or_else(|| 128 + std::os::unix::process::ExitStatusExt::signal(&output.status).unwrap_or(0)))
+}
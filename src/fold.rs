@@ -0,0 +1,122 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::manifest;
+use crate::repo::{self, Entry};
+use crate::ui::{self, message_box};
+
+/// Resolves each name to a managed directory entry, as `sync` does for selective
+/// syncs. Parts of an entry cannot be folded on their own.
+fn directory_entries(dfl_path: &Path, names: &[String]) -> io::Result<Vec<Entry>> {
+    let entries = repo::managed_entries(dfl_path)?;
+    let selected = repo::select_entries(dfl_path, entries.clone(), names)?;
+    for entry in &selected {
+        if !entries.iter().any(|e| e.stored == entry.stored) {
+            return Err(errors::error(Code::InvalidArgument, format!("'{}' is part of a larger managed entry; only whole entries can be folded or unfolded.", entry.target.display())));
+        }
+        if !entry.stored.is_dir() {
+            return Err(errors::error(Code::InvalidArgument, format!("'{}' is not a directory.", entry.target.display())));
+        }
+        if !entry.mode.is_symlink() {
+            return Err(errors::error(Code::InvalidArgument, format!("'{}' is deployed as a copy or hard links, which has nothing to fold.", entry.target.display())));
+        }
+    }
+    Ok(selected)
+}
+
+/// Lists what is in the way of folding a real directory into one symlink: anything
+/// in it that is not a link to the matching child in the repository.
+fn unmanaged_children(entry: &Entry) -> io::Result<Vec<PathBuf>> {
+    let mut blocking = Vec::new();
+    for child in fs::read_dir(&entry.target)? {
+        let path = child?.path();
+        let stored = entry.stored.join(path.file_name().unwrap_or_default());
+        if !repo::points_to(&path, &stored) {
+            blocking.push(path);
+        }
+    }
+    blocking.sort();
+    Ok(blocking)
+}
+
+/// Handles the 'unfold' command.
+/// It marks directory entries as unfolded in dfl.toml and deploys them again as a
+/// real directory holding a link to each child.
+pub fn handle_unfold_command(names: &[String]) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let mut manifest = manifest::load(&dfl_path)?;
+    let mut entries = directory_entries(&dfl_path, names)?;
+    for entry in &mut entries {
+        let stored = entry.stored.strip_prefix(&dfl_path).map_err(io::Error::other)?.to_path_buf();
+        if !manifest.unfold.contains(&stored) {
+            manifest.unfold.push(stored);
+        }
+        entry.unfold = true;
+    }
+    manifest.unfold.sort();
+    manifest::save(&dfl_path, &manifest)?;
+
+    let options = crate::sync::SyncOptions::from_config()?;
+    let tally = crate::sync::transactional(&dfl_path, &options, || {
+        let mut tally = crate::sync::Tally::default();
+        for entry in &entries {
+            tally += crate::sync::deploy_entry(entry, &options)?;
+        }
+        Ok(tally)
+    })?;
+    message_box("Unfolded", &format!("{} directory(ies) are now deployed child by child ({}). Commit dfl.toml to keep this on every machine.", entries.len(), tally));
+    Ok(())
+}
+
+/// Handles the 'fold' command.
+/// It deploys unfolded directory entries as a single symlink again. A directory
+/// holding anything besides links into the repository is left alone, since
+/// replacing it would move those files into the repository.
+pub fn handle_fold_command(names: &[String]) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let mut manifest = manifest::load(&dfl_path)?;
+    let entries = directory_entries(&dfl_path, names)?;
+    for entry in &entries {
+        if repo::deploys_per_file(&entry.stored)? {
+            return Err(errors::error(Code::InvalidArgument, format!("'{}' holds alternates or templates, so it is always deployed child by child.", entry.target.display())));
+        }
+        if fs::symlink_metadata(&entry.target).is_ok_and(|m| m.is_dir()) {
+            let blocking = unmanaged_children(entry)?;
+            if !blocking.is_empty() {
+                let list: Vec<String> = blocking.iter().map(|path| format!("  {}", path.display())).collect();
+                return Err(errors::error(Code::InvalidArgument, format!("'{}' also holds files dfl does not manage:\n{}\nMove or remove them first.", entry.target.display(), list.join("\n"))));
+            }
+        }
+    }
+
+    let relative = crate::sync::SyncOptions::from_config()?.relative;
+    for entry in &entries {
+        let stored = entry.stored.strip_prefix(&dfl_path).map_err(io::Error::other)?;
+        manifest.unfold.retain(|path| path != stored);
+        if !fs::symlink_metadata(&entry.target).is_ok_and(|m| m.is_dir()) {
+            continue;
+        }
+        // The directory only holds links, so it can be swapped for one link and dropped.
+        let staged = fsutil::staging_path(&entry.target);
+        fsutil::symlink_to(&entry.stored, &staged, relative)?;
+        if let Err(e) = fsutil::replace_atomically(&staged, &entry.target, None) {
+            let _ = fsutil::remove_path(&staged);
+            return Err(errors::error(Code::SymlinkFailed, format!("Error folding '{}': {}", entry.target.display(), e)));
+        }
+        ui::say(&format!("✅ Folded '{}' into a single link", entry.target.display()));
+    }
+    manifest::save(&dfl_path, &manifest)?;
+    message_box("Folded", &format!("{} directory(ies) are now deployed as a single link. Commit dfl.toml to keep this on every machine.", entries.len()));
+    Ok(())
+}
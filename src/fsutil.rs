@@ -0,0 +1,476 @@
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::fs::symlink;
+use std::path::{Component, Path, PathBuf};
+
+use tracing::{debug, info};
+
+/// What dfl knows about the filesystem a path lives on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsInfo {
+    /// Short filesystem name such as `ext4`, `nfs`, `smbfs`, or `fuse.sshfs`.
+    pub name: String,
+    /// True for network filesystems, whose rename and symlink semantics vary by server.
+    pub is_network: bool,
+}
+
+/// FUSE filesystems are named by their subtype, since most of them, such as an
+/// encrypted home or an AppImage, are local.
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs", "smb", "smbfs", "cifs", "smb2", "afs", "ceph", "afpfs", "webdav", "9p",
+    "fuse.sshfs", "fuse.rclone", "fuse.s3fs", "fuse.gcsfuse", "fuse.glusterfs", "fuse.ceph-fuse", "fuse.davfs", "fusefs.sshfs", "fusefs.rclone",
+];
+
+impl FsInfo {
+    fn named(name: &str) -> FsInfo {
+        FsInfo { name: name.to_string(), is_network: NETWORK_FILESYSTEMS.contains(&name) }
+    }
+}
+
+/// Detects the filesystem of `path`, walking up to the nearest existing ancestor.
+pub fn fs_info(path: &Path) -> io::Result<FsInfo> {
+    // The parent of a bare relative name such as `.bashrc` is the empty path.
+    let mut existing = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+    while !existing.exists() {
+        existing = existing.parent().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No existing ancestor of '{}'", path.display())))?;
+    }
+
+    let c_path = CString::new(existing.as_os_str().as_bytes()).map_err(io::Error::other)?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid NUL-terminated string and `stat` is a properly sized, writable buffer.
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(describe(&stat, existing))
+}
+
+#[cfg(target_os = "linux")]
+fn describe(stat: &libc::statfs, path: &Path) -> FsInfo {
+    // Magic numbers from statfs(2).
+    let name = match stat.f_type as u64 {
+        0xEF53 => "ext4",
+        0x9123_683E => "btrfs",
+        0x5846_5342 => "xfs",
+        0x2FC1_2FC1 => "zfs",
+        0x0102_1994 => "tmpfs",
+        0x794C_7630 => "overlay",
+        0xF15F => "ecryptfs",
+        0x6969 => "nfs",
+        0x517B => "smb",
+        0xFF53_4D42 => "cifs",
+        0xFE53_4D42 => "smb2",
+        0x5346_414F | 0x6B41_4653 => "afs",
+        0x00C3_6400 => "ceph",
+        0x6573_5546 => return FsInfo::named(&fuse_type(path).unwrap_or_else(|| "fuse".to_string())),
+        0x0102_1997 => "9p",
+        other => return FsInfo { name: format!("unknown (0x{:x})", other), is_network: false },
+    };
+    FsInfo::named(name)
+}
+
+/// Returns the type of the FUSE filesystem `path` is on, such as `fuse.sshfs`, from
+/// the mount table, since every FUSE filesystem reports the same magic number.
+#[cfg(target_os = "linux")]
+fn fuse_type(path: &Path) -> Option<String> {
+    let dev = fs::metadata(path).ok()?.dev();
+    let device = format!("{}:{}", libc::major(dev), libc::minor(dev));
+    let mounts = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    // Each line reads "<id> <parent> <major>:<minor> <root> <mount point> <options> [<optional>...] - <type> <source> <options>".
+    mounts.lines().filter(|line| line.split(' ').nth(2) == Some(device.as_str())).find_map(|line| line.split_once(" - ")?.1.split(' ').next().map(str::to_string))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn describe(stat: &libc::statfs, _path: &Path) -> FsInfo {
+    // BSD-derived systems report the filesystem name directly.
+    let name: String = stat.f_fstypename.iter().take_while(|c| **c != 0).map(|c| *c as u8 as char).collect();
+    FsInfo::named(&name)
+}
+
+/// Copies a file, directory tree, or symlink, preserving permissions and symlinks.
+/// Copied files and directories are flushed to disk before this returns.
+pub fn copy_recursive(source: &Path, destination: &Path) -> io::Result<()> {
+    debug!("copy {} -> {}", source.display(), destination.display());
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.file_type().is_symlink() {
+        symlink(fs::read_link(source)?, destination)?;
+    } else if metadata.is_dir() {
+        fs::create_dir(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+        fs::set_permissions(destination, metadata.permissions())?;
+        File::open(destination)?.sync_all()?;
+    } else {
+        fs::copy(source, destination)?;
+        File::open(destination)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Adds up the sizes of the files in a directory tree without following symlinks;
+/// 0 if the path does not exist.
+pub fn disk_usage(path: &Path) -> io::Result<u64> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(0);
+    };
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += disk_usage(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+/// Recreates `source` at `destination` with every file hard-linked to the original.
+/// Directories are created anew, since they cannot be hard-linked, and symlinks are copied.
+pub fn hardlink_recursive(source: &Path, destination: &Path) -> io::Result<()> {
+    debug!("hard-link {} -> {}", source.display(), destination.display());
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.file_type().is_symlink() {
+        symlink(fs::read_link(source)?, destination)?;
+    } else if metadata.is_dir() {
+        fs::create_dir(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            hardlink_recursive(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+        fs::set_permissions(destination, metadata.permissions())?;
+    } else {
+        fs::hard_link(source, destination)?;
+    }
+    Ok(())
+}
+
+/// Returns true if every file in `link` is a hard link to the matching file in `original`.
+pub fn same_inodes(original: &Path, link: &Path) -> bool {
+    let (Ok(a), Ok(b)) = (fs::symlink_metadata(original), fs::symlink_metadata(link)) else {
+        return false;
+    };
+    if a.file_type().is_symlink() {
+        return b.file_type().is_symlink() && fs::read_link(original).ok() == fs::read_link(link).ok();
+    }
+    if a.is_dir() {
+        let Ok(entries) = fs::read_dir(original) else {
+            return false;
+        };
+        let mut count = 0;
+        for entry in entries.flatten() {
+            if !same_inodes(&entry.path(), &link.join(entry.file_name())) {
+                return false;
+            }
+            count += 1;
+        }
+        return b.is_dir() && fs::read_dir(link).is_ok_and(|entries| entries.count() == count);
+    }
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+/// Returns true if `path` and `other` live on the same device, so one can be hard-linked
+/// to the other. Missing paths are judged by their nearest existing ancestor.
+pub fn same_device(path: &Path, other: &Path) -> io::Result<bool> {
+    Ok(device_of(path)? == device_of(other)?)
+}
+
+fn device_of(path: &Path) -> io::Result<u64> {
+    let absolute = std::path::absolute(if path.as_os_str().is_empty() { Path::new(".") } else { path })?;
+    let mut existing = absolute.as_path();
+    loop {
+        match fs::metadata(existing) {
+            Ok(metadata) => return Ok(metadata.dev()),
+            Err(_) => {
+                existing = existing.parent().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No existing ancestor of '{}'", path.display())))?;
+            }
+        }
+    }
+}
+
+/// Creates a symlink at `link` pointing to `original`. With `relative`, the link stores
+/// the path from its own directory, so it keeps working when both are mounted elsewhere.
+/// Both directories are resolved first, so a symlinked parent such as `~/.config` still
+/// gets a link that leads to the right place.
+pub fn symlink_to(original: &Path, link: &Path, relative: bool) -> io::Result<()> {
+    debug!("symlink {} -> {} (relative: {})", link.display(), original.display(), relative);
+    let absolute_link = std::path::absolute(link)?;
+    if relative && let (Some(link_dir), Some(original_dir), Some(name)) = (absolute_link.parent(), original.parent(), original.file_name()) {
+        let from = fs::canonicalize(link_dir)?;
+        let to = fs::canonicalize(original_dir)?.join(name);
+        return symlink(relative_path(&from, &to), link);
+    }
+    symlink(original, link)
+}
+
+/// Returns the relative path that leads from directory `from` to `to`. Both must be absolute.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut path: PathBuf = from[common..].iter().map(|_| Component::ParentDir).collect();
+    path.extend(&to[common..]);
+    path
+}
+
+/// Resolves `.` and `..` in `path` without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Returns the sibling name an entry is built at before it replaces `target`.
+pub fn staging_path(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{}.dfl-new", name))
+}
+
+/// Puts `staged` in place of `target` with a rename, so `target` is never missing:
+/// it is either what was there before or `staged`. When `keep` is given, what was
+/// at `target` ends up there; otherwise it is removed once replaced. A file is kept
+/// by hard-linking (or copying) it before the rename; a directory cannot be renamed
+/// over, so it is swapped with `staged` instead.
+pub fn replace_atomically(staged: &Path, target: &Path, keep: Option<&Path>) -> io::Result<()> {
+    debug!("replace {} with {} (keeping it at {:?})", target.display(), staged.display(), keep);
+    let Ok(metadata) = fs::symlink_metadata(target) else {
+        return fs::rename(staged, target);
+    };
+    if !metadata.is_dir() && !fs::symlink_metadata(staged)?.is_dir() {
+        if let Some(keep) = keep
+            && (metadata.file_type().is_symlink() || fs::hard_link(target, keep).is_err())
+        {
+            copy_recursive(target, keep)?;
+        }
+        return fs::rename(staged, target);
+    }
+
+    exchange(staged, target)?;
+    match keep {
+        Some(keep) => move_path(staged, keep),
+        None => remove_path(staged),
+    }
+}
+
+/// Swaps two paths. Linux does it in one step; elsewhere `b` is briefly missing
+/// between two renames.
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let c_a = CString::new(a.as_os_str().as_bytes()).map_err(io::Error::other)?;
+        let c_b = CString::new(b.as_os_str().as_bytes()).map_err(io::Error::other)?;
+        // SAFETY: both paths are valid NUL-terminated strings.
+        if unsafe { libc::renameat2(libc::AT_FDCWD, c_a.as_ptr(), libc::AT_FDCWD, c_b.as_ptr(), libc::RENAME_EXCHANGE) } == 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        // Older kernels and some filesystems cannot exchange; fall back to renames.
+        if !matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) {
+            return Err(e);
+        }
+    }
+
+    let swap = a.with_file_name(format!("{}.dfl-swap", a.file_name().unwrap_or_default().to_string_lossy()));
+    fs::rename(b, &swap)?;
+    if let Err(e) = fs::rename(a, b) {
+        fs::rename(&swap, b)?;
+        return Err(e);
+    }
+    fs::rename(&swap, a)
+}
+
+/// Creates the missing directories above `path`, like `mkdir -p` on its parent,
+/// and returns the directories it created, outermost first. Fails with a message
+/// naming the blocking path when an ancestor exists but is not a directory.
+pub fn create_parent_dirs(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut missing = Vec::new();
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        match fs::metadata(dir) {
+            Ok(metadata) if metadata.is_dir() => break,
+            Ok(_) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("'{}' exists but is not a directory", dir.display()))),
+            Err(_) if fs::symlink_metadata(dir).is_ok() => return Err(io::Error::new(io::ErrorKind::NotFound, format!("'{}' is a broken symlink", dir.display()))),
+            Err(_) => missing.push(dir.to_path_buf()),
+        }
+        current = dir.parent();
+    }
+
+    missing.reverse();
+    for dir in &missing {
+        debug!("create directory {}", dir.display());
+        fs::create_dir(dir)?;
+    }
+    Ok(missing)
+}
+
+/// Removes a file, symlink, or directory tree.
+pub fn remove_path(path: &Path) -> io::Result<()> {
+    debug!("remove {}", path.display());
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Moves `source` to `destination`. A plain rename is used when possible; when the
+/// two paths are on different filesystems, or either is on a network filesystem
+/// whose rename semantics vary by server, the move is done as copy, verify, and
+/// remove, and any failure rolls back so the original stays intact.
+pub fn move_path(source: &Path, destination: &Path) -> io::Result<()> {
+    debug!("move {} -> {}", source.display(), destination.display());
+    let source_parent = source.parent().unwrap_or(source);
+    let destination_parent = destination.parent().unwrap_or(destination);
+    if fs_info(source_parent)?.is_network || fs_info(destination_parent)?.is_network {
+        info!("Network filesystem detected; copying instead of renaming.");
+        return copy_and_remove(source, destination);
+    }
+
+    match fs::rename(source, destination) {
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            info!("'{}' is on a different filesystem; copying it instead.", source.display());
+            copy_and_remove(source, destination)
+        }
+        result => result,
+    }
+}
+
+fn copy_and_remove(source: &Path, destination: &Path) -> io::Result<()> {
+    let copied = copy_recursive(source, destination).and_then(|()| verify_copy(source, destination));
+    if let Err(e) = copied {
+        let _ = remove_path(destination);
+        return Err(e);
+    }
+    if let Some(parent) = destination.parent() {
+        File::open(parent)?.sync_all()?;
+    }
+
+    if let Err(e) = remove_path(source) {
+        // Part of a directory may already be gone; put it back from the copy.
+        restore_missing(destination, source)?;
+        remove_path(destination)?;
+        return Err(io::Error::new(e.kind(), format!("Could not remove '{}' after copying it: {}", source.display(), e)));
+    }
+    Ok(())
+}
+
+/// Returns true if `copy` has the same tree, link targets, and file contents as `original`.
+pub fn same_tree(original: &Path, copy: &Path) -> bool {
+    verify_copy(original, copy).is_ok()
+}
+
+/// Checks that `copy` has the same tree, link targets, and file contents as `original`.
+fn verify_copy(original: &Path, copy: &Path) -> io::Result<()> {
+    let mismatch = || io::Error::other(format!("The copy of '{}' does not match the original.", original.display()));
+    let metadata = fs::symlink_metadata(original)?;
+    if metadata.file_type().is_symlink() {
+        if fs::read_link(original)? != fs::read_link(copy)? {
+            return Err(mismatch());
+        }
+    } else if metadata.is_dir() {
+        let mut count = 0;
+        for entry in fs::read_dir(original)? {
+            let entry = entry?;
+            verify_copy(&entry.path(), &copy.join(entry.file_name()))?;
+            count += 1;
+        }
+        if fs::read_dir(copy)?.count() != count {
+            return Err(mismatch());
+        }
+    } else if !same_contents(original, copy)? {
+        return Err(mismatch());
+    }
+    Ok(())
+}
+
+/// Returns true if two files have identical contents.
+pub fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let mut a = File::open(a)?;
+    let mut b = File::open(b)?;
+    let mut buf_a = vec![0; 64 * 1024];
+    let mut buf_b = vec![0; 64 * 1024];
+    loop {
+        let read = a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Copies everything under `from` that no longer exists under `to`.
+fn restore_missing(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(to).is_err() {
+        return copy_recursive(from, to);
+    }
+    if fs::symlink_metadata(from)?.is_dir() && to.is_dir() {
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            restore_missing(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// A directory only its owner can enter, for files that must not be seen or
+/// replaced by other users while dfl works on them. It is removed when dropped.
+#[derive(Debug)]
+pub struct PrivateDir {
+    path: PathBuf,
+}
+
+impl PrivateDir {
+    /// Creates a new directory named `<prefix>-<pid>-<n>` in the system's temporary
+    /// directory with mode 0700. A name that is taken, perhaps by someone else
+    /// planting it, is never reused; the next number is tried instead.
+    pub fn create(prefix: &str) -> io::Result<PrivateDir> {
+        let base = std::env::temp_dir();
+        let mut attempt = 0;
+        loop {
+            let path = base.join(format!("{}-{}-{}", prefix, std::process::id(), attempt));
+            match fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(PrivateDir { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes `contents` to `name` in the directory, readable by its owner only.
+    /// A file already there is replaced, never followed if it is a symlink.
+    pub fn write(&self, name: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<PathBuf> {
+        let path = self.path.join(name);
+        if fs::symlink_metadata(&path).is_ok() {
+            fs::remove_file(&path)?;
+        }
+        let mut file = fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path)?;
+        file.write_all(contents.as_ref())?;
+        Ok(path)
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,55 @@
+use std::io;
+
+use crate::backup;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::git;
+use crate::repo;
+use crate::ui::{self, message_box};
+
+/// How many of the largest stored files the report lists.
+const LARGEST: usize = 10;
+
+/// Handles the 'gc' command.
+/// It reports how much space the repository and the backups take and which files
+/// in the history are the largest, then has git repack the repository and delete
+/// what nothing refers to. With `dry_run` it only reports; with `now` it also
+/// drops what only the undo history still refers to.
+pub fn handle_gc_command(dry_run: bool, now: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let before = fsutil::disk_usage(&git::git_dir(&dfl_path))?;
+    println!("Repository history: {} ({})", ui::bytes(before), git::git_dir(&dfl_path).display());
+    let backups = backup::backups_dir()?;
+    println!("Backups: {} ({})", ui::bytes(fsutil::disk_usage(&backups)?), backups.display());
+
+    let largest = git::largest_objects(&dfl_path, LARGEST)?;
+    if !largest.is_empty() {
+        println!("\nLargest files in the history:");
+        for object in &largest {
+            let note = if object.current { "" } else { " (only in older commits)" };
+            println!("  {:>10}  {}{}", ui::bytes(object.size), object.path, note);
+        }
+    }
+    // Old versions of small files are normal history; only large leftovers are worth rewriting it for.
+    let large = config::load()?.lfs_threshold_kb.saturating_mul(1024);
+    if largest.iter().any(|object| !object.current && object.size >= large) {
+        println!("\nFiles only in older commits stay in every clone until those commits are rewritten. 'dfl squash' does");
+        println!("that for recent commits; older ones need a tool such as git filter-repo.");
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+    ui::say("\nCompacting the repository...");
+    if let Err(e) = git::gc(&dfl_path, now) {
+        return Err(errors::error(Code::GitFailed, format!("Error running git gc, which needs the git command: {}", e)));
+    }
+    let after = fsutil::disk_usage(&git::git_dir(&dfl_path))?;
+    message_box("Compacted", &format!("The repository history went from {} to {}. Old backups are pruned by 'backup_retention' in the config.", ui::bytes(before), ui::bytes(after)));
+    Ok(())
+}
@@ -0,0 +1,1707 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use git2::build::CheckoutBuilder;
+use git2::{BranchType, Cred, CredentialType, Delta, FetchOptions, IndexAddOption, MergeFileInput, MergeFileOptions, Oid, StatusOptions, SubmoduleIgnore, SubmoduleUpdateOptions, PushOptions, RemoteCallbacks, Repository};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::alternate;
+use crate::bare;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::githooks;
+use crate::lfs;
+use crate::signing;
+use crate::sparse;
+use crate::ui;
+
+/// How dfl talks to git. The embedded library needs nothing installed; the
+/// `git` command is an opt-in fallback for setups the library cannot handle, such
+/// as custom credential or transport programs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Library,
+    Command,
+}
+
+/// Returns the backend chosen in the user configuration for the repository at
+/// `dfl_path`. The library cannot run git-lfs's filters or keep files out of a
+/// sparse checkout, so a repository using either always uses the command.
+pub fn backend(dfl_path: &Path) -> io::Result<Backend> {
+    let backend = config::load()?.git_backend;
+    if backend == Backend::Library && (lfs::in_use(dfl_path) || sparse::in_use(dfl_path)) {
+        return Ok(Backend::Command);
+    }
+    Ok(backend)
+}
+
+/// Turns a library error into an I/O error carrying only its message.
+fn failed(e: git2::Error) -> io::Error {
+    io::Error::other(e.message().to_string())
+}
+
+/// Points a `git` command at the repository at `dfl_path`. It runs in the work
+/// tree, which in bare mode is the home directory with the repository as `GIT_DIR`,
+/// and tells dfl's git hooks that dfl is running it.
+trait InRepo {
+    fn in_repo(self, dfl_path: &Path) -> Self;
+}
+
+impl InRepo for duct::Expression {
+    fn in_repo(self, dfl_path: &Path) -> Self {
+        let expression = self.env(githooks::SKIP_VARIABLE, "1");
+        let expression = match bare::work_tree(dfl_path) {
+            Some(work_tree) => expression.dir(work_tree).env("GIT_DIR", dfl_path),
+            None => expression.dir(dfl_path),
+        };
+        debug!("running {:?}", expression);
+        expression
+    }
+}
+
+/// Returns the directory holding the files the repository at `dfl_path` tracks:
+/// the repository itself, or the home directory in bare mode.
+pub fn work_tree(dfl_path: &Path) -> PathBuf {
+    bare::work_tree(dfl_path).unwrap_or_else(|| dfl_path.to_path_buf())
+}
+
+/// Returns the directory git keeps the history and settings of the repository in.
+pub fn git_dir(dfl_path: &Path) -> PathBuf {
+    if bare::in_use(dfl_path) { dfl_path.to_path_buf() } else { dfl_path.join(".git") }
+}
+
+fn open(dfl_path: &Path) -> io::Result<Repository> {
+    Repository::open(dfl_path).map_err(failed)
+}
+
+/// Returns the branch `HEAD` points to, which may not have any commits yet.
+fn current_branch(repo: &Repository) -> Result<String, git2::Error> {
+    let head = repo.find_reference("HEAD")?;
+    head.symbolic_target()?
+        .and_then(|target| target.strip_prefix("refs/heads/"))
+        .map(str::to_string)
+        .ok_or_else(|| git2::Error::from_str("HEAD is detached; check out a branch first"))
+}
+
+/// Returns the remote and remote branch a local branch tracks, if any.
+fn tracking(repo: &Repository, branch: &str) -> Result<Option<(String, String)>, git2::Error> {
+    let config = repo.config()?;
+    let (Ok(remote), Ok(merge)) = (config.get_string(&format!("branch.{}.remote", branch)), config.get_string(&format!("branch.{}.merge", branch))) else {
+        return Ok(None);
+    };
+    let merge = merge.strip_prefix("refs/heads/").unwrap_or(&merge).to_string();
+    Ok(Some((remote, merge)))
+}
+
+/// Returns the remote of the current branch's upstream, or `origin` without one.
+fn default_remote(repo: &Repository) -> Result<String, git2::Error> {
+    Ok(tracking(repo, &current_branch(repo)?)?.map_or_else(|| "origin".to_string(), |(remote, _)| remote))
+}
+
+/// Answers authentication requests from the remote: SSH keys come from the agent,
+/// then from `~/.ssh/id_ed25519` and `~/.ssh/id_rsa`; passwords and tokens come
+/// from git's credential helper, as configured for the `git` command.
+fn callbacks(config: &git2::Config) -> RemoteCallbacks<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut attempts = 0;
+    callbacks.credentials(move |url, username, allowed| {
+        attempts += 1;
+        if allowed.contains(CredentialType::SSH_KEY) {
+            let user = username.unwrap_or("git");
+            let key = match attempts {
+                1 => return Cred::ssh_key_from_agent(user),
+                2 => "id_ed25519",
+                3 => "id_rsa",
+                _ => return Err(git2::Error::from_str("no SSH key was accepted; add one to ssh-agent")),
+            };
+            let home = dirs::home_dir().unwrap_or_default();
+            return Cred::ssh_key(user, None, &home.join(".ssh").join(key), None);
+        }
+        if attempts > 1 {
+            return Err(git2::Error::from_str("authentication failed; store working credentials with a git credential helper"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            return Cred::credential_helper(config, url, username);
+        }
+        Cred::default()
+    });
+    callbacks
+}
+
+/// Creates an empty repository at `dfl_path`, or leaves an existing one alone.
+pub fn init(dfl_path: &Path) -> io::Result<()> {
+    debug!("git -C {} init", dfl_path.display());
+    match backend(dfl_path)? {
+        Backend::Library => Repository::init(dfl_path).map(drop).map_err(failed),
+        Backend::Command => cmd!("git", "init", "--quiet").in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Points `HEAD` of a repository without commits at `branch`, so the first commit starts it.
+pub fn set_initial_branch(dfl_path: &Path, branch: &str) -> io::Result<()> {
+    debug!("git -C {} symbolic-ref HEAD refs/heads/{}", dfl_path.display(), branch);
+    let reference = format!("refs/heads/{}", branch);
+    match backend(dfl_path)? {
+        Backend::Library => open(dfl_path)?.set_head(&reference).map_err(failed),
+        Backend::Command => cmd!("git", "symbolic-ref", "HEAD", &reference).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Clones `url` into `dfl_path` without checking out any files yet, which
+/// `checkout_head` or `sparse_checkout` does next. `depth` limits the history to that many commits,
+/// and `filter`, such as `blob:none`, leaves old file contents on the server until
+/// they are needed. Only the `git` command can fetch those later, so a filter needs it.
+pub fn clone(dfl_path: &Path, url: &str, depth: Option<u32>, filter: Option<&str>) -> io::Result<()> {
+    debug!("git clone --no-checkout {} {} (depth {:?}, filter {:?})", url, dfl_path.display(), depth, filter);
+    if backend(dfl_path)? == Backend::Library && filter.is_some() {
+        return Err(io::Error::other("partial clones need the git command to fetch file contents later; set git_backend = \"command\" in the dfl config first"));
+    }
+    if backend(dfl_path)? == Backend::Command {
+        let mut args = vec!["clone".to_string(), "--quiet".to_string(), "--no-checkout".to_string()];
+        if let Some(depth) = depth {
+            args.push(format!("--depth={}", depth));
+        }
+        if let Some(filter) = filter {
+            args.push(format!("--filter={}", filter));
+        }
+        args.extend([url.to_string(), dfl_path.display().to_string()]);
+        return cmd("git", args).run().map(drop);
+    }
+
+    let config = git2::Config::open_default().map_err(failed)?;
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks(&config));
+    if let Some(depth) = depth {
+        options.depth(i32::try_from(depth).unwrap_or(i32::MAX));
+    }
+    let mut checkout = CheckoutBuilder::new();
+    checkout.dry_run();
+    git2::build::RepoBuilder::new().fetch_options(options).with_checkout(checkout).clone(url, dfl_path).map(drop).map_err(failed)
+}
+
+/// Checks out every file of the current commit, as after a clone.
+pub fn checkout_head(dfl_path: &Path) -> io::Result<()> {
+    debug!("git -C {} checkout HEAD", dfl_path.display());
+    match backend(dfl_path)? {
+        Backend::Library => open(dfl_path)?.checkout_head(Some(CheckoutBuilder::new().force())).map_err(failed),
+        Backend::Command => cmd!("git", "checkout", "--quiet").in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Limits the working tree to the files matching gitignore-style `patterns`, or
+/// brings every file back with `None`. The library cannot do this, so it always
+/// takes the `git` command.
+pub fn sparse_checkout(dfl_path: &Path, patterns: Option<&[String]>) -> io::Result<()> {
+    debug!("git -C {} sparse-checkout {:?}", dfl_path.display(), patterns);
+    match patterns {
+        Some(patterns) => {
+            let args = [vec!["sparse-checkout".to_string(), "set".to_string(), "--no-cone".to_string()], patterns.to_vec()].concat();
+            cmd("git", args).in_repo(dfl_path).run()?;
+            // Right after a clone nothing is checked out yet, which `set` leaves alone.
+            cmd!("git", "checkout", "--quiet").in_repo(dfl_path).run().map(drop)
+        }
+        None => cmd!("git", "sparse-checkout", "disable").in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Returns the contents of `path` as of `target`, such as a file in `HEAD` that
+/// is not checked out, or `None` if it does not exist there.
+pub fn file_at(dfl_path: &Path, target: &str, path: &str) -> io::Result<Option<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let Ok(object) = repo.revparse_single(&format!("{}:{}", target, path)) else {
+                return Ok(None);
+            };
+            let blob = object.peel_to_blob().map_err(failed)?;
+            Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+        }
+        Backend::Command => {
+            let output = cmd!("git", "show", format!("{}:{}", target, path)).in_repo(dfl_path).stdout_capture().stderr_null().unchecked().run()?;
+            Ok(output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned()))
+        }
+    }
+}
+
+/// Returns true for a shallow clone, which lacks the history before some depth.
+pub fn is_shallow(dfl_path: &Path) -> io::Result<bool> {
+    match backend(dfl_path)? {
+        Backend::Library => Ok(open(dfl_path)?.is_shallow()),
+        Backend::Command => Ok(cmd!("git", "rev-parse", "--is-shallow-repository").in_repo(dfl_path).read()?.trim() == "true"),
+    }
+}
+
+/// Fetches the history a shallow clone left out.
+pub fn unshallow(dfl_path: &Path) -> io::Result<()> {
+    debug!("git -C {} fetch --unshallow", dfl_path.display());
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let config = repo.config().map_err(failed)?;
+            let mut remote = repo.find_remote(&default_remote(&repo).map_err(failed)?).map_err(failed)?;
+            let mut options = FetchOptions::new();
+            // libgit2 reads the largest depth as "everything", as `git fetch --unshallow` does.
+            options.remote_callbacks(callbacks(&config)).depth(i32::MAX);
+            remote.fetch(&[] as &[&str], Some(&mut options), None).map_err(failed)
+        }
+        Backend::Command => cmd!("git", "fetch", "--quiet", "--unshallow").in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Sets a value in the repository's own git configuration.
+pub fn set_config(dfl_path: &Path, key: &str, value: &str) -> io::Result<()> {
+    debug!("git -C {} config {} {}", dfl_path.display(), key, value);
+    match backend(dfl_path)? {
+        Backend::Library => open(dfl_path)?.config().and_then(|mut config| config.set_str(key, value)).map_err(failed),
+        Backend::Command => cmd!("git", "config", key, value).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Returns a value from the git configuration the repository sees, its own or
+/// the user's, or `None` if it is not set.
+pub fn get_config(dfl_path: &Path, key: &str) -> io::Result<Option<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => Ok(open(dfl_path)?.config().map_err(failed)?.get_string(key).ok()),
+        Backend::Command => {
+            let value = cmd!("git", "config", "--get", key).in_repo(dfl_path).stderr_null().unchecked().read()?;
+            Ok(Some(value.trim().to_string()).filter(|value| !value.is_empty()))
+        }
+    }
+}
+
+/// Returns a value from the repository's own git configuration only, ignoring
+/// the user's global one, or `None` if it is not set there.
+pub fn get_local_config(dfl_path: &Path, key: &str) -> io::Result<Option<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => Ok(open(dfl_path)?.config().and_then(|config| config.open_level(git2::ConfigLevel::Local)).map_err(failed)?.get_string(key).ok()),
+        Backend::Command => {
+            let value = cmd!("git", "config", "--local", "--get", key).in_repo(dfl_path).stderr_null().unchecked().read()?;
+            Ok(Some(value.trim().to_string()).filter(|value| !value.is_empty()))
+        }
+    }
+}
+
+/// Lists the names of the configured remotes.
+pub fn remotes(dfl_path: &Path) -> io::Result<Vec<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => Ok(open(dfl_path)?.remotes().map_err(failed)?.iter().flatten().flatten().map(str::to_string).collect()),
+        Backend::Command => Ok(cmd!("git", "remote").in_repo(dfl_path).read()?.lines().map(str::to_string).collect()),
+    }
+}
+
+/// Returns the URL of a remote, or `None` if there is no such remote.
+pub fn remote_url(dfl_path: &Path, name: &str) -> io::Result<Option<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => Ok(open(dfl_path)?.find_remote(name).ok().and_then(|remote| remote.url().ok().map(str::to_string))),
+        Backend::Command => {
+            let url = cmd!("git", "remote", "get-url", name).in_repo(dfl_path).stderr_null().unchecked().read()?;
+            Ok(Some(url.trim().to_string()).filter(|url| !url.is_empty()))
+        }
+    }
+}
+
+/// Adds a remote.
+pub fn add_remote(dfl_path: &Path, name: &str, url: &str) -> io::Result<()> {
+    debug!("git -C {} remote add {} {}", dfl_path.display(), name, url);
+    match backend(dfl_path)? {
+        Backend::Library => open(dfl_path)?.remote(name, url).map(drop).map_err(failed),
+        Backend::Command => cmd!("git", "remote", "add", name, url).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Renames a remote, along with the branches that track it.
+pub fn rename_remote(dfl_path: &Path, name: &str, new_name: &str) -> io::Result<()> {
+    debug!("git -C {} remote rename {} {}", dfl_path.display(), name, new_name);
+    match backend(dfl_path)? {
+        Backend::Library => open(dfl_path)?.remote_rename(name, new_name).map(drop).map_err(failed),
+        Backend::Command => cmd!("git", "remote", "rename", name, new_name).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Points an existing remote at another URL.
+pub fn set_remote_url(dfl_path: &Path, name: &str, url: &str) -> io::Result<()> {
+    debug!("git -C {} remote set-url {} {}", dfl_path.display(), name, url);
+    match backend(dfl_path)? {
+        Backend::Library => open(dfl_path)?.remote_set_url(name, url).map_err(failed),
+        Backend::Command => cmd!("git", "remote", "set-url", name, url).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Removes a remote along with its remote-tracking branches.
+pub fn remove_remote(dfl_path: &Path, name: &str) -> io::Result<()> {
+    debug!("git -C {} remote remove {}", dfl_path.display(), name);
+    match backend(dfl_path)? {
+        Backend::Library => open(dfl_path)?.remote_delete(name).map_err(failed),
+        Backend::Command => cmd!("git", "remote", "remove", name).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Returns the name of the checked-out branch, as `init.defaultBranch` or a later
+/// checkout chose it.
+pub fn branch(dfl_path: &Path) -> io::Result<String> {
+    match backend(dfl_path)? {
+        Backend::Library => current_branch(&open(dfl_path)?).map_err(failed),
+        Backend::Command => Ok(cmd!("git", "symbolic-ref", "--short", "HEAD").in_repo(dfl_path).read()?.trim().to_string()),
+    }
+}
+
+/// Returns the remote and remote branch that `branch` tracks, if any.
+pub fn upstream(dfl_path: &Path, branch: &str) -> io::Result<Option<(String, String)>> {
+    match backend(dfl_path)? {
+        Backend::Library => tracking(&open(dfl_path)?, branch).map_err(failed),
+        Backend::Command => {
+            let get = |key: &str| cmd!("git", "config", "--get", format!("branch.{}.{}", branch, key)).in_repo(dfl_path).stderr_null().unchecked().read();
+            let (remote, merge) = (get("remote")?, get("merge")?);
+            if remote.is_empty() || merge.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some((remote.trim().to_string(), merge.trim().trim_start_matches("refs/heads/").to_string())))
+        }
+    }
+}
+
+/// Lists the local branches by name.
+pub fn branches(dfl_path: &Path) -> io::Result<Vec<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let mut names = Vec::new();
+            for branch in repo.branches(Some(BranchType::Local)).map_err(failed)? {
+                let (branch, _) = branch.map_err(failed)?;
+                if let Some(name) = branch.name().map_err(failed)? {
+                    names.push(name.to_string());
+                }
+            }
+            Ok(names)
+        }
+        Backend::Command => Ok(cmd!("git", "branch", "--format=%(refname:short)").in_repo(dfl_path).read()?.lines().map(str::to_string).collect()),
+    }
+}
+
+/// Creates a branch at the current commit without switching to it.
+pub fn create_branch(dfl_path: &Path, name: &str) -> io::Result<()> {
+    debug!("git -C {} branch {}", dfl_path.display(), name);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let head = repo.head().and_then(|head| head.peel_to_commit()).map_err(|_| io::Error::other("there are no commits to branch from yet"))?;
+            repo.branch(name, &head, false).map(drop).map_err(failed)
+        }
+        Backend::Command => cmd!("git", "branch", name).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Checks out another local branch. Local changes the switch would overwrite
+/// stop it before anything changes.
+pub fn switch(dfl_path: &Path, name: &str) -> io::Result<()> {
+    debug!("git -C {} switch {}", dfl_path.display(), name);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let branch = repo.find_branch(name, BranchType::Local).map_err(failed)?;
+            let commit = branch.get().peel_to_commit().map_err(failed)?;
+            repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe())).map_err(failed)?;
+            repo.set_head(&format!("refs/heads/{}", name)).map_err(failed)
+        }
+        Backend::Command => cmd!("git", "checkout", "--quiet", name).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Merges a local branch into the current one, as `pull` merges a remote branch.
+pub fn merge(dfl_path: &Path, name: &str, resolver: Option<Resolver>) -> io::Result<()> {
+    debug!("git -C {} merge {}", dfl_path.display(), name);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let theirs = repo.find_branch(name, BranchType::Local).and_then(|branch| branch.get().peel_to_commit()).map_err(failed)?;
+            merge_into_head(&repo, &theirs, &format!("Merge branch '{}'", name), resolver)
+        }
+        Backend::Command => run_resolving(dfl_path, &["merge", "--quiet", "--no-edit", name], &[], resolver),
+    }
+}
+
+/// Stages the files at or below `paths`, relative to the work tree, as `git add` does.
+pub fn add_paths(dfl_path: &Path, paths: &[String]) -> io::Result<()> {
+    debug!("git -C {} add -- {}", dfl_path.display(), paths.join(" "));
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let mut index = repo.index().map_err(failed)?;
+            index.add_all(paths, IndexAddOption::DEFAULT, None).map_err(failed)?;
+            index.write().map_err(failed)
+        }
+        Backend::Command => cmd("git", ["add", "--"].iter().map(|arg| arg.to_string()).chain(paths.iter().cloned()).collect::<Vec<_>>()).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Lists the files of the current commit, relative to the work tree, or none
+/// in a repository without commits.
+pub fn tracked_files(dfl_path: &Path) -> io::Result<Vec<String>> {
+    if head(dfl_path)?.is_none() {
+        return Ok(Vec::new());
+    }
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let tree = repo.head().and_then(|head| head.peel_to_tree()).map_err(failed)?;
+            let mut files = Vec::new();
+            tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+                if entry.kind() == Some(git2::ObjectType::Blob) {
+                    files.push(format!("{}{}", dir, entry.name().unwrap_or_default()));
+                }
+                git2::TreeWalkResult::Ok
+            })
+            .map_err(failed)?;
+            Ok(files)
+        }
+        Backend::Command => Ok(cmd!("git", "-c", "core.quotePath=false", "ls-tree", "-r", "--name-only", "HEAD").in_repo(dfl_path).read()?.lines().map(str::to_string).collect()),
+    }
+}
+
+/// Stages every change in the working tree, including deletions, as `git add -A` does.
+/// In a sparse checkout, new files outside its patterns are staged too. In bare mode
+/// the work tree is the whole home directory, so only tracked files are staged.
+pub fn add_all(dfl_path: &Path) -> io::Result<()> {
+    debug!("git -C {} add --all", dfl_path.display());
+    match backend(dfl_path)? {
+        Backend::Library if bare::in_use(dfl_path) => {
+            let repo = open(dfl_path)?;
+            let mut index = repo.index().map_err(failed)?;
+            index.update_all(["*"], None).map_err(failed)?;
+            index.write().map_err(failed)
+        }
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let mut index = repo.index().map_err(failed)?;
+            index.add_all(["*"], IndexAddOption::DEFAULT, None).map_err(failed)?;
+            index.update_all(["*"], None).map_err(failed)?;
+            index.write().map_err(failed)
+        }
+        Backend::Command if bare::in_use(dfl_path) => cmd!("git", "add", "--update").in_repo(dfl_path).run().map(drop),
+        Backend::Command if sparse::in_use(dfl_path) => cmd!("git", "add", "-A", "--sparse", ".").in_repo(dfl_path).run().map(drop),
+        Backend::Command => cmd!("git", "add", "-A", ".").in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Commits what is staged. Committing when nothing changed is an error, as with `git commit`.
+pub fn commit(dfl_path: &Path, message: &str) -> io::Result<()> {
+    debug!("git -C {} commit -m {:?}", dfl_path.display(), message);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let tree = repo.index().and_then(|mut index| index.write_tree()).and_then(|id| repo.find_tree(id)).map_err(failed)?;
+            let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+            if parent.as_ref().is_some_and(|parent| parent.tree_id() == tree.id()) {
+                return Err(io::Error::other("nothing to commit, working tree clean"));
+            }
+            let signature = repo.signature().map_err(failed)?;
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+            create_commit(&repo, Some("HEAD"), &signature, &signature, message, &tree, &parents).map(drop)
+        }
+        Backend::Command => {
+            let args = [signing::command_options()?, vec!["commit".to_string(), "--quiet".to_string(), "-m".to_string(), message.to_string()]].concat();
+            cmd("git", args).in_repo(dfl_path).run().map(drop)
+        }
+    }
+}
+
+/// Builds the message for a commit dfl makes on its own. With `commit_template` set
+/// in the config, its `{action}`, `{files}`, `{count}`, and `{hostname}` are filled
+/// in, and several files are listed below it; otherwise `default` is used.
+pub fn commit_message(action: &str, files: &[PathBuf], default: String) -> io::Result<String> {
+    let template = config::load()?.commit_template;
+    if template.is_empty() {
+        return Ok(default);
+    }
+    let names: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+    let shown = match names.as_slice() {
+        [] => String::new(),
+        [first, second, third, rest @ ..] if !rest.is_empty() => format!("{}, {}, {} and {} more", first, second, third, rest.len()),
+        _ => names.join(", "),
+    };
+    let subject = template.replace("{action}", action).replace("{files}", &shown).replace("{count}", &files.len().to_string()).replace("{hostname}", &alternate::hostname());
+    // An empty placeholder would otherwise leave a double space behind.
+    let subject = subject.split_whitespace().collect::<Vec<_>>().join(" ");
+    if names.len() < 2 {
+        return Ok(subject);
+    }
+    let list: Vec<String> = names.iter().map(|name| format!("- {}", name)).collect();
+    Ok(format!("{}\n\n{}", subject, list.join("\n")))
+}
+
+/// Pushes a local branch to `remote_branch` on `remote`, along with the tags on its
+/// commits. With `set_upstream`, the local branch tracks it from then on.
+pub fn push(dfl_path: &Path, branch: &str, remote: &str, remote_branch: &str, set_upstream: bool) -> io::Result<()> {
+    debug!("git -C {} push {} {}:{} (set upstream: {})", dfl_path.display(), remote, branch, remote_branch, set_upstream);
+    let refspec = format!("refs/heads/{}:refs/heads/{}", branch, remote_branch);
+    if backend(dfl_path)? == Backend::Command {
+        let mut args = vec!["push", "--follow-tags"];
+        if set_upstream {
+            args.push("--set-upstream");
+        }
+        args.extend([remote, refspec.as_str()]);
+        return cmd("git", args).in_repo(dfl_path).run().map(drop);
+    }
+
+    let repo = open(dfl_path)?;
+    let tip = repo.refname_to_id(&format!("refs/heads/{}", branch)).map_err(failed)?;
+    let mut refspecs = vec![refspec];
+    for name in repo.tag_names(None).map_err(failed)?.iter() {
+        let Ok(Some(name)) = name else {
+            continue;
+        };
+        let Ok(tagged) = repo.revparse_single(&format!("refs/tags/{}", name)).and_then(|object| object.peel_to_commit()) else {
+            continue;
+        };
+        if tagged.id() == tip || repo.graph_descendant_of(tip, tagged.id()).map_err(failed)? {
+            refspecs.push(format!("refs/tags/{}:refs/tags/{}", name, name));
+        }
+    }
+    push_refspecs(&repo, remote, &refspecs)?;
+    if set_upstream {
+        let mut config = repo.config().map_err(failed)?;
+        config.set_str(&format!("branch.{}.remote", branch), remote).map_err(failed)?;
+        config.set_str(&format!("branch.{}.merge", branch), &format!("refs/heads/{}", remote_branch)).map_err(failed)?;
+    }
+    Ok(())
+}
+
+/// Pushes a local branch over `remote_branch` on `remote` even if that drops commits
+/// there, but only while the remote branch is still at `expected`, the commit last
+/// fetched from it (`None` if it did not exist). Anything pushed from elsewhere in
+/// the meantime makes this fail instead of being thrown away.
+pub fn force_push(dfl_path: &Path, branch: &str, remote: &str, remote_branch: &str, expected: Option<&str>) -> io::Result<()> {
+    debug!("git -C {} push --force-with-lease {} {}:{} (expected {:?})", dfl_path.display(), remote, branch, remote_branch, expected);
+    if backend(dfl_path)? == Backend::Command {
+        // A `+` refspec would force the push past the lease.
+        let lease = format!("--force-with-lease=refs/heads/{}:{}", remote_branch, expected.unwrap_or_default());
+        return cmd!("git", "push", lease, remote, format!("refs/heads/{}:refs/heads/{}", branch, remote_branch)).in_repo(dfl_path).run().map(drop);
+    }
+    let refspec = format!("+refs/heads/{}:refs/heads/{}", branch, remote_branch);
+
+    let repo = open(dfl_path)?;
+    let config = repo.config().map_err(failed)?;
+    repo.find_remote(remote)
+        .and_then(|mut remote| remote.fetch(&[] as &[&str], Some(FetchOptions::new().remote_callbacks(callbacks(&config))), None))
+        .map_err(failed)?;
+    let current = repo.refname_to_id(&format!("refs/remotes/{}/{}", remote, remote_branch)).ok().map(|id| id.to_string());
+    if current.as_deref() != expected {
+        return Err(io::Error::other(format!("{}/{} changed since it was last fetched; pull first so commits pushed from elsewhere are not lost", remote, remote_branch)));
+    }
+    push_refspecs(&repo, remote, &[refspec])
+}
+
+/// Pushes refspecs with the library, turning a rejection by the remote into an error.
+fn push_refspecs(repo: &Repository, remote: &str, refspecs: &[String]) -> io::Result<()> {
+    let mut rejected = None;
+    let config = repo.config().map_err(failed)?;
+    let mut callbacks = callbacks(&config);
+    callbacks.push_update_reference(|reference, status| {
+        if let Some(status) = status {
+            rejected = Some(format!("{} was rejected: {}", reference, status));
+        }
+        Ok(())
+    });
+    repo.find_remote(remote).and_then(|mut remote| remote.push(refspecs, Some(PushOptions::new().remote_callbacks(callbacks)))).map_err(failed)?;
+    if let Some(rejected) = rejected {
+        return Err(io::Error::other(format!("{}. Pull first to merge the remote changes.", rejected)));
+    }
+    Ok(())
+}
+
+/// Downloads new commits from the upstream remote, or `origin` if the current
+/// branch has none, without changing the working tree.
+pub fn fetch(dfl_path: &Path) -> io::Result<()> {
+    debug!("git -C {} fetch", dfl_path.display());
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let config = repo.config().map_err(failed)?;
+            let mut remote = repo.find_remote(&default_remote(&repo).map_err(failed)?).map_err(failed)?;
+            remote.fetch(&[] as &[&str], Some(FetchOptions::new().remote_callbacks(callbacks(&config))), None).map_err(failed)
+        }
+        Backend::Command => cmd!("git", "fetch", "--quiet").in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Logs in to `remote` and lists its branches without changing anything, to find
+/// out whether pushing there can work. The error carries git's explanation.
+pub fn check_access(dfl_path: &Path, remote: &str) -> io::Result<()> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let config = repo.config().map_err(failed)?;
+            let mut remote = repo.find_remote(remote).map_err(failed)?;
+            remote.connect_auth(git2::Direction::Fetch, Some(callbacks(&config)), None).map(drop).map_err(failed)
+        }
+        Backend::Command => {
+            // Nobody is there to answer a password or host key prompt during the check.
+            let ssh = std::env::var("GIT_SSH_COMMAND").unwrap_or_else(|_| "ssh".to_string());
+            let output = cmd!("git", "ls-remote", "--heads", remote)
+                .in_repo(dfl_path)
+                .env("GIT_TERMINAL_PROMPT", "0")
+                .env("GIT_SSH_COMMAND", format!("{} -o BatchMode=yes", ssh))
+                .stdout_null()
+                .stderr_capture()
+                .unchecked()
+                .run()?;
+            if output.status.success() {
+                return Ok(());
+            }
+            Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+        }
+    }
+}
+
+/// Fetches the upstream branch, or `remote_branch` of the same remote when given,
+/// and brings it into the current branch: by replaying local commits on top of it
+/// with `rebase`, or with a merge commit otherwise.
+/// Conflicts go to `resolver`, or leave everything unchanged without one.
+pub fn pull(dfl_path: &Path, remote_branch: Option<&str>, rebase: bool, resolver: Option<Resolver>) -> io::Result<()> {
+    debug!("git -C {} pull {:?} (rebase: {})", dfl_path.display(), remote_branch, rebase);
+    if backend(dfl_path)? == Backend::Command {
+        let mut source = Vec::new();
+        if let Some(remote_branch) = remote_branch {
+            source.push(default_remote(&open(dfl_path)?).map_err(failed)?);
+            source.push(remote_branch.to_string());
+        }
+        if is_shallow(dfl_path)? {
+            cmd!("git", "fetch", "--quiet").in_repo(dfl_path).run()?;
+            let theirs = if source.is_empty() { "@{upstream}".to_string() } else { source.join("/") };
+            let found = cmd!("git", "merge-base", "HEAD", theirs).in_repo(dfl_path).stdout_null().stderr_null().unchecked().run()?.status.success();
+            if !found {
+                deepen(dfl_path)?;
+            }
+        }
+        let source: Vec<&str> = source.iter().map(String::as_str).collect();
+        let merge = [&["pull", "--quiet", "--no-rebase"], source.as_slice()].concat();
+        if rebase {
+            return run_resolving(dfl_path, &[&["pull", "--quiet", "--rebase"], source.as_slice()].concat(), &merge, resolver);
+        }
+        return run_resolving(dfl_path, &merge, &[], resolver);
+    }
+
+    fetch(dfl_path)?;
+    let repo = open(dfl_path)?;
+    let branch = current_branch(&repo).map_err(failed)?;
+    let requested = remote_branch;
+    let (remote, remote_branch) = match (remote_branch, tracking(&repo, &branch).map_err(failed)?) {
+        (Some(remote_branch), _) => (default_remote(&repo).map_err(failed)?, remote_branch.to_string()),
+        (None, Some(upstream)) => upstream,
+        (None, None) => return Err(io::Error::other(format!("the branch '{}' has no upstream; push it first with 'dfl push'", branch))),
+    };
+    let theirs = repo
+        .find_reference(&format!("refs/remotes/{}/{}", remote, remote_branch))
+        .and_then(|reference| reference.peel_to_commit())
+        .map_err(|_| io::Error::other(format!("'{}' has no branch named '{}'", remote, remote_branch)))?;
+    if repo.is_shallow() && repo.head().and_then(|head| head.peel_to_commit()).is_ok_and(|ours| repo.merge_base(ours.id(), theirs.id()).is_err()) {
+        deepen(dfl_path)?;
+        return pull(dfl_path, requested, rebase, resolver);
+    }
+    let message = format!("Merge branch '{}' of {}", remote_branch, remote);
+    if rebase {
+        // Earlier tips of the remote branch were published already; if its history was
+        // rewritten since, say by a squash, their commits are not local work to replay.
+        let published: Vec<Oid> = repo.reflog(&format!("refs/remotes/{}/{}", remote, remote_branch)).map(|log| log.iter().map(|entry| entry.id_new()).collect()).unwrap_or_default();
+        rebase_onto(&repo, &theirs, &published, &message, resolver)
+    } else { merge_into_head(&repo, &theirs, &message, resolver) }
+}
+
+/// Fetches the rest of a shallow clone's history when it does not reach back to
+/// where the local and remote branches diverged, which merging and rebasing need.
+fn deepen(dfl_path: &Path) -> io::Result<()> {
+    ui::say("Fetching older history: this shallow clone does not reach back to where your branch and the remote diverged.");
+    unshallow(dfl_path)
+}
+
+/// Pulls as `pull` does and updates the submodules to match, first setting
+/// uncommitted changes aside and putting them back afterwards,
+/// whether or not the pull succeeded.
+pub fn pull_keeping_changes(dfl_path: &Path, remote_branch: Option<&str>, rebase: bool, resolver: Option<Resolver>) -> io::Result<()> {
+    if local_changes(dfl_path)?.is_empty() {
+        return pull(dfl_path, remote_branch, rebase, resolver).and_then(|()| update_submodules(dfl_path));
+    }
+    stash(dfl_path, "dfl: local changes set aside while pulling")?;
+    ui::say("📦 Set your uncommitted changes aside while pulling.");
+    let pulled = pull(dfl_path, remote_branch, rebase, resolver).and_then(|()| update_submodules(dfl_path));
+    match unstash(dfl_path) {
+        Ok(()) => ui::say("📦 Put your uncommitted changes back."),
+        Err(_) => ui::warn(&format!(
+            "Your uncommitted changes conflict with what was pulled, so they were kept in the stash. Run 'git -C {} stash pop' to resolve them, or 'git -C {} stash drop' to discard them.",
+            dfl_path.display(),
+            dfl_path.display()
+        ))?,
+    }
+    pulled
+}
+
+/// Replays the current branch's own commits on top of `theirs`, leaving out those
+/// reachable from a `published` commit. Commits are replayed in memory, so a conflict leaves the branch and working tree as they
+/// were; with a resolver, the branches are then merged instead, so each file is
+/// settled only once.
+fn rebase_onto(repo: &Repository, theirs: &git2::Commit, published: &[Oid], message: &str, resolver: Option<Resolver>) -> io::Result<()> {
+    let Some(ours) = repo.head().ok().and_then(|head| head.peel_to_commit().ok()) else {
+        return merge_into_head(repo, theirs, message, None);
+    };
+    // Without local commits to replay, a rebase is a fast-forward.
+    if repo.graph_descendant_of(ours.id(), theirs.id()).map_err(failed)? || ours.id() == theirs.id() || repo.graph_descendant_of(theirs.id(), ours.id()).map_err(failed)? {
+        return merge_into_head(repo, theirs, message, None);
+    }
+
+    let mut walk = repo.revwalk().map_err(failed)?;
+    walk.push(ours.id()).map_err(failed)?;
+    walk.hide(theirs.id()).map_err(failed)?;
+    for id in published {
+        if repo.graph_descendant_of(ours.id(), *id).unwrap_or(false) {
+            walk.hide(*id).map_err(failed)?;
+        }
+    }
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE).map_err(failed)?;
+    let committer = repo.signature().map_err(failed)?;
+    let mut onto = theirs.clone();
+    for id in walk {
+        let commit = repo.find_commit(id.map_err(failed)?).map_err(failed)?;
+        // Merge commits are dropped, as `git rebase` does.
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        let mut index = repo.cherrypick_commit(&commit, &onto, 0, None).map_err(failed)?;
+        if index.has_conflicts() {
+            if resolver.is_some() {
+                return merge_into_head(repo, theirs, message, resolver);
+            }
+            return Err(errors::error(Code::MergeConflict, format!("your local commits conflict with the remote changes in {}; nothing was changed", conflicted_paths(&index)?.join(", "))));
+        }
+        let tree = index.write_tree_to(repo).and_then(|id| repo.find_tree(id)).map_err(failed)?;
+        // A local commit the remote already has leaves nothing to commit and is dropped.
+        if tree.id() == onto.tree_id() {
+            continue;
+        }
+        let id = create_commit(repo, None, &commit.author(), &committer, commit.message().unwrap_or_default(), &tree, &[&onto])?;
+        onto = repo.find_commit(id).map_err(failed)?;
+    }
+
+    let local = format!("refs/heads/{}", current_branch(repo).map_err(failed)?);
+    repo.checkout_tree(onto.as_object(), Some(CheckoutBuilder::new().safe())).map_err(failed)?;
+    repo.reference(&local, onto.id(), true, "pull: rebase").map(drop).map_err(failed)
+}
+
+/// Creates a commit, signed when signing is configured, and moves `update_ref` to
+/// it when given. Every commit dfl makes itself goes through here.
+fn create_commit(repo: &Repository, update_ref: Option<&str>, author: &git2::Signature, committer: &git2::Signature, message: &str, tree: &git2::Tree, parents: &[&git2::Commit]) -> io::Result<Oid> {
+    let Some(signing) = signing::settings(&repo.config().map_err(failed)?)? else {
+        return repo.commit(update_ref, author, committer, message, tree, parents).map_err(failed);
+    };
+    let buffer = repo.commit_create_buffer(author, committer, message, tree, parents).map_err(failed)?;
+    let content = buffer.as_str().map_err(|_| io::Error::other("the commit is not valid UTF-8 and cannot be signed"))?;
+    let id = repo.commit_signed(content, &signing::sign(content, &signing)?, None).map_err(failed)?;
+    if let Some(name) = update_ref {
+        let reference = repo.find_reference(name).map_err(failed)?;
+        let name = reference.symbolic_target().map_err(failed)?.unwrap_or(name).to_string();
+        let summary = message.lines().next().unwrap_or_default();
+        repo.reference(&name, id, true, &format!("commit: {}", summary)).map_err(failed)?;
+    }
+    Ok(id)
+}
+
+/// A file changed differently on both sides of a merge.
+pub struct Conflict {
+    /// Repository-relative path.
+    pub path: String,
+    /// The current branch's version, or `None` if it deleted the file.
+    pub ours: Option<Vec<u8>>,
+    /// The incoming version, or `None` if it deleted the file.
+    pub theirs: Option<Vec<u8>>,
+    /// Both versions merged, with conflict markers around the parts that clash.
+    pub marked: Vec<u8>,
+}
+
+/// Decides what a conflicting file should contain, or `None` to delete it.
+pub type Resolver = fn(&Conflict) -> io::Result<Option<Vec<u8>>>;
+
+/// Merges three versions of a file, marking the parts that clash.
+fn mark_conflicts(ancestor: &Option<Vec<u8>>, ours: &Option<Vec<u8>>, theirs: &Option<Vec<u8>>) -> io::Result<Vec<u8>> {
+    fn input(content: &Option<Vec<u8>>) -> MergeFileInput<'_> {
+        let mut input = MergeFileInput::new();
+        input.content(content.as_deref().unwrap_or_default());
+        input
+    }
+    let mut options = MergeFileOptions::new();
+    options.our_label("local").their_label("incoming");
+    let merged = git2::merge_file(&input(ancestor), &input(ours), &input(theirs), Some(&mut options)).map_err(failed)?;
+    Ok(merged.content().to_vec())
+}
+
+/// Settles every conflict in a merged index with `resolve`, staging its answers.
+fn resolve_in_index(repo: &Repository, index: &mut git2::Index, resolve: Resolver) -> io::Result<()> {
+    let conflicts = index.conflicts().map_err(failed)?.collect::<Result<Vec<_>, _>>().map_err(failed)?;
+    println!("{} file(s) were changed on both sides:", conflicts.len());
+    for conflict in conflicts {
+        let blob = |entry: &Option<git2::IndexEntry>| entry.as_ref().map(|entry| repo.find_blob(entry.id).map(|blob| blob.content().to_vec())).transpose().map_err(failed);
+        let (ancestor, ours, theirs) = (blob(&conflict.ancestor)?, blob(&conflict.our)?, blob(&conflict.their)?);
+        let Some(mut entry) = conflict.our.or(conflict.their) else {
+            continue;
+        };
+        let path = String::from_utf8_lossy(&entry.path).into_owned();
+        let marked = mark_conflicts(&ancestor, &ours, &theirs)?;
+        let resolved = resolve(&Conflict { path: path.clone(), ours, theirs, marked })?;
+        index.conflict_remove(Path::new(&path)).map_err(failed)?;
+        if let Some(contents) = resolved {
+            // Clear the conflict stage so the entry is staged normally.
+            entry.flags &= !0x3000;
+            entry.id = repo.blob(&contents).map_err(failed)?;
+            entry.file_size = contents.len() as u32;
+            index.add(&entry).map_err(failed)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs a git command that may stop with conflicts, as `merge` and `pull` do. If
+/// it does and a rebase was interrupted, the rebase is aborted and `fallback` runs
+/// instead, when given. Each conflict then goes to `resolver` and the merge is
+/// committed; without a resolver, the merge is aborted and nothing changes.
+fn run_resolving(dfl_path: &Path, args: &[&str], fallback: &[&str], resolver: Option<Resolver>) -> io::Result<()> {
+    let options = signing::command_options()?;
+    let git = |args: &[&str]| cmd("git", options.iter().map(String::as_str).chain(args.iter().copied()).collect::<Vec<_>>()).in_repo(dfl_path);
+    let output = git(args).stderr_capture().unchecked().run()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut conflicted = command_conflicts(dfl_path)?;
+    if conflicted.is_empty() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    if git_dir(dfl_path).join("rebase-merge").exists() || git_dir(dfl_path).join("rebase-apply").exists() {
+        cmd!("git", "rebase", "--abort").in_repo(dfl_path).run()?;
+        if resolver.is_none() || fallback.is_empty() {
+            return Err(errors::error(Code::MergeConflict, format!("your local commits conflict with the remote changes in {}; nothing was changed", conflicted.join(", "))));
+        }
+        git(fallback).stderr_null().unchecked().run()?;
+        conflicted = command_conflicts(dfl_path)?;
+    }
+    let Some(resolve) = resolver else {
+        cmd!("git", "merge", "--abort").in_repo(dfl_path).run()?;
+        return Err(errors::error(Code::MergeConflict, format!("the changes conflict with local commits in {}; nothing was changed. Resolve them with git in the repository", conflicted.join(", "))));
+    };
+
+    println!("{} file(s) were changed on both sides:", conflicted.len());
+    for path in conflicted {
+        let stage = |n: u8| -> io::Result<Option<Vec<u8>>> {
+            let output = cmd!("git", "show", format!(":{}:{}", n, path)).in_repo(dfl_path).stdout_capture().stderr_null().unchecked().run()?;
+            Ok(output.status.success().then_some(output.stdout))
+        };
+        let (ours, theirs) = (stage(2)?, stage(3)?);
+        let marked = std::fs::read(work_tree(dfl_path).join(&path)).unwrap_or_default();
+        match resolve(&Conflict { path: path.clone(), ours, theirs, marked })? {
+            Some(contents) => {
+                std::fs::write(work_tree(dfl_path).join(&path), contents)?;
+                cmd!("git", "add", "--", &path).in_repo(dfl_path).run()?;
+            }
+            None => {
+                cmd!("git", "rm", "--quiet", "--", &path).in_repo(dfl_path).run()?;
+            }
+        }
+    }
+    git(&["commit", "--quiet", "--no-edit"]).run().map(drop)
+}
+
+/// Lists the files git left with conflicts in the working tree.
+fn command_conflicts(dfl_path: &Path) -> io::Result<Vec<String>> {
+    Ok(cmd!("git", "diff", "--name-only", "--diff-filter=U").in_repo(dfl_path).read()?.lines().map(str::to_string).collect())
+}
+
+/// Lists the paths with conflicts in a merged index.
+fn conflicted_paths(index: &git2::Index) -> io::Result<Vec<String>> {
+    Ok(index.conflicts().map_err(failed)?.flatten().filter_map(|conflict| conflict.our.or(conflict.their)).map(|entry| String::from_utf8_lossy(&entry.path).into_owned()).collect())
+}
+
+/// Brings `theirs` into the current branch. A fast-forward moves the branch;
+/// diverged histories get a merge commit unless they conflict, in which case
+/// nothing changes and the conflicting paths are reported.
+fn merge_into_head(repo: &Repository, theirs: &git2::Commit, message: &str, resolver: Option<Resolver>) -> io::Result<()> {
+    let local = format!("refs/heads/{}", current_branch(repo).map_err(failed)?);
+    let mut checkout = CheckoutBuilder::new();
+    checkout.safe();
+
+    let Some(ours) = repo.head().ok().and_then(|head| head.peel_to_commit().ok()) else {
+        // A branch without commits simply starts at the other one.
+        repo.checkout_tree(theirs.as_object(), Some(&mut checkout)).map_err(failed)?;
+        return repo.reference(&local, theirs.id(), true, "merge: initial").map(drop).map_err(failed);
+    };
+    if ours.id() == theirs.id() || repo.graph_descendant_of(ours.id(), theirs.id()).map_err(failed)? {
+        return Ok(());
+    }
+
+    // The working tree is updated before the branch moves, so a local change in the
+    // way leaves both untouched.
+    if repo.graph_descendant_of(theirs.id(), ours.id()).map_err(failed)? {
+        repo.checkout_tree(theirs.as_object(), Some(&mut checkout)).map_err(failed)?;
+        return repo.reference(&local, theirs.id(), true, "merge: fast-forward").map(drop).map_err(failed);
+    }
+
+    let mut index = repo.merge_commits(&ours, theirs, None).map_err(failed)?;
+    if let Some(resolve) = resolver.filter(|_| index.has_conflicts()) {
+        resolve_in_index(repo, &mut index, resolve)?;
+    }
+    if index.has_conflicts() {
+        return Err(errors::error(Code::MergeConflict, format!("the changes conflict with local commits in {}; nothing was changed. Resolve them with git in the repository", conflicted_paths(&index)?.join(", "))));
+    }
+    let tree = index.write_tree_to(repo).and_then(|id| repo.find_tree(id)).map_err(failed)?;
+    repo.checkout_tree(tree.as_object(), Some(&mut checkout)).map_err(failed)?;
+    let signature = repo.signature().map_err(failed)?;
+    create_commit(repo, Some("HEAD"), &signature, &signature, message, &tree, &[&ours, theirs]).map(drop)
+}
+
+/// Returns true if tracked files have changes that are not committed. Edits made
+/// through a symlink land here, since the link points into the repository.
+pub fn has_local_changes(dfl_path: &Path) -> io::Result<bool> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let statuses = repo.statuses(Some(StatusOptions::new().include_untracked(false))).map_err(failed)?;
+            Ok(!statuses.is_empty())
+        }
+        Backend::Command => Ok(!cmd!("git", "status", "--porcelain", "--untracked-files=no").in_repo(dfl_path).read()?.trim().is_empty()),
+    }
+}
+
+/// Lists the paths with uncommitted changes, including new files git does not
+/// track yet, such as one created inside a linked directory. Ignored files are left
+/// out, and so are untracked ones in bare mode, where they are the rest of the home directory.
+pub fn local_changes(dfl_path: &Path) -> io::Result<Vec<String>> {
+    let untracked = !bare::in_use(dfl_path);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let statuses = repo.statuses(Some(StatusOptions::new().include_untracked(untracked).recurse_untracked_dirs(untracked))).map_err(failed)?;
+            Ok(statuses.iter().filter_map(|entry| entry.path().ok().map(str::to_string)).collect())
+        }
+        Backend::Command => {
+            let output = cmd!("git", "-c", "core.quotePath=false", "status", "--porcelain", if untracked { "--untracked-files=all" } else { "--untracked-files=no" }).in_repo(dfl_path).read()?;
+            Ok(output.lines().filter_map(|line| line.get(3..)).map(|path| path.rsplit(" -> ").next().unwrap_or(path).to_string()).collect())
+        }
+    }
+}
+
+/// Sets uncommitted changes aside, new files included, leaving the working tree
+/// clean. In bare mode only tracked files are set aside, not the whole home directory.
+pub fn stash(dfl_path: &Path, message: &str) -> io::Result<()> {
+    debug!("git -C {} stash push -m {:?}", dfl_path.display(), message);
+    let untracked = !bare::in_use(dfl_path);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let mut repo = open(dfl_path)?;
+            let signature = repo.signature().map_err(failed)?;
+            let flags = if untracked { git2::StashFlags::INCLUDE_UNTRACKED } else { git2::StashFlags::DEFAULT };
+            repo.stash_save(&signature, message, Some(flags)).map(drop).map_err(failed)
+        }
+        Backend::Command if untracked => cmd!("git", "stash", "push", "--quiet", "--include-untracked", "-m", message).in_repo(dfl_path).run().map(drop),
+        Backend::Command => cmd!("git", "stash", "push", "--quiet", "-m", message).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Returns true if a new file set aside with the newest stash exists again, say
+/// because a pull brought it in. Such files live in the stash's third parent, and
+/// libgit2 merges them into the existing file without reporting a conflict.
+fn stashed_files_taken(repo: &Repository, dfl_path: &Path) -> io::Result<bool> {
+    let Ok(untracked) = repo.refname_to_id("refs/stash").and_then(|id| repo.find_commit(id)).and_then(|stash| stash.parent(2)).and_then(|parent| parent.tree()) else {
+        return Ok(false);
+    };
+    let mut taken = false;
+    let walked = untracked.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() == Some(git2::ObjectType::Blob) && std::fs::symlink_metadata(work_tree(dfl_path).join(dir).join(entry.name().unwrap_or_default())).is_ok() {
+            taken = true;
+            return git2::TreeWalkResult::Abort;
+        }
+        git2::TreeWalkResult::Ok
+    });
+    // Stopping the walk early is reported as an error.
+    if taken {
+        return Ok(true);
+    }
+    walked.map_err(failed)?;
+    Ok(false)
+}
+
+/// Re-applies the newest stash and drops it. If it conflicts with the working
+/// tree, it is kept in the stash and nothing changes.
+pub fn unstash(dfl_path: &Path) -> io::Result<()> {
+    debug!("git -C {} stash pop", dfl_path.display());
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let mut repo = open(dfl_path)?;
+            if stashed_files_taken(&repo, dfl_path)? {
+                return Err(io::Error::other("the stashed changes conflict with the working tree"));
+            }
+            repo.stash_apply(0, None).map_err(failed)?;
+            // A conflicting stash is applied with conflict markers, so undo it and keep the stash.
+            if repo.index().map_err(failed)?.has_conflicts() {
+                let head = repo.head().and_then(|head| head.peel(git2::ObjectType::Commit)).map_err(failed)?;
+                repo.reset(&head, git2::ResetType::Hard, None).map_err(failed)?;
+                return Err(io::Error::other("the stashed changes conflict with the working tree"));
+            }
+            repo.stash_drop(0).map_err(failed)
+        }
+        Backend::Command => {
+            if cmd!("git", "stash", "pop", "--quiet").in_repo(dfl_path).stdout_null().stderr_null().unchecked().run()?.status.success() {
+                return Ok(());
+            }
+            // A conflicting pop leaves conflict markers behind and keeps the stash, so undo it as above.
+            cmd!("git", "reset", "--hard", "--quiet").in_repo(dfl_path).run()?;
+            Err(io::Error::other("the stashed changes conflict with the working tree"))
+        }
+    }
+}
+
+/// Returns the current commit, or `None` in a repository without commits.
+pub fn head(dfl_path: &Path) -> io::Result<Option<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => Ok(open(dfl_path)?.head().ok().and_then(|head| head.target()).map(|id| id.to_string())),
+        Backend::Command => {
+            let output = cmd!("git", "rev-parse", "--verify", "--quiet", "HEAD").in_repo(dfl_path).stderr_null().unchecked().read()?;
+            Ok(Some(output.trim().to_string()).filter(|h| !h.is_empty()))
+        }
+    }
+}
+
+/// Counts the commits reachable from `to` but not from `from`.
+pub fn count_commits(dfl_path: &Path, from: Option<&str>, to: &str) -> io::Result<usize> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let mut walk = repo.revwalk().map_err(failed)?;
+            walk.push(Oid::from_str(to).map_err(failed)?).map_err(failed)?;
+            if let Some(from) = from {
+                walk.hide(Oid::from_str(from).map_err(failed)?).map_err(failed)?;
+            }
+            Ok(walk.count())
+        }
+        Backend::Command => {
+            let range = from.map_or_else(|| to.to_string(), |from| format!("{}..{}", from, to));
+            let count = cmd!("git", "rev-list", "--count", range).in_repo(dfl_path).read()?;
+            count.trim().parse().map_err(io::Error::other)
+        }
+    }
+}
+
+/// Counts the fetched commits the current branch does not have yet; 0 without an upstream.
+pub fn incoming(dfl_path: &Path) -> io::Result<usize> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let Some((remote, remote_branch)) = tracking(&repo, &current_branch(&repo).map_err(failed)?).map_err(failed)? else {
+                return Ok(0);
+            };
+            let Ok(theirs) = repo.refname_to_id(&format!("refs/remotes/{}/{}", remote, remote_branch)) else {
+                return Ok(0);
+            };
+            let ours = head(dfl_path)?;
+            count_commits(dfl_path, ours.as_deref(), &theirs.to_string())
+        }
+        Backend::Command => {
+            let count = cmd!("git", "rev-list", "--count", "HEAD..@{upstream}").in_repo(dfl_path).stderr_null().unchecked().read()?;
+            Ok(count.trim().parse().unwrap_or(0))
+        }
+    }
+}
+
+/// Lists the files changed between two commits as `git diff --name-status` does:
+/// a status letter, a tab, and the path.
+pub fn changed_files(dfl_path: &Path, before: &str, after: &str) -> io::Result<Vec<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let tree = |id: &str| Oid::from_str(id).and_then(|id| repo.find_commit(id)).and_then(|commit| commit.tree());
+            let diff = repo.diff_tree_to_tree(Some(&tree(before).map_err(failed)?), Some(&tree(after).map_err(failed)?), None).map_err(failed)?;
+            Ok(diff
+                .deltas()
+                .map(|delta| {
+                    let path = delta.new_file().path().or(delta.old_file().path()).unwrap_or(Path::new(""));
+                    let status = match delta.status() {
+                        Delta::Added => 'A',
+                        Delta::Deleted => 'D',
+                        Delta::Renamed => 'R',
+                        Delta::Copied => 'C',
+                        Delta::Typechange => 'T',
+                        _ => 'M',
+                    };
+                    format!("{}\t{}", status, path.display())
+                })
+                .collect())
+        }
+        Backend::Command => Ok(cmd!("git", "diff", "--name-status", before, after).in_repo(dfl_path).read()?.lines().map(str::to_string).collect()),
+    }
+}
+
+/// How much a file changed between two versions of the repository.
+pub struct FileStat {
+    /// Repository-relative path.
+    pub path: String,
+    /// `A` for added, `D` for deleted, `M` for changed, as `git diff --name-status` shows.
+    pub status: char,
+    /// Lines added and removed, or `None` for binary files.
+    pub lines: Option<(usize, usize)>,
+}
+
+/// Collects the per-file statistics of a diff made by the library.
+fn file_stats(diff: &git2::Diff) -> io::Result<Vec<FileStat>> {
+    let mut stats = Vec::new();
+    for (index, delta) in diff.deltas().enumerate() {
+        let path = delta.new_file().path().or(delta.old_file().path()).unwrap_or(Path::new("")).display().to_string();
+        let status = match delta.status() {
+            Delta::Added => 'A',
+            Delta::Deleted => 'D',
+            _ => 'M',
+        };
+        let lines = match git2::Patch::from_diff(diff, index).map_err(failed)? {
+            Some(patch) if !patch.delta().flags().is_binary() => patch.line_stats().map(|(_, added, removed)| Some((added, removed))).map_err(failed)?,
+            _ => None,
+        };
+        stats.push(FileStat { path, status, lines });
+    }
+    Ok(stats)
+}
+
+/// Runs `git diff` with `args` twice, for the statuses and the line counts, and
+/// puts the two together.
+fn command_file_stats(dfl_path: &Path, args: &[&str]) -> io::Result<Vec<FileStat>> {
+    let run = |format: &str| cmd("git", [&["diff", format, "--no-renames"], args].concat()).in_repo(dfl_path).read();
+    let statuses: HashMap<String, char> = run("--name-status")?
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(status, path)| (path.to_string(), status.chars().next().unwrap_or('M')))
+        .collect();
+    Ok(run("--numstat")?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let (added, removed, path) = (fields.next()?, fields.next()?, fields.next()?);
+            let status = statuses.get(path).copied().unwrap_or('M');
+            Some(FileStat { path: path.to_string(), status, lines: added.parse().ok().zip(removed.parse().ok()) })
+        })
+        .collect())
+}
+
+/// Counts the lines added and removed in each file changed between two commits,
+/// as `git diff --numstat` does.
+pub fn diffstat(dfl_path: &Path, before: &str, after: &str) -> io::Result<Vec<FileStat>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let tree = |id: &str| Oid::from_str(id).and_then(|id| repo.find_commit(id)).and_then(|commit| commit.tree());
+            let diff = repo.diff_tree_to_tree(Some(&tree(before).map_err(failed)?), Some(&tree(after).map_err(failed)?), None).map_err(failed)?;
+            file_stats(&diff)
+        }
+        Backend::Command => command_file_stats(dfl_path, &[before, after]),
+    }
+}
+
+/// Counts the lines added and removed in each staged file, compared with the
+/// last commit, for the message of the commit about to be made.
+pub fn staged_diffstat(dfl_path: &Path) -> io::Result<Vec<FileStat>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+            let diff = repo.diff_tree_to_index(head.as_ref(), None, None).map_err(failed)?;
+            file_stats(&diff)
+        }
+        Backend::Command => command_file_stats(dfl_path, &["--cached"]),
+    }
+}
+
+/// Lists the lines added to tracked files since the last commit, staged or not,
+/// as the path, the line number, and the line, to check what the next commit adds.
+pub fn uncommitted_lines(dfl_path: &Path) -> io::Result<Vec<(String, usize, String)>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+            let diff = repo.diff_tree_to_workdir_with_index(head.as_ref(), None).map_err(failed)?;
+            let mut lines = Vec::new();
+            diff.foreach(
+                &mut |_, _| true,
+                None,
+                None,
+                Some(&mut |delta, _, line| {
+                    if line.origin() == '+' {
+                        let path = delta.new_file().path().unwrap_or(Path::new("")).display().to_string();
+                        lines.push((path, line.new_lineno().unwrap_or(0) as usize, String::from_utf8_lossy(line.content()).trim_end().to_string()));
+                    }
+                    true
+                }),
+            )
+            .map_err(failed)?;
+            Ok(lines)
+        }
+        Backend::Command => {
+            let base = if head(dfl_path)?.is_some() { "HEAD" } else { "--cached" };
+            let diff = cmd!("git", "-c", "core.quotePath=false", "diff", base, "-U0", "--no-color", "--no-ext-diff").in_repo(dfl_path).read()?;
+            let (mut lines, mut path, mut number) = (Vec::new(), String::new(), 0);
+            for line in diff.lines() {
+                if let Some(new_path) = line.strip_prefix("+++ ") {
+                    path = new_path.strip_prefix("b/").unwrap_or(new_path).to_string();
+                } else if let Some(hunk) = line.strip_prefix("@@ ") {
+                    // "@@ -a,b +c,d @@": added lines are numbered from c.
+                    number = hunk.split_whitespace().nth(1).and_then(|new| new.trim_start_matches('+').split(',').next()?.parse().ok()).unwrap_or(0);
+                } else if let Some(added) = line.strip_prefix('+') {
+                    lines.push((path.clone(), number, added.to_string()));
+                    number += 1;
+                }
+            }
+            Ok(lines)
+        }
+    }
+}
+
+/// Returns the newest commit both `one` and `other` descend from, or `None` if
+/// their histories share nothing, or not as far back as a shallow clone reaches.
+pub fn merge_base(dfl_path: &Path, one: &str, other: &str) -> io::Result<Option<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            Ok(repo.merge_base(Oid::from_str(one).map_err(failed)?, Oid::from_str(other).map_err(failed)?).ok().map(|id| id.to_string()))
+        }
+        Backend::Command => {
+            let output = cmd!("git", "merge-base", one, other).in_repo(dfl_path).stderr_null().unchecked().read()?;
+            Ok(Some(output.trim().to_string()).filter(|id| !id.is_empty()))
+        }
+    }
+}
+
+/// Returns the commit last fetched from the upstream of `branch`, or `None` if it
+/// has no upstream or nothing was fetched from it yet.
+pub fn upstream_head(dfl_path: &Path, branch: &str) -> io::Result<Option<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let Some((remote, remote_branch)) = tracking(&repo, branch).map_err(failed)? else {
+                return Ok(None);
+            };
+            Ok(repo.refname_to_id(&format!("refs/remotes/{}/{}", remote, remote_branch)).ok().map(|id| id.to_string()))
+        }
+        Backend::Command => {
+            let output = cmd!("git", "rev-parse", "--verify", "--quiet", format!("{}@{{upstream}}", branch)).in_repo(dfl_path).stderr_null().unchecked().read()?;
+            Ok(Some(output.trim().to_string()).filter(|id| !id.is_empty()))
+        }
+    }
+}
+
+/// Resolves a commit id, tag, or branch name to a commit id, or `None` if nothing has that name.
+pub fn find_commit(dfl_path: &Path, name: &str) -> io::Result<Option<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => Ok(open(dfl_path)?.revparse_single(name).and_then(|object| object.peel_to_commit()).ok().map(|commit| commit.id().to_string())),
+        Backend::Command => {
+            let output = cmd!("git", "rev-parse", "--verify", "--quiet", format!("{}^{{commit}}", name)).in_repo(dfl_path).stderr_null().unchecked().read()?;
+            Ok(Some(output.trim().to_string()).filter(|id| !id.is_empty()))
+        }
+    }
+}
+
+/// Returns the newest commit of the current branch made before `time`, in seconds
+/// since the epoch. Only the branch's own line is followed, not merged-in branches.
+pub fn last_commit_before(dfl_path: &Path, time: i64) -> io::Result<Option<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let mut walk = repo.revwalk().map_err(failed)?;
+            walk.simplify_first_parent().map_err(failed)?;
+            walk.push_head().map_err(failed)?;
+            for id in walk {
+                let commit = repo.find_commit(id.map_err(failed)?).map_err(failed)?;
+                if commit.time().seconds() < time {
+                    return Ok(Some(commit.id().to_string()));
+                }
+            }
+            Ok(None)
+        }
+        Backend::Command => {
+            let output = cmd!("git", "rev-list", "-1", "--first-parent", format!("--before=@{}", time), "HEAD").in_repo(dfl_path).read()?;
+            Ok(Some(output.trim().to_string()).filter(|id| !id.is_empty()))
+        }
+    }
+}
+
+/// Lists the subject lines of the commits reachable from `to` but not from `from`, oldest first.
+pub fn subjects(dfl_path: &Path, from: &str, to: &str) -> io::Result<Vec<String>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let mut walk = repo.revwalk().map_err(failed)?;
+            walk.push(Oid::from_str(to).map_err(failed)?).map_err(failed)?;
+            walk.hide(Oid::from_str(from).map_err(failed)?).map_err(failed)?;
+            walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE).map_err(failed)?;
+            walk.map(|id| {
+                let commit = repo.find_commit(id.map_err(failed)?).map_err(failed)?;
+                Ok(commit.summary().ok().flatten().unwrap_or_default().to_string())
+            })
+            .collect()
+        }
+        Backend::Command => Ok(cmd!("git", "log", "--reverse", "--format=%s", format!("{}..{}", from, to)).in_repo(dfl_path).read()?.lines().map(str::to_string).collect()),
+    }
+}
+
+/// Replaces the current branch's commits after `onto` with a single commit holding
+/// the same files. The working tree must be clean, so nothing else is committed.
+pub fn squash(dfl_path: &Path, onto: &str, message: &str) -> io::Result<()> {
+    debug!("git -C {} reset --soft {} && git commit -m {:?}", dfl_path.display(), onto, message);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let head = repo.head().and_then(|head| head.peel_to_commit()).map_err(failed)?;
+            let onto = Oid::from_str(onto).and_then(|id| repo.find_commit(id)).map_err(failed)?;
+            let tree = head.tree().map_err(failed)?;
+            let signature = repo.signature().map_err(failed)?;
+            // The branch moves off its old tip, which `commit` refuses to do for a ref it updates.
+            let id = create_commit(&repo, None, &signature, &signature, message, &tree, &[&onto])?;
+            let local = format!("refs/heads/{}", current_branch(&repo).map_err(failed)?);
+            repo.reference(&local, id, true, "squash").map(drop).map_err(failed)
+        }
+        Backend::Command => {
+            let head = cmd!("git", "rev-parse", "HEAD").in_repo(dfl_path).read()?;
+            cmd!("git", "reset", "--soft", "--quiet", onto).in_repo(dfl_path).run()?;
+            let committed = commit(dfl_path, message);
+            if committed.is_err() {
+                cmd!("git", "reset", "--soft", "--quiet", head.trim()).in_repo(dfl_path).run()?;
+            }
+            committed
+        }
+    }
+}
+
+/// Replaces the last commit with one that also holds the staged changes, keeping
+/// its author and, without a new `message`, its message.
+pub fn amend(dfl_path: &Path, message: Option<&str>) -> io::Result<()> {
+    debug!("git -C {} commit --amend (message {:?})", dfl_path.display(), message);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let head = repo.head().and_then(|head| head.peel_to_commit()).map_err(failed)?;
+            let tree = repo.index().and_then(|mut index| index.write_tree()).and_then(|id| repo.find_tree(id)).map_err(failed)?;
+            let committer = repo.signature().map_err(failed)?;
+            let message = message.unwrap_or_else(|| head.message().unwrap_or_default());
+            let parents: Vec<git2::Commit> = head.parents().collect();
+            // As with `squash`, the branch moves off its old tip, which `commit` refuses for a ref it updates.
+            let id = create_commit(&repo, None, &head.author(), &committer, message, &tree, &parents.iter().collect::<Vec<_>>())?;
+            let local = format!("refs/heads/{}", current_branch(&repo).map_err(failed)?);
+            repo.reference(&local, id, true, "commit (amend)").map(drop).map_err(failed)
+        }
+        Backend::Command => {
+            let mut args = [signing::command_options()?, vec!["commit".to_string(), "--amend".to_string(), "--quiet".to_string(), "--allow-empty".to_string()]].concat();
+            match message {
+                Some(message) => args.extend(["-m".to_string(), message.to_string()]),
+                None => args.push("--no-edit".to_string()),
+            }
+            cmd("git", args).in_repo(dfl_path).run().map(drop)
+        }
+    }
+}
+
+/// Lists the tags with the first line of their message, or of the tagged commit's.
+pub fn tags(dfl_path: &Path) -> io::Result<Vec<(String, String)>> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let mut tags = Vec::new();
+            for name in repo.tag_names(None).map_err(failed)?.iter() {
+                let Ok(Some(name)) = name else {
+                    continue;
+                };
+                let object = repo.revparse_single(&format!("refs/tags/{}", name)).map_err(failed)?;
+                let message = match object.as_tag() {
+                    Some(tag) => tag.message().ok().flatten().unwrap_or_default().to_string(),
+                    None => object.peel_to_commit().ok().and_then(|commit| commit.message().ok().map(str::to_string)).unwrap_or_default(),
+                };
+                tags.push((name.to_string(), message.lines().next().unwrap_or_default().to_string()));
+            }
+            tags.sort();
+            Ok(tags)
+        }
+        Backend::Command => {
+            let output = cmd!("git", "tag", "--list", "--format=%(refname:short)\t%(contents:subject)").in_repo(dfl_path).read()?;
+            Ok(output.lines().map(|line| line.split_once('\t').map_or((line.to_string(), String::new()), |(name, subject)| (name.to_string(), subject.to_string()))).collect())
+        }
+    }
+}
+
+/// Tags the current commit with an annotated tag, which `push` sends along with the branch.
+pub fn create_tag(dfl_path: &Path, name: &str, message: &str) -> io::Result<()> {
+    debug!("git -C {} tag -a {} -m {:?}", dfl_path.display(), name, message);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let head = repo.head().and_then(|head| head.peel(git2::ObjectType::Commit)).map_err(failed)?;
+            let signature = repo.signature().map_err(failed)?;
+            repo.tag(name, &head, &signature, message, false).map(drop).map_err(failed)
+        }
+        Backend::Command => cmd!("git", "tag", "--annotate", "--message", message, name).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Deletes a local tag; a copy already pushed stays on the remote.
+pub fn delete_tag(dfl_path: &Path, name: &str) -> io::Result<()> {
+    debug!("git -C {} tag --delete {}", dfl_path.display(), name);
+    match backend(dfl_path)? {
+        Backend::Library => open(dfl_path)?.tag_delete(name).map_err(failed),
+        Backend::Command => cmd!("git", "tag", "--delete", name).in_repo(dfl_path).stdout_null().run().map(drop),
+    }
+}
+
+/// Puts the files back as they were at the commit `target` and commits that on top of the
+/// current branch, so the rollback is ordinary history that can be pushed and
+/// undone. The working tree must be clean.
+pub fn restore(dfl_path: &Path, target: &str, message: &str) -> io::Result<()> {
+    debug!("git -C {} restore --source {} . && git commit -m {:?}", dfl_path.display(), target, message);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let head = repo.head().and_then(|head| head.peel_to_commit()).map_err(failed)?;
+            let tree = Oid::from_str(target).and_then(|id| repo.find_commit(id)).and_then(|commit| commit.tree()).map_err(failed)?;
+            if tree.id() == head.tree_id() {
+                return Err(io::Error::other("the files already match; nothing to roll back"));
+            }
+            repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe().remove_untracked(false))).map_err(failed)?;
+            let signature = repo.signature().map_err(failed)?;
+            create_commit(&repo, Some("HEAD"), &signature, &signature, message, &tree, &[&head]).map(drop)
+        }
+        Backend::Command => {
+            cmd!("git", "read-tree", "-u", "--reset", target).in_repo(dfl_path).run()?;
+            let committed = commit(dfl_path, message);
+            if committed.is_err() {
+                cmd!("git", "read-tree", "-u", "--reset", "HEAD").in_repo(dfl_path).run()?;
+            }
+            committed
+        }
+    }
+}
+
+/// Returns true if the commit `target` has a file or directory at the
+/// repository-relative `path`.
+pub fn has_path(dfl_path: &Path, target: &str, path: &str) -> io::Result<bool> {
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let tree = Oid::from_str(target).and_then(|id| repo.find_commit(id)).and_then(|commit| commit.tree()).map_err(failed)?;
+            Ok(tree.get_path(Path::new(path)).is_ok())
+        }
+        Backend::Command => Ok(cmd!("git", "cat-file", "-e", format!("{}:{}", target, path)).in_repo(dfl_path).stderr_null().unchecked().run()?.status.success()),
+    }
+}
+
+/// Puts the file or directory at `path` as it is in the commit `target` into the
+/// working tree and stages it, as `git checkout <target> -- <path>` does. Files
+/// below `path` that `target` does not have are left alone.
+pub fn checkout_path(dfl_path: &Path, target: &str, path: &str) -> io::Result<()> {
+    debug!("git -C {} checkout {} -- {}", dfl_path.display(), target, path);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let commit = Oid::from_str(target).and_then(|id| repo.find_commit(id)).map_err(failed)?;
+            let mut checkout = CheckoutBuilder::new();
+            checkout.force().path(path);
+            repo.checkout_tree(commit.as_object(), Some(&mut checkout)).map_err(failed)
+        }
+        Backend::Command => cmd!("git", "checkout", "--quiet", target, "--", path).in_repo(dfl_path).run().map(drop),
+    }
+}
+
+/// Applies the changes of the commit `target` on top of the current branch as a
+/// new commit with the same author and message, as `git cherry-pick` does. If they
+/// conflict, nothing changes and the conflicting paths are reported.
+pub fn cherry_pick(dfl_path: &Path, target: &str) -> io::Result<()> {
+    debug!("git -C {} cherry-pick {}", dfl_path.display(), target);
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let commit = Oid::from_str(target).and_then(|id| repo.find_commit(id)).map_err(failed)?;
+            if commit.parent_count() > 1 {
+                return Err(io::Error::other("it is a merge commit; pick the commits it merged instead"));
+            }
+            let head = repo.head().and_then(|head| head.peel_to_commit()).map_err(failed)?;
+            let mut index = repo.cherrypick_commit(&commit, &head, 0, None).map_err(failed)?;
+            if index.has_conflicts() {
+                return Err(io::Error::other(format!("it conflicts with the current branch in {}; nothing was changed", conflicted_paths(&index)?.join(", "))));
+            }
+            let tree = index.write_tree_to(&repo).and_then(|id| repo.find_tree(id)).map_err(failed)?;
+            if tree.id() == head.tree_id() {
+                return Err(io::Error::other("the current branch already has its changes"));
+            }
+            repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe())).map_err(failed)?;
+            let committer = repo.signature().map_err(failed)?;
+            create_commit(&repo, Some("HEAD"), &commit.author(), &committer, commit.message().unwrap_or_default(), &tree, &[&head]).map(drop)
+        }
+        Backend::Command => {
+            let args = [signing::command_options()?, vec!["cherry-pick".to_string(), target.to_string()]].concat();
+            let output = cmd("git", args).in_repo(dfl_path).stdout_null().stderr_capture().unchecked().run()?;
+            if output.status.success() {
+                return Ok(());
+            }
+            let conflicted = command_conflicts(dfl_path)?;
+            if conflicted.is_empty() {
+                cmd!("git", "cherry-pick", "--abort").in_repo(dfl_path).stderr_null().unchecked().run()?;
+                return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+            }
+            cmd!("git", "cherry-pick", "--abort").in_repo(dfl_path).run()?;
+            Err(io::Error::other(format!("it conflicts with the current branch in {}; nothing was changed", conflicted.join(", "))))
+        }
+    }
+}
+
+/// File at the repository root that lists the submodules.
+const SUBMODULES_FILE: &str = ".gitmodules";
+
+/// Checks out every submodule at the commit the repository records, cloning the
+/// ones that are not there yet, and does the same for submodules inside them.
+pub fn update_submodules(dfl_path: &Path) -> io::Result<()> {
+    debug!("git -C {} submodule update --init --recursive", dfl_path.display());
+    if !work_tree(dfl_path).join(SUBMODULES_FILE).exists() {
+        return Ok(());
+    }
+    match backend(dfl_path)? {
+        Backend::Library => update_submodules_of(&open(dfl_path)?).map_err(failed),
+        Backend::Command => cmd!("git", "submodule", "update", "--init", "--recursive", "--quiet").in_repo(dfl_path).run().map(drop),
+    }
+}
+
+fn update_submodules_of(repo: &Repository) -> Result<(), git2::Error> {
+    let config = repo.config()?;
+    for mut submodule in repo.submodules()? {
+        let mut fetch = FetchOptions::new();
+        fetch.remote_callbacks(callbacks(&config));
+        let mut options = SubmoduleUpdateOptions::new();
+        options.fetch(fetch);
+        submodule.update(true, Some(&mut options))?;
+        update_submodules_of(&submodule.open()?)?;
+    }
+    Ok(())
+}
+
+/// Lists the submodules that are not checked out at the commit the repository
+/// records, with what is wrong with each.
+pub fn stale_submodules(dfl_path: &Path) -> io::Result<Vec<(String, &'static str)>> {
+    if !work_tree(dfl_path).join(SUBMODULES_FILE).exists() {
+        return Ok(Vec::new());
+    }
+    match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let mut stale = Vec::new();
+            for submodule in repo.submodules().map_err(failed)? {
+                let status = repo.submodule_status(submodule.name().map_err(failed)?, SubmoduleIgnore::None).map_err(failed)?;
+                let path = submodule.path().display().to_string();
+                if status.is_wd_uninitialized() {
+                    stale.push((path, "not checked out"));
+                } else if status.is_wd_modified() {
+                    stale.push((path, "checked out at a different commit"));
+                }
+            }
+            Ok(stale)
+        }
+        Backend::Command => {
+            let output = cmd!("git", "submodule", "status", "--recursive").in_repo(dfl_path).read()?;
+            Ok(output
+                .lines()
+                .filter_map(|line| {
+                    let reason = match line.chars().next()? {
+                        '-' => "not checked out",
+                        '+' => "checked out at a different commit",
+                        'U' => "has merge conflicts",
+                        _ => return None,
+                    };
+                    Some((line.split_whitespace().nth(1)?.to_string(), reason))
+                })
+                .collect())
+        }
+    }
+}
+
+/// A file's contents as stored in the repository's history.
+pub struct StoredObject {
+    /// A path the contents were committed under.
+    pub path: String,
+    /// Uncompressed size in bytes.
+    pub size: u64,
+    /// Whether the current commit still has these contents, or only older ones do.
+    pub current: bool,
+}
+
+/// Lists the `count` largest file contents anywhere in the history, biggest first.
+pub fn largest_objects(dfl_path: &Path, count: usize) -> io::Result<Vec<StoredObject>> {
+    let mut objects = match backend(dfl_path)? {
+        Backend::Library => {
+            let repo = open(dfl_path)?;
+            let mut walk = repo.revwalk().map_err(failed)?;
+            walk.push_glob("*").map_err(failed)?;
+            let mut trees = HashSet::new();
+            let mut blobs = HashMap::new();
+            for id in walk {
+                let tree = repo.find_commit(id.map_err(failed)?).and_then(|commit| commit.tree()).map_err(failed)?;
+                collect_blobs(&repo, &tree, "", &mut trees, &mut blobs).map_err(failed)?;
+            }
+            let mut current = HashMap::new();
+            if let Ok(tree) = repo.head().and_then(|head| head.peel_to_tree()) {
+                collect_blobs(&repo, &tree, "", &mut HashSet::new(), &mut current).map_err(failed)?;
+            }
+            let odb = repo.odb().map_err(failed)?;
+            // A partial clone lacks some contents, which are left out rather than fetched.
+            blobs
+                .into_iter()
+                .filter_map(|(id, path)| odb.read_header(id).ok().map(|(size, _)| StoredObject { path, size: size as u64, current: current.contains_key(&id) }))
+                .collect::<Vec<_>>()
+        }
+        Backend::Command => {
+            let listing = cmd!("git", "rev-list", "--objects", "--all").pipe(cmd!("git", "cat-file", "--batch-check=%(objecttype) %(objectname) %(objectsize) %(rest)")).in_repo(dfl_path).read()?;
+            let current: HashSet<String> = cmd!("git", "ls-tree", "-r", "HEAD").in_repo(dfl_path).stderr_null().unchecked().read()?.lines().filter_map(|line| line.split_whitespace().nth(2).map(str::to_string)).collect();
+            listing
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.splitn(4, ' ');
+                    let (Some("blob"), Some(id), Some(size), Some(path)) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+                        return None;
+                    };
+                    Some(StoredObject { path: path.to_string(), size: size.parse().ok()?, current: current.contains(id) })
+                })
+                .collect()
+        }
+    };
+    objects.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    objects.truncate(count);
+    Ok(objects)
+}
+
+/// Records every file below `tree` with the first path it was seen at, skipping
+/// trees already visited, since most of them are unchanged from commit to commit.
+fn collect_blobs(repo: &Repository, tree: &git2::Tree, prefix: &str, trees: &mut HashSet<Oid>, blobs: &mut HashMap<Oid, String>) -> Result<(), git2::Error> {
+    if !trees.insert(tree.id()) {
+        return Ok(());
+    }
+    for entry in tree.iter() {
+        let path = format!("{}{}", prefix, String::from_utf8_lossy(entry.name_bytes()));
+        match entry.kind() {
+            Some(git2::ObjectType::Tree) => collect_blobs(repo, &repo.find_tree(entry.id())?, &format!("{}/", path), trees, blobs)?,
+            Some(git2::ObjectType::Blob) => {
+                blobs.entry(entry.id()).or_insert(path);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Repacks the repository and deletes objects nothing refers to anymore, through
+/// the `git` command, since the library cannot repack. Normally only objects
+/// unreferenced for two weeks go; with `now`, everything unreferenced goes at
+/// once, including commits only the undo history still had, such as those a squash replaced.
+pub fn gc(dfl_path: &Path, now: bool) -> io::Result<()> {
+    debug!("git -C {} gc (now: {})", dfl_path.display(), now);
+    if now {
+        cmd!("git", "reflog", "expire", "--expire-unreachable=now", "--all").in_repo(dfl_path).run()?;
+    }
+    cmd!("git", "gc", "--quiet", if now { "--prune=now" } else { "--prune=2.weeks.ago" }).in_repo(dfl_path).run().map(drop)
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::bare;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::lfs;
+use crate::repo;
+use crate::scan;
+use crate::ui;
+
+/// Git hooks dfl installs into the repository, each running `dfl git-hook <name>`.
+const HOOKS: &[&str] = &["pre-commit", "post-merge"];
+
+/// Line that marks a hook as dfl's own, so it is updated; any other hook is the user's and left alone.
+const MARKER: &str = "# Installed by dfl; 'dfl init' and 'dfl doctor' keep it up to date.";
+
+/// Environment variable set on every `git` command dfl runs. Its own commits and
+/// pulls already check what the hooks check, so the hooks do nothing then.
+pub const SKIP_VARIABLE: &str = "DFL_GIT";
+
+/// What the repository has where one of dfl's hooks goes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HookState {
+    /// dfl's hook, as this version of dfl writes it.
+    Current,
+    /// dfl's hook, written by another version or for a dfl binary elsewhere.
+    Outdated,
+    /// No hook at all.
+    Missing,
+    /// A hook of the user's own.
+    Foreign,
+}
+
+/// Returns the script of the hook `name` for the repository at `dfl_path`. It runs
+/// the dfl binary that installed it, or the one on `PATH` if that one moved, on
+/// that repository wherever dfl would look by default.
+fn script(dfl_path: &Path, name: &str) -> io::Result<String> {
+    let exe = std::env::current_exe()?;
+    Ok(format!(
+        "#!/bin/sh\n{}\n[ -n \"${}\" ] && exit 0\ndfl='{}'\ncommand -v \"$dfl\" >/dev/null 2>&1 || dfl=dfl\n{}='{}' exec \"$dfl\" git-hook {} \"$@\"\n",
+        MARKER,
+        SKIP_VARIABLE,
+        exe.display(),
+        repo::DIR_VARIABLE,
+        dfl_path.display(),
+        name
+    ))
+}
+
+/// Returns where git looks for the hook `name`.
+fn hook_path(dfl_path: &Path, name: &str) -> PathBuf {
+    git::git_dir(dfl_path).join("hooks").join(name)
+}
+
+/// Returns the state of each of dfl's hooks in the repository.
+pub fn states(dfl_path: &Path) -> io::Result<Vec<(&'static str, HookState)>> {
+    let mut states = Vec::new();
+    for name in HOOKS {
+        let state = match fs::read_to_string(hook_path(dfl_path, name)) {
+            Err(_) => HookState::Missing,
+            Ok(contents) if !contents.contains(MARKER) => HookState::Foreign,
+            Ok(contents) if contents == script(dfl_path, name)? => HookState::Current,
+            Ok(_) => HookState::Outdated,
+        };
+        states.push((*name, state));
+    }
+    Ok(states)
+}
+
+/// Writes dfl's hooks that are missing or outdated, leaving the user's own hooks
+/// alone. Returns the names of the hooks it wrote.
+pub fn install(dfl_path: &Path) -> io::Result<Vec<&'static str>> {
+    let mut written = Vec::new();
+    for (name, state) in states(dfl_path)? {
+        if state == HookState::Missing || state == HookState::Outdated {
+            let path = hook_path(dfl_path, name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, script(dfl_path, name)?)?;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+            written.push(name);
+        }
+    }
+    Ok(written)
+}
+
+/// Installs dfl's hooks into a new or cloned repository, warning about hooks of
+/// the user's own that keep dfl's from running. Nothing is installed when
+/// `git_hooks` is off in the user configuration.
+pub fn install_reporting(dfl_path: &Path) -> io::Result<()> {
+    if !config::load()?.git_hooks {
+        return Ok(());
+    }
+    if !install(dfl_path)?.is_empty() {
+        ui::say("✅ Installed git hooks that scan commits made with git for secrets and large files, and sync after 'git pull'.");
+    }
+    for (name, state) in states(dfl_path)? {
+        if state == HookState::Foreign {
+            ui::warn(&format!("{} has a {} hook of its own, so dfl's was not installed.", git::git_dir(dfl_path).join("hooks").display(), name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Refuses files above `max_file_kb` among the staged changes, unless git-lfs stores them.
+fn check_sizes(dfl_path: &Path) -> io::Result<()> {
+    let limit = config::load()?.max_file_kb * 1024;
+    if limit == 0 {
+        return Ok(());
+    }
+    let work_tree = git::work_tree(dfl_path);
+    let mut too_large = Vec::new();
+    for stat in git::staged_diffstat(dfl_path)? {
+        let Ok(metadata) = fs::metadata(work_tree.join(&stat.path)) else {
+            continue;
+        };
+        if metadata.len() > limit && !lfs::stores(dfl_path, Path::new(&stat.path))? {
+            too_large.push(format!("{} ({})", stat.path, ui::bytes(metadata.len())));
+        }
+    }
+    if too_large.is_empty() {
+        return Ok(());
+    }
+    Err(errors::error(
+        Code::FileTooLarge,
+        format!("Not committing files above {}: {}. Store them with 'dfl add --lfs', or raise max_file_kb in the dfl config.", ui::bytes(limit), too_large.join(", ")),
+    ))
+}
+
+/// Handles the hidden 'git-hook' command, which dfl's git hooks run.
+/// Before a commit, it scans the changes for secrets and large files; after a
+/// merge, such as from 'git pull', it syncs the links.
+pub fn handle_git_hook_command(hook: &str) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    match hook {
+        "pre-commit" => scan::check(&dfl_path, &repo::home_dir()?, &[]).and_then(|()| check_sizes(&dfl_path)).map_err(|e| match errors::code_of(&e) {
+            Some(code) => errors::error(code, format!("{}\nTo commit with git anyway, pass --no-verify to 'git commit'.", e)),
+            None => e,
+        }),
+        "post-merge" if bare::in_use(&dfl_path) => bare::handle_sync_command(&dfl_path),
+        "post-merge" => crate::sync::handle_sync_command(&crate::sync::SyncOptions::from_config()?, false),
+        _ => Err(errors::error(Code::InvalidArgument, format!("Unknown git hook. dfl installs {}.", HOOKS.join(" and ")))),
+    }
+}
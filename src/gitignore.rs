@@ -0,0 +1,133 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::GitignoreBuilder;
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::repo;
+use crate::ui;
+
+/// File at the repository root listing paths git never commits.
+pub const GITIGNORE_FILE: &str = ".gitignore";
+
+/// Comment above the patterns dfl writes when the first directory is added. Once
+/// it is there, the list is the user's to edit and is never written again.
+const JUNK_HEADER: &str = "# Caches, backups, and history files that programs write into config directories. Added by dfl.";
+
+/// Well-known files in config directories that should never be shared between machines.
+const JUNK: &[&str] = &[".DS_Store", "__pycache__/", "*.pyc", "*.swp", "*~", "lazy-lock.json.*", "*_history", ".netrwhist", ".zcompdump*"];
+
+/// Returns the patterns in `.gitignore`, without comments and blank lines.
+fn patterns(dfl_path: &Path) -> Vec<String> {
+    let contents = fs::read_to_string(dfl_path.join(GITIGNORE_FILE)).unwrap_or_default();
+    contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(str::to_string).collect()
+}
+
+/// Appends `lines` to `.gitignore`, creating it if needed.
+fn append(dfl_path: &Path, lines: &[String]) -> io::Result<()> {
+    let path = dfl_path.join(GITIGNORE_FILE);
+    let mut contents = fs::read_to_string(&path).unwrap_or_default();
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    for line in lines {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    fs::write(&path, contents)
+}
+
+/// Writes the well-known junk patterns to `.gitignore` the first time a directory
+/// is added, so caches and history files moved along with it are never committed.
+/// Patterns the user removed later are not brought back.
+pub fn ensure_defaults(dfl_path: &Path) -> io::Result<()> {
+    let contents = fs::read_to_string(dfl_path.join(GITIGNORE_FILE)).unwrap_or_default();
+    if contents.lines().any(|line| line == JUNK_HEADER) {
+        return Ok(());
+    }
+    let present = patterns(dfl_path);
+    let mut lines = vec![JUNK_HEADER.to_string()];
+    lines.extend(JUNK.iter().filter(|pattern| !present.iter().any(|line| line == *pattern)).map(|pattern| pattern.to_string()));
+    append(dfl_path, &lines)?;
+    ui::say(&format!("✅ Keeping caches, backups, and history files out of the repository (listed in {}).", GITIGNORE_FILE));
+    Ok(())
+}
+
+/// Lists the patterns in `.gitignore`.
+fn list(dfl_path: &Path) -> io::Result<()> {
+    let patterns = patterns(dfl_path);
+    if patterns.is_empty() {
+        println!("Nothing is ignored yet. Add a pattern with 'dfl ignore add <pattern>'.");
+    }
+    for pattern in patterns {
+        println!("{}", pattern);
+    }
+    Ok(())
+}
+
+/// Adds `new` patterns to `.gitignore` and commits it. With other uncommitted
+/// changes in the repository, the file is only written, so they are not committed by surprise.
+fn add(dfl_path: &Path, new: &[String]) -> io::Result<()> {
+    if new.is_empty() {
+        return Err(errors::error(Code::InvalidArgument, "'ignore add' needs at least one gitignore-style pattern, e.g. 'dfl ignore add \"*.log\"'."));
+    }
+    let mut builder = GitignoreBuilder::new(dfl_path);
+    for pattern in new {
+        builder.add_line(None, pattern).map_err(|e| errors::error(Code::InvalidArgument, format!("'{}' is not a valid gitignore pattern: {}", pattern, e)))?;
+    }
+
+    let present = patterns(dfl_path);
+    let mut added: Vec<String> = Vec::new();
+    for pattern in new {
+        if !present.contains(pattern) && !added.contains(pattern) {
+            added.push(pattern.clone());
+        }
+    }
+    if added.is_empty() {
+        ui::say(&format!("{} already ignored.", new.join(", ")));
+        return Ok(());
+    }
+
+    let pending = git::has_local_changes(dfl_path)?;
+    append(dfl_path, &added)?;
+    ui::say(&format!("✅ Ignoring {} (listed in {}).", added.join(", "), GITIGNORE_FILE));
+    if pending {
+        ui::say(&format!("You have other uncommitted changes, so {} is left for you to commit with them using 'dfl commit'.", GITIGNORE_FILE));
+        return Ok(());
+    }
+
+    git::add_all(dfl_path).map_err(|e| errors::error(Code::GitFailed, format!("Error staging changes: {}", e)))?;
+    let message = git::commit_message("ignore", &[PathBuf::from(GITIGNORE_FILE)], format!("chore: Ignore {}", added.join(", ")))?;
+    git::commit(dfl_path, &message).map_err(|e| errors::error(Code::GitFailed, format!("Error committing changes: {}", e)))?;
+    ui::say(&format!("✅ Changes committed. Files committed before stay tracked; 'git -C {} rm --cached <file>' stops tracking one.", repo::display(dfl_path)));
+    if config::load()?.auto_push {
+        crate::remote::push_after_commit(dfl_path)?;
+    }
+    Ok(())
+}
+
+/// What the 'ignore' command does.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum IgnoreCommand {
+    /// Lists the patterns in the repository's .gitignore (the default).
+    List,
+    /// Adds patterns to the repository's .gitignore.
+    Add { patterns: Vec<String> },
+}
+
+/// Handles the 'ignore' command.
+/// It lists the patterns in the repository's `.gitignore`, or adds patterns to it
+/// so files such as caches written into managed directories are never committed.
+pub fn handle_ignore_command(command: Option<IgnoreCommand>) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+    match command.unwrap_or(IgnoreCommand::List) {
+        IgnoreCommand::List => list(&dfl_path),
+        IgnoreCommand::Add { patterns } => add(&dfl_path, &patterns),
+    }
+}
@@ -0,0 +1,118 @@
+use std::io;
+
+use crate::config::Config;
+use crate::state::{self, State};
+use crate::ui;
+
+/// A one-time tip shown after a command when its condition holds.
+struct Hint {
+    id: &'static str,
+    /// The command after which this hint may be shown.
+    after: &'static str,
+    /// Decides whether the hint is relevant given the usage recorded so far.
+    applies: fn(&State) -> bool,
+    message: &'static str,
+}
+
+fn used(state: &State, command: &str) -> u32 {
+    state.usage.get(command).copied().unwrap_or(0)
+}
+
+const HINTS: &[Hint] = &[
+    Hint {
+        id: "first-add",
+        after: "init",
+        applies: |s| used(s, "add") == 0,
+        message: "add your first dotfile with `dfl add ~/.bashrc`.",
+    },
+    Hint {
+        id: "connect-remote",
+        after: "add",
+        applies: |s| used(s, "remote") == 0,
+        message: "back up your dotfiles by connecting a remote with `dfl remote add <url>`.",
+    },
+    Hint {
+        id: "first-push",
+        after: "remote",
+        applies: |s| used(s, "push") == 0,
+        message: "upload your committed dotfiles with `dfl push`.",
+    },
+    Hint {
+        id: "push-often",
+        after: "add",
+        applies: |s| used(s, "add") >= 3 && used(s, "push") == 0 && used(s, "remote") > 0,
+        message: "you have added several dotfiles but never pushed; run `dfl push` to back them up.",
+    },
+    Hint {
+        id: "sync-after-pull",
+        after: "pull",
+        applies: |s| used(s, "sync") == 0,
+        message: "pulled changes are not linked until you run `dfl sync`.",
+    },
+    Hint {
+        id: "pull-then-sync",
+        after: "pull",
+        applies: |s| used(s, "pull") >= 5,
+        message: "on a machine you only deploy to, `dfl pull && dfl sync` keeps everything current in one line.",
+    },
+];
+
+/// Records a successful run of `command` and prints at most one relevant tip.
+/// `first_run` should be computed before any state is written for this invocation.
+pub fn after_command(command: &str, config: &Config, first_run: bool) -> io::Result<()> {
+    let mut state = state::load()?;
+    *state.usage.entry(command.to_string()).or_insert(0) += 1;
+
+    if config.hints {
+        if first_run && state.hints_shown.insert("welcome".to_string()) {
+            print_hint("welcome to dfl! Run `dfl --help` to see every command. Disable tips with `hints = false` in ~/.config/dfl/config.toml.");
+        } else if let Some(hint) = HINTS
+            .iter()
+            .find(|h| h.after == command && !state.hints_shown.contains(h.id) && (h.applies)(&state))
+        {
+            print_hint(hint.message);
+            state.hints_shown.insert(hint.id.to_string());
+        }
+    }
+
+    state::save(&state)
+}
+
+/// Records a use of `command` that happened as part of another command, such as
+/// the push `add --push` makes, so tips about it are not shown needlessly.
+pub fn record_use(command: &str) -> io::Result<()> {
+    let mut state = state::load()?;
+    *state.usage.entry(command.to_string()).or_insert(0) += 1;
+    state::save(&state)
+}
+
+fn print_hint(message: &str) {
+    ui::say(&format!("💡 tip: {}", message));
+}
+
+/// What the 'hints' command does.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum HintsCommand {
+    /// Forgets which tips were shown, so they are shown again.
+    Reset,
+}
+
+/// Handles the 'hints' command.
+/// `dfl hints reset` forgets which tips were shown and which commands were used.
+pub fn handle_hints_command(command: Option<HintsCommand>, config: &Config) -> io::Result<()> {
+    match command {
+        Some(HintsCommand::Reset) => {
+            let mut state = state::load()?;
+            state.hints_shown.clear();
+            state.usage.clear();
+            state::save(&state)?;
+            ui::say("✅ Hints reset. Tips will be shown again as you use dfl.");
+        }
+        None => {
+            let state = state::load()?;
+            println!("Hints are {}.", if config.hints { "enabled" } else { "disabled" });
+            println!("{} of {} tips shown so far.", state.hints_shown.iter().filter(|id| HINTS.iter().any(|h| h.id == id.as_str())).count(), HINTS.len());
+        }
+    }
+    Ok(())
+}
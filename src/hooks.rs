@@ -0,0 +1,174 @@
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use duct::{Expression, cmd};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{self, Code};
+use crate::manifest::{self, Manifest};
+use crate::repo;
+use crate::ui;
+
+/// Shell commands declared under `[hooks]` in `dfl.toml`, run at the same points
+/// as the scripts of the same name in `hooks/`, after them.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+    /// Runs before `add` with the paths being added as arguments, and stops it by failing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_add: Option<String>,
+    /// Runs before `sync`, and stops it by failing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_sync: Option<String>,
+    /// Runs after `sync`; failing is only a warning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_sync: Option<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        *self == Hooks::default()
+    }
+
+    /// Returns the command declared for the hook `name`, such as `pre-add`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "pre-add" => self.pre_add.as_deref(),
+            "pre-sync" => self.pre_sync.as_deref(),
+            "post-sync" => self.post_sync.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Returns how to run the command declared for the hook `name` in `dfl.toml`
+/// with `args`, which it sees as `$1`, `$2`, and so on.
+fn declared(dfl_path: &Path, name: &str, args: &[PathBuf]) -> io::Result<Option<(String, Expression)>> {
+    let manifest = manifest::load(dfl_path)?;
+    let Some(command) = manifest.hooks.get(name) else {
+        return Ok(None);
+    };
+    let mut shell_args: Vec<OsString> = vec!["-c".into(), command.into(), "sh".into()];
+    shell_args.extend(args.iter().map(|arg| arg.clone().into_os_string()));
+    Ok(Some((command.to_string(), cmd("sh", shell_args))))
+}
+
+/// Returns the hook script `name` from the repository's `hooks/` directory, or
+/// `None` if there is no such hook. A hook that exists but is not executable is an
+/// error, since silently skipping it would hide a check the user relies on.
+fn find(dfl_path: &Path, name: &str) -> io::Result<Option<PathBuf>> {
+    let hook = dfl_path.join(repo::HOOKS_DIR).join(name);
+    let Ok(metadata) = hook.metadata() else {
+        return Ok(None);
+    };
+    if metadata.permissions().mode() & 0o111 == 0 {
+        return Err(errors::error(Code::HookFailed, format!("The {} hook '{}' is not executable. Run 'chmod +x {}' to enable it.", name, hook.display(), hook.display())));
+    }
+    Ok(Some(hook))
+}
+
+/// Describes how a hook exited, e.g. "exited with status 1".
+fn describe(status: std::process::ExitStatus) -> String {
+    status.code().map_or_else(|| "was killed by a signal".to_string(), |code| format!("exited with status {}", code))
+}
+
+/// Runs the hook script `name` from the repository's `hooks/` directory, if there is
+/// one, and then the command `dfl.toml` declares for it, with the repository as
+/// their working directory. Their output goes straight to the terminal. A hook that
+/// is missing counts as success; one that cannot run or exits non-zero is reported
+/// as a warning. Returns whether every hook succeeded.
+pub fn run(dfl_path: &Path, name: &str) -> io::Result<bool> {
+    let mut succeeded = true;
+    match find(dfl_path, name) {
+        Ok(Some(hook)) => succeeded &= run_one(dfl_path, name, &hook.display().to_string(), cmd!(&hook))?,
+        Ok(None) => {}
+        Err(e) => {
+            ui::warn(&e.to_string())?;
+            succeeded = false;
+        }
+    }
+    if let Some((command, expression)) = declared(dfl_path, name, &[])? {
+        succeeded &= run_one(dfl_path, name, &command, expression)?;
+    }
+    Ok(succeeded)
+}
+
+/// Runs one hook, the script or command `shown` in messages, for `run`.
+fn run_one(dfl_path: &Path, name: &str, shown: &str, expression: Expression) -> io::Result<bool> {
+    ui::say(&format!("🪝 Running the {} hook...", name));
+    let output = expression.dir(dfl_path).env("DFL_DIR", dfl_path).env("DFL_HOOK", name).unchecked().run();
+    match output {
+        Ok(output) if output.status.success() => {
+            ui::say(&format!("✅ The {} hook finished.", name));
+            Ok(true)
+        }
+        Ok(output) => {
+            ui::warn(&format!("The {} hook {}.", name, describe(output.status)))?;
+            Ok(false)
+        }
+        Err(e) => {
+            ui::warn(&format!("Could not run the {} hook '{}': {}", name, shown, e))?;
+            Ok(false)
+        }
+    }
+}
+
+/// Runs the hook script `name` and then the command `dfl.toml` declares for it
+/// before an operation, passing `args` to them. A hook that exits non-zero vetoes
+/// the operation: the returned error carries whatever it wrote to stderr, so it
+/// can explain why.
+pub fn check(dfl_path: &Path, name: &str, args: &[PathBuf]) -> io::Result<()> {
+    if let Some(hook) = find(dfl_path, name)? {
+        check_one(dfl_path, name, &hook.display().to_string(), cmd(&hook, args))?;
+    }
+    if let Some((command, expression)) = declared(dfl_path, name, args)? {
+        check_one(dfl_path, name, &command, expression)?;
+    }
+    Ok(())
+}
+
+/// Runs one vetoing hook, the script or command `shown` in messages, for `check`.
+fn check_one(dfl_path: &Path, name: &str, shown: &str, expression: Expression) -> io::Result<()> {
+    let output = expression
+        .dir(dfl_path)
+        .env("DFL_DIR", dfl_path)
+        .env("DFL_HOOK", name)
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .map_err(|e| errors::error(Code::HookFailed, format!("Could not run the {} hook '{}': {}", name, shown, e)))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() {
+        eprint!("{}", stderr);
+        return Ok(());
+    }
+
+    let mut message = format!("The {} hook {}, so nothing was changed.", name, describe(output.status));
+    if !stderr.trim().is_empty() {
+        message.push_str(&format!("\n{}", stderr.trim_end()));
+    }
+    Err(errors::error(Code::HookFailed, message))
+}
+
+/// Runs the reload action of every `[reload]` pattern in `dfl.toml` that matches one
+/// of the `changed` repository-relative paths. A pattern matches the path itself,
+/// anything below it, or, as a glob, any path it describes. Each command runs once
+/// through `sh -c` in the home directory; failures are reported as warnings.
+pub fn run_reloads(manifest: &Manifest, home_dir: &Path, changed: &[PathBuf]) -> io::Result<()> {
+    for (pattern, command) in &manifest.reload {
+        let glob = glob::Pattern::new(pattern).map_err(|e| errors::error(Code::InvalidConfig, format!("'{}' in the [reload] table of dfl.toml is not a valid pattern: {}", pattern, e)))?;
+        let Some(file) = changed.iter().find(|file| file.starts_with(pattern) || glob.matches_path(file)) else {
+            continue;
+        };
+
+        ui::say(&format!("🔄 '{}' changed, running: {}", file.display(), command));
+        match cmd!("sh", "-c", command).dir(home_dir).unchecked().run() {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => ui::warn(&format!("The reload action for '{}' {}.", pattern, describe(output.status)))?,
+            Err(e) => ui::warn(&format!("Could not run the reload action for '{}': {}", pattern, e))?,
+        }
+    }
+    Ok(())
+}
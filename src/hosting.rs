@@ -0,0 +1,285 @@
+use std::env;
+use std::io;
+use std::path::Path;
+
+use duct::cmd;
+use serde::Deserialize;
+
+use crate::errors::{self, Code};
+use crate::git;
+use crate::hints;
+use crate::repo;
+use crate::ssh;
+use crate::ui::{self, message_box};
+
+/// Name of the repository created when none is given.
+const DEFAULT_REPOSITORY: &str = "dotfiles";
+
+const REPOSITORY_DESCRIPTION: &str = "My dotfiles, managed with dfl";
+
+/// A repository a provider created, with the URLs to reach it.
+#[derive(Debug, Deserialize)]
+pub struct CreatedRepository {
+    pub full_name: String,
+    pub html_url: String,
+    pub clone_url: String,
+    pub ssh_url: String,
+}
+
+/// A Git host dfl can create repositories on through its API.
+pub trait Provider {
+    fn name(&self) -> &'static str;
+
+    /// The instance used without `--url`, or `None` for hosts that are only self-hosted.
+    fn default_url(&self) -> Option<&'static str>;
+
+    /// Environment variables the API token is read from, in order.
+    fn token_vars(&self) -> &'static [&'static str];
+
+    /// What the token needs to be allowed to do, for prompts and errors.
+    fn token_scope(&self) -> &'static str;
+
+    /// Creates a repository on the account the token belongs to.
+    fn create(&self, base_url: &str, token: &str, name: &str, public: bool) -> io::Result<CreatedRepository>;
+}
+
+/// The error body the hosts answer failed requests with. GitLab sends an object
+/// of messages per field instead of a string.
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    message: serde_json::Value,
+}
+
+/// Returns an API token from the first of `vars` that is set, or asks for one in a
+/// terminal. The token is never echoed or stored.
+fn token(host: &str, vars: &[&str], scope_hint: &str) -> io::Result<String> {
+    if let Some(token) = vars.iter().filter_map(|var| env::var(var).ok()).find(|token| !token.trim().is_empty()) {
+        return Ok(token.trim().to_string());
+    }
+    if !ui::interactive() {
+        return Err(errors::error(Code::HostingFailed, format!("No {} token found. Set {} to a token {}.", host, vars.join(" or "), scope_hint)));
+    }
+    println!("dfl needs a {} access token {}. It is used for this request only.", host, scope_hint);
+    dialoguer::Password::new()
+        .with_prompt(format!("{} token", host))
+        .interact()
+        .map(|token| token.trim().to_string())
+        .map_err(|e| errors::error(Code::InvalidArgument, format!("The token prompt needs a terminal: {}", e)))
+}
+
+/// Sends a JSON request with curl and returns the HTTP status and body. Headers,
+/// which carry the token, go through stdin so they never show up in `ps`.
+fn request(method: &str, url: &str, headers: &[String], body: &serde_json::Value) -> io::Result<(u16, String)> {
+    let output = cmd!("curl", "-sS", "-X", method, "-H", "@-", "-H", "Content-Type: application/json", "-d", body.to_string(), "-w", "\n%{http_code}", url)
+        .stdin_bytes(headers.join("\n"))
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .map_err(|e| errors::error(Code::HostingFailed, format!("Error running curl, which is needed to talk to the API: {}", e)))?;
+    if !output.status.success() {
+        return Err(errors::error(Code::HostingFailed, format!("Could not reach {}: {}", url, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    let response = String::from_utf8_lossy(&output.stdout);
+    let (body, status) = response.rsplit_once('\n').unwrap_or(("", &response));
+    let status = status.trim().parse().map_err(|_| errors::error(Code::HostingFailed, format!("Unexpected response from {}.", url)))?;
+    Ok((status, body.to_string()))
+}
+
+/// Turns a failed API answer into an error that says what to do about it.
+fn failure(provider: &dyn Provider, status: u16, response: &str, name: &str) -> io::Error {
+    let message = match serde_json::from_str::<ApiError>(response).map(|error| error.message) {
+        Ok(serde_json::Value::String(message)) => message,
+        Ok(serde_json::Value::Object(fields)) => fields
+            .iter()
+            .map(|(field, problems)| match problems.as_array() {
+                Some(problems) => format!("{} {}", field, problems.iter().filter_map(|problem| problem.as_str()).collect::<Vec<_>>().join(", ")),
+                None => format!("{} {}", field, problems),
+            })
+            .collect::<Vec<_>>()
+            .join("; "),
+        Ok(message) => message.to_string(),
+        Err(_) => response.trim().to_string(),
+    };
+    let host = provider.name();
+    match status {
+        401 => errors::error(Code::HostingFailed, format!("{} rejected the token. Check that it is correct and has not expired.", host)),
+        403 | 404 => errors::error(Code::HostingFailed, format!("The token may not create repositories on {} ({}). It needs {}.", host, message, provider.token_scope())),
+        400 | 409 | 422 => errors::error(Code::HostingFailed, format!("{} refused to create '{}' ({}). If it already exists, connect it with 'dfl remote add <url>', or pass another name.", host, name, message)),
+        _ => errors::error(Code::HostingFailed, format!("{} answered {}: {}", host, status, message)),
+    }
+}
+
+pub struct GitHub;
+
+impl Provider for GitHub {
+    fn name(&self) -> &'static str {
+        "GitHub"
+    }
+
+    fn default_url(&self) -> Option<&'static str> {
+        Some("https://github.com")
+    }
+
+    fn token_vars(&self) -> &'static [&'static str] {
+        &["GH_TOKEN", "GITHUB_TOKEN"]
+    }
+
+    fn token_scope(&self) -> &'static str {
+        "the 'repo' scope"
+    }
+
+    fn create(&self, base_url: &str, token: &str, name: &str, public: bool) -> io::Result<CreatedRepository> {
+        // GitHub Enterprise serves the API below the instance instead of on its own host.
+        let api = if base_url == "https://github.com" { "https://api.github.com".to_string() } else { format!("{}/api/v3", base_url) };
+        let headers = [format!("Authorization: Bearer {}", token), "Accept: application/vnd.github+json".to_string()];
+        let body = serde_json::json!({ "name": name, "private": !public, "description": REPOSITORY_DESCRIPTION });
+        let (status, response) = request("POST", &format!("{}/user/repos", api), &headers, &body)?;
+        if status != 201 {
+            return Err(failure(self, status, &response, name));
+        }
+        serde_json::from_str(&response).map_err(|e| errors::error(Code::HostingFailed, format!("Unexpected response from GitHub: {}", e)))
+    }
+}
+
+/// The parts of GitLab's answer to creating a project that dfl uses.
+#[derive(Debug, Deserialize)]
+struct GitLabProject {
+    path_with_namespace: String,
+    web_url: String,
+    http_url_to_repo: String,
+    ssh_url_to_repo: String,
+}
+
+pub struct GitLab;
+
+impl Provider for GitLab {
+    fn name(&self) -> &'static str {
+        "GitLab"
+    }
+
+    fn default_url(&self) -> Option<&'static str> {
+        Some("https://gitlab.com")
+    }
+
+    fn token_vars(&self) -> &'static [&'static str] {
+        &["GITLAB_TOKEN"]
+    }
+
+    fn token_scope(&self) -> &'static str {
+        "the 'api' scope"
+    }
+
+    fn create(&self, base_url: &str, token: &str, name: &str, public: bool) -> io::Result<CreatedRepository> {
+        let headers = [format!("PRIVATE-TOKEN: {}", token)];
+        let body = serde_json::json!({ "name": name, "path": name, "visibility": if public { "public" } else { "private" }, "description": REPOSITORY_DESCRIPTION });
+        let (status, response) = request("POST", &format!("{}/api/v4/projects", base_url), &headers, &body)?;
+        if status != 201 {
+            return Err(failure(self, status, &response, name));
+        }
+        let project: GitLabProject = serde_json::from_str(&response).map_err(|e| errors::error(Code::HostingFailed, format!("Unexpected response from GitLab: {}", e)))?;
+        Ok(CreatedRepository { full_name: project.path_with_namespace, html_url: project.web_url, clone_url: project.http_url_to_repo, ssh_url: project.ssh_url_to_repo })
+    }
+}
+
+pub struct Gitea;
+
+impl Provider for Gitea {
+    fn name(&self) -> &'static str {
+        "Gitea"
+    }
+
+    fn default_url(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn token_vars(&self) -> &'static [&'static str] {
+        &["GITEA_TOKEN"]
+    }
+
+    fn token_scope(&self) -> &'static str {
+        "the 'write:repository' scope"
+    }
+
+    fn create(&self, base_url: &str, token: &str, name: &str, public: bool) -> io::Result<CreatedRepository> {
+        let headers = [format!("Authorization: token {}", token)];
+        let body = serde_json::json!({ "name": name, "private": !public, "description": REPOSITORY_DESCRIPTION });
+        let (status, response) = request("POST", &format!("{}/api/v1/user/repos", base_url), &headers, &body)?;
+        if status != 201 {
+            return Err(failure(self, status, &response, name));
+        }
+        // Gitea answers with the same fields as GitHub.
+        serde_json::from_str(&response).map_err(|e| errors::error(Code::HostingFailed, format!("Unexpected response from Gitea: {}", e)))
+    }
+}
+
+/// Looks up a supported Git host by the name used on the command line.
+pub fn provider(name: &str) -> Option<&'static dyn Provider> {
+    match name {
+        "github" => Some(&GitHub),
+        "gitlab" => Some(&GitLab),
+        "gitea" => Some(&Gitea),
+        _ => None,
+    }
+}
+
+/// Handles the 'remote create' command.
+/// It creates an empty private repository on a Git host through its API, connects
+/// it as a remote, and pushes the current branch there, so nothing has to be set
+/// up in the host's web interface. `base_url` points at a self-hosted instance;
+/// `https` picks the HTTPS URL over SSH.
+pub fn handle_remote_create_command(host: &str, base_url: Option<&str>, repository: Option<&str>, remote: &str, public: bool, https: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' first."));
+    }
+    let Some(provider) = provider(host) else {
+        return Err(errors::error(Code::InvalidArgument, format!("Unknown host '{}'. 'dfl remote create' supports: github, gitlab, gitea.", host)));
+    };
+    let Some(base_url) = base_url.or(provider.default_url()) else {
+        return Err(errors::error(Code::InvalidArgument, format!("{} is self-hosted; pass the address of your instance with --url, e.g. --url https://git.example.com.", provider.name())));
+    };
+    let base_url = base_url.trim_end_matches('/');
+    if let Some(url) = git::remote_url(&dfl_path, remote)? {
+        return Err(errors::error(Code::RemoteExists, format!("Remote '{}' already points to {}. Pass --name <other> to add the new repository alongside it.", remote, url)));
+    }
+    let name = repository.unwrap_or(DEFAULT_REPOSITORY);
+
+    let token = token(provider.name(), provider.token_vars(), &format!("with {} for {}", provider.token_scope(), base_url))?;
+    let created = provider.create(base_url, &token, name, public)?;
+    ui::say(&format!("✅ Created {} repository {} ({}).", if public { "public" } else { "private" }, created.full_name, created.html_url));
+
+    let url = if https { &created.clone_url } else { &created.ssh_url };
+    if let Err(e) = git::add_remote(&dfl_path, remote, url) {
+        return Err(errors::error(Code::GitFailed, format!("Error adding remote {}: {}", remote, e)));
+    }
+    ui::say(&format!("✅ Remote '{}' added: {}", remote, url));
+
+    push_initial(&dfl_path, remote, &created, https)?;
+    message_box("Remote Created", &format!("Your dotfiles now live at {}. On another machine, run 'dfl clone {}'.", created.html_url, url));
+    Ok(())
+}
+
+/// Pushes the current branch to a freshly created remote and makes it the
+/// upstream. A failed push only warns, since the repository exists either way.
+fn push_initial(dfl_path: &Path, remote: &str, created: &CreatedRepository, https: bool) -> io::Result<()> {
+    if git::head(dfl_path)?.is_none() {
+        ui::say("Nothing to push yet; 'dfl add' your first dotfile and then run 'dfl push'.");
+        return Ok(());
+    }
+    let branch = git::branch(dfl_path)?;
+    if let Err(e) = ssh::preflight(dfl_path, remote).and_then(|()| git::push(dfl_path, &branch, remote, &branch, true)) {
+        let hint = if https {
+            "Check that your git credential helper has a login for the host".to_string()
+        } else {
+            format!("Check that your SSH key is added to your account on the host, or switch to HTTPS with 'dfl remote add --force --name {} {}'", remote, created.clone_url)
+        };
+        ui::warn(&format!("The repository was created but could not be pushed to: {}\n{}, then run 'dfl push'.", e, hint))?;
+        return Ok(());
+    }
+    ui::say(&format!("✅ Pushed '{}' to {}.", branch, remote));
+    if let Err(e) = hints::record_use("push") {
+        tracing::warn!("Could not update hint state: {}", e);
+    }
+    Ok(())
+}
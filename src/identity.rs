@@ -0,0 +1,98 @@
+use std::io;
+use std::path::Path;
+
+use crate::errors::{self, Code};
+use crate::git;
+use crate::signing;
+use crate::ui;
+
+/// Name set when no identity is chosen, so commits never wait for one and never
+/// borrow the global identity, which may be a work one.
+const PLACEHOLDER_NAME: &str = "Dotfile Manager";
+
+/// Email set along with `PLACEHOLDER_NAME`.
+const PLACEHOLDER_EMAIL: &str = "dfl-bot@example.com";
+
+/// Who commits to the dfl repository, as given to `init`. Anything left out is asked for in a
+/// terminal, or otherwise kept as it is.
+#[derive(Debug, Default)]
+pub struct Identity {
+    /// Value for `user.name`.
+    pub name: Option<String>,
+    /// Value for `user.email`.
+    pub email: Option<String>,
+    /// GPG key ID or SSH public key to sign commits with.
+    pub signing_key: Option<String>,
+}
+
+impl Identity {
+    /// Returns true if nothing was given on the command line.
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.email.is_none() && self.signing_key.is_none()
+    }
+}
+
+/// Returns the repository's own value of `key`, or `None` if it is unset or the placeholder.
+fn local(dfl_path: &Path, key: &str) -> io::Result<Option<String>> {
+    Ok(git::get_local_config(dfl_path, key)?.filter(|value| value != PLACEHOLDER_NAME && value != PLACEHOLDER_EMAIL))
+}
+
+/// Asks for a value in the terminal, suggesting the repository's own value or else
+/// the global one. `optional` values may be left empty.
+fn ask(dfl_path: &Path, prompt: &str, key: &str, optional: bool) -> io::Result<Option<String>> {
+    let current = match local(dfl_path, key)? {
+        Some(value) => Some(value),
+        None => git::get_config(dfl_path, key)?.filter(|value| value != PLACEHOLDER_NAME && value != PLACEHOLDER_EMAIL),
+    };
+    let mut input = dialoguer::Input::<String>::new().with_prompt(prompt).allow_empty(optional);
+    if let Some(current) = current {
+        input = input.default(current);
+    }
+    let answer = input.interact_text().map_err(|e| errors::error(Code::InvalidArgument, format!("The identity prompt needs a terminal: {}", e)))?;
+    Ok(Some(answer.trim().to_string()).filter(|answer| !answer.is_empty()))
+}
+
+/// Sets who commits to the repository in its own git configuration, so a global
+/// identity such as a work one never ends up on dotfile commits. With `interactive`,
+/// the name, email, and signing key are asked for; whatever stays unset falls back
+/// to a placeholder rather than the global identity.
+pub fn configure(dfl_path: &Path, identity: &Identity, interactive: bool) -> io::Result<()> {
+    let interactive = interactive && identity.is_empty() && ui::interactive();
+    let (name, email, signing_key) = if interactive {
+        println!("Commits to your dotfiles use their own git identity, separate from your global one.");
+        (
+            ask(dfl_path, "Name for dotfile commits", "user.name", false)?,
+            ask(dfl_path, "Email for dotfile commits", "user.email", false)?,
+            ask(dfl_path, "Key to sign them with (GPG key ID or SSH public key file; empty to not sign)", "user.signingkey", true)?,
+        )
+    } else {
+        (identity.name.clone(), identity.email.clone(), identity.signing_key.clone())
+    };
+    if name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+        return Err(errors::error(Code::InvalidArgument, "The name for dotfile commits cannot be empty."));
+    }
+    if email.as_deref().is_some_and(|email| !email.contains('@')) {
+        return Err(errors::error(Code::InvalidArgument, format!("'{}' is not an email address.", email.unwrap_or_default())));
+    }
+
+    let set = |key: &str, value: &str| git::set_config(dfl_path, key, value).map_err(|e| errors::error(Code::GitFailed, format!("Error configuring git {}: {}", key, e)));
+    for (key, value, placeholder) in [("user.name", &name, PLACEHOLDER_NAME), ("user.email", &email, PLACEHOLDER_EMAIL)] {
+        match value {
+            Some(value) => set(key, value)?,
+            // A repository that has no identity of its own yet gets the placeholder.
+            None if git::get_local_config(dfl_path, key)?.is_none() => set(key, placeholder)?,
+            None => {}
+        }
+    }
+    if let Some(key) = &signing_key {
+        set("user.signingkey", key)?;
+        set("commit.gpgsign", "true")?;
+        let ssh = key.starts_with("ssh-") || Path::new(key).extension().is_some_and(|extension| extension == signing::SSH_PUBLIC_KEY);
+        set("gpg.format", if ssh { "ssh" } else { "openpgp" })?;
+    }
+
+    if let (Some(name), Some(email)) = (local(dfl_path, "user.name")?, local(dfl_path, "user.email")?) {
+        ui::say(&format!("✅ Commits to your dotfiles are by {} <{}>, whatever your global git identity is.", name, email));
+    }
+    Ok(())
+}
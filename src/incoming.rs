@@ -0,0 +1,167 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::mirror;
+use crate::offline;
+use crate::repo;
+use crate::ui::{self, message_box};
+
+/// Returns the repository path, or an error if there is no repository or remote yet.
+fn dfl_path() -> io::Result<PathBuf> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+    if git::remotes(&dfl_path)?.is_empty() {
+        return Err(errors::error(Code::NoRemote, "No remote configured. Please run 'dfl remote add <url>' first."));
+    }
+    Ok(dfl_path)
+}
+
+/// Downloads new commits without touching the working tree. Offline, it only
+/// warns, so what was fetched before can still be looked at.
+fn fetch(dfl_path: &Path) -> io::Result<()> {
+    mirror::refresh_upstream(dfl_path)?;
+    ui::say("Fetching...");
+    if let Err(e) = git::fetch(dfl_path) {
+        let branch = git::branch(dfl_path)?;
+        let remote = git::upstream(dfl_path, &branch)?.map(|(remote, _)| remote).unwrap_or_else(|| "origin".to_string());
+        if offline::is_unreachable(dfl_path, &remote) {
+            return ui::warn(&format!("You are offline: {} cannot be reached. Showing what was fetched before.", remote));
+        }
+        return Err(errors::error(Code::PullFailed, format!("Error fetching from remote: {}", e)));
+    }
+    Ok(())
+}
+
+/// Handles the 'fetch' command.
+/// It downloads new commits from the remote without changing the checked-out files or the home
+/// directory, and says how many a pull would bring in.
+pub fn handle_fetch_command() -> io::Result<()> {
+    let dfl_path = dfl_path()?;
+    fetch(&dfl_path)?;
+    match git::incoming(&dfl_path)? {
+        0 => ui::say("✅ Nothing new to pull."),
+        count => ui::say(&format!("✅ {} commit(s) to pull. Run 'dfl incoming' to see what they change.", count)),
+    }
+    Ok(())
+}
+
+/// What the 'incoming' command prints with `--json`. `commits` and `files` are
+/// empty when the branch tracks nothing, nothing was fetched yet, or there are no
+/// local commits to compare with.
+#[derive(Debug, Serialize)]
+struct Incoming {
+    branch: String,
+    upstream: Option<Tracked>,
+    commits: Vec<String>,
+    files: Vec<IncomingFile>,
+    local_commits: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Tracked {
+    remote: String,
+    branch: String,
+}
+
+/// A file a pull would change. `status` is `A`, `D`, or `M`; `added` and
+/// `removed` are `null` for binary files.
+#[derive(Debug, Serialize)]
+struct IncomingFile {
+    path: String,
+    status: char,
+    added: Option<usize>,
+    removed: Option<usize>,
+}
+
+/// Collects what a pull would bring in, for `--json`.
+fn incoming(dfl_path: &Path) -> io::Result<Incoming> {
+    let branch = git::branch(dfl_path)?;
+    let mut incoming = Incoming { upstream: None, commits: Vec::new(), files: Vec::new(), local_commits: 0, branch };
+    let Some((remote, remote_branch)) = git::upstream(dfl_path, &incoming.branch)? else {
+        return Ok(incoming);
+    };
+    incoming.upstream = Some(Tracked { remote, branch: remote_branch });
+    let (Some(theirs), Some(ours)) = (git::upstream_head(dfl_path, &incoming.branch)?, git::head(dfl_path)?) else {
+        return Ok(incoming);
+    };
+    incoming.commits = git::subjects(dfl_path, &ours, &theirs)?;
+    incoming.local_commits = git::count_commits(dfl_path, Some(&theirs), &ours)?;
+    if !incoming.commits.is_empty() {
+        let base = git::merge_base(dfl_path, &ours, &theirs)?.unwrap_or_else(|| ours.clone());
+        incoming.files = git::diffstat(dfl_path, &base, &theirs)?
+            .into_iter()
+            .map(|stat| IncomingFile { status: stat.status, added: stat.lines.map(|(added, _)| added), removed: stat.lines.map(|(_, removed)| removed), path: stat.path })
+            .collect();
+    }
+    Ok(incoming)
+}
+
+/// Handles the 'incoming' command.
+/// It compares the current branch with the commits last fetched from its remote
+/// branch, fetching first with `fetch`, and shows what a pull would bring in: the
+/// commit messages and how much each file changes, or all of that as JSON with
+/// `json`. Nothing is changed.
+pub fn handle_incoming_command(fetch_first: bool, json: bool) -> io::Result<()> {
+    let dfl_path = dfl_path()?;
+    if fetch_first {
+        let _redirect = if json { Some(ui::StdoutToStderr::new()?) } else { None };
+        fetch(&dfl_path)?;
+    }
+    if json {
+        return ui::print_json(&incoming(&dfl_path)?);
+    }
+
+    let branch = git::branch(&dfl_path)?;
+    let Some((remote, remote_branch)) = git::upstream(&dfl_path, &branch)? else {
+        println!("'{}' does not track a remote branch yet, so a pull has nothing to bring in. 'dfl push' sets that up.", branch);
+        return Ok(());
+    };
+    let Some(theirs) = git::upstream_head(&dfl_path, &branch)? else {
+        println!("Nothing was fetched from {}/{} yet. Run 'dfl fetch' first.", remote, remote_branch);
+        return Ok(());
+    };
+    let Some(ours) = git::head(&dfl_path)? else {
+        println!("There are no local commits yet; a pull brings in everything on {}/{}.", remote, remote_branch);
+        return Ok(());
+    };
+
+    let subjects = git::subjects(&dfl_path, &ours, &theirs)?;
+    if subjects.is_empty() {
+        let as_of = if fetch_first { "" } else { " as of the last fetch; 'dfl incoming --fetch' checks the remote first" };
+        println!("✅ Nothing incoming: '{}' has everything on {}/{}{}.", branch, remote, remote_branch, as_of);
+        return Ok(());
+    }
+
+    // Files are compared from where the histories split, so local commits do not show up as incoming.
+    let base = git::merge_base(&dfl_path, &ours, &theirs)?.unwrap_or_else(|| ours.clone());
+    let stats = git::diffstat(&dfl_path, &base, &theirs)?;
+    let as_of = if fetch_first { "" } else { " (as of the last fetch)" };
+    let mut summary = vec![format!("{} commit(s) on {}/{}{}:", subjects.len(), remote, remote_branch, as_of)];
+    summary.extend(subjects.iter().map(|subject| format!("  - {}", subject)));
+    let (added, removed) = stats.iter().filter_map(|stat| stat.lines).fold((0, 0), |(a, r), (added, removed)| (a + added, r + removed));
+    summary.push(String::new());
+    summary.push(format!("{} file(s) change, {} line(s) added, {} removed:", stats.len(), added, removed));
+    let width = stats.iter().map(|stat| stat.path.len()).max().unwrap_or(0);
+    for stat in &stats {
+        match stat.lines {
+            Some((added, removed)) => summary.push(format!("  {:width$}  +{} -{}", stat.path, added, removed)),
+            None => summary.push(format!("  {:width$}  (binary)", stat.path)),
+        }
+    }
+    let local = git::count_commits(&dfl_path, Some(&theirs), &ours)?;
+    if local > 0 {
+        let how = if config::load()?.pull_rebase { "replayed on top of them" } else { "merged with them" };
+        summary.push(String::new());
+        summary.push(format!("Your {} local commit(s) are {}.", local, how));
+    }
+    message_box("Incoming Changes", &summary.join("\n"));
+    println!("Run 'dfl pull' or 'dfl up' to apply them.");
+    Ok(())
+}
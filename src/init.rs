@@ -0,0 +1,148 @@
+use std::io;
+use std::path::Path;
+
+use crate::bare;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::githooks;
+use crate::identity;
+use crate::layout::LayoutKind;
+use crate::manifest::{self, Manifest};
+use crate::mirror;
+use crate::profile;
+use crate::repo;
+use crate::signing;
+use crate::sparse;
+use crate::ui::{self, message_box};
+use crate::verify;
+
+/// Handles the 'init' command.
+/// It creates the repository directory and initializes a Git repository inside it,
+/// or with `bare`, makes it a bare repository tracking files in place in $HOME.
+/// It also gives the repository a git identity of its own, asked for when
+/// `interactive` and none is given, or a placeholder so commits never hang.
+pub fn handle_init_command(layout: Option<LayoutKind>, identity: &identity::Identity, interactive: bool, bare: bool) -> io::Result<()> {
+    message_box("Initializing dfl...", "Creating repository directory and initializing Git.");
+
+    let dfl_path = repo::dfl_path()?;
+
+    if bare {
+        if dfl_path.exists() {
+            return Err(errors::error(Code::InvalidArgument, format!("{} already exists. Bare mode starts a new repository; move it aside first.", dfl_path.display())));
+        }
+        if let Err(e) = bare::init(&dfl_path, &repo::home_dir()?) {
+            return Err(errors::error(Code::GitFailed, format!("Error initializing git repository: {}", e)));
+        }
+        ui::say(&format!("✅ Created a bare repository at {:?}; your dotfiles stay where they are, without symlinks.", dfl_path));
+    } else {
+        if let Err(e) = std::fs::create_dir_all(&dfl_path) {
+            return Err(errors::error(Code::InitFailed, format!("Error creating directory: {}", e)));
+        };
+        ui::say(&format!("✅ Created directory: {:?}", dfl_path));
+
+        if let Err(e) = git::init(&dfl_path) {
+            return Err(errors::error(Code::GitFailed, format!("Error initializing git repository: {}", e)));
+        }
+    }
+    let branch = config::load()?.default_branch;
+    if !branch.is_empty() && git::head(&dfl_path)?.is_none() {
+        git::set_initial_branch(&dfl_path, &branch).map_err(|e| errors::error(Code::InvalidConfig, format!("Could not start the repository on default_branch '{}': {}", branch, e)))?;
+    }
+    identity::configure(&dfl_path, identity, interactive)?;
+    githooks::install_reporting(&dfl_path)?;
+    if let Some(url) = profile::remote(&config::load()?)
+        && git::remotes(&dfl_path)?.is_empty()
+    {
+        git::add_remote(&dfl_path, "origin", &url).map_err(|e| errors::error(Code::GitFailed, format!("Error adding the profile's remote: {}", e)))?;
+        ui::say(&format!("✅ Added the profile's remote as origin: {}", url));
+    }
+
+    ui::say("✅ Git repository initialized and configured.");
+
+    // A key that cannot sign would only surface at the first commit otherwise.
+    match signing::check(&dfl_path) {
+        Ok(Some(key)) => ui::say(&format!("✅ Commits will be signed with the {}.", key)),
+        Ok(None) => {}
+        Err(e) => ui::warn(&format!("Commit signing is enabled but does not work: {}\nCommits will fail until this is fixed.", e))?,
+    }
+
+    // Files in a bare repository stay where they are, so there is no layout to choose.
+    if bare {
+        message_box("dfl Initialized", &format!("You can now add your dotfiles; they are committed where they are. Your repository is at: {}", repo::display(&dfl_path)));
+        return Ok(());
+    }
+
+    // An existing manifest already decides the layout; changing it means moving files.
+    if dfl_path.join(manifest::MANIFEST_FILE).exists() {
+        let existing = manifest::load(&dfl_path)?.layout;
+        if let Some(kind) = layout.filter(|kind| *kind != existing) {
+            return Err(errors::error(Code::InvalidArgument, format!("This repository already uses the {} layout. Run 'dfl layout convert {}' to change it.", existing, kind)));
+        }
+    } else if layout.is_some() || repo::managed_entries(&dfl_path)?.is_empty() {
+        // New repositories mirror $HOME so nested dotfiles keep their paths. Older
+        // repositories without a manifest keep the flat layout they were created with.
+        let kind = layout.unwrap_or(LayoutKind::Home);
+        manifest::save(&dfl_path, &Manifest { layout: kind, ..Manifest::default() })?;
+        ui::say(&format!("✅ Using the {} repository layout.", kind));
+    }
+
+    message_box("dfl Initialized", &format!("You can now add your dotfiles. Your repository is at: {:?}", dfl_path));
+
+    Ok(())
+}
+
+/// Handles the 'clone' command.
+/// It clones an existing dotfiles repository into the repository directory. With `depth` only the
+/// latest commits are downloaded; later pulls fetch older history if they need it.
+pub fn handle_clone_command(url: &str, depth: Option<u32>, filter: Option<&str>, bare: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if dfl_path.exists() {
+        return Err(errors::error(Code::InvalidArgument, format!("{} already exists. Run 'dfl pull' to update it, or move it aside to clone again.", dfl_path.display())));
+    }
+
+    message_box("Cloning", &format!("Downloading your dotfiles from {}.", url));
+    if bare {
+        bare::clone(&dfl_path, &repo::home_dir()?, url, depth, filter).map_err(|e| errors::error(Code::PullFailed, format!("Error cloning {}: {}", url, e)))?;
+        githooks::install_reporting(&dfl_path)?;
+        verify::record_checksums(&dfl_path)?;
+        ui::say(&format!("✅ Cloned into {}, with the files checked out in place in your home directory.", dfl_path.display()));
+        return Ok(());
+    }
+    if let Err(e) = git::clone(&dfl_path, url, depth, filter) {
+        return Err(errors::error(Code::PullFailed, format!("Error cloning {}: {}", url, e)));
+    }
+    sparse::check_out(&dfl_path)?;
+    finish_clone(&dfl_path)
+}
+
+/// Handles 'clone --mirror'.
+/// It clones the repository from a mirror other machines push to, for machines
+/// that cannot reach the git host. Pulls download the mirror again first.
+pub fn handle_clone_mirror_command(mirror: &mirror::Mirror) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if dfl_path.exists() {
+        return Err(errors::error(Code::InvalidArgument, format!("{} already exists. Run 'dfl pull' to update it, or move it aside to clone again.", dfl_path.display())));
+    }
+
+    message_box("Cloning", &format!("Downloading your dotfiles from the mirror at {}.", mirror.target()));
+    let remote = mirror::clone(&dfl_path, mirror)?;
+    ui::say(&format!("✅ The mirror is the remote '{}'; 'dfl pull' downloads it again. Pushes go to the git host, added with 'dfl remote add <url>'.", remote));
+    finish_clone(&dfl_path)
+}
+
+/// Checks out submodules, installs dfl's git hooks, and records checksums after a clone.
+fn finish_clone(dfl_path: &Path) -> io::Result<()> {
+    if let Err(e) = git::update_submodules(dfl_path) {
+        return Err(errors::error(Code::PullFailed, format!("Error checking out submodules: {}", e)));
+    }
+    githooks::install_reporting(dfl_path)?;
+    verify::record_checksums(dfl_path)?;
+    ui::say(&format!("✅ Cloned into {}.", dfl_path.display()));
+    if git::is_shallow(dfl_path)? {
+        ui::say("This is a shallow clone; older history is fetched when a pull or squash needs it.");
+    }
+
+    message_box("Success", "Run 'dfl sync' to link your dotfiles, or 'dfl bootstrap' to also install your plugin managers.");
+    Ok(())
+}
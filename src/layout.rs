@@ -0,0 +1,355 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use ignore::gitignore::Gitignore;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::git;
+use crate::manifest::{self, MANIFEST_FILE, Manifest};
+use crate::repo;
+use crate::ui::{self, message_box};
+
+/// Package used by the package layout when none is given.
+pub const DEFAULT_PACKAGE: &str = "default";
+
+/// The available repository layout strategies, as written in `dfl.toml`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutKind {
+    /// Every entry sits at the top of the repository under its file name.
+    #[default]
+    Flat,
+    /// Entries mirror their path relative to `$HOME`, e.g. `.config/kitty/kitty.conf`.
+    Home,
+    /// Entries are grouped into packages, each mirroring `$HOME`, e.g. `shell/.bashrc`.
+    Package,
+}
+
+impl LayoutKind {
+    /// Returns the strategy implementing this layout.
+    pub fn layout(self) -> &'static dyn Layout {
+        match self {
+            LayoutKind::Flat => &FlatLayout,
+            LayoutKind::Home => &HomeLayout,
+            LayoutKind::Package => &PackageLayout,
+        }
+    }
+}
+
+impl fmt::Display for LayoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LayoutKind::Flat => "flat",
+            LayoutKind::Home => "home",
+            LayoutKind::Package => "package",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for LayoutKind {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(LayoutKind::Flat),
+            "home" => Ok(LayoutKind::Home),
+            "package" => Ok(LayoutKind::Package),
+            other => Err(errors::error(Code::InvalidArgument, format!("Unknown layout '{}'. Expected 'flat', 'home', or 'package'.", other))),
+        }
+    }
+}
+
+/// Maps between where a dotfile is deployed (relative to `$HOME`) and where it
+/// is stored (relative to the repository). Commands only talk to this trait, so
+/// they work the same whichever layout a repository uses.
+pub trait Layout {
+    fn kind(&self) -> LayoutKind;
+
+    /// Returns the repository-relative path that stores the dotfile deployed at `target`.
+    fn store_path(&self, target: &Path, package: Option<&str>) -> io::Result<PathBuf>;
+
+    /// Returns the home-relative path the stored entry is deployed to.
+    fn target_path(&self, stored: &Path) -> PathBuf;
+
+    /// Returns the package a stored entry belongs to, for layouts that have packages.
+    fn package(&self, _stored: &Path) -> Option<String> {
+        None
+    }
+
+    /// Lists the repository-relative paths of all managed entries: those recorded in
+    /// the manifest, plus any file in the repository that none of them covers.
+    fn entries(&self, dfl_path: &Path, manifest: &Manifest) -> io::Result<Vec<PathBuf>> {
+        let rules = repo::ignore_rules(dfl_path)?;
+        let mut entries: Vec<PathBuf> = manifest.entries.iter().filter(|stored| !repo::is_ignored(&rules, dfl_path, stored)).cloned().collect();
+        collect_uncovered(dfl_path, Path::new(""), manifest, &rules, &mut entries)?;
+        entries.sort();
+        Ok(entries)
+    }
+
+    /// Records a newly stored entry in the manifest.
+    fn register(&self, manifest: &mut Manifest, stored: &Path) {
+        if !manifest.entries.iter().any(|e| e == stored) {
+            manifest.entries.push(stored.to_path_buf());
+            manifest.entries.sort();
+        }
+    }
+}
+
+pub struct FlatLayout;
+
+impl Layout for FlatLayout {
+    fn kind(&self) -> LayoutKind {
+        LayoutKind::Flat
+    }
+
+    fn store_path(&self, target: &Path, _package: Option<&str>) -> io::Result<PathBuf> {
+        let file_name = target.file_name().ok_or_else(|| errors::error(Code::InvalidArgument, "Invalid file path"))?;
+        Ok(PathBuf::from(file_name))
+    }
+
+    fn target_path(&self, stored: &Path) -> PathBuf {
+        stored.to_path_buf()
+    }
+
+    fn entries(&self, dfl_path: &Path, manifest: &Manifest) -> io::Result<Vec<PathBuf>> {
+        let rules = repo::ignore_rules(dfl_path)?;
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dfl_path)? {
+            let file_name = entry?.file_name();
+            // Mapped entries are deployed to their own targets, not under `$HOME`.
+            if repo::is_reserved(Path::new(&file_name)) || manifest.paths.contains_key(Path::new(&file_name)) || repo::is_ignored(&rules, dfl_path, Path::new(&file_name)) {
+                continue;
+            }
+            entries.push(PathBuf::from(file_name));
+        }
+        entries.sort();
+        Ok(entries)
+    }
+
+    fn register(&self, _manifest: &mut Manifest, _stored: &Path) {}
+}
+
+pub struct HomeLayout;
+
+impl Layout for HomeLayout {
+    fn kind(&self) -> LayoutKind {
+        LayoutKind::Home
+    }
+
+    fn store_path(&self, target: &Path, _package: Option<&str>) -> io::Result<PathBuf> {
+        Ok(target.to_path_buf())
+    }
+
+    fn target_path(&self, stored: &Path) -> PathBuf {
+        stored.to_path_buf()
+    }
+}
+
+pub struct PackageLayout;
+
+impl Layout for PackageLayout {
+    fn kind(&self) -> LayoutKind {
+        LayoutKind::Package
+    }
+
+    fn store_path(&self, target: &Path, package: Option<&str>) -> io::Result<PathBuf> {
+        let package = package.unwrap_or(DEFAULT_PACKAGE);
+        if package.is_empty() || package.contains('/') || package.starts_with('.') {
+            return Err(errors::error(Code::InvalidArgument, format!("Invalid package name '{}'.", package)));
+        }
+        Ok(Path::new(package).join(target))
+    }
+
+    fn target_path(&self, stored: &Path) -> PathBuf {
+        stored.components().skip(1).collect()
+    }
+
+    fn package(&self, stored: &Path) -> Option<String> {
+        stored.components().next().map(|c| c.as_os_str().to_string_lossy().into_owned())
+    }
+}
+
+/// Adds every file below `relative` that no manifest entry or path mapping covers,
+/// such as files committed by hand or by an older dfl, so they are deployed at their
+/// nested paths instead of being ignored.
+fn collect_uncovered(dfl_path: &Path, relative: &Path, manifest: &Manifest, rules: &Gitignore, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dfl_path.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        let covered = |stored: &PathBuf| path.starts_with(stored);
+        if repo::is_reserved(&path) || manifest.entries.iter().any(covered) || manifest.paths.keys().any(covered) || repo::is_ignored(rules, dfl_path, &path) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_uncovered(dfl_path, &path, manifest, rules, found)?;
+        } else {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Resolves `path` to a path relative to `home_dir` without following a symlink
+/// at the final component. Returns `None` for paths outside the home directory.
+pub fn home_relative(path: &Path, home_dir: &Path) -> io::Result<Option<PathBuf>> {
+    let absolute = if path.is_absolute() { path.to_path_buf() } else { std::env::current_dir()?.join(path) };
+    let file_name = match absolute.components().next_back() {
+        Some(Component::Normal(name)) => name.to_owned(),
+        _ => return Err(errors::error(Code::InvalidArgument, "Invalid file path")),
+    };
+    let parent = absolute.parent().ok_or_else(|| errors::error(Code::InvalidArgument, "Invalid file path"))?;
+    let resolved = parent.canonicalize()?.join(file_name);
+    let home = home_dir.canonicalize()?;
+
+    Ok(resolved.strip_prefix(&home).ok().map(Path::to_path_buf))
+}
+
+/// What the 'layout' command does.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum LayoutCommand {
+    /// Moves every entry into a different layout, relinks it, and commits.
+    Convert {
+        layout: LayoutKind,
+        /// The package entries go into when converting to the package layout.
+        #[arg(long, value_name = "NAME")]
+        package: Option<String>,
+    },
+}
+
+/// Handles the 'layout' command.
+/// Without arguments it prints the current layout; `layout convert <layout>`
+/// moves every entry to its place in the new layout, relinks it, and commits.
+pub fn handle_layout_command(command: Option<LayoutCommand>) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let manifest = manifest::load(&dfl_path)?;
+    match command {
+        None => {
+            println!("Repository layout: {}", manifest.layout);
+            Ok(())
+        }
+        Some(LayoutCommand::Convert { layout, package }) => convert(&dfl_path, manifest, layout, package.as_deref()),
+    }
+}
+
+fn convert(dfl_path: &Path, mut manifest: Manifest, kind: LayoutKind, package: Option<&str>) -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let old = manifest.layout.layout();
+    let new = kind.layout();
+    if old.kind() == new.kind() {
+        ui::say(&format!("The repository already uses the {} layout.", kind));
+        return Ok(());
+    }
+
+    message_box("Converting layout", &format!("Moving entries from the {} layout to the {} layout.", old.kind(), kind));
+
+    // Plan every move up front so a collision aborts before anything is touched.
+    let mut moves = Vec::new();
+    let mut planned = BTreeSet::new();
+    for stored in old.entries(dfl_path, &manifest)? {
+        let target = old.target_path(&stored);
+        let entry_package = package.map(str::to_string).or_else(|| old.package(&stored));
+        let new_stored = new.store_path(&target, entry_package.as_deref())?;
+        if !planned.insert(new_stored.clone()) {
+            return Err(errors::error(Code::LayoutCollision, format!("More than one entry would be stored at '{}' in the {} layout.", new_stored.display(), kind)));
+        }
+        moves.push((stored, new_stored, target));
+    }
+    let moving: BTreeSet<&PathBuf> = moves.iter().map(|(stored, _, _)| stored).collect();
+    for (stored, new_stored, _) in &moves {
+        if stored != new_stored && dfl_path.join(new_stored).exists() && !moving.contains(new_stored) {
+            return Err(errors::error(Code::LayoutCollision, format!("'{}' already exists in the repository.", new_stored.display())));
+        }
+    }
+
+    // Move through temporary names first, so entries can swap places without clobbering each other.
+    let staging = dfl_path.join(".dfl-convert");
+    fs::create_dir_all(&staging)?;
+    for (index, (stored, _, _)) in moves.iter().enumerate() {
+        fs::rename(dfl_path.join(stored), staging.join(index.to_string()))?;
+        remove_empty_parents(dfl_path, &dfl_path.join(stored));
+    }
+
+    // Recorded file modes move with their entries.
+    manifest.permissions = std::mem::take(&mut manifest.permissions)
+        .into_iter()
+        .map(|(path, mode)| {
+            let moved = moves.iter().find_map(|(stored, new_stored, _)| {
+                let rest = path.strip_prefix(stored).ok()?;
+                Some(if rest.as_os_str().is_empty() { new_stored.clone() } else { new_stored.join(rest) })
+            });
+            (moved.unwrap_or(path), mode)
+        })
+        .collect();
+    manifest.entries.clear();
+    for (index, (stored, new_stored, target)) in moves.iter().enumerate() {
+        let destination = dfl_path.join(new_stored);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(staging.join(index.to_string()), &destination)?;
+        new.register(&mut manifest, new_stored);
+
+        // Re-point the deployed link, but only if it is one of ours, keeping its style.
+        let link_path = home_dir.join(target);
+        if repo::points_to(&link_path, &dfl_path.join(stored)) {
+            let relative = fs::read_link(&link_path)?.is_relative();
+            fs::remove_file(&link_path)?;
+            fsutil::symlink_to(&destination, &link_path, relative)?;
+        }
+        if stored != new_stored {
+            ui::say(&format!("✅ Moved '{}' to '{}'", stored.display(), new_stored.display()));
+        }
+    }
+    fs::remove_dir(&staging)?;
+
+    manifest.layout = kind;
+    manifest::save(dfl_path, &manifest)?;
+
+    ui::say("Automatically committing changes...");
+    if let Err(e) = git::add_all(dfl_path) {
+        return Err(errors::error(Code::GitFailed, format!("Error staging changes: {}", e)));
+    }
+    let moved: Vec<PathBuf> = moves.into_iter().filter(|(stored, new_stored, _)| stored != new_stored).map(|(_, new_stored, _)| new_stored).collect();
+    let message = git::commit_message(&format!("convert to the {} layout", kind), &moved, format!("refactor: Convert repository to the {} layout", kind))?;
+    if let Err(e) = git::commit(dfl_path, &message) {
+        return Err(errors::error(Code::GitFailed, format!("Error committing changes: {}", e)));
+    }
+    ui::say("✅ Changes committed.");
+    if config::load()?.auto_push {
+        crate::remote::push_after_commit(dfl_path)?;
+    }
+
+    message_box("Success", &format!("The repository now uses the {} layout. Run 'dfl sync' on your other machines after pulling.", kind));
+    Ok(())
+}
+
+/// Removes directories left empty between `path` and the repository root.
+pub fn remove_empty_parents(dfl_path: &Path, path: &Path) {
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        if dir == dfl_path || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
+
+/// Sanity check used by `add`: entries must not shadow files dfl keeps at the repository root.
+pub fn check_store_path(stored: &Path) -> io::Result<()> {
+    if repo::is_reserved(stored) {
+        return Err(errors::error(Code::ReservedPath, format!("'{}' is reserved by dfl and cannot be managed ({} and .git live at the repository root).", stored.display(), MANIFEST_FILE)));
+    }
+    Ok(())
+}
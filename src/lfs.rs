@@ -0,0 +1,154 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::ui;
+
+/// File at the repository root that tells git which paths git-lfs stores.
+pub const ATTRIBUTES_FILE: &str = ".gitattributes";
+
+/// Attributes that hand a pattern's files to git-lfs, as `git lfs track` writes them.
+const LFS_ATTRIBUTES: &str = "filter=lfs diff=lfs merge=lfs -text";
+
+/// How much of a file is read to decide whether it is binary, as git does.
+const SNIFF_LEN: usize = 8000;
+
+/// Returns the patterns `.gitattributes` hands to git-lfs.
+fn tracked_patterns(dfl_path: &Path) -> Vec<String> {
+    let attributes = fs::read_to_string(dfl_path.join(ATTRIBUTES_FILE)).unwrap_or_default();
+    attributes.lines().filter(|line| line.split_whitespace().any(|attribute| attribute == "filter=lfs")).filter_map(|line| line.split_whitespace().next()).map(str::to_string).collect()
+}
+
+/// Returns true if the repository stores any files with git-lfs.
+pub fn in_use(dfl_path: &Path) -> bool {
+    !tracked_patterns(dfl_path).is_empty()
+}
+
+/// Returns true if git-lfs stores the file at `path`, relative to the repository.
+pub fn stores(dfl_path: &Path, path: &Path) -> io::Result<bool> {
+    let mut builder = GitignoreBuilder::new(dfl_path);
+    for pattern in tracked_patterns(dfl_path) {
+        builder.add_line(None, &pattern).map_err(io::Error::other)?;
+    }
+    Ok(builder.build().map_err(io::Error::other)?.matched_path_or_any_parents(path, false).is_ignore())
+}
+
+/// Returns the installed git-lfs version, or `None` if it is missing.
+pub fn version() -> Option<String> {
+    cmd!("git", "lfs", "version").stdout_capture().stderr_null().read().ok().map(|version| version.trim().to_string())
+}
+
+/// Returns true if a file looks binary: it has a NUL byte near the start.
+fn is_binary(path: &Path) -> io::Result<bool> {
+    let mut start = Vec::with_capacity(SNIFF_LEN);
+    fs::File::open(path)?.take(SNIFF_LEN as u64).read_to_end(&mut start)?;
+    Ok(start.contains(&0))
+}
+
+/// Collects the binary files of at least `threshold` bytes below each stored path
+/// that git-lfs does not store yet, with their sizes.
+fn large_binaries(dfl_path: &Path, stored: &[PathBuf], threshold: u64) -> io::Result<Vec<(PathBuf, u64)>> {
+    let mut builder = GitignoreBuilder::new(dfl_path);
+    for pattern in tracked_patterns(dfl_path) {
+        builder.add_line(None, &pattern).map_err(io::Error::other)?;
+    }
+    let tracked: Gitignore = builder.build().map_err(io::Error::other)?;
+
+    let mut found = Vec::new();
+    let mut pending: Vec<PathBuf> = stored.iter().map(|path| dfl_path.join(path)).collect();
+    while let Some(path) = pending.pop() {
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            for child in fs::read_dir(&path)? {
+                pending.push(child?.path());
+            }
+        } else if metadata.is_file() && metadata.len() >= threshold && !tracked.matched_path_or_any_parents(&path, false).is_ignore() && is_binary(&path)? {
+            found.push((path.strip_prefix(dfl_path).map_err(io::Error::other)?.to_path_buf(), metadata.len()));
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Returns the pattern a large file is tracked under: every file with its
+/// extension, such as `*.png`, or the file itself without one.
+fn pattern_for(path: &Path) -> String {
+    match path.extension() {
+        Some(extension) => format!("*.{}", extension.to_string_lossy()),
+        None => format!("/{}", path.display()),
+    }
+}
+
+/// Hands `patterns` to git-lfs: enables its filters in the repository and records
+/// the patterns in `.gitattributes`, so the next commit stores the files with it.
+fn track(dfl_path: &Path, patterns: &[String]) -> io::Result<()> {
+    if version().is_none() {
+        return Err(errors::error(Code::GitFailed, "git-lfs is not installed. Install it (e.g. 'apt install git-lfs' or 'brew install git-lfs') to store large files with it."));
+    }
+    cmd!("git", "lfs", "install", "--local").dir(dfl_path).stdout_null().run().map_err(|e| errors::error(Code::GitFailed, format!("Error enabling git-lfs: {}", e)))?;
+
+    let path = dfl_path.join(ATTRIBUTES_FILE);
+    let mut attributes = fs::read_to_string(&path).unwrap_or_default();
+    if !attributes.is_empty() && !attributes.ends_with('\n') {
+        attributes.push('\n');
+    }
+    for pattern in patterns {
+        attributes.push_str(&format!("{} {}\n", pattern, LFS_ATTRIBUTES));
+    }
+    fs::write(&path, attributes)
+}
+
+/// Looks for large binary files among freshly added entries and offers to store
+/// them with git-lfs, keeping them out of the history every clone downloads. With
+/// `always`, they are tracked without asking; outside a terminal, they are only listed.
+pub fn offer(dfl_path: &Path, stored: &[PathBuf], always: bool) -> io::Result<()> {
+    let threshold = config::load()?.lfs_threshold_kb.saturating_mul(1024);
+    let large = large_binaries(dfl_path, stored, threshold)?;
+    if large.is_empty() {
+        return Ok(());
+    }
+
+    // Files with the same extension are offered together, as wallpapers or fonts usually come in sets.
+    let mut groups: Vec<(String, usize, u64)> = Vec::new();
+    for (path, size) in &large {
+        let pattern = pattern_for(path);
+        match groups.iter_mut().find(|(seen, _, _)| *seen == pattern) {
+            Some((_, count, total)) => {
+                *count += 1;
+                *total += size;
+            }
+            None => groups.push((pattern, 1, *size)),
+        }
+    }
+    let labels: Vec<String> = groups.iter().map(|(pattern, count, total)| format!("{} ({} file(s), {})", pattern, count, ui::bytes(*total))).collect();
+
+    let chosen: Vec<usize> = if always {
+        (0..groups.len()).collect()
+    } else if ui::interactive() && version().is_some() {
+        println!("These large binary files would make every clone of your dotfiles slower:");
+        dialoguer::MultiSelect::new()
+            .with_prompt("Store them with git-lfs instead? (space to toggle, enter to confirm)")
+            .items(&labels)
+            .defaults(&vec![true; labels.len()])
+            .interact_opt()
+            .map_err(|e| errors::error(Code::InvalidArgument, format!("The git-lfs prompt needs a terminal: {}", e)))?
+            .unwrap_or_default()
+    } else {
+        ui::say(&format!("These large binary files are committed to git directly: {}.", labels.join(", ")));
+        ui::say("To keep files like these out of your history, install git-lfs and add them with 'dfl add --lfs'.");
+        return Ok(());
+    };
+    if chosen.is_empty() {
+        return Ok(());
+    }
+
+    let patterns: Vec<String> = chosen.iter().map(|index| groups[*index].0.clone()).collect();
+    track(dfl_path, &patterns)?;
+    ui::say(&format!("✅ Storing {} with git-lfs (recorded in {}).", patterns.join(", "), ATTRIBUTES_FILE));
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! dfl manages dotfiles in a Git repository and links them into the home directory.
+//!
+//! The `dfl` command is built on this library. Other tools, such as a GUI or a
+//! provisioning script, can drive a repository through [`DflRepo`], whose
+//! operations return what they did instead of only printing it. The modules hold
+//! the rest of what the commands are made of.
+//!
+//! Operations fail with an `io::Error`; [`errors::dfl_error`] returns the
+//! [`errors::DflError`] inside it, whose kind tells a missing repository, a
+//! conflict, and a network or git failure apart.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::add::AddOptions;
+use crate::errors::Code;
+use crate::layout::LayoutKind;
+use crate::manifest::Manifest;
+use crate::repo::{Entry, LinkStatus};
+use crate::status::Status;
+use crate::sync::{SyncOptions, Tally};
+
+pub mod add;
+pub mod alternate;
+pub mod backup;
+pub mod bare;
+pub mod branch;
+pub mod commit;
+pub mod config;
+pub mod conflict;
+pub mod credentials;
+pub mod doctor;
+pub mod errors;
+pub mod external;
+pub mod fold;
+pub mod fsutil;
+pub mod gc;
+pub mod git;
+pub mod githooks;
+pub mod gitignore;
+pub mod hints;
+pub mod hooks;
+pub mod hosting;
+pub mod identity;
+pub mod incoming;
+pub mod init;
+pub mod layout;
+pub mod lfs;
+pub mod list;
+pub mod lock;
+pub mod logging;
+pub mod machine;
+pub mod manifest;
+pub mod migrate;
+pub mod mirror;
+pub mod mv_entry;
+pub mod offline;
+pub mod perms;
+pub mod plugins;
+pub mod profile;
+pub mod prune;
+pub mod remove;
+pub mod remote;
+pub mod repo;
+pub mod scan;
+pub mod self_update;
+pub mod signing;
+pub mod sparse;
+pub mod squash;
+pub mod ssh;
+pub mod state;
+pub mod status;
+pub mod sync;
+pub mod tag;
+pub mod template;
+pub mod transaction;
+pub mod ui;
+pub mod up;
+pub mod verify;
+
+/// A dfl repository and the dotfiles it deploys into the home directory.
+#[derive(Debug, Clone)]
+pub struct DflRepo {
+    path: PathBuf,
+}
+
+impl DflRepo {
+    /// Opens the repository at `path`.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<DflRepo> {
+        let path = path.into();
+        if !git::git_dir(&path).exists() {
+            return Err(errors::error(Code::NotInitialized, format!("There is no dfl repository at {}.", path.display())));
+        }
+        Ok(DflRepo { path })
+    }
+
+    /// Opens the repository the `dfl` command uses: the one in `$DFL_DIR`, at
+    /// `repo_path` in the user configuration, or at the default location.
+    pub fn discover() -> io::Result<DflRepo> {
+        DflRepo::open(repo::dfl_path()?)
+    }
+
+    /// Creates a repository at `path` that mirrors the home directory, or opens
+    /// the one already there.
+    pub fn init(path: impl Into<PathBuf>) -> io::Result<DflRepo> {
+        let path = path.into();
+        fs::create_dir_all(&path)?;
+        git::init(&path)?;
+        if !path.join(manifest::MANIFEST_FILE).exists() {
+            manifest::save(&path, &Manifest { layout: LayoutKind::Home, ..Manifest::default() })?;
+        }
+        Ok(DflRepo { path })
+    }
+
+    /// Returns where the repository is.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the entries that apply to this machine, with where each is deployed.
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        repo::managed_entries(&self.path)
+    }
+
+    /// Returns each entry with whether it is deployed.
+    pub fn links(&self) -> io::Result<Vec<(Entry, LinkStatus)>> {
+        let entries = self.entries()?;
+        let statuses = repo::link_statuses(&entries)?;
+        Ok(entries.into_iter().zip(statuses).collect())
+    }
+
+    /// Returns the branch, how it compares with its upstream, and the uncommitted changes.
+    pub fn status(&self) -> io::Result<Status> {
+        status::status(&self.path)
+    }
+
+    /// Moves `source` into the repository and deploys it back in its place, without
+    /// committing. Returns where it is stored, or `None` if it already was.
+    pub fn add(&self, source: &Path, options: &AddOptions) -> io::Result<Option<PathBuf>> {
+        add::store(&self.path, source, options)
+    }
+
+    /// Stops managing the entry deployed at `target`, leaving a plain file there,
+    /// without committing. Returns where it was stored.
+    pub fn remove(&self, target: &Path) -> io::Result<PathBuf> {
+        remove::remove(&self.path, target)
+    }
+
+    /// Deploys every entry, or those `options.only` names, and returns what was done.
+    pub fn sync(&self, options: &SyncOptions) -> io::Result<Tally> {
+        sync::sync(&self.path, options)
+    }
+
+    /// Commits every change in the repository with `message`. Returns false if
+    /// there was nothing to commit.
+    pub fn commit(&self, message: &str) -> io::Result<bool> {
+        if git::local_changes(&self.path)?.is_empty() {
+            return Ok(false);
+        }
+        git::add_all(&self.path)?;
+        git::commit(&self.path, message)?;
+        Ok(true)
+    }
+}
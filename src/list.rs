@@ -0,0 +1,146 @@
+use std::io;
+
+use serde::Serialize;
+
+use crate::errors::{self, Code};
+use crate::git;
+use crate::manifest;
+use crate::offline;
+use crate::repo::{self, LinkMode, LinkStatus};
+use crate::state;
+use crate::ui::{self, Tone};
+
+/// What the 'list' command prints with `--json`.
+#[derive(Debug, Serialize)]
+struct Listing {
+    layout: String,
+    entries: Vec<ListedEntry>,
+    queued: Vec<Queued>,
+    stale_submodules: Vec<StaleSubmodule>,
+}
+
+/// One managed entry. `status` is one of `linked`, `missing`, `conflict`,
+/// `wrong_target` (with `links_to`), or `diverged`, or for a bare repository
+/// `tracked`, `modified`, or `deleted`.
+#[derive(Debug, Serialize)]
+pub(crate) struct ListedEntry {
+    pub(crate) target: String,
+    pub(crate) stored: String,
+    pub(crate) package: Option<String>,
+    pub(crate) mode: Option<&'static str>,
+    pub(crate) status: &'static str,
+    pub(crate) links_to: Option<String>,
+    pub(crate) restored: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Queued {
+    pub(crate) branch: String,
+    pub(crate) commits: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct StaleSubmodule {
+    pub(crate) path: String,
+    pub(crate) reason: String,
+}
+
+/// Handles the 'list' command.
+/// It prints every managed entry with where it is deployed and whether its link is in place,
+/// or prints the same as JSON with `json`.
+pub fn handle_list_command(json: bool) -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let dfl_path = repo::dfl_path()?;
+
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let manifest = manifest::load(&dfl_path)?;
+    let layout = manifest.layout.layout();
+    let entries = repo::managed_entries(&dfl_path)?;
+    let restored = state::load_repo(&dfl_path)?.restored;
+    if json {
+        let mut listed = Vec::new();
+        for (entry, status) in entries.iter().zip(repo::link_statuses(&entries)?) {
+            let stored = entry.stored.strip_prefix(&dfl_path).unwrap_or(&entry.stored);
+            let package = if manifest.paths.contains_key(stored) { None } else { layout.package(stored) };
+            let mode = match entry.mode {
+                LinkMode::Symlink => "symlink",
+                LinkMode::Copy => "copy",
+                LinkMode::Hardlink => "hardlink",
+                LinkMode::Template => "template",
+            };
+            let (status, links_to) = match status {
+                LinkStatus::Linked => ("linked", None),
+                LinkStatus::Missing => ("missing", None),
+                LinkStatus::Conflict => ("conflict", None),
+                LinkStatus::WrongTarget(other) => ("wrong_target", Some(other.display().to_string())),
+                LinkStatus::Diverged => ("diverged", None),
+            };
+            listed.push(ListedEntry {
+                target: entry.target.display().to_string(),
+                stored: stored.display().to_string(),
+                package,
+                mode: Some(mode),
+                status,
+                links_to,
+                restored: restored.contains(&entry.target),
+            });
+        }
+        return print_listing(&dfl_path, layout.kind().to_string(), listed);
+    }
+    if entries.is_empty() {
+        println!("No dotfiles are managed yet. Add one with 'dfl add <path>'.");
+        return Ok(());
+    }
+
+    let mut rows = Vec::new();
+    for (entry, status) in entries.iter().zip(repo::link_statuses(&entries)?) {
+        let stored = entry.stored.strip_prefix(&dfl_path).unwrap_or(&entry.stored);
+        let target = entry.target.strip_prefix(&home_dir).map(|t| format!("~/{}", t.display())).unwrap_or_else(|_| entry.target.display().to_string());
+        let (tone, status) = match status {
+            LinkStatus::Linked if entry.mode == LinkMode::Copy => (Tone::Ok, "copied".to_string()),
+            LinkStatus::Linked if entry.mode == LinkMode::Hardlink => (Tone::Ok, "hard-linked".to_string()),
+            LinkStatus::Linked if entry.mode == LinkMode::Template => (Tone::Ok, "rendered".to_string()),
+            LinkStatus::Linked => (Tone::Ok, "linked".to_string()),
+            LinkStatus::Diverged if entry.mode == LinkMode::Hardlink => (Tone::Conflict, "diverged: no longer hard-linked to the repository".to_string()),
+            LinkStatus::Diverged if entry.mode == LinkMode::Template => (Tone::Conflict, "diverged: differs from the rendered template".to_string()),
+            LinkStatus::Diverged => (Tone::Conflict, "diverged: the copy differs from the repository".to_string()),
+            LinkStatus::Missing => (Tone::Broken, "not linked".to_string()),
+            LinkStatus::Conflict if restored.contains(&entry.target) => (Tone::Conflict, "conflict: restored from a backup".to_string()),
+            LinkStatus::Conflict => (Tone::Conflict, "conflict: a regular file is in the way".to_string()),
+            LinkStatus::WrongTarget(other) => (Tone::Conflict, format!("conflict: links to {}", other.display())),
+        };
+        let package = if manifest.paths.contains_key(stored) { None } else { layout.package(stored) };
+        let package = package.map(|package| format!("[{}]", package)).unwrap_or_default();
+        rows.push((tone, package, target, stored.display().to_string(), status));
+    }
+    let width = |column: fn(&(Tone, String, String, String, String)) -> &String| rows.iter().map(|row| column(row).chars().count()).max().unwrap_or(0);
+    let (package_width, target_width, stored_width) = (width(|row| &row.1), width(|row| &row.2), width(|row| &row.3));
+    for (tone, package, target, stored, status) in &rows {
+        let package = if package_width > 0 { format!("{:package_width$} ", package) } else { String::new() };
+        println!("{} {}{:target_width$} -> {:stored_width$}  {}", ui::mark(*tone), package, target, stored, ui::paint(*tone, status));
+    }
+    println!("\n{} managed entries ({} layout).", entries.len(), layout.kind());
+
+    for (branch, count) in offline::queued(&dfl_path)? {
+        println!("\nOffline: {} commit(s) on '{}' are queued and not pushed yet (run 'dfl push' once you are back online).", count, branch);
+    }
+
+    let stale = git::stale_submodules(&dfl_path)?;
+    if !stale.is_empty() {
+        println!("\nSubmodules out of date (run 'dfl sync' to update them):");
+        for (path, reason) in stale {
+            println!("  {} ({})", path, reason);
+        }
+    }
+    Ok(())
+}
+
+/// Prints a listing as JSON, with the offline queue and stale submodules of the repository at `dfl_path`.
+pub(crate) fn print_listing(dfl_path: &std::path::Path, layout: String, entries: Vec<ListedEntry>) -> io::Result<()> {
+    let queued = offline::queued(dfl_path)?.into_iter().map(|(branch, commits)| Queued { branch, commits }).collect();
+    let stale_submodules = git::stale_submodules(dfl_path)?.into_iter().map(|(path, reason)| StaleSubmodule { path, reason: reason.to_string() }).collect();
+    ui::print_json(&Listing { layout, entries, queued, stale_submodules })
+}
@@ -0,0 +1,75 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::errors::{self, Code};
+use crate::git;
+use crate::repo;
+
+/// Name of the lock file, kept in the git directory so it is never committed.
+const LOCK_FILE: &str = "dfl.lock";
+
+/// Environment variable naming the lock file a dfl process holds, so the hooks and
+/// scripts it runs can call dfl on the same repository without waiting for it.
+pub const HELD_VARIABLE: &str = "DFL_LOCK";
+
+/// Commands that only read the repository, which run while another command holds the lock.
+pub const READ_ONLY: &[&str] = &["status", "list", "backups", "incoming", "doctor", "explain-error", "hints", "self-update"];
+
+/// The lock on a repository, held until it is dropped or the process exits.
+#[derive(Debug)]
+pub struct RepoLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl RepoLock {
+    /// Returns the lock file, for `HELD_VARIABLE`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Returns who holds the lock, as written in the lock file, for messages.
+fn holder(path: &Path) -> String {
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    match contents.trim().split_once(' ') {
+        Some((pid, command)) => format!(" (process {}, '{}')", pid, command),
+        None => String::new(),
+    }
+}
+
+/// Takes the advisory lock on the repository at `dfl_path` for `command`, so
+/// two dfl processes, such as a sync from cron and an 'add' typed meanwhile,
+/// never change the repository and the links at the same time. When another
+/// process holds it, this fails, or with `wait`, blocks until it is released.
+/// Returns `None` when there is no repository yet, or when this process runs
+/// under a dfl process that already holds the lock.
+pub fn acquire(dfl_path: &Path, command: &str, wait: bool) -> io::Result<Option<RepoLock>> {
+    let git_dir = git::git_dir(dfl_path);
+    if !git_dir.is_dir() {
+        return Ok(None);
+    }
+    let path = git_dir.join(LOCK_FILE);
+    if std::env::var_os(HELD_VARIABLE).is_some_and(|held| Path::new(&held) == path) {
+        return Ok(None);
+    }
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(fs::TryLockError::WouldBlock) if wait => {
+            tracing::warn!("Waiting for another dfl operation on {}{} to finish.", repo::display(dfl_path), holder(&path));
+            file.lock()?;
+        }
+        Err(fs::TryLockError::WouldBlock) => {
+            return Err(errors::error(
+                Code::Locked,
+                format!("Another dfl operation is in progress on {}{}. Try again once it has finished, or pass --wait to start then.", repo::display(dfl_path), holder(&path)),
+            ));
+        }
+        Err(fs::TryLockError::Error(e)) => return Err(e),
+    }
+    file.set_len(0)?;
+    writeln!(file, "{} dfl {}", std::process::id(), command)?;
+    Ok(Some(RepoLock { _file: file, path }))
+}
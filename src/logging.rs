@@ -0,0 +1,70 @@
+use std::fmt;
+use std::io;
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::FmtContext;
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::ui;
+
+/// Writes log events as plain lines, prefixed by how serious they are, since they
+/// are read by people next to dfl's own output rather than collected.
+struct Plain;
+
+impl<S, N> FormatEvent<S, N> for Plain
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: format::Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        match *event.metadata().level() {
+            Level::ERROR => write!(writer, "Error: ")?,
+            Level::WARN => write!(writer, "Warning: ")?,
+            Level::INFO => {}
+            Level::DEBUG => write!(writer, "debug: ")?,
+            Level::TRACE => write!(writer, "trace: ")?,
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Writes what commands report through `ui::say` as it is, since it is their output.
+struct Bare;
+
+impl<S, N> FormatEvent<S, N> for Bare
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: format::Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Returns the most detailed level shown: errors only with `quiet`, warnings by
+/// default, and each `-v` one level more, down to every git command and file
+/// operation at `-vv`.
+pub fn level(verbose: u8, quiet: bool) -> Level {
+    match (quiet, verbose) {
+        (true, _) => Level::ERROR,
+        (false, 0) => Level::WARN,
+        (false, 1) => Level::INFO,
+        (false, 2) => Level::DEBUG,
+        (false, _) => Level::TRACE,
+    }
+}
+
+/// Sends log events at `level` and above to standard error for the rest of the
+/// process, and what commands report to standard output unless `quiet`.
+pub fn init(level: Level, quiet: bool) {
+    let output = tracing_subscriber::fmt::layer().with_writer(io::stdout).event_format(Bare).with_filter(filter_fn(move |metadata| !quiet && metadata.target() == ui::OUTPUT));
+    let log = tracing_subscriber::fmt::layer().with_writer(io::stderr).event_format(Plain).with_filter(filter_fn(move |metadata| metadata.target() != ui::OUTPUT && *metadata.level() <= level));
+    // Only fails if a subscriber is already set, e.g. by a program using the library; that one is kept.
+    let _ = tracing_subscriber::registry().with(output).with(log).try_init();
+}
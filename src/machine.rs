@@ -0,0 +1,246 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::alternate;
+use crate::branch;
+use crate::conflict;
+use crate::errors::{self, Code};
+use crate::config;
+use crate::git;
+use crate::mirror;
+use crate::repo;
+use crate::state;
+use crate::ui;
+
+/// Prefix of the branches machines commit to while they keep their changes to themselves.
+const PREFIX: &str = "machine/";
+
+/// Returns the branch this machine commits to, `machine/` and its short hostname.
+fn own_branch() -> io::Result<String> {
+    let hostname = alternate::hostname();
+    let short = hostname.split('.').next().unwrap_or_default().to_ascii_lowercase();
+    if short.is_empty() {
+        return Err(errors::error(Code::InvalidArgument, "Could not find out this machine's hostname to name its branch."));
+    }
+    Ok(format!("{}{}", PREFIX, short))
+}
+
+/// Returns the shared branch if this machine commits to its own branch and has it
+/// checked out, `None` otherwise.
+fn active(dfl_path: &Path) -> io::Result<Option<String>> {
+    let Some(shared) = state::load_repo(dfl_path)?.shared_branch else {
+        return Ok(None);
+    };
+    Ok((git::branch(dfl_path)? == own_branch()?).then_some(shared))
+}
+
+/// Returns the repository path, or an error if there is no repository yet.
+fn dfl_path() -> io::Result<PathBuf> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+    Ok(dfl_path)
+}
+
+/// Brings what other machines promoted to the shared branch into this machine's
+/// branch, after `pull` and `up` pulled the machine branch itself. Nothing happens
+/// unless this machine commits to its own branch.
+pub fn pull_shared(dfl_path: &Path) -> io::Result<()> {
+    let Some(shared) = active(dfl_path)? else {
+        return Ok(());
+    };
+    git::pull_keeping_changes(dfl_path, Some(&shared), false, conflict::merge_resolver())
+        .map_err(|e| errors::error(errors::code_or(&e, Code::PullFailed), format!("Error merging the shared branch '{}': {}", shared, e)))
+}
+
+/// Starts committing to this machine's own branch, created from the current one,
+/// which becomes the shared branch that `promote` merges into.
+fn start(dfl_path: &Path) -> io::Result<()> {
+    let own = own_branch()?;
+    let shared = git::branch(dfl_path)?;
+    if shared.starts_with(PREFIX) {
+        return Err(errors::error(Code::InvalidArgument, format!("'{}' is a machine branch. Switch to the branch every machine shares first, e.g. with 'dfl branch switch main'.", shared)));
+    }
+    let failed = |action: &str, e: io::Error| errors::error(Code::GitFailed, format!("Error {}: {}", action, e));
+    if !git::branches(dfl_path)?.contains(&own) {
+        git::create_branch(dfl_path, &own).map_err(|e| failed("creating the machine branch", e))?;
+    }
+    git::switch(dfl_path, &own).map_err(|e| failed("switching branches", e))?;
+    let mut state = state::load_repo(dfl_path)?;
+    state.shared_branch = Some(shared.clone());
+    state::save_repo(dfl_path, &state)?;
+    ui::say(&format!("✅ This machine now commits to '{}'. 'dfl promote' moves its changes to '{}', and pulls bring in what other machines promoted.", own, shared));
+    branch::after_checkout(dfl_path)
+}
+
+/// Goes back to committing to the shared branch. The machine branch is kept, so
+/// anything not promoted yet can still be merged.
+fn stop(dfl_path: &Path) -> io::Result<()> {
+    let mut state = state::load_repo(dfl_path)?;
+    let Some(shared) = state.shared_branch.take() else {
+        return Err(errors::error(Code::InvalidArgument, "This machine already commits to the shared branch."));
+    };
+    git::switch(dfl_path, &shared).map_err(|e| errors::error(Code::GitFailed, format!("Error switching branches: {}", e)))?;
+    state::save_repo(dfl_path, &state)?;
+    ui::say(&format!("✅ This machine commits to '{}' again. '{}' is kept; 'dfl branch merge {}' brings over what was not promoted.", shared, own_branch()?, own_branch()?));
+    branch::after_checkout(dfl_path)
+}
+
+/// Shows which branch this machine commits to and how many of its commits the
+/// shared branch does not have yet.
+fn status(dfl_path: &Path) -> io::Result<()> {
+    let Some(shared) = state::load_repo(dfl_path)?.shared_branch else {
+        println!("This machine commits to the shared branch '{}'. 'dfl machine start' gives it a branch of its own.", git::branch(dfl_path)?);
+        return Ok(());
+    };
+    let own = own_branch()?;
+    let (Some(head), Some(base)) = (git::find_commit(dfl_path, &format!("refs/heads/{}", own))?, git::find_commit(dfl_path, &format!("refs/heads/{}", shared))?) else {
+        println!("This machine commits to '{}'; the shared branch is '{}'.", own, shared);
+        return Ok(());
+    };
+    let pending = git::count_commits(dfl_path, Some(&base), &head)?;
+    println!("This machine commits to '{}'; the shared branch is '{}'.", own, shared);
+    println!("{} commit(s) not promoted yet.", pending);
+    Ok(())
+}
+
+/// What the 'machine' command does.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum MachineCommand {
+    /// Shows which branch this machine commits to (the default).
+    Status,
+    /// Gives this machine a branch of its own, named after its hostname, to commit to.
+    Start,
+    /// Goes back to committing to the shared branch.
+    Stop,
+}
+
+/// Handles the 'machine' command.
+/// `start` gives this machine a `machine/<hostname>` branch to commit to, so its
+/// changes stay on it until promoted; `stop` goes back to the shared branch; and
+/// without a subcommand it shows which branch this machine commits to.
+pub fn handle_machine_command(command: Option<MachineCommand>) -> io::Result<()> {
+    let dfl_path = dfl_path()?;
+    match command.unwrap_or(MachineCommand::Status) {
+        MachineCommand::Status => status(&dfl_path),
+        MachineCommand::Start => start(&dfl_path),
+        MachineCommand::Stop => stop(&dfl_path),
+    }
+}
+
+/// Handles the 'merge-from' command.
+/// It fetches the branch another machine commits to and merges it into the
+/// current branch, to try out that machine's changes here before they are promoted.
+pub fn handle_merge_from_command(host: &str) -> io::Result<()> {
+    let dfl_path = dfl_path()?;
+    let source = format!("{}{}", PREFIX, host.to_ascii_lowercase());
+    git::pull_keeping_changes(&dfl_path, Some(&source), false, conflict::merge_resolver())
+        .map_err(|e| errors::error(errors::code_or(&e, Code::PullFailed), format!("Error merging '{}': {}", source, e)))?;
+    ui::say(&format!("✅ Merged '{}' into the current branch.", source));
+    branch::after_checkout(&dfl_path)
+}
+
+/// Handles the 'promote' command.
+/// It merges this machine's branch into the shared branch and pushes that, so
+/// every machine gets the changes with its next pull. The shared branch is checked
+/// out only while merging; this machine's branch then takes in the merge.
+pub fn handle_promote_command() -> io::Result<()> {
+    let dfl_path = dfl_path()?;
+    let Some(shared) = active(&dfl_path)? else {
+        return Err(errors::error(Code::InvalidArgument, "This machine does not commit to a branch of its own. Run 'dfl machine start' first."));
+    };
+    if git::has_local_changes(&dfl_path)? {
+        return Err(errors::error(Code::InvalidArgument, format!("There are uncommitted changes in {}. Commit them before promoting.", repo::display(&dfl_path))));
+    }
+    let own = own_branch()?;
+    git::switch(&dfl_path, &shared).map_err(|e| errors::error(Code::GitFailed, format!("Error switching to '{}': {}", shared, e)))?;
+    let promoted = merge_and_push(&dfl_path, &shared, &own);
+    // Back on this machine's branch whatever happened, so its files are what stays linked.
+    git::switch(&dfl_path, &own).map_err(|e| errors::error(Code::GitFailed, format!("Error switching back to '{}': {}", own, e)))?;
+    promoted?;
+    git::merge(&dfl_path, &shared, conflict::merge_resolver()).map_err(|e| errors::error(errors::code_or(&e, Code::GitFailed), format!("Error merging '{}' back into '{}': {}", shared, own, e)))?;
+    ui::say(&format!("✅ Promoted '{}' to '{}'. Other machines get the changes with their next pull.", own, shared));
+    mirror::publish(&dfl_path)?;
+    branch::after_checkout(&dfl_path)
+}
+
+/// Brings the checked-out shared branch up to date, merges `own` into it, and pushes it.
+fn merge_and_push(dfl_path: &Path, shared: &str, own: &str) -> io::Result<()> {
+    let upstream = git::upstream(dfl_path, shared)?;
+    if upstream.is_some() {
+        git::pull(dfl_path, None, false, conflict::merge_resolver()).map_err(|e| errors::error(errors::code_or(&e, Code::PullFailed), format!("Error pulling '{}': {}", shared, e)))?;
+    }
+    git::merge(dfl_path, own, conflict::merge_resolver()).map_err(|e| errors::error(errors::code_or(&e, Code::GitFailed), format!("Error merging '{}' into '{}': {}", own, shared, e)))?;
+    let (remote, remote_branch) = upstream.unwrap_or_else(|| ("origin".to_string(), shared.to_string()));
+    git::push(dfl_path, shared, &remote, &remote_branch, git::upstream(dfl_path, shared)?.is_none())
+        .map_err(|e| errors::error(Code::PushFailed, format!("Error pushing '{}': {}", shared, e)))
+}
+
+/// Returns the repository-relative paths `name` may stand for: the name as given,
+/// and for a path in the home directory, where the home layout stores it and where
+/// a managed entry deployed there is stored.
+fn candidate_paths(dfl_path: &Path, name: &str) -> io::Result<Vec<String>> {
+    let mut candidates = vec![name.trim_start_matches("./").trim_end_matches('/').to_string()];
+    let home_dir = repo::home_dir()?;
+    let expanded = repo::expand_home(Path::new(name), &home_dir);
+    if expanded.is_absolute() {
+        let target = std::path::absolute(&expanded)?;
+        if let Ok(relative) = target.strip_prefix(&home_dir) {
+            candidates.push(relative.to_string_lossy().into_owned());
+        }
+        for entry in repo::managed_entries(dfl_path)? {
+            if entry.target == target && let Ok(stored) = entry.stored.strip_prefix(dfl_path) {
+                candidates.push(stored.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(candidates)
+}
+
+/// Handles the 'pick' command.
+/// It fetches the branch another machine commits to and brings over just one file
+/// or directory as it is there, or just one of its commits, as a new commit on the
+/// current branch, without merging anything else.
+pub fn handle_pick_command(host: &str, name: &str) -> io::Result<()> {
+    let dfl_path = dfl_path()?;
+    if git::has_local_changes(&dfl_path)? {
+        return Err(errors::error(Code::InvalidArgument, format!("There are uncommitted changes in {}. Commit them before picking.", repo::display(&dfl_path))));
+    }
+    let source = format!("{}{}", PREFIX, host.to_ascii_lowercase());
+    git::fetch(&dfl_path).map_err(|e| errors::error(Code::PullFailed, format!("Error fetching from remote: {}", e)))?;
+    let current = git::branch(&dfl_path)?;
+    let remote = git::upstream(&dfl_path, &current)?.map(|(remote, _)| remote).unwrap_or_else(|| "origin".to_string());
+    let Some(tip) = git::find_commit(&dfl_path, &format!("refs/remotes/{}/{}", remote, source))? else {
+        return Err(errors::error(Code::InvalidArgument, format!("{} has no branch '{}'. Does that machine run 'dfl machine start' and push?", remote, source)));
+    };
+
+    let mut path = None;
+    for candidate in candidate_paths(&dfl_path, name)? {
+        if git::has_path(&dfl_path, &tip, &candidate)? {
+            path = Some(candidate);
+            break;
+        }
+    }
+    if let Some(path) = path {
+        git::checkout_path(&dfl_path, &tip, &path).map_err(|e| errors::error(Code::GitFailed, format!("Error taking '{}' from '{}': {}", path, source, e)))?;
+        if !git::has_local_changes(&dfl_path)? {
+            ui::say(&format!("'{}' is already the same as on '{}'; there is nothing to pick.", path, source));
+            return Ok(());
+        }
+        let message = git::commit_message(&format!("pick from {}", host), &[PathBuf::from(&path)], format!("Pick {} from {}", path, host))?;
+        git::commit(&dfl_path, &message).map_err(|e| errors::error(Code::GitFailed, format!("Error committing: {}", e)))?;
+        ui::say(&format!("✅ Picked '{}' as it is on '{}'.", path, source));
+    } else if let Some(commit) = git::find_commit(&dfl_path, name)? {
+        git::cherry_pick(&dfl_path, &commit).map_err(|e| errors::error(Code::GitFailed, format!("Error picking commit {}: {}", name, e)))?;
+        ui::say(&format!("✅ Picked commit {} from '{}'.", name, source));
+    } else {
+        return Err(errors::error(Code::InvalidArgument, format!("'{}' is neither a file on '{}' nor a commit.", name, source)));
+    }
+
+    branch::after_checkout(&dfl_path)?;
+    if config::load()?.auto_push {
+        crate::remote::push_after_commit(&dfl_path)?;
+    }
+    Ok(())
+}
@@ -1,315 +1,692 @@
 use std::env;
-use std::fs;
-use std::io::{self};
+use std::io::{self, IsTerminal};
 use std::path::PathBuf;
-use std::os::unix::fs::symlink;
-use duct::cmd;
-use dirs;
-
-fn main() -> io::Result<()> {
-    // Collect command-line arguments into a vector of strings.
-    let args: Vec<String> = env::args().collect();
-
-    // The first argument is the program name itself.
-    if args.len() < 2 {
-        print_usage(&args[0]);
-        return Ok(());
+use std::process;
+
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use dfl::conflict;
+use dfl::errors::{self, Code};
+use dfl::init::{handle_clone_command, handle_clone_mirror_command, handle_init_command};
+use dfl::layout::LayoutKind;
+use dfl::remote::{handle_pull_command, handle_push_all_command, handle_push_command, handle_remote_command, handle_remote_list_command, handle_remote_remove_command, handle_remote_set_url_command};
+use dfl::sync::{SyncOptions, handle_sync_command};
+use dfl::ui;
+use dfl::{add, backup, bare, branch, commit, config, doctor, external, fold, gc, githooks, gitignore, hints, hosting, identity, incoming, layout, list, lock, logging, machine, migrate, mirror, mv_entry, perms, plugins, profile, prune, remove, repo, self_update, sparse, squash, state, status, tag, up, verify};
+
+fn main() {
+    // Without a terminal, git would wait for an HTTPS username nobody can type; make it fail instead.
+    if !io::stdin().is_terminal() && env::var_os("GIT_TERMINAL_PROMPT").is_none() {
+        // SAFETY: this runs before any other thread is started.
+        unsafe { env::set_var("GIT_TERMINAL_PROMPT", "0") };
     }
-
-    // Get the first command (e.g., "init", "sync", "add", "remote").
-    let command = &args[1];
-
-    match command.as_str() {
-        "init" => {
-            handle_init_command()?;
-        }
-        "add" => {
-            if args.len() < 3 {
-                eprintln!("Error: 'add' command requires a file or directory path.");
-                print_usage(&args[0]);
-                return Ok(());
-            }
-            let file_path = &args[2];
-            handle_add_command(file_path)?;
-        }
-        "sync" => {
-            handle_sync_command()?;
-        }
-        "remote" => {
-            if args.len() < 4 || args[2].as_str() != "add" {
-                eprintln!("Error: 'remote' command requires 'add' and a URL.");
-                print_usage(&args[0]);
-                return Ok(());
+    if let Err(e) = run() {
+        match errors::code_of(&e) {
+            Some(code) => {
+                eprintln!("Error [{}]: {}", code, e);
+                eprintln!("Run 'dfl explain-error {}' for details.", code);
             }
-            let url = &args[3];
-            handle_remote_command(url)?;
-        }
-        "push" => {
-            handle_push_command()?;
-        }
-        "pull" => {
-            handle_pull_command()?;
-        }
-        "-h" | "--help" => {
-            print_usage(&args[0]);
-        }
-        _ => {
-            eprintln!("Unknown command: {}", command);
-            print_usage(&args[0]);
+            None => eprintln!("Error: {}", e),
         }
+        process::exit(errors::exit_code(&e));
     }
-
-    Ok(())
 }
 
-/// A friendly and conversational message box function.
-fn message_box(title: &str, message: &str) {
-    println!("\n--- {} ---", title);
-    println!("{}\n", message);
+/// A command-line tool for managing your dotfiles with Git.
+#[derive(Parser)]
+#[command(name = "dfl", version, arg_required_else_help = true, after_help = "Any other command runs the dfl-<name> executable on your PATH, so 'dfl encrypt' runs dfl-encrypt.")]
+struct Cli {
+    #[arg(long, global = true, help = format!("Treats every warning as an error and exits with code {}", ui::EXIT_STRICT))]
+    strict: bool,
+    /// Uses the repository at <PATH> instead of ~/.local/share/dfl/repo, as the DFL_DIR environment variable or repo_path in ~/.config/dfl/config.toml do
+    #[arg(long, global = true, value_name = "PATH")]
+    repo: Option<PathBuf>,
+    /// Uses the repository of a profile under [profiles.<NAME>] in ~/.config/dfl/config.toml, such as one for work dotfiles; 'default' is the usual one
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// Runs status, sync, or list for the default repository and then every profile
+    #[arg(long, global = true)]
+    all_profiles: bool,
+    /// Shows what dfl does: -v explains its decisions, -vv also logs every git command and file operation
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Shows only errors and the output asked for, leaving out progress, warnings, message boxes, and tips
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Colors output: auto does on a terminal unless NO_COLOR is set
+    #[arg(long, global = true, value_enum, value_name = "WHEN")]
+    color: Option<ui::ColorChoice>,
+    /// Never stops to ask: every question gets the answer dfl gives without a terminal
+    #[arg(short, long, global = true)]
+    yes: bool,
+    /// Waits for another dfl command changing the same repository to finish, instead of failing
+    #[arg(long, global = true)]
+    wait: bool,
+    #[command(subcommand)]
+    command: Command,
 }
 
-/// Handles the 'init' command.
-/// It creates the ~/.dfl directory and initializes a Git repository inside it.
-/// It also configures a default Git user name and email to prevent hanging.
-fn handle_init_command() -> io::Result<()> {
-    message_box("Initializing dfl...", "Creating repository directory and initializing Git.");
-
-    let mut dfl_path = PathBuf::new();
-    if let Some(home_dir) = dirs::home_dir() {
-        dfl_path.push(home_dir);
-        dfl_path.push(".dfl");
-    } else {
-        return Err(io::Error::new(io::ErrorKind::Other, "Could not find home directory"));
-    }
-
-    if let Err(e) = std::fs::create_dir_all(&dfl_path) {
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error creating directory: {}", e)));
-    };
-    println!("✅ Created directory: {:?}", dfl_path);
-
-    if let Err(e) = cmd!("git", "init").dir(&dfl_path).run() {
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error initializing git repository: {}", e)));
-    }
-    
-    // Configure a generic user name and email to prevent the `git commit` command from hanging.
-    if let Err(e) = cmd!("git", "config", "user.name", "Dotfile Manager").dir(&dfl_path).run() {
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error configuring git user name: {}", e)));
-    }
-    if let Err(e) = cmd!("git", "config", "user.email", "dfl-bot@example.com").dir(&dfl_path).run() {
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error configuring git user email: {}", e)));
-    }
-
-    println!("✅ Git repository initialized and configured.");
-
-    message_box("dfl Initialized", &format!("You can now add your dotfiles. Your repository is at: {:?}", dfl_path));
-
-    Ok(())
+#[derive(Subcommand)]
+enum Command {
+    /// Initializes a new dfl repository in ~/.local/share/dfl/repo.
+    ///
+    /// New repositories mirror your home directory (home layout). Asks in a terminal who commits to the dfl repository, set for that repository only so your global git identity stays out of it; --name, --email, and --signing-key set it without asking, also on an existing or cloned repository.
+    Init {
+        /// How files are stored in the repository
+        #[arg(long, value_enum)]
+        layout: Option<LayoutKind>,
+        /// Makes the dfl repository a bare one with your home directory as its work tree, which tracks files in place without symlinks
+        #[arg(long, conflicts_with = "layout")]
+        bare: bool,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        email: Option<String>,
+        #[arg(long, value_name = "KEY")]
+        signing_key: Option<String>,
+    },
+    /// Adds files or directories to the dfl repository, symlinks them back, and commits.
+    ///
+    /// A glob such as '~/.bash*' adds everything it matches in one commit. Creates the repository if it is missing. Nothing is committed if a new or changed line looks like a token, key, or password, unless --allow-secrets is passed or the line has the comment dfl:allow-secret.
+    Add {
+        /// Files, directories, or glob patterns to add
+        #[arg(required_unless_present = "interactive")]
+        paths: Vec<String>,
+        /// Picks unmanaged dotfiles from a checklist
+        #[arg(short, long, conflicts_with = "paths")]
+        interactive: bool,
+        /// Puts the entry into this package (package layout)
+        #[arg(long, value_name = "NAME")]
+        package: Option<String>,
+        /// Stores the entry under a different name, e.g. 'bashrc'
+        #[arg(long = "as", value_name = "REPO_PATH", conflicts_with = "interactive")]
+        store_as: Option<String>,
+        /// Leaves paths matching a gitignore-style pattern out of added directories
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+        /// Replaces the generated commit message
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Leaves the changes for you to commit
+        #[arg(long, conflicts_with = "message")]
+        no_commit: bool,
+        /// Pushes the commit right away
+        #[arg(long, conflicts_with = "no_commit")]
+        push: bool,
+        /// Stores large binary files with git-lfs without asking
+        #[arg(long)]
+        lfs: bool,
+        #[arg(long)]
+        allow_secrets: bool,
+    },
+    /// Commits the edits made to managed files since the last commit.
+    Commit {
+        /// Folds the edits into the last commit, keeping its message unless -m is given; refused once that commit was pushed
+        #[arg(long)]
+        amend: bool,
+        #[arg(short, long)]
+        message: Option<String>,
+        #[arg(long)]
+        push: bool,
+        #[arg(long)]
+        allow_secrets: bool,
+    },
+    /// Stops managing dotfiles.
+    ///
+    /// Each is put back in its place as a plain file, deleted from the repository and dfl.toml, and the removal is committed.
+    Remove {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        #[arg(short, long)]
+        message: Option<String>,
+        #[arg(long, conflicts_with = "message")]
+        no_commit: bool,
+    },
+    /// Moves a managed dotfile into another package or another profile's repository.
+    ///
+    /// Every variant of it moves too, along with its settings in dfl.toml such as its mode and [when] conditions; its link is pointed at the new place, and the move is committed in each repository it touched.
+    MvEntry {
+        path: String,
+        /// The package to store it in, in a repository using the package layout
+        #[arg(long, value_name = "NAME", required_unless_present = "to_profile")]
+        to_package: Option<String>,
+        /// The profile whose repository it moves to
+        #[arg(long, value_name = "NAME")]
+        to_profile: Option<String>,
+    },
+    /// Lists or extends the .gitignore of the dfl repository, which keeps files such as caches in managed directories out of commits.
+    ///
+    /// Adding the first directory fills it with well-known junk like __pycache__/, .DS_Store, and history files.
+    Ignore {
+        #[command(subcommand)]
+        command: Option<gitignore::IgnoreCommand>,
+    },
+    /// Shows or applies which paths this machine checks out.
+    ///
+    /// Machines whose tags in config.toml name a class under [sparse] in dfl.toml check out only that class's paths when cloning; pull and up keep the checkout in line, and apply does it right away after editing the tags.
+    Sparse {
+        #[command(subcommand)]
+        command: Option<sparse::SparseCommand>,
+    },
+    /// Links all dotfiles from the repository into your home directory.
+    ///
+    /// Files in the way are backed up to ~/.local/state/dfl/backups. A failed sync is undone unless --keep-partial is passed.
+    Sync {
+        /// Limits the sync to these files, directories, or packages
+        names: Vec<String>,
+        /// Replaces files in the way instead of backing them up
+        #[arg(long)]
+        force: bool,
+        /// Creates relative links
+        #[arg(long, conflicts_with = "absolute")]
+        relative: bool,
+        /// Creates absolute links
+        #[arg(long)]
+        absolute: bool,
+        /// Keeps what a failed sync did instead of undoing it
+        #[arg(long)]
+        keep_partial: bool,
+        /// Removes orphaned links
+        #[arg(long)]
+        prune: bool,
+        /// Prints what the sync did as JSON, with progress on standard error
+        #[arg(long)]
+        json: bool,
+    },
+    /// Installs the plugin managers declared in dfl.toml, then syncs.
+    Bootstrap,
+    /// Shows the current branch, how far it is ahead of or behind the remote as of the last fetch, and the files changed since the last commit.
+    Status {
+        /// Prints the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Lists managed dotfiles and whether they are linked.
+    List {
+        /// Prints the entries as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Lists the backups sync made of files it replaced, newest first.
+    Backups { path: Option<String> },
+    /// Puts a backup back in place of dfl's symlink.
+    ///
+    /// Uses the newest backup unless one is named or picked.
+    RestoreBackup {
+        path: String,
+        #[arg(long, value_name = "TIMESTAMP")]
+        backup: Option<String>,
+    },
+    /// Checks or restores the file modes recorded in dfl.toml, such as 0600 on ~/.ssh/config.
+    Perms {
+        #[command(subcommand)]
+        command: Option<perms::PermsCommand>,
+    },
+    /// Deploys a managed directory as a real directory with each child linked on its own.
+    ///
+    /// Files other programs write there stay out of the repository.
+    Unfold {
+        #[arg(required = true, value_name = "DIR")]
+        dirs: Vec<String>,
+    },
+    /// Deploys an unfolded directory as a single symlink again, if it holds nothing dfl does not manage.
+    Fold {
+        #[arg(required = true, value_name = "DIR")]
+        dirs: Vec<String>,
+    },
+    /// Removes symlinks into the dfl repository whose files were deleted from the repository.
+    Prune {
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Moves ~/.dfl, ~/.dfl-backups, and dfl's configuration and state to the XDG base directories.
+    ///
+    /// The repository goes to $XDG_DATA_HOME/dfl/repo, backups to $XDG_STATE_HOME/dfl/backups, and every symlink into ~/.dfl is pointed at the new place. --dry-run lists what would move.
+    Migrate {
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Reports the size of the repository and backups and the largest files in the history, then compacts the repository with git gc.
+    Gc {
+        /// Only reports
+        #[arg(long)]
+        dry_run: bool,
+        /// Also deletes what only the undo history refers to
+        #[arg(long)]
+        now: bool,
+    },
+    /// Checks git, the repository, and whether your filesystems support dfl's moves and symlinks.
+    ///
+    /// Installs or updates the git hooks that check commits and sync after pulls made with git in the dfl repository.
+    Doctor,
+    /// Shows the repository layout, or moves every entry into a different layout.
+    Layout {
+        #[command(subcommand)]
+        command: Option<layout::LayoutCommand>,
+    },
+    /// Clones your dotfiles repository into ~/.local/share/dfl/repo.
+    ///
+    /// Without a URL, --profile clones the profile's remote.
+    Clone {
+        url: Option<String>,
+        /// Downloads only the latest N commits
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        depth: Option<u32>,
+        /// Makes a partial clone, e.g. blob:none, which needs git_backend = "command"
+        #[arg(long, value_name = "SPEC")]
+        filter: Option<String>,
+        /// Clones a bare repository and checks the files out in place in your home directory, moving files already there to the backups
+        #[arg(long)]
+        bare: bool,
+        /// Clones from an rsync or S3 mirror instead of the git host
+        #[arg(long, value_name = "TARGET", conflicts_with_all = ["url", "depth", "filter", "bare"])]
+        mirror: Option<String>,
+        /// Points --mirror at S3-compatible storage
+        #[arg(long, value_name = "URL")]
+        endpoint: Option<String>,
+    },
+    /// Adds, creates, repoints, lists, or removes remotes.
+    Remote {
+        #[command(subcommand)]
+        command: Option<RemoteCommand>,
+    },
+    /// Lists, adds, or removes mirrors in dfl.toml, or updates them now.
+    ///
+    /// Every push also copies the repository to each mirror, with rsync over SSH or as a git bundle uploaded with the aws CLI.
+    Mirror {
+        #[command(subcommand)]
+        command: Option<mirror::MirrorCommand>,
+    },
+    /// Pushes your committed changes to the remote repository.
+    ///
+    /// The first push to an SSH remote checks the login first and helps set up a key. When the remote cannot be reached, the push is queued for the next push, pull, or up.
+    Push {
+        /// Pushes another local branch
+        #[arg(long)]
+        branch: Option<String>,
+        /// Pushes to every remote and reports each
+        #[arg(long)]
+        all_remotes: bool,
+        /// Replaces rewritten history, such as squashed commits, unless the remote changed since your last pull
+        #[arg(long, conflicts_with = "all_remotes")]
+        force: bool,
+    },
+    /// Pulls the latest changes from the remote repository, replaying your local commits on top of them.
+    ///
+    /// With uncommitted edits in the dfl repository, it asks whether to commit them first, set them aside and put them back, or stop; --commit, --stash, and --abort-if-dirty answer without asking, and outside a terminal they are set aside. Offline, it changes nothing and only warns; back online, it also pushes commits queued while offline.
+    Pull {
+        /// Pulls that remote branch into the current one
+        #[arg(long)]
+        branch: Option<String>,
+        #[arg(long, conflicts_with = "merge")]
+        rebase: bool,
+        /// Creates a merge commit instead of replaying local commits
+        #[arg(long)]
+        merge: bool,
+        #[arg(long, conflicts_with_all = ["stash", "abort_if_dirty"])]
+        commit: bool,
+        #[arg(long, conflicts_with = "abort_if_dirty")]
+        stash: bool,
+        #[arg(long)]
+        abort_if_dirty: bool,
+    },
+    /// Lists branches, creates one and switches to it, switches to another, or merges one into the current branch.
+    Branch {
+        #[command(subcommand)]
+        command: Option<branch::BranchCommand>,
+    },
+    /// Shows or changes which branch this machine commits to.
+    ///
+    /// start gives it a branch of its own, machine/<hostname>, created from the current one, which becomes the shared branch; pulls still bring in what other machines promote to it. stop goes back to the shared branch.
+    Machine {
+        #[command(subcommand)]
+        command: Option<machine::MachineCommand>,
+    },
+    /// Merges the branch another machine commits to into the current one, to try its changes before they are promoted.
+    MergeFrom { host: String },
+    /// Brings over one file or directory as it is on another machine's branch, or one of its commits, as a new commit.
+    Pick {
+        host: String,
+        /// A file, directory, or commit
+        name: String,
+    },
+    /// Merges this machine's branch into the shared branch and pushes it, so every machine gets the changes.
+    Promote,
+    /// Lists tags, or tags the current state as a known-good point to roll back to.
+    ///
+    /// Tags are pushed with the branch.
+    Tag {
+        name: Option<String>,
+        #[arg(short, long, conflicts_with = "delete")]
+        message: Option<String>,
+        /// Deletes the tag
+        #[arg(long)]
+        delete: bool,
+    },
+    /// Puts your dotfiles back as they were at a tag, as a new commit.
+    Rollback {
+        #[arg(long, value_name = "NAME")]
+        tag: String,
+    },
+    /// Squashes the commits since a point into one with a generated summary.
+    Squash {
+        /// A commit, tag, date (2024-05-01), or number of days (7d)
+        #[arg(long)]
+        since: String,
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Publishes the result, replacing commits that were already pushed
+        #[arg(long)]
+        push: bool,
+    },
+    /// Downloads new commits from the remote without changing anything, and says how many a pull would bring in.
+    Fetch,
+    /// Shows what a pull would bring in, as of the last fetch: the commit messages and the lines added and removed in each file.
+    Incoming {
+        /// Fetches first
+        #[arg(long)]
+        fetch: bool,
+        /// Prints the incoming commits and files as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Pulls the latest changes, links anything new, and summarizes what changed.
+    ///
+    /// Offline, it skips the pull and links what is already in the repository.
+    Up,
+    /// Checks that every managed symlink and file matches the recorded checksums.
+    Verify {
+        /// Records the current checksums instead
+        #[arg(long)]
+        update: bool,
+    },
+    /// Upgrades a release-tarball install to the latest GitHub release.
+    SelfUpdate {
+        /// Only reports whether a newer release exists
+        #[arg(long)]
+        check: bool,
+    },
+    /// Explains an error code such as DFL-0001, or lists all codes.
+    ExplainError { code: Option<String> },
+    /// Lists the settings in effect, or shows or changes one in ~/.config/dfl/config.toml.
+    Config {
+        #[command(subcommand)]
+        command: Option<config::ConfigCommand>,
+    },
+    /// Shows whether tips are enabled, or resets them so they are shown again.
+    Hints {
+        #[command(subcommand)]
+        command: Option<hints::HintsCommand>,
+    },
+    /// Runs the check a git hook installed by dfl stands for.
+    #[command(hide = true)]
+    GitHook { hook: String },
+    #[command(external_subcommand)]
+    External(Vec<String>),
 }
 
-/// Handles the 'add' command.
-/// It moves a file or directory, creates a symlink, and automatically commits the change.
-fn handle_add_command(file_path: &str) -> io::Result<()> {
-    println!("Adding file or directory: {}", file_path);
-
-    let home_dir = dirs::home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not find home directory"))?;
-    let dfl_path = home_dir.join(".dfl");
+#[derive(Subcommand)]
+enum RemoteCommand {
+    /// Adds a remote URL, or points the remote at the new URL if it exists.
+    ///
+    /// HTTPS remotes log in with the token in DFL_GIT_TOKEN when it is set, then with the credential helper.
+    Add {
+        url: String,
+        #[arg(long, default_value = "origin")]
+        name: String,
+        /// Picks the credential helper; the system keyring is used if none is set up
+        #[arg(long, value_name = "HELPER")]
+        credential_helper: Option<String>,
+        /// Stores a token in the credential helper
+        #[arg(long)]
+        token: bool,
+        // Does nothing: a different URL always replaces the old one now. Changing it
+        // once needed --force, so it is still accepted for scripts that pass it.
+        #[arg(long, hide = true)]
+        force: bool,
+    },
+    /// Creates a private repository on the host, adds it as a remote, and pushes to it.
+    ///
+    /// The token is read from GH_TOKEN or GITHUB_TOKEN, GITLAB_TOKEN, or GITEA_TOKEN, or asked for.
+    Create {
+        /// github, gitlab, or gitea
+        host: String,
+        /// The repository to create (default 'dotfiles')
+        repository: Option<String>,
+        /// Points at a self-hosted instance (required for Gitea)
+        #[arg(long, value_name = "INSTANCE")]
+        url: Option<String>,
+        #[arg(long, default_value = "origin")]
+        name: String,
+        /// Makes the repository public
+        #[arg(long)]
+        public: bool,
+        /// Connects over HTTPS instead of SSH
+        #[arg(long)]
+        https: bool,
+    },
+    /// Points an existing remote, origin unless named, at a new URL.
+    #[command(override_usage = "dfl remote set-url [OPTIONS] [NAME] <URL>")]
+    SetUrl {
+        #[arg(value_name = "NAME")]
+        first: String,
+        url: Option<String>,
+        #[arg(long, default_value = "origin")]
+        name: String,
+        #[arg(long, value_name = "HELPER")]
+        credential_helper: Option<String>,
+        #[arg(long)]
+        token: bool,
+        // Does nothing, as for 'remote add'; still accepted for scripts that pass it.
+        #[arg(long, hide = true)]
+        force: bool,
+    },
+    /// Lists the remotes and their URLs (the default).
+    List,
+    /// Disconnects a remote.
+    Remove { name: String },
+}
 
-    if !dfl_path.exists() || !dfl_path.join(".git").exists() {
-        message_box("Setting up", "Initializing dotfiles repository in ~/.dfl for you.");
-        handle_init_command()?;
+fn run() -> io::Result<()> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let command = matches.subcommand_name().unwrap_or_default();
+    logging::init(logging::level(cli.verbose, cli.quiet), cli.quiet);
+    if [cli.repo.is_some(), cli.profile.is_some(), cli.all_profiles].iter().filter(|given| **given).count() > 1 {
+        return Err(errors::error(Code::InvalidArgument, "Pick the repository with one of '--repo', '--profile', and '--all-profiles'."));
     }
-
-    let source_path = PathBuf::from(file_path);
-    if !source_path.exists() {
-        message_box("Error", &format!("Source path '{}' does not exist.", file_path));
-        return Ok(());
+    if let Some(repo) = &cli.repo {
+        // Through the environment, every part of dfl and the git hooks it runs see the same repository.
+        // SAFETY: no other thread has been started yet.
+        unsafe { env::set_var(repo::DIR_VARIABLE, std::path::absolute(repo)?) };
     }
 
-    let file_name = source_path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file path"))?;
-    let destination_path = dfl_path.join(file_name);
-
-    // Check if the destination already exists to avoid overwriting.
-    if destination_path.exists() {
-        message_box("Warning", &format!("A file or directory named '{}' already exists in the repository. Please move it manually or remove it first.", destination_path.display()));
-        return Ok(());
+    // Runs before the configuration is loaded, so it can fix a file that does not load.
+    if let Command::Config { command } = &cli.command {
+        return config::handle_config_command(command.clone());
     }
-
-    // Move the file or directory into the repository
-    if let Err(e) = fs::rename(&source_path, &destination_path) {
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error moving file: {}", e)));
+    let config = config::load()?;
+    ui::set_strict(cli.strict || config.strict);
+    ui::set_color(cli.color.unwrap_or(config.color), &config.theme);
+    ui::set_assume_yes(cli.yes);
+    if cli.yes {
+        // git and ssh ask for passwords on the terminal itself, not on standard input.
+        let ssh = env::var("GIT_SSH_COMMAND").unwrap_or_else(|_| "ssh".to_string());
+        // SAFETY: no other thread has been started yet.
+        unsafe {
+            env::set_var("GIT_TERMINAL_PROMPT", "0");
+            env::set_var("GIT_SSH_COMMAND", format!("{} -o BatchMode=yes", ssh));
+        }
     }
-    println!("✅ Moved file or directory to repository: {:?}", destination_path);
-
-    // Create a symbolic link
-    if let Err(e) = symlink(&destination_path, &source_path) {
-        // If symlink creation fails, move the original file back to prevent data loss
-        let _ = fs::rename(&destination_path, &source_path);
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error creating symlink: {}. Original file has been restored.", e)));
+    if let Some(name) = cli.profile {
+        let path = profile::repo_path(&config, &name)?;
+        // SAFETY: no other thread has been started yet.
+        unsafe {
+            env::set_var(repo::DIR_VARIABLE, path);
+            env::set_var(profile::PROFILE_VARIABLE, name);
+        }
     }
-    println!("✅ Created symlink at: {:?}", source_path);
-
-    // Automatically stage and commit the change
-    println!("Automatically committing changes...");
-    if let Err(e) = cmd!("git", "add", ".").dir(&dfl_path).run() {
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error staging changes: {}", e)));
+    if cli.all_profiles {
+        let args: Vec<String> = env::args().skip(1).filter(|a| a != "--all-profiles").collect();
+        return profile::run_all(&config, command, &args);
     }
-    if let Err(e) = cmd!("git", "commit", "-m", &format!("feat: Add {}", file_name.to_string_lossy())).dir(&dfl_path).run() {
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error committing changes: {}", e)));
+    if let Command::External(words) = &cli.command && let Some((name, args)) = words.split_first() {
+        let commands = Cli::command();
+        let known: Vec<&str> = commands.get_subcommands().filter(|command| !command.is_hide_set()).map(|command| command.get_name()).collect();
+        process::exit(external::run(name, args, &known)?);
     }
-    println!("✅ Changes committed.");
-
-    message_box("Success", "Dotfile added and linked successfully!");
-    println!("Remember to add a remote and 'dfl push' to sync your changes.");
-
-    Ok(())
-}
-
-/// Handles the 'sync' command.
-/// It creates symlinks for all files in the dfl repository.
-fn handle_sync_command() -> io::Result<()> {
-    println!("Syncing dotfiles...");
-
-    let home_dir = dirs::home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not find home directory"))?;
-    let dfl_path = home_dir.join(".dfl");
-
-    if !dfl_path.exists() {
-        message_box("Error", "dfl repository not found. Please run 'dfl init' or clone your repository first.");
-        return Ok(());
+    // Checked before the command runs, since hint bookkeeping creates the state file.
+    let first_run = state::is_first_run()?;
+
+    let dfl_path = repo::dfl_path()?;
+    tracing::info!("Using the repository at {} with the {:?} git backend.", dfl_path.display(), config.git_backend);
+    bare::check_supported(&dfl_path, command)?;
+    // Held until the command returns. Git runs the hooks, so they always wait rather than fail a 'git pull'.
+    let lock = if lock::READ_ONLY.contains(&command) { None } else { lock::acquire(&dfl_path, command, cli.wait || command == "git-hook")? };
+    if let Some(lock) = &lock {
+        // SAFETY: no other thread has been started yet.
+        unsafe { env::set_var(lock::HELD_VARIABLE, lock.path()) };
     }
+    let no_empty_message = |message: &Option<String>, what: &str| {
+        if message.as_deref().is_some_and(|m| m.trim().is_empty()) {
+            return Err(errors::error(Code::InvalidArgument, format!("The {} message cannot be empty.", what)));
+        }
+        Ok(())
+    };
 
-    // Link all files in the repository
-    for entry in fs::read_dir(&dfl_path)? {
-        let entry = entry?;
-        let file_path_in_repo = entry.path();
+    // JSON output is read by scripts, which a hint on the next line would break.
+    let json = matches!(cli.command, Command::Status { json: true } | Command::List { json: true } | Command::Sync { json: true, .. } | Command::Incoming { json: true, .. });
 
-        if file_path_in_repo.file_name().and_then(|f| f.to_str()) == Some(".git") {
-            continue;
+    match cli.command {
+        Command::Init { layout, bare, name, email, signing_key } => {
+            handle_init_command(layout, &identity::Identity { name, email, signing_key }, true, bare)?;
         }
-
-        let file_name = file_path_in_repo.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file path"))?;
-        let symlink_path = home_dir.join(file_name);
-        
-        // Skip hidden directories and non-dotfiles
-        if file_name.to_string_lossy().starts_with('.') && file_path_in_repo.is_dir() {
-            continue;
+        Command::Add { paths, interactive, package, store_as, exclude, message, no_commit, push, lfs, allow_secrets } => {
+            no_empty_message(&message, "commit")?;
+            if interactive && !ui::interactive() {
+                return Err(errors::error(Code::InvalidArgument, "'--interactive' needs a terminal to ask which files to add, and does not ask with '--yes'; pass the paths instead."));
+            }
+            if store_as.is_some() && paths.len() > 1 {
+                return Err(errors::error(Code::InvalidArgument, "'--as' names a single entry; pass exactly one path with it."));
+            }
+            let options = add::AddOptions { package, message, no_commit, interactive, store_as, exclude, push, lfs, allow_secrets };
+            if bare::in_use(&dfl_path) {
+                bare::handle_add_command(&paths, &options)?;
+            } else {
+                add::handle_add_command(&paths, &options)?;
+            }
         }
-
-        if symlink_path.exists() {
-            message_box("Warning", &format!("'{}' already exists. Backing up and creating symlink.", symlink_path.display()));
-            let backup_path = home_dir.join(format!("{}.backup", file_name.to_string_lossy()));
-            fs::rename(&symlink_path, &backup_path)?;
+        Command::Commit { amend, message, push, allow_secrets } => {
+            no_empty_message(&message, "commit")?;
+            commit::handle_commit_command(&commit::CommitOptions { amend, message, push, allow_secrets })?;
         }
-
-        if let Err(e) = symlink(&file_path_in_repo, &symlink_path) {
-            eprintln!("  - Error creating symlink for '{}': {}", file_path_in_repo.display(), e);
-        } else {
-            println!("✅ Synced '{}' to '{}'", file_path_in_repo.display(), symlink_path.display());
+        Command::Remove { paths, message, no_commit } => {
+            no_empty_message(&message, "commit")?;
+            remove::handle_remove_command(&paths, message.as_deref(), no_commit)?;
         }
-    }
-
-    message_box("Success", "All managed dotfiles have been synced!");
-    Ok(())
-}
-
-/// Handles the new 'remote' command.
-/// It adds a remote URL to the repository.
-fn handle_remote_command(url: &str) -> io::Result<()> {
-    message_box("Adding Remote Origin", "Connecting your local repository to a remote URL.");
-
-    let home_dir = dirs::home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not find home directory"))?;
-    let dfl_path = home_dir.join(".dfl");
-
-    if !dfl_path.exists() {
-        message_box("Error", "dfl repository not found. Please run 'dfl init' first.");
-        return Ok(());
-    }
-
-    // Add the remote origin
-    if let Err(e) = cmd!("git", "remote", "add", "origin", url).dir(&dfl_path).run() {
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error adding remote origin: {}", e)));
-    }
-    println!("✅ Remote 'origin' added: {}", url);
-
-    message_box("Remote Added", "Your local repository is now connected to your remote!");
-
-    Ok(())
-}
-
-/// Handles the new 'push' command.
-/// It pushes committed changes to the remote repository.
-fn handle_push_command() -> io::Result<()> {
-    message_box("Pushing to Remote", "Uploading your committed changes to the remote repository.");
-    let home_dir = dirs::home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not find home directory"))?;
-    let dfl_path = home_dir.join(".dfl");
-
-    if !dfl_path.exists() {
-        message_box("Error", "dfl repository not found. Please run 'dfl init' or clone your repository first.");
-        return Ok(());
-    }
-
-    // Check if a remote named 'origin' exists
-    let remotes_output = cmd!("git", "remote").dir(&dfl_path).read()?;
-    if !remotes_output.contains("origin") {
-        return Err(io::Error::new(io::ErrorKind::Other, "Error: No remote named 'origin' found. Please run 'dfl remote add <url>' first."));
-    }
-
-    // Check if the current branch has an upstream set
-    let status_output = cmd!("git", "status", "-sb").dir(&dfl_path).read()?;
-    let is_upstream_set = status_output.contains("...");
-
-    if is_upstream_set {
-        // Upstream is set, just do a normal push
-        if let Err(e) = cmd!("git", "push").dir(&dfl_path).run() {
-            return Err(io::Error::new(io::ErrorKind::Other, format!("Error pushing to remote: {}", e)));
+        Command::MvEntry { path, to_package, to_profile } => mv_entry::handle_mv_entry_command(&path, to_package.as_deref(), to_profile.as_deref())?,
+        Command::Sync { names, force, relative, absolute, keep_partial, prune, json } => {
+            let options = SyncOptions {
+                force,
+                interactive: !force && !json && ui::interactive(),
+                relative: relative || (config.relative_links && !absolute),
+                keep_partial,
+                prune,
+                only: names,
+                ..SyncOptions::default()
+            };
+            if bare::in_use(&dfl_path) {
+                if json {
+                    return Err(errors::error(Code::InvalidArgument, "'sync --json' is not available for a bare repository, which has nothing to link."));
+                }
+                bare::handle_sync_command(&dfl_path)?;
+            } else {
+                handle_sync_command(&options, json)?;
+            }
         }
-    } else {
-        // No upstream set, perform an initial push
-        message_box("Initial Push", "No upstream branch found. Setting upstream for you.");
-        if let Err(e) = cmd!("git", "push", "--set-upstream", "origin", "master").dir(&dfl_path).run() {
-            return Err(io::Error::new(io::ErrorKind::Other, format!("Error performing initial push: {}", e)));
+        Command::Status { json } => status::handle_status_command(json)?,
+        Command::Clone { mirror: Some(target), endpoint, .. } => {
+            handle_clone_mirror_command(&mirror::Mirror::parse(&target, endpoint.as_deref()))?;
+            return Ok(());
         }
+        Command::Clone { url, depth, filter, bare, mirror: None, .. } => {
+            let Some(url) = url.or_else(|| profile::remote(&config)) else {
+                return Err(errors::error(Code::InvalidArgument, "'clone' needs the URL of your dotfiles repository, or --mirror and a mirror."));
+            };
+            handle_clone_command(&url, depth, filter.as_deref(), bare)?;
+        }
+        Command::Remote { command } => match command.unwrap_or(RemoteCommand::List) {
+            RemoteCommand::Add { url, name, credential_helper, token, force: _ } => handle_remote_command(&name, &url, credential_helper.as_deref(), token)?,
+            RemoteCommand::Create { host, repository, url, name, public, https } => hosting::handle_remote_create_command(&host, url.as_deref(), repository.as_deref(), &name, public, https)?,
+            RemoteCommand::SetUrl { first, url: Some(url), credential_helper, token, .. } => handle_remote_set_url_command(&first, &url, credential_helper.as_deref(), token)?,
+            RemoteCommand::SetUrl { first, url: None, name, credential_helper, token, .. } => handle_remote_set_url_command(&name, &first, credential_helper.as_deref(), token)?,
+            RemoteCommand::List => handle_remote_list_command()?,
+            RemoteCommand::Remove { name } => handle_remote_remove_command(&name)?,
+        },
+        Command::Push { branch, all_remotes, force } => {
+            if all_remotes {
+                handle_push_all_command(branch.as_deref())?;
+            } else {
+                handle_push_command(branch.as_deref(), force)?;
+            }
+        }
+        Command::Pull { branch, rebase, merge, commit, stash, abort_if_dirty } => {
+            let local_changes = [(commit, conflict::LocalChanges::Commit), (stash, conflict::LocalChanges::Stash), (abort_if_dirty, conflict::LocalChanges::Abort)]
+                .into_iter()
+                .find(|(given, _)| *given)
+                .map(|(_, choice)| choice);
+            handle_pull_command(branch.as_deref(), rebase || (config.pull_rebase && !merge), local_changes)?;
+        }
+        Command::Mirror { command } => mirror::handle_mirror_command(command)?,
+        Command::Branch { command } => branch::handle_branch_command(command)?,
+        Command::Ignore { command } => gitignore::handle_ignore_command(command)?,
+        Command::Sparse { command } => sparse::handle_sparse_command(command)?,
+        Command::GitHook { hook } => githooks::handle_git_hook_command(&hook)?,
+        Command::Machine { command } => machine::handle_machine_command(command)?,
+        Command::MergeFrom { host } => machine::handle_merge_from_command(&host)?,
+        Command::Pick { host, name } => machine::handle_pick_command(&host, &name)?,
+        Command::Promote => machine::handle_promote_command()?,
+        Command::Tag { name, message, delete } => {
+            no_empty_message(&message, "tag")?;
+            tag::handle_tag_command(name.as_deref(), message, delete)?;
+        }
+        Command::Rollback { tag } => tag::handle_rollback_command(&tag)?,
+        Command::Squash { since, message, push } => {
+            no_empty_message(&message, "commit")?;
+            squash::handle_squash_command(&since, message, push)?;
+        }
+        Command::Fetch => incoming::handle_fetch_command()?,
+        Command::Incoming { fetch, json } => incoming::handle_incoming_command(fetch, json)?,
+        Command::Up => up::handle_up_command()?,
+        Command::Bootstrap => plugins::handle_bootstrap_command()?,
+        Command::Backups { path } => backup::handle_backups_command(path.as_deref())?,
+        Command::RestoreBackup { path, backup } => backup::handle_restore_backup_command(&path, backup.as_deref())?,
+        Command::List { json } => {
+            if bare::in_use(&dfl_path) {
+                bare::handle_list_command(&dfl_path, json)?;
+            } else {
+                list::handle_list_command(json)?;
+            }
+        }
+        Command::Doctor => doctor::handle_doctor_command()?,
+        Command::Perms { command } => perms::handle_perms_command(command)?,
+        Command::Unfold { dirs } => fold::handle_unfold_command(&dirs)?,
+        Command::Fold { dirs } => fold::handle_fold_command(&dirs)?,
+        Command::Prune { dry_run } => prune::handle_prune_command(dry_run)?,
+        Command::Migrate { dry_run } => migrate::handle_migrate_command(dry_run)?,
+        Command::Gc { dry_run, now } => gc::handle_gc_command(dry_run, now)?,
+        Command::Layout { command } => layout::handle_layout_command(command)?,
+        Command::Verify { update } => verify::handle_verify_command(update)?,
+        Command::SelfUpdate { check } => self_update::handle_self_update_command(check)?,
+        Command::ExplainError { code } => return errors::handle_explain_error_command(code.as_deref()),
+        Command::Hints { command } => return hints::handle_hints_command(command, &config),
+        Command::Config { .. } | Command::External(_) => {}
     }
 
-    println!("✅ Changes pushed successfully!");
-
-    message_box("Success", "Your dotfiles are now synced with your remote repository!");
-
-    Ok(())
-}
-/// Handles the new 'pull' command.
-/// It pulls changes from the remote repository.
-fn handle_pull_command() -> io::Result<()> {
-    message_box("Pulling from Remote", "Fetching the latest changes from your remote repository.");
-
-    let home_dir = dirs::home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not find home directory"))?;
-    let dfl_path = home_dir.join(".dfl");
-
-    if !dfl_path.exists() {
-        message_box("Error", "dfl repository not found. Please run 'dfl init' or clone your repository first.");
-        return Ok(());
-    }
-
-    if let Err(e) = cmd!("git", "pull").dir(&dfl_path).run() {
-        return Err(io::Error::new(io::ErrorKind::Other, format!("Error pulling from remote: {}", e)));
+    // Hints are a nicety; never fail a command that already succeeded because of them.
+    if !json && !cli.quiet && let Err(e) = hints::after_command(command, &config, first_run) {
+        tracing::warn!("Could not update hint state: {}", e);
     }
-    println!("✅ Pulled latest changes successfully!");
-
-    message_box("Success", "Your local dotfiles repository is now up-to-date. Run 'dfl sync' to apply the changes.");
 
     Ok(())
 }
-
-/// Prints the usage information for the program.
-fn print_usage(program_name: &str) {
-    println!("\nA command-line tool for managing your dotfiles with Git.");
-    println!("\nUsage: {} <command> [arguments]", program_name);
-    println!("\nCommands:");
-    println!("  init            Initializes a new dfl repository in ~/.dfl.");
-    println!("  add <path>      Adds a file or directory. Auto-initializes ~/.dfl if missing, moves it into ~/.dfl, symlinks back, and commits.");
-    println!("  sync            Creates symlinks for all dotfiles from the repository to your home directory.");
-    println!("  remote add <url> Adds a remote URL (e.g., a GitHub repository) to your dfl repository.");
-    println!("  push            Pushes your committed changes to the remote repository.");
-    println!("  pull            Pulls the latest changes from the remote repository.");
-    println!("  -h, --help      Prints this help message.");
-    println!("");
-}
\ No newline at end of file
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::alternate;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::hooks::Hooks;
+use crate::layout::LayoutKind;
+use crate::mirror::Mirror;
+use crate::plugins::PluginList;
+use crate::repo::LinkMode;
+
+/// Name of the manifest file at the root of the repository. It travels with the
+/// dotfiles, so every machine that clones the repository deploys it the same way.
+pub const MANIFEST_FILE: &str = "dfl.toml";
+
+/// Repository-level settings and the list of managed entries.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Manifest {
+    /// How entries are arranged inside the repository.
+    pub layout: LayoutKind,
+    /// Repository-relative paths of managed entries. The flat layout derives its
+    /// entries from the top level of the repository instead.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<PathBuf>,
+    /// Plugin lists keyed by plugin manager name (antidote, zinit, fisher, tpm).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub plugins: BTreeMap<String, PluginList>,
+    /// Entries deployed outside `$HOME`, mapping repository-relative paths to
+    /// absolute targets. A leading `~/` in a target expands to the home directory.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub paths: BTreeMap<PathBuf, PathBuf>,
+    /// How entries are deployed unless `modes` says otherwise.
+    #[serde(skip_serializing_if = "LinkMode::is_symlink")]
+    pub mode: LinkMode,
+    /// Per-entry deployment modes keyed by repository-relative path. A mode set
+    /// on a directory applies to everything below it.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub modes: BTreeMap<PathBuf, LinkMode>,
+    /// File modes Git does not keep, such as `0600`, keyed by repository-relative
+    /// path. `add` records them and `sync` re-applies them.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub permissions: BTreeMap<PathBuf, String>,
+    /// Shell commands to run when a managed file changes, keyed by a
+    /// repository-relative path or glob pattern such as `**/kitty.conf`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub reload: BTreeMap<String, String>,
+    /// Gitignore-style patterns that `add` leaves out of every directory it adds,
+    /// on top of those passed with `--exclude`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Custom variables for templates, such as `email = "me@example.com"`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub values: BTreeMap<String, toml::Value>,
+    /// Directory entries deployed as a real directory with each child linked on its
+    /// own, instead of as one symlink, keyed by repository-relative path.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unfold: Vec<PathBuf>,
+    /// Places `push` also copies the repository to, keyed by name, for machines
+    /// that cannot reach the git host.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub mirrors: BTreeMap<String, Mirror>,
+    /// Gitignore-style patterns of what machines of a class check out, keyed by one
+    /// of the `tags` in the user configuration, such as `server = ["/.bashrc", "/.config/tmux/"]`.
+    /// Machines with none of these tags check out the whole repository.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub sparse: BTreeMap<String, Vec<String>>,
+    /// Conditions an entry is deployed under, written as in `##` names such as
+    /// `os.linux,tag.work`, keyed by the repository-relative path of the entry or a
+    /// directory above it, for entries whose names should stay as they are.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub when: BTreeMap<PathBuf, String>,
+    /// Commands run before `add` and around `sync`, next to the scripts in `hooks/`.
+    #[serde(skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// `link_mode` from the user configuration, which stands in for `mode` on
+    /// this machine when `dfl.toml` leaves it unset.
+    #[serde(skip)]
+    pub machine_mode: Option<LinkMode>,
+}
+
+impl Manifest {
+    /// Returns the deployment mode of a repository-relative path: the mode of the
+    /// closest entry in `modes` that contains it, or the repository-wide mode.
+    pub fn mode_for(&self, stored: &Path) -> LinkMode {
+        let mode = self.machine_mode.filter(|_| self.mode.is_symlink()).unwrap_or(self.mode);
+        self.modes
+            .iter()
+            .filter(|(path, _)| stored.starts_with(path))
+            .max_by_key(|(path, _)| path.components().count())
+            .map_or(mode, |(_, mode)| *mode)
+    }
+
+    /// Returns true if the entry stored at a repository-relative path is deployed on
+    /// this machine: the conditions in its name, such as `##os.darwin`, and those
+    /// `when` gives it or a directory above it all hold.
+    pub fn applies(&self, stored: &Path) -> io::Result<bool> {
+        if !alternate::applies(stored)? {
+            return Ok(false);
+        }
+        for (path, conditions) in self.when.iter().filter(|(path, _)| stored.starts_with(path)) {
+            if !alternate::holds(conditions, &format!("the [when] entry for '{}' in dfl.toml", path.display()))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Loads the manifest of the repository at `dfl_path`. Repositories without a
+/// manifest predate it and use the flat layout. `machine_mode` comes from the user configuration.
+pub fn load(dfl_path: &Path) -> io::Result<Manifest> {
+    let path = dfl_path.join(MANIFEST_FILE);
+    let mut manifest = if path.exists() { parse(&fs::read_to_string(&path)?, &path.display().to_string())? } else { Manifest::default() };
+    manifest.machine_mode = config::load()?.link_mode;
+    Ok(manifest)
+}
+
+/// Parses the contents of a manifest, such as one read from a commit. `origin`
+/// names where it came from in errors.
+pub fn parse(contents: &str, origin: &str) -> io::Result<Manifest> {
+    toml::from_str(contents).map_err(|e| errors::error(Code::InvalidConfig, format!("Error parsing {}: {}", origin, e)))
+}
+
+/// Writes the manifest to the root of the repository.
+pub fn save(dfl_path: &Path, manifest: &Manifest) -> io::Result<()> {
+    let contents = toml::to_string(manifest).map_err(io::Error::other)?;
+    fs::write(dfl_path.join(MANIFEST_FILE), contents)
+}
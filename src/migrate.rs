@@ -0,0 +1,223 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::backup;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::githooks;
+use crate::repo;
+use crate::state;
+use crate::ui::{self, message_box};
+
+/// A symlink into the old repository, with what it leads to inside it.
+struct Link {
+    path: PathBuf,
+    /// Path of what the link points to, relative to the repository.
+    stored: PathBuf,
+    /// The link was written relative to its own directory.
+    relative: bool,
+}
+
+/// Lists the directories an older dfl used that are still in use, each with where
+/// it moves to: the repository, the configuration, the state, and the backups.
+fn planned_moves(home_dir: &Path) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut moves = Vec::new();
+    let legacy_repo = home_dir.join(repo::LEGACY_DIR);
+    if config::repo_path(&config::load()?)? == legacy_repo && fs::symlink_metadata(&legacy_repo).is_ok() {
+        let new_repo = repo::xdg_repo_path()?;
+        if fs::symlink_metadata(&new_repo).is_ok() {
+            return Err(errors::error(
+                Code::MigrateFailed,
+                format!("Both {} and {} exist, so the repository cannot be moved. Move or remove one of them first.", repo::display(&legacy_repo), repo::display(&new_repo)),
+            ));
+        }
+        moves.push((legacy_repo, new_repo));
+    }
+    let (config_dir, legacy_config_dir) = config::config_dirs()?;
+    let (state_dir, legacy_state_dir) = state::state_dirs()?;
+    for (current, legacy) in [(config_dir, legacy_config_dir), (state_dir.clone(), legacy_state_dir)] {
+        if repo::current_or_legacy(current.clone(), legacy.clone()) != current {
+            moves.push((legacy, current));
+        }
+    }
+    let backups_dir = state_dir.join("backups");
+    if repo::current_or_legacy(backups_dir.clone(), home_dir.join(backup::LEGACY_DIR)) != backups_dir {
+        moves.push((home_dir.join(backup::LEGACY_DIR), backups_dir));
+    }
+    Ok(moves)
+}
+
+/// Finds the symlinks into the repository at `dfl_path`: in the home directory, in
+/// the directories of managed entries, and anywhere below directories deployed
+/// file by file.
+fn find_links(dfl_path: &Path, home_dir: &Path) -> io::Result<Vec<Link>> {
+    let mut dirs = BTreeSet::from([home_dir.to_path_buf()]);
+    let mut trees = Vec::new();
+    for entry in repo::managed_entries(dfl_path)? {
+        if let Some(parent) = entry.target.parent() {
+            dirs.insert(parent.to_path_buf());
+        }
+        if fs::symlink_metadata(&entry.target).is_ok_and(|m| m.is_dir()) {
+            trees.push(entry.target);
+        }
+    }
+    while let Some(tree) = trees.pop() {
+        if dirs.insert(tree.clone()) {
+            for child in fs::read_dir(&tree)? {
+                let child = child?;
+                if child.file_type()?.is_dir() {
+                    trees.push(child.path());
+                }
+            }
+        }
+    }
+
+    // A link may lead into the repository through a symlinked path to it.
+    let roots = [Some(dfl_path.to_path_buf()), fs::canonicalize(dfl_path).ok()];
+    let mut links = Vec::new();
+    for dir in dirs {
+        let Ok(children) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for child in children {
+            let path = child?.path();
+            let Ok(target) = fs::read_link(&path) else {
+                continue;
+            };
+            let resolved = fsutil::normalize(&dir.join(&target));
+            if let Some(stored) = roots.iter().flatten().find_map(|root| resolved.strip_prefix(root).ok()) {
+                links.push(Link { stored: stored.to_path_buf(), relative: target.is_relative(), path });
+            }
+        }
+    }
+    Ok(links)
+}
+
+/// Points `link` at the same entry in the repository at `dfl_path`, replacing the
+/// old link with a rename so it is never missing.
+fn relink(link: &Link, dfl_path: &Path) -> io::Result<()> {
+    let staged = fsutil::staging_path(&link.path);
+    if fs::symlink_metadata(&staged).is_ok() {
+        fs::remove_file(&staged)?;
+    }
+    fsutil::symlink_to(&dfl_path.join(&link.stored), &staged, link.relative)?;
+    fs::rename(&staged, &link.path)
+}
+
+/// What a migration did so far, so a failure part way can be undone.
+#[derive(Default)]
+struct Progress<'a> {
+    /// Directories moved, in order.
+    moved: Vec<(&'a Path, &'a Path)>,
+    /// Symlinks pointed at the new repository.
+    relinked: Vec<&'a Link>,
+    /// The configuration file as it was before `repo_path` was removed from it.
+    config: Option<Vec<u8>>,
+}
+
+/// Makes the `moves` and points the `links` into the repository at its new place,
+/// recording each step in `done`.
+fn apply<'a>(moves: &'a [(PathBuf, PathBuf)], links: &'a [Link], legacy_repo: &Path, done: &mut Progress<'a>) -> io::Result<()> {
+    for (from, to) in moves {
+        fsutil::create_parent_dirs(to)?;
+        fsutil::move_path(from, to).map_err(|e| errors::error(Code::MigrateFailed, format!("Could not move {} to {}: {}", repo::display(from), repo::display(to), e)))?;
+        done.moved.push((from, to));
+        ui::say(&format!("✅ Moved {} to {}", repo::display(from), repo::display(to)));
+        if from == legacy_repo {
+            // The configuration may name the old place explicitly; the default now finds the new one.
+            done.config = fs::read(config::config_path()?).ok();
+            config::unset("repo_path")?;
+            for link in links {
+                relink(link, to).map_err(|e| errors::error(Code::MigrateFailed, format!("Could not repoint '{}': {}", link.path.display(), e)))?;
+                done.relinked.push(link);
+            }
+            // The hooks run dfl on the repository they were installed in.
+            if config::load()?.git_hooks {
+                githooks::install(to)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Undoes what `apply` recorded in `done`, newest first. Steps that fail are
+/// reported and skipped so the rest can still be undone; returns how many failed.
+fn undo(done: Progress, legacy_repo: &Path) -> usize {
+    let mut failed = 0;
+    for link in done.relinked.iter().rev() {
+        if let Err(e) = relink(link, legacy_repo) {
+            tracing::error!("Could not point '{}' back at {}: {}", link.path.display(), repo::display(legacy_repo), e);
+            failed += 1;
+        }
+    }
+    for (from, to) in done.moved.iter().rev() {
+        if let Err(e) = fsutil::move_path(to, from) {
+            tracing::error!("Could not move {} back to {}: {}", repo::display(to), repo::display(from), e);
+            failed += 1;
+        }
+    }
+    // Written after the moves, so it lands where the configuration is back to.
+    if let Some(contents) = done.config
+        && let Err(e) = config::config_path().and_then(|path| fs::write(path, contents))
+    {
+        tracing::error!("Could not restore the configuration file: {}", e);
+        failed += 1;
+    }
+    if done.moved.iter().any(|(from, _)| *from == legacy_repo)
+        && config::load().is_ok_and(|config| config.git_hooks)
+        && let Err(e) = githooks::install(legacy_repo)
+    {
+        tracing::error!("Could not reinstall the git hooks in {}: {}", repo::display(legacy_repo), e);
+        failed += 1;
+    }
+    failed
+}
+
+/// Handles the 'migrate' command.
+/// It moves what an older dfl kept in `~/.dfl`, `~/.dfl-backups`, and the system's
+/// configuration and state directories to the XDG base directories, and points
+/// every symlink into the old repository at the new one, putting everything back if
+/// a step fails. With `dry_run` it only lists what it would move.
+pub fn handle_migrate_command(dry_run: bool) -> io::Result<()> {
+    if std::env::var_os(repo::DIR_VARIABLE).is_some_and(|dir| !dir.is_empty()) {
+        return Err(errors::error(Code::InvalidArgument, "'dfl migrate' moves the repository in ~/.dfl, but DFL_DIR, --repo, or --profile names another one. Run it without them."));
+    }
+    let home_dir = repo::home_dir()?;
+    let moves = planned_moves(&home_dir)?;
+    if moves.is_empty() {
+        ui::say("✅ Nothing to migrate; dfl already uses the XDG base directories.");
+        return Ok(());
+    }
+
+    let legacy_repo = home_dir.join(repo::LEGACY_DIR);
+    let moves_repo = moves.iter().any(|(from, _)| *from == legacy_repo);
+    let links = if moves_repo { find_links(&legacy_repo, &home_dir)? } else { Vec::new() };
+    if dry_run {
+        for (from, to) in &moves {
+            println!("{} -> {}", repo::display(from), repo::display(to));
+        }
+        if moves_repo {
+            println!("\n{} symlink(s) into {} would be repointed. Run 'dfl migrate' to move everything.", links.len(), repo::display(&legacy_repo));
+        }
+        return Ok(());
+    }
+
+    let mut done = Progress::default();
+    if let Err(e) = apply(&moves, &links, &legacy_repo, &mut done) {
+        ui::say("Migration failed; putting everything back...");
+        if undo(done, &legacy_repo) > 0 {
+            return Err(errors::error(Code::MigrateFailed, format!("{}\nSome changes could not be undone; see the messages above.", e)));
+        }
+        return Err(e);
+    }
+
+    let mut summary = format!("Moved {} director{} to the XDG base directories.", moves.len(), if moves.len() == 1 { "y" } else { "ies" });
+    if moves_repo {
+        summary.push_str(&format!("\nRepointed {} symlink(s) at the repository in {}.", links.len(), repo::display(&repo::xdg_repo_path()?)));
+    }
+    message_box("Migrated", &summary);
+    Ok(())
+}
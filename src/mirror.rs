@@ -0,0 +1,278 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::git;
+use crate::manifest;
+use crate::repo;
+use crate::sparse;
+use crate::state;
+use crate::ui;
+
+/// A place `push` also copies the repository to, for machines that cannot reach
+/// the git host, declared in `dfl.toml`, e.g.
+///
+/// ```toml
+/// [mirrors.nas]
+/// rsync = "me@nas:/srv/dotfiles"
+///
+/// [mirrors.cloud]
+/// s3 = "s3://my-bucket/dotfiles.bundle"
+/// endpoint = "https://s3.eu-central-1.wasabisys.com"
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Mirror {
+    /// An rsync destination, usually `user@host:/path` over SSH, that receives a
+    /// copy of the repository's `.git` directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rsync: Option<String>,
+    /// An `s3://bucket/key` URL that receives the repository as a git bundle.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s3: Option<String>,
+    /// The endpoint of S3-compatible storage other than AWS.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+}
+
+impl Mirror {
+    /// Builds a mirror from a target as given on the command line: an `s3://`
+    /// URL, or anything else as an rsync destination.
+    pub fn parse(target: &str, endpoint: Option<&str>) -> Mirror {
+        let endpoint = endpoint.map(str::to_string);
+        if target.starts_with("s3://") {
+            Mirror { s3: Some(target.to_string()), endpoint, ..Mirror::default() }
+        } else {
+            Mirror { rsync: Some(target.to_string()), endpoint, ..Mirror::default() }
+        }
+    }
+
+    /// Returns where the mirror lives, for messages and for matching a target.
+    pub fn target(&self) -> &str {
+        self.rsync.as_deref().or(self.s3.as_deref()).unwrap_or_default()
+    }
+}
+
+/// Parts of `.git` that only matter on this machine and stay out of rsync mirrors:
+/// remotes and credentials in `config`, hooks, the index, and logs.
+const LOCAL_ONLY: &[&str] = &["/config", "/hooks/", "/index", "/logs/", "/*_HEAD", "/COMMIT_EDITMSG", "/dfl/", "/dfl.lock"];
+
+/// Git configuration keys that mark a remote as a read-only copy of a mirror.
+const TARGET_KEY: &str = "dflmirror";
+const ENDPOINT_KEY: &str = "dflendpoint";
+
+/// Returns the local bare repository a mirror of the repository at `dfl_path` is
+/// downloaded into before git reads it.
+fn cache_dir(dfl_path: &Path, name: &str) -> PathBuf {
+    state::repo_dir(dfl_path).join("mirrors").join(name)
+}
+
+/// Returns `path` as the directory argument rsync copies the contents of.
+fn rsync_dir(path: &str) -> String {
+    format!("{}/", path.trim_end_matches('/'))
+}
+
+/// Runs `aws s3 cp` from `from` to `to`, against `endpoint` when given.
+fn s3_copy(from: &str, to: &str, endpoint: Option<&str>) -> io::Result<()> {
+    let mut args = vec!["s3", "cp", "--only-show-errors", from, to];
+    if let Some(endpoint) = endpoint {
+        args.extend(["--endpoint-url", endpoint]);
+    }
+    cmd("aws", &args).run().map(drop).map_err(|e| errors::error(Code::MirrorFailed, format!("Error copying {} to {} with the aws CLI: {}", from, to, e)))
+}
+
+/// Copies the repository to a mirror: its `.git` directory with rsync, or a
+/// bundle of every branch and tag to S3.
+fn upload(dfl_path: &Path, name: &str, mirror: &Mirror) -> io::Result<()> {
+    if let Some(target) = &mirror.rsync {
+        let mut args = vec!["-az".to_string(), "--delete".to_string()];
+        args.extend(LOCAL_ONLY.iter().map(|pattern| format!("--exclude={}", pattern)));
+        args.push(rsync_dir(&git::git_dir(dfl_path).to_string_lossy()));
+        args.push(rsync_dir(target));
+        return cmd("rsync", &args).run().map(drop).map_err(|e| errors::error(Code::MirrorFailed, format!("Error running rsync to {}: {}", target, e)));
+    }
+    let Some(url) = &mirror.s3 else {
+        return Err(errors::error(Code::InvalidConfig, format!("Mirror '{}' in dfl.toml needs either rsync or s3.", name)));
+    };
+    let bundle = cache_dir(dfl_path, name).with_extension("bundle");
+    if let Some(parent) = bundle.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    cmd!("git", "bundle", "create", "--quiet", &bundle, "--all")
+        .dir(dfl_path)
+        .run()
+        .map_err(|e| errors::error(Code::MirrorFailed, format!("Error bundling the repository, which needs the git command: {}", e)))?;
+    let copied = s3_copy(&bundle.to_string_lossy(), url, mirror.endpoint.as_deref());
+    fs::remove_file(&bundle)?;
+    copied
+}
+
+/// Brings the bare repository at `cache` up to date with a mirror.
+fn download(mirror: &Mirror, cache: &Path) -> io::Result<()> {
+    fs::create_dir_all(cache)?;
+    if let Some(target) = &mirror.rsync {
+        cmd!("rsync", "-az", "--delete", "--exclude=/config", rsync_dir(target), rsync_dir(&cache.to_string_lossy()))
+            .run()
+            .map_err(|e| errors::error(Code::MirrorFailed, format!("Error running rsync from {}: {}", target, e)))?;
+        // The mirror leaves out the config of the machine it came from; git needs one of its own.
+        if !cache.join("config").exists() {
+            fs::write(cache.join("config"), "[core]\n\trepositoryformatversion = 0\n\tbare = true\n")?;
+        }
+        return Ok(());
+    }
+    let Some(url) = &mirror.s3 else {
+        return Err(errors::error(Code::InvalidConfig, "A mirror needs either rsync or s3."));
+    };
+    let failed = |e: io::Error| errors::error(Code::MirrorFailed, format!("Error reading the bundle from {}, which needs the git command: {}", url, e));
+    if !cache.join("HEAD").exists() {
+        cmd!("git", "init", "--bare", "--quiet", cache).run().map_err(failed)?;
+    }
+    let bundle = cache.with_extension("bundle");
+    s3_copy(url, &bundle.to_string_lossy(), mirror.endpoint.as_deref())?;
+    let fetched = cmd!("git", "fetch", "--quiet", "--prune", &bundle, "+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*").dir(cache).run().map_err(failed);
+    // A bundle names the commit HEAD is on, not the branch; point HEAD at the branch that has it.
+    let heads = cmd!("git", "bundle", "list-heads", &bundle).dir(cache).read().map_err(failed);
+    fs::remove_file(&bundle)?;
+    fetched?;
+    let heads: Vec<(String, String)> = heads?.lines().filter_map(|line| line.split_once(' ')).map(|(id, name)| (id.to_string(), name.to_string())).collect();
+    let head = heads.iter().find(|(_, name)| name == "HEAD").map(|(id, _)| id.clone());
+    if let Some((_, branch)) = heads.iter().find(|(id, name)| Some(id) == head.as_ref() && name.starts_with("refs/heads/")) {
+        cmd!("git", "symbolic-ref", "HEAD", branch).dir(cache).run().map_err(failed)?;
+    }
+    Ok(())
+}
+
+/// Returns the mirror a remote was cloned from, or `None` for ordinary remotes.
+pub fn mirror_of(dfl_path: &Path, remote: &str) -> io::Result<Option<Mirror>> {
+    let Some(target) = git::get_config(dfl_path, &format!("remote.{}.{}", remote, TARGET_KEY))? else {
+        return Ok(None);
+    };
+    let endpoint = git::get_config(dfl_path, &format!("remote.{}.{}", remote, ENDPOINT_KEY))?;
+    Ok(Some(Mirror::parse(&target, endpoint.as_deref())))
+}
+
+/// Returns true if `remote` is a read-only copy of a mirror.
+pub fn is_mirror(dfl_path: &Path, remote: &str) -> io::Result<bool> {
+    Ok(mirror_of(dfl_path, remote)?.is_some())
+}
+
+/// Copies the repository to every mirror in `dfl.toml` after a push. A mirror
+/// that fails only warns, since the push itself went through.
+pub fn publish(dfl_path: &Path) -> io::Result<()> {
+    for (name, mirror) in manifest::load(dfl_path)?.mirrors {
+        match upload(dfl_path, &name, &mirror) {
+            Ok(()) => ui::say(&format!("✅ Mirrored to {} ({}).", name, mirror.target())),
+            Err(e) => ui::warn(&format!("Could not update mirror '{}': {}", name, e))?,
+        }
+    }
+    Ok(())
+}
+
+/// Downloads the mirror the current branch pulls from, if it pulls from one, so
+/// the following fetch or pull sees what was last pushed to it.
+pub fn refresh_upstream(dfl_path: &Path) -> io::Result<()> {
+    let branch = git::branch(dfl_path)?;
+    let remote = git::upstream(dfl_path, &branch)?.map(|(remote, _)| remote).unwrap_or_else(|| "origin".to_string());
+    let Some(mirror) = mirror_of(dfl_path, &remote)? else {
+        return Ok(());
+    };
+    let Some(cache) = git::remote_url(dfl_path, &remote)? else {
+        return Ok(());
+    };
+    ui::say(&format!("Downloading mirror {}...", mirror.target()));
+    download(&mirror, Path::new(&cache))
+}
+
+/// Clones the repository from a mirror into `dfl_path`. The mirror becomes a
+/// read-only remote named as in `dfl.toml`, or `mirror`, that pulls download again.
+pub fn clone(dfl_path: &Path, mirror: &Mirror) -> io::Result<String> {
+    // Downloaded next to the repository, since its own state directory comes with the clone.
+    let staging = dfl_path.with_file_name(format!(".{}.dfl-mirror", dfl_path.file_name().unwrap_or_default().to_string_lossy()));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fsutil::create_parent_dirs(dfl_path)?;
+    download(mirror, &staging)?;
+    git::clone(dfl_path, &staging.to_string_lossy(), None, None)?;
+    sparse::check_out(dfl_path)?;
+
+    let name = manifest::load(dfl_path)?.mirrors.into_iter().find(|(_, declared)| declared.target() == mirror.target()).map_or_else(|| "mirror".to_string(), |(name, _)| name);
+    let cache = cache_dir(dfl_path, &name);
+    fsutil::create_parent_dirs(&cache)?;
+    fs::rename(&staging, &cache)?;
+    git::rename_remote(dfl_path, "origin", &name)?;
+    git::set_remote_url(dfl_path, &name, &cache.to_string_lossy())?;
+    git::set_config(dfl_path, &format!("remote.{}.{}", name, TARGET_KEY), mirror.target())?;
+    if let Some(endpoint) = &mirror.endpoint {
+        git::set_config(dfl_path, &format!("remote.{}.{}", name, ENDPOINT_KEY), endpoint)?;
+    }
+    Ok(name)
+}
+
+/// What the 'mirror' command does.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum MirrorCommand {
+    /// Lists the mirrors in dfl.toml (the default).
+    List,
+    /// Adds a mirror that every push copies the repository to.
+    Add {
+        name: String,
+        /// Where the copy goes: user@host:/path over SSH, or s3://bucket/key.
+        target: String,
+        /// Points at S3-compatible storage instead of AWS.
+        #[arg(long, value_name = "URL")]
+        endpoint: Option<String>,
+    },
+    /// Removes a mirror from dfl.toml; the copy already there is left alone.
+    Remove { name: String },
+    /// Copies the repository to every mirror now.
+    Push,
+}
+
+/// Handles the 'mirror' command.
+/// It lists the mirrors in `dfl.toml`, adds or removes one, or copies the
+/// repository to them right away with `push`, as every `dfl push` does.
+pub fn handle_mirror_command(command: Option<MirrorCommand>) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let mut manifest = manifest::load(&dfl_path)?;
+    match command.unwrap_or(MirrorCommand::List) {
+        MirrorCommand::List => {
+            if manifest.mirrors.is_empty() {
+                println!("No mirrors yet. Add one with 'dfl mirror add <name> <user@host:/path | s3://bucket/key>'.");
+            }
+            for (name, mirror) in &manifest.mirrors {
+                println!("{}\t{}", name, mirror.target());
+            }
+            Ok(())
+        }
+        MirrorCommand::Add { name, target, endpoint } => {
+            manifest.mirrors.insert(name.clone(), Mirror::parse(&target, endpoint.as_deref()));
+            manifest::save(&dfl_path, &manifest)?;
+            ui::say(&format!("✅ Added mirror '{}' ({}). Every 'dfl push' now copies the repository there; commit dfl.toml to keep it on every machine.", name, target));
+            Ok(())
+        }
+        MirrorCommand::Remove { name } => {
+            if manifest.mirrors.remove(&name).is_none() {
+                return Err(errors::error(Code::InvalidArgument, format!("There is no mirror named '{}'. Run 'dfl mirror list' to see them.", name)));
+            }
+            manifest::save(&dfl_path, &manifest)?;
+            ui::say(&format!("✅ Removed mirror '{}'. The copy already there is left alone; commit dfl.toml to keep this.", name));
+            Ok(())
+        }
+        MirrorCommand::Push => {
+            if manifest.mirrors.is_empty() {
+                return Err(errors::error(Code::InvalidArgument, "There are no mirrors in dfl.toml to push to."));
+            }
+            publish(&dfl_path)
+        }
+    }
+}
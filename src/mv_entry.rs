@@ -0,0 +1,301 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bare;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::git;
+use crate::layout::{self, DEFAULT_PACKAGE, LayoutKind};
+use crate::lock;
+use crate::manifest::{self, Manifest};
+use crate::profile;
+use crate::repo::{self, LinkMode};
+use crate::ui::{self, message_box};
+use crate::verify;
+
+/// What `dfl.toml` records about an entry, carried along when it moves.
+#[derive(Debug, Default)]
+struct Settings {
+    /// The target of a path mapping.
+    target: Option<PathBuf>,
+    /// The mode it is deployed with, set on it or on a directory above it.
+    mode: LinkMode,
+    /// Recorded file modes, keyed by the path below the entry ("" for the entry itself).
+    permissions: Vec<(PathBuf, String)>,
+    unfold: bool,
+    /// The `[when]` conditions set on it or on a directory above it.
+    when: Vec<String>,
+    reload: Option<String>,
+    /// Sparse classes that name it, with the suffix its pattern was written with.
+    sparse: Vec<(String, String)>,
+}
+
+/// Returns the mode `dfl.toml` gives a stored path, leaving out `link_mode` from
+/// the user configuration, which only applies to this machine.
+fn recorded_mode(manifest: &Manifest, stored: &Path) -> LinkMode {
+    manifest.modes.iter().filter(|(path, _)| stored.starts_with(path)).max_by_key(|(path, _)| path.components().count()).map_or(manifest.mode, |(_, mode)| *mode)
+}
+
+/// Returns the patterns of a sparse class that name the stored path itself.
+fn sparse_patterns(stored: &Path) -> [String; 2] {
+    let pattern = format!("/{}", stored.display());
+    [pattern.clone(), format!("{}/", pattern)]
+}
+
+/// Removes what `dfl.toml` records about the entry at `stored` and returns it.
+fn take(manifest: &mut Manifest, stored: &Path) -> Settings {
+    let mut settings = Settings { mode: recorded_mode(manifest, stored), ..Settings::default() };
+    manifest.entries.retain(|entry| entry != stored);
+    settings.target = manifest.paths.remove(stored);
+    manifest.modes.remove(stored);
+    manifest.permissions.retain(|path, mode| match path.strip_prefix(stored) {
+        Ok(rest) => {
+            settings.permissions.push((rest.to_path_buf(), mode.clone()));
+            false
+        }
+        Err(_) => true,
+    });
+    settings.unfold = manifest.unfold.contains(&stored.to_path_buf());
+    manifest.unfold.retain(|entry| entry != stored);
+    settings.when = manifest.when.iter().filter(|(path, _)| stored.starts_with(path)).map(|(_, conditions)| conditions.clone()).collect();
+    manifest.when.remove(stored);
+    settings.reload = manifest.reload.remove(&stored.to_string_lossy().into_owned());
+    let names = sparse_patterns(stored);
+    for (class, patterns) in manifest.sparse.iter_mut() {
+        patterns.retain(|pattern| match names.iter().position(|name| name == pattern) {
+            Some(index) => {
+                settings.sparse.push((class.clone(), if index == 0 { String::new() } else { "/".to_string() }));
+                false
+            }
+            None => true,
+        });
+    }
+    settings
+}
+
+/// Records the settings of an entry taken from another place at `stored`, leaving
+/// out what `dfl.toml` already gives it there.
+fn put(manifest: &mut Manifest, stored: &Path, settings: Settings) {
+    match settings.target {
+        Some(target) => {
+            manifest.paths.insert(stored.to_path_buf(), target);
+        }
+        None => manifest.layout.layout().register(manifest, stored),
+    }
+    if recorded_mode(manifest, stored) != settings.mode {
+        manifest.modes.insert(stored.to_path_buf(), settings.mode);
+    }
+    for (rest, mode) in settings.permissions {
+        let path = if rest.as_os_str().is_empty() { stored.to_path_buf() } else { stored.join(rest) };
+        manifest.permissions.insert(path, mode);
+    }
+    if settings.unfold && !manifest.unfold.iter().any(|entry| entry == stored) {
+        manifest.unfold.push(stored.to_path_buf());
+        manifest.unfold.sort();
+    }
+    let imposed: Vec<&String> = manifest.when.iter().filter(|(path, _)| stored.starts_with(path)).map(|(_, conditions)| conditions).collect();
+    let when: Vec<String> = settings.when.into_iter().filter(|conditions| !imposed.contains(&conditions)).collect();
+    if !when.is_empty() {
+        manifest.when.insert(stored.to_path_buf(), when.join(","));
+    }
+    if let Some(command) = settings.reload {
+        manifest.reload.insert(stored.to_string_lossy().into_owned(), command);
+    }
+    // A class only names what its machines check out, so one the repository does not have is not started.
+    for (class, suffix) in settings.sparse {
+        if let Some(patterns) = manifest.sparse.get_mut(&class) {
+            patterns.push(format!("/{}{}", stored.display(), suffix));
+        }
+    }
+}
+
+/// Points the link at `path`, or each link below it when it is a real directory
+/// deployed file by file, from where it leads under `from` to the same place under
+/// `to`, keeping its style. Returns how many links were repointed.
+fn repoint(path: &Path, from: &[PathBuf], to: &Path) -> io::Result<usize> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(0);
+    };
+    if metadata.is_dir() {
+        let mut repointed = 0;
+        for child in fs::read_dir(path)? {
+            repointed += repoint(&child?.path(), from, to)?;
+        }
+        return Ok(repointed);
+    }
+    if !metadata.file_type().is_symlink() {
+        return Ok(0);
+    }
+    let link = fs::read_link(path)?;
+    let resolved = fsutil::normalize(&path.parent().map_or(link.clone(), |parent| parent.join(&link)));
+    let Some(rest) = from.iter().find_map(|root| resolved.strip_prefix(root).ok()) else {
+        return Ok(0);
+    };
+    let original = if rest.as_os_str().is_empty() { to.to_path_buf() } else { to.join(rest) };
+    let staged = fsutil::staging_path(path);
+    if fs::symlink_metadata(&staged).is_ok() {
+        fs::remove_file(&staged)?;
+    }
+    fsutil::symlink_to(&original, &staged, link.is_relative())?;
+    fs::rename(&staged, path)?;
+    Ok(1)
+}
+
+/// Commits everything changed in the repository at `dfl_path`, and pushes if `auto_push` is on.
+fn commit(dfl_path: &Path, moved: &[PathBuf], default: String) -> io::Result<()> {
+    verify::record_checksums(dfl_path)?;
+    git::add_all(dfl_path).map_err(|e| errors::error(Code::GitFailed, format!("Error staging changes: {}", e)))?;
+    let message = git::commit_message("mv-entry", moved, default)?;
+    git::commit(dfl_path, &message).map_err(|e| errors::error(Code::GitFailed, format!("Error committing changes: {}", e)))?;
+    ui::say(&format!("✅ Changes committed in {}.", repo::display(dfl_path)));
+    if config::load()?.auto_push {
+        crate::remote::push_after_commit(dfl_path)?;
+    }
+    Ok(())
+}
+
+/// Handles the 'mv-entry' command.
+/// It moves the entry deployed at `path`, with every variant of it such as
+/// `.gitconfig##os.darwin`, into another package, another profile's repository,
+/// or both. Its settings in `dfl.toml` move with it, its link is pointed at the
+/// new place, and the move is committed in each repository it touched.
+pub fn handle_mv_entry_command(path: &str, to_package: Option<&str>, to_profile: Option<&str>) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+    let config = config::load()?;
+    let source_profile = env::var(profile::PROFILE_VARIABLE).unwrap_or_else(|_| profile::DEFAULT.to_string());
+    let destination = match to_profile {
+        Some(name) => profile::repo_path(&config, name)?,
+        None => dfl_path.clone(),
+    };
+    let same_repo = destination == dfl_path;
+    if !same_repo {
+        if !git::git_dir(&destination).exists() {
+            let name = to_profile.unwrap_or_default();
+            return Err(errors::error(Code::NotInitialized, format!("The {} profile has no repository at {}. Run 'dfl --profile {} init' or 'dfl --profile {} clone' first.", name, repo::display(&destination), name, name)));
+        }
+        if bare::in_use(&destination) {
+            return Err(errors::error(Code::InvalidArgument, format!("'mv-entry' works on symlinked dotfiles, and {} is a bare repository tracking files in place.", repo::display(&destination))));
+        }
+    }
+
+    let home_dir = repo::home_dir()?;
+    let target = fsutil::normalize(&std::path::absolute(repo::expand_home(Path::new(path), &home_dir))?);
+    // Every variant of the entry moves together, including those for other machines.
+    let mut variants = Vec::new();
+    for entry in repo::all_entries(&dfl_path)?.into_iter().filter(|entry| entry.target == target) {
+        variants.push(entry.stored.strip_prefix(&dfl_path).map_err(io::Error::other)?.to_path_buf());
+    }
+    if variants.is_empty() {
+        return Err(errors::error(Code::InvalidArgument, format!("{} is not managed by dfl. Run 'dfl list' to see what is.", repo::display(&target))));
+    }
+    let deployed = repo::managed_entries(&dfl_path)?.into_iter().find(|entry| entry.target == target);
+
+    let mut manifest = manifest::load(&dfl_path)?;
+    let mut destination_manifest = if same_repo { None } else { Some(manifest::load(&destination)?) };
+    let old_layout = manifest.layout.layout();
+    let new_layout = destination_manifest.as_ref().unwrap_or(&manifest).layout.layout();
+    if to_package.is_some() && variants.iter().any(|stored| manifest.paths.contains_key(stored)) {
+        return Err(errors::error(Code::InvalidArgument, format!("{} is deployed through [paths] in dfl.toml, so it is in no package.", repo::display(&target))));
+    }
+    if to_package.is_some() && new_layout.kind() != LayoutKind::Package {
+        return Err(errors::error(
+            Code::InvalidArgument,
+            format!("'--to-package' needs the package layout, and {} uses the {} layout. Run 'dfl layout convert package' first.", repo::display(&destination), new_layout.kind()),
+        ));
+    }
+
+    let mut moves = Vec::new();
+    for variant in variants {
+        let new_stored = if manifest.paths.contains_key(&variant) {
+            variant.clone()
+        } else {
+            let home_path = old_layout.target_path(&variant);
+            if new_layout.kind() == LayoutKind::Flat && home_path.components().count() > 1 {
+                return Err(errors::error(
+                    Code::InvalidArgument,
+                    format!("{} uses the flat layout, which would link '{}' at '~/{}'. Run 'dfl layout convert home' there first.", repo::display(&destination), home_path.display(), home_path.file_name().unwrap_or_default().to_string_lossy()),
+                ));
+            }
+            let package = to_package.map(str::to_string).or_else(|| old_layout.package(&variant)).unwrap_or_else(|| DEFAULT_PACKAGE.to_string());
+            new_layout.store_path(&home_path, Some(&package))?
+        };
+        layout::check_store_path(&new_stored)?;
+        moves.push((variant, new_stored));
+    }
+    if same_repo && moves.iter().all(|(stored, new_stored)| stored == new_stored) {
+        ui::say(&format!("➖ {} is already stored at '{}'.", repo::display(&target), moves[0].0.display()));
+        return Ok(());
+    }
+    for (_, new_stored) in &moves {
+        if fs::symlink_metadata(destination.join(new_stored)).is_ok() {
+            return Err(errors::error(Code::AlreadyInRepo, format!("'{}' already exists in {}. Rename or remove one of them first.", new_stored.display(), repo::display(&destination))));
+        }
+    }
+    // Held until the move is committed, like the lock on the repository it comes from.
+    let _lock = if same_repo { None } else { lock::acquire(&destination, "mv-entry", false)? };
+
+    let where_to = match to_profile {
+        Some(name) if !same_repo => format!(" in the {} profile", name),
+        _ => String::new(),
+    };
+    message_box("Moving entry", &format!("Moving {} to '{}'{}.", repo::display(&target), moves[0].1.display(), where_to));
+
+    // A link may lead into the repository through a symlinked path to it.
+    let old_roots = match &deployed {
+        Some(entry) => vec![entry.stored.clone(), fs::canonicalize(&entry.stored)?],
+        None => Vec::new(),
+    };
+    let mut new_root = None;
+    for (stored, new_stored) in &moves {
+        let from = dfl_path.join(stored);
+        let to = destination.join(new_stored);
+        fsutil::create_parent_dirs(&to)?;
+        fsutil::move_path(&from, &to).map_err(|e| errors::error(Code::MoveFailed, format!("Error moving '{}' to '{}': {}", stored.display(), new_stored.display(), e)))?;
+        layout::remove_empty_parents(&dfl_path, &from);
+        let settings = take(&mut manifest, stored);
+        put(destination_manifest.as_mut().unwrap_or(&mut manifest), new_stored, settings);
+        if deployed.as_ref().is_some_and(|entry| entry.stored == from) {
+            new_root = Some(to);
+        }
+        ui::say(&format!("✅ Moved '{}' to '{}'{}", stored.display(), new_stored.display(), where_to));
+    }
+    manifest::save(&dfl_path, &manifest)?;
+    if let Some(destination_manifest) = &destination_manifest {
+        manifest::save(&destination, destination_manifest)?;
+    }
+
+    // Copies, hard links, and rendered templates already hold the contents, so only links need to follow.
+    if let Some(new_root) = new_root {
+        let repointed = repoint(&target, &old_roots, &new_root)?;
+        if repointed > 0 {
+            ui::say(&format!("✅ Pointed {} link(s) at the new place.", repointed));
+        }
+    }
+
+    let moved: Vec<PathBuf> = moves.iter().map(|(_, new_stored)| new_stored.clone()).collect();
+    let names: Vec<String> = moves.iter().map(|(stored, _)| stored.display().to_string()).collect();
+    if same_repo {
+        let renames: Vec<String> = moves.iter().map(|(stored, new_stored)| format!("{} to {}", stored.display(), new_stored.display())).collect();
+        commit(&dfl_path, &moved, format!("refactor: Move {}", renames.join(", ")))?;
+    } else {
+        let name = to_profile.unwrap_or_default();
+        commit(&dfl_path, &moved, format!("chore: Move {} to the {} profile", names.join(", "), name))?;
+        let added: Vec<String> = moved.iter().map(|stored| stored.display().to_string()).collect();
+        commit(&destination, &moved, format!("feat: Add {} from the {} profile", added.join(", "), source_profile))?;
+    }
+
+    let mut summary = format!("{} is now stored at '{}'{}.", repo::display(&target), moved[0].display(), where_to);
+    if !same_repo {
+        summary.push_str(&format!("\nRun 'dfl --profile {} sync' on your other machines after pulling both repositories.", to_profile.unwrap_or_default()));
+    } else {
+        summary.push_str("\nRun 'dfl sync' on your other machines after pulling.");
+    }
+    message_box("Success", &summary);
+    Ok(())
+}
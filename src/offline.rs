@@ -0,0 +1,99 @@
+use std::io;
+use std::path::Path;
+
+use crate::git;
+use crate::mirror;
+use crate::state;
+use crate::ui;
+
+/// Phrases git, ssh, curl, and libgit2 use when the network or the host is not
+/// there at all, as opposed to refusing a login or a push.
+const NETWORK_ERRORS: &[&str] = &[
+    "could not resolve",
+    "failed to resolve",
+    "name resolution",
+    "name or service not known",
+    "network is unreachable",
+    "no route to host",
+    "timed out",
+    "connection refused",
+    "failed to connect",
+    "could not connect",
+    "couldn't connect",
+];
+
+/// Returns true if `remote` cannot be reached at all, checked after a push or
+/// pull failed to tell being offline apart from other failures.
+pub fn is_unreachable(dfl_path: &Path, remote: &str) -> bool {
+    match git::check_access(dfl_path, remote) {
+        Ok(()) => false,
+        Err(e) => {
+            let message = e.to_string().to_ascii_lowercase();
+            NETWORK_ERRORS.iter().any(|phrase| message.contains(phrase))
+        }
+    }
+}
+
+/// Remembers that `branch` could not be pushed because `remote` was unreachable,
+/// so the next pull or up pushes it, and tells the user so.
+pub fn queue(dfl_path: &Path, branch: &str, remote: &str) -> io::Result<()> {
+    let mut state = state::load_repo(dfl_path)?;
+    state.queued_pushes.insert(branch.to_string());
+    state::save_repo(dfl_path, &state)?;
+    ui::warn(&format!("You are offline: {} cannot be reached. Your commits on '{}' are queued and go out with the next 'dfl push', 'dfl pull', or 'dfl up'.", remote, branch))
+}
+
+/// Forgets a queued push of `branch` once it went through.
+pub fn dequeue(dfl_path: &Path, branch: &str) -> io::Result<()> {
+    let mut state = state::load_repo(dfl_path)?;
+    if state.queued_pushes.remove(branch) {
+        state::save_repo(dfl_path, &state)?;
+    }
+    Ok(())
+}
+
+/// Pushes the branches queued while offline, once a pull or fetch showed the
+/// remote is back. A branch whose push fails again stays queued.
+pub fn flush(dfl_path: &Path) -> io::Result<()> {
+    let queued = state::load_repo(dfl_path)?.queued_pushes;
+    let mut pushed = false;
+    for branch in queued {
+        if !git::branches(dfl_path)?.contains(&branch) {
+            dequeue(dfl_path, &branch)?;
+            continue;
+        }
+        let (remote, remote_branch, set_upstream) = match git::upstream(dfl_path, &branch)? {
+            Some((remote, remote_branch)) => (remote, remote_branch, false),
+            None => ("origin".to_string(), branch.clone(), true),
+        };
+        match git::push(dfl_path, &branch, &remote, &remote_branch, set_upstream) {
+            Ok(()) => {
+                ui::say(&format!("✅ Pushed the commits on '{}' queued while offline.", branch));
+                dequeue(dfl_path, &branch)?;
+                pushed = true;
+            }
+            Err(e) => ui::warn(&format!("The commits on '{}' queued while offline could not be pushed yet: {}", branch, e))?,
+        }
+    }
+    if pushed {
+        mirror::publish(dfl_path)?;
+    }
+    Ok(())
+}
+
+/// Returns each queued branch with how many of its commits the remote does not
+/// have yet, for status output. Branches that were pushed since are left out.
+pub fn queued(dfl_path: &Path) -> io::Result<Vec<(String, usize)>> {
+    let mut pending = Vec::new();
+    for branch in state::load_repo(dfl_path)?.queued_pushes {
+        let Some(head) = git::find_commit(dfl_path, &format!("refs/heads/{}", branch))? else {
+            continue;
+        };
+        let published = git::upstream_head(dfl_path, &branch)?;
+        let count = git::count_commits(dfl_path, published.as_deref(), &head)?;
+        if count > 0 {
+            pending.push((branch, count));
+        }
+    }
+    Ok(pending)
+}
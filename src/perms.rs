@@ -0,0 +1,192 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::errors::{self, Code};
+use crate::manifest::{self, Manifest};
+use crate::repo;
+use crate::ui;
+
+/// A managed path whose mode differs from the one recorded in `dfl.toml`.
+pub struct Mismatch {
+    /// Repository-relative path.
+    pub path: PathBuf,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+/// Returns true for the modes a checkout produces anyway, which need no record.
+fn is_default(mode: u32, is_dir: bool) -> bool {
+    if is_dir { mode == 0o755 } else { mode == 0o644 || mode == 0o755 }
+}
+
+/// Parses a mode written in octal, such as `0600`.
+fn parse_mode(mode: &str) -> io::Result<u32> {
+    u32::from_str_radix(mode, 8).map_err(|_| errors::error(Code::InvalidConfig, format!("'{}' in the [permissions] table of dfl.toml is not an octal mode such as 0600.", mode)))
+}
+
+/// Records the modes of `stored` and everything below it in the `[permissions]`
+/// table, replacing earlier records for those paths. Git only keeps the executable
+/// bit, so only modes a checkout would not reproduce are recorded. Returns whether
+/// the table changed.
+pub fn record(dfl_path: &Path, manifest: &mut Manifest, stored: &Path) -> io::Result<bool> {
+    let before = manifest.permissions.clone();
+    manifest.permissions.retain(|path, _| !path.starts_with(stored));
+    record_tree(dfl_path, manifest, stored)?;
+    Ok(manifest.permissions != before)
+}
+
+fn record_tree(dfl_path: &Path, manifest: &mut Manifest, stored: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(dfl_path.join(stored))?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    let mode = metadata.permissions().mode() & 0o7777;
+    if !is_default(mode, metadata.is_dir()) {
+        manifest.permissions.insert(stored.to_path_buf(), format!("{:04o}", mode));
+    }
+    if metadata.is_dir() {
+        for entry in fs::read_dir(dfl_path.join(stored))? {
+            record_tree(dfl_path, manifest, &stored.join(entry?.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the recorded paths whose current mode differs from the record.
+pub fn mismatches(dfl_path: &Path, manifest: &Manifest) -> io::Result<Vec<Mismatch>> {
+    let mut found = Vec::new();
+    for (path, mode) in &manifest.permissions {
+        let expected = parse_mode(mode)?;
+        // A path deleted from the repository is reported by other commands.
+        let Ok(metadata) = fs::metadata(dfl_path.join(path)) else {
+            continue;
+        };
+        let actual = metadata.permissions().mode() & 0o7777;
+        if actual != expected {
+            found.push(Mismatch { path: path.clone(), expected, actual });
+        }
+    }
+    Ok(found)
+}
+
+/// Re-applies every recorded mode that differs, printing each change. Links point
+/// into the repository, so fixing the stored file fixes what is deployed.
+pub fn apply(dfl_path: &Path, manifest: &Manifest) -> io::Result<usize> {
+    let found = mismatches(dfl_path, manifest)?;
+    for mismatch in &found {
+        fs::set_permissions(dfl_path.join(&mismatch.path), fs::Permissions::from_mode(mismatch.expected))?;
+        ui::say(&format!("🔒 Set mode {:04o} on '{}' (was {:04o})", mismatch.expected, mismatch.path.display(), mismatch.actual));
+    }
+    Ok(found.len())
+}
+
+/// Home-relative paths whose contents must only be readable by their owner. ssh and
+/// gpg refuse to use files there that others can read.
+const SENSITIVE: &[&str] = &[".ssh", ".gnupg", ".netrc"];
+
+/// Makes every managed file below a sensitive path readable only by its owner
+/// (0600, or 0700 for directories and executables), along with the sensitive
+/// directories themselves in the home directory. Each path that was open to other
+/// users is reported, since it may have been exposed already.
+pub fn tighten_sensitive(dfl_path: &Path, home_dir: &Path) -> io::Result<()> {
+    let mut tightened = Vec::new();
+    for entry in repo::managed_entries(dfl_path)? {
+        let Ok(relative) = entry.target.strip_prefix(home_dir) else {
+            continue;
+        };
+        let Some(root) = SENSITIVE.iter().find(|root| relative.starts_with(root)) else {
+            continue;
+        };
+        let root = home_dir.join(root);
+        // The deployed ~/.ssh may be a real directory holding links to single files.
+        if fs::symlink_metadata(&root).is_ok_and(|m| m.is_dir()) {
+            tighten(&root, false, &mut tightened)?;
+        }
+        tighten(&entry.stored, true, &mut tightened)?;
+        // Copies do not share the stored file's mode.
+        if !entry.mode.is_symlink() && fs::symlink_metadata(&entry.target).is_ok() {
+            tighten(&entry.target, true, &mut tightened)?;
+        }
+    }
+
+    if !tightened.is_empty() {
+        let list: Vec<String> = tightened.iter().map(|(path, mode)| format!("  {} (was {:04o})", path.display(), mode)).collect();
+        ui::warn(&format!("These sensitive files were readable by other users; dfl restricted them to you:\n{}\nIf this machine is shared, consider rotating the keys they contain.", list.join("\n")))?;
+    }
+    Ok(())
+}
+
+/// Removes group and other permissions from `path`, and from everything below it when
+/// `recursive` is set, recording each path that had any.
+fn tighten(path: &Path, recursive: bool, tightened: &mut Vec<(PathBuf, u32)>) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    let mode = metadata.permissions().mode() & 0o7777;
+    if mode & 0o077 != 0 {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o700))?;
+        tightened.push((path.to_path_buf(), mode));
+    }
+    if recursive && metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            tighten(&entry?.path(), true, tightened)?;
+        }
+    }
+    Ok(())
+}
+
+/// What the 'perms' command does.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum PermsCommand {
+    /// Lists managed files whose mode differs from the recorded one (the default).
+    Check,
+    /// Restores the modes recorded in dfl.toml.
+    Fix,
+    /// Records the current modes of the managed files in dfl.toml.
+    Record,
+}
+
+/// Handles the 'perms' command.
+/// `check` lists managed files whose mode differs from the one recorded in dfl.toml,
+/// `fix` re-applies the recorded modes, and `record` records the current modes again.
+pub fn handle_perms_command(command: Option<PermsCommand>) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+    let mut manifest = manifest::load(&dfl_path)?;
+
+    match command.unwrap_or(PermsCommand::Check) {
+        PermsCommand::Check => {
+            let found = mismatches(&dfl_path, &manifest)?;
+            if found.is_empty() {
+                println!("✅ All {} recorded mode(s) match.", manifest.permissions.len());
+                return Ok(());
+            }
+            for mismatch in &found {
+                println!("  - '{}' has mode {:04o}, expected {:04o}.", mismatch.path.display(), mismatch.actual, mismatch.expected);
+            }
+            Err(errors::error(Code::VerificationFailed, format!("{} file(s) have the wrong mode. Run 'dfl perms fix' to restore them.", found.len())))
+        }
+        PermsCommand::Fix => {
+            let fixed = apply(&dfl_path, &manifest)?;
+            ui::say(&format!("✅ Restored {} mode(s).", fixed));
+            Ok(())
+        }
+        PermsCommand::Record => {
+            let mut changed = false;
+            for entry in repo::managed_entries(&dfl_path)? {
+                let stored = entry.stored.strip_prefix(&dfl_path).map_err(io::Error::other)?.to_path_buf();
+                changed |= record(&dfl_path, &mut manifest, &stored)?;
+            }
+            if changed {
+                manifest::save(&dfl_path, &manifest)?;
+            }
+            ui::say(&format!("✅ Recorded {} mode(s) in {}. Commit dfl.toml to keep them.", manifest.permissions.len(), manifest::MANIFEST_FILE));
+            Ok(())
+        }
+    }
+}
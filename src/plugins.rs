@@ -0,0 +1,278 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{self, Code};
+use crate::manifest;
+use crate::repo;
+use crate::ui::{self, message_box};
+
+/// Plugins declared for one plugin manager in `dfl.toml`, e.g.
+///
+/// ```toml
+/// [plugins.antidote]
+/// plugins = ["zsh-users/zsh-autosuggestions", "zsh-users/zsh-syntax-highlighting"]
+/// update = true
+/// ```
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PluginList {
+    pub plugins: Vec<String>,
+    /// Run the manager's update command after its plugin file changes.
+    pub update: bool,
+}
+
+/// A shell or tmux plugin manager dfl knows how to install and feed a plugin list.
+pub trait PluginManager {
+    fn name(&self) -> &'static str;
+
+    /// Returns true if the manager is already installed for this user.
+    fn is_installed(&self, home_dir: &Path) -> bool;
+
+    /// Installs the manager.
+    fn install(&self, home_dir: &Path) -> io::Result<()>;
+
+    /// Home-relative path of the plugin file dfl generates.
+    fn plugin_file(&self) -> PathBuf;
+
+    /// Renders the plugin file for the declared plugins.
+    fn render(&self, plugins: &[String]) -> String;
+
+    /// Applies the plugin file by installing, updating, or removing plugins.
+    fn update(&self, home_dir: &Path) -> io::Result<()>;
+}
+
+const GENERATED_HEADER: &str = "Generated by dfl from dfl.toml. Edit the plugin list there, not here.";
+
+fn git_clone(url: &str, destination: &Path) -> io::Result<()> {
+    cmd!("git", "clone", "--depth", "1", url, destination)
+        .run()
+        .map_err(|e| errors::error(Code::GitFailed, format!("Error cloning {}: {}", url, e)))?;
+    Ok(())
+}
+
+fn run_shell(shell: &str, script: &str) -> io::Result<()> {
+    cmd!(shell, "-c", script)
+        .run()
+        .map_err(|e| io::Error::other(format!("Error running '{}' with {}: {}", script, shell, e)))?;
+    Ok(())
+}
+
+pub struct Antidote;
+
+impl PluginManager for Antidote {
+    fn name(&self) -> &'static str {
+        "antidote"
+    }
+
+    fn is_installed(&self, home_dir: &Path) -> bool {
+        home_dir.join(".antidote").exists()
+    }
+
+    fn install(&self, home_dir: &Path) -> io::Result<()> {
+        git_clone("https://github.com/mattmc3/antidote.git", &home_dir.join(".antidote"))
+    }
+
+    fn plugin_file(&self) -> PathBuf {
+        PathBuf::from(".zsh_plugins.txt")
+    }
+
+    fn render(&self, plugins: &[String]) -> String {
+        let mut contents = format!("# {}\n", GENERATED_HEADER);
+        for plugin in plugins {
+            contents.push_str(&format!("{}\n", plugin));
+        }
+        contents
+    }
+
+    fn update(&self, _home_dir: &Path) -> io::Result<()> {
+        run_shell("zsh", "source ~/.antidote/antidote.zsh && antidote bundle < ~/.zsh_plugins.txt > ~/.zsh_plugins.zsh && antidote update")
+    }
+}
+
+pub struct Zinit;
+
+impl PluginManager for Zinit {
+    fn name(&self) -> &'static str {
+        "zinit"
+    }
+
+    fn is_installed(&self, home_dir: &Path) -> bool {
+        home_dir.join(".local/share/zinit/zinit.git").exists()
+    }
+
+    fn install(&self, home_dir: &Path) -> io::Result<()> {
+        git_clone("https://github.com/zdharma-continuum/zinit.git", &home_dir.join(".local/share/zinit/zinit.git"))
+    }
+
+    fn plugin_file(&self) -> PathBuf {
+        PathBuf::from(".zinit_plugins.zsh")
+    }
+
+    fn render(&self, plugins: &[String]) -> String {
+        let mut contents = format!("# {}\n# Source this file from ~/.zshrc after loading zinit.\n", GENERATED_HEADER);
+        for plugin in plugins {
+            contents.push_str(&format!("zinit light {}\n", plugin));
+        }
+        contents
+    }
+
+    fn update(&self, _home_dir: &Path) -> io::Result<()> {
+        run_shell("zsh", "source ~/.local/share/zinit/zinit.git/zinit.zsh && source ~/.zinit_plugins.zsh && zinit update --all")
+    }
+}
+
+pub struct Fisher;
+
+impl PluginManager for Fisher {
+    fn name(&self) -> &'static str {
+        "fisher"
+    }
+
+    fn is_installed(&self, home_dir: &Path) -> bool {
+        home_dir.join(".config/fish/functions/fisher.fish").exists()
+    }
+
+    fn install(&self, _home_dir: &Path) -> io::Result<()> {
+        run_shell("fish", "curl -sL https://raw.githubusercontent.com/jorgebucaran/fisher/main/functions/fisher.fish | source && fisher install jorgebucaran/fisher")
+    }
+
+    fn plugin_file(&self) -> PathBuf {
+        PathBuf::from(".config/fish/fish_plugins")
+    }
+
+    fn render(&self, plugins: &[String]) -> String {
+        // fish_plugins has no comment syntax, and fisher must manage itself.
+        let mut contents = String::from("jorgebucaran/fisher\n");
+        for plugin in plugins.iter().filter(|p| p.as_str() != "jorgebucaran/fisher") {
+            contents.push_str(&format!("{}\n", plugin));
+        }
+        contents
+    }
+
+    fn update(&self, _home_dir: &Path) -> io::Result<()> {
+        // `fisher update` installs, updates, and removes plugins to match fish_plugins.
+        run_shell("fish", "fisher update")
+    }
+}
+
+pub struct Tpm;
+
+impl PluginManager for Tpm {
+    fn name(&self) -> &'static str {
+        "tpm"
+    }
+
+    fn is_installed(&self, home_dir: &Path) -> bool {
+        home_dir.join(".tmux/plugins/tpm").exists()
+    }
+
+    fn install(&self, home_dir: &Path) -> io::Result<()> {
+        git_clone("https://github.com/tmux-plugins/tpm.git", &home_dir.join(".tmux/plugins/tpm"))
+    }
+
+    fn plugin_file(&self) -> PathBuf {
+        PathBuf::from(".tmux/plugins.conf")
+    }
+
+    fn render(&self, plugins: &[String]) -> String {
+        let mut contents = format!("# {}\n# Add 'source-file ~/.tmux/plugins.conf' to ~/.tmux.conf before 'run ~/.tmux/plugins/tpm/tpm'.\n", GENERATED_HEADER);
+        contents.push_str("set -g @plugin 'tmux-plugins/tpm'\n");
+        for plugin in plugins.iter().filter(|p| p.as_str() != "tmux-plugins/tpm") {
+            contents.push_str(&format!("set -g @plugin '{}'\n", plugin));
+        }
+        contents
+    }
+
+    fn update(&self, home_dir: &Path) -> io::Result<()> {
+        let bin = home_dir.join(".tmux/plugins/tpm/bin");
+        cmd!(bin.join("install_plugins")).run().map_err(|e| io::Error::other(format!("Error installing tmux plugins: {}", e)))?;
+        cmd!(bin.join("update_plugins"), "all").run().map_err(|e| io::Error::other(format!("Error updating tmux plugins: {}", e)))?;
+        Ok(())
+    }
+}
+
+/// Looks up a supported plugin manager by the name used in `dfl.toml`.
+pub fn manager(name: &str) -> Option<&'static dyn PluginManager> {
+    match name {
+        "antidote" => Some(&Antidote),
+        "zinit" => Some(&Zinit),
+        "fisher" => Some(&Fisher),
+        "tpm" => Some(&Tpm),
+        _ => None,
+    }
+}
+
+/// Writes each declared manager's plugin file and, when it changed and `update`
+/// is set, runs the manager's update command. Called at the end of `sync`.
+pub fn sync_plugins(dfl_path: &Path, home_dir: &Path) -> io::Result<()> {
+    let manifest = manifest::load(dfl_path)?;
+    for (name, list) in &manifest.plugins {
+        let Some(manager) = manager(name) else {
+            ui::warn(&format!("Unknown plugin manager '{}' in dfl.toml. Supported: antidote, zinit, fisher, tpm.", name))?;
+            continue;
+        };
+
+        let plugin_file = home_dir.join(manager.plugin_file());
+        // Never write through a link into the repository; the file would end up committed.
+        if fs::read_link(&plugin_file).is_ok_and(|target| target.starts_with(dfl_path)) {
+            ui::warn(&format!("'{}' is a managed dotfile, so dfl will not regenerate it from the {} plugin list.", plugin_file.display(), name))?;
+            continue;
+        }
+
+        let contents = manager.render(&list.plugins);
+        if fs::read_to_string(&plugin_file).is_ok_and(|existing| existing == contents) {
+            continue;
+        }
+        if let Some(parent) = plugin_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&plugin_file, contents)?;
+        ui::say(&format!("✅ Wrote {} plugin list to '{}'", manager.name(), plugin_file.display()));
+
+        if list.update {
+            if !manager.is_installed(home_dir) {
+                ui::warn(&format!("{} is not installed; run 'dfl bootstrap' to install it.", manager.name()))?;
+                continue;
+            }
+            ui::say(&format!("Updating {} plugins...", manager.name()));
+            if let Err(e) = manager.update(home_dir) {
+                ui::warn(&format!("{} update failed: {}", manager.name(), e))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Handles the 'bootstrap' command.
+/// It installs every plugin manager declared in `dfl.toml` that is missing on
+/// this machine, then runs a sync so plugin files and links are in place.
+pub fn handle_bootstrap_command() -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let dfl_path = repo::dfl_path()?;
+
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    message_box("Bootstrapping", "Installing plugin managers declared in dfl.toml.");
+    let manifest = manifest::load(&dfl_path)?;
+    for name in manifest.plugins.keys() {
+        let Some(manager) = manager(name) else {
+            ui::warn(&format!("Unknown plugin manager '{}' in dfl.toml. Supported: antidote, zinit, fisher, tpm.", name))?;
+            continue;
+        };
+        if manager.is_installed(&home_dir) {
+            ui::say(&format!("✅ {} is already installed.", manager.name()));
+            continue;
+        }
+        ui::say(&format!("Installing {}...", manager.name()));
+        manager.install(&home_dir)?;
+        ui::say(&format!("✅ Installed {}.", manager.name()));
+    }
+
+    crate::sync::handle_sync_command(&crate::sync::SyncOptions::from_config()?, false)
+}
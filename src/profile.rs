@@ -0,0 +1,78 @@
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+
+use crate::config::{self, Config};
+use crate::errors::{self, Code};
+use crate::repo;
+use crate::ui::{self, message_box};
+
+/// Name of the repository `repo_path` in the user configuration points at.
+pub const DEFAULT: &str = "default";
+
+/// Environment variable naming the profile `--profile` picked.
+pub const PROFILE_VARIABLE: &str = "DFL_PROFILE";
+
+/// Commands `--all-profiles` runs once for every profile.
+pub const ACROSS: &[&str] = &["status", "sync", "list"];
+
+/// Returns the names of the profiles: the default repository first, then those in
+/// the user configuration.
+pub fn names(config: &Config) -> Vec<String> {
+    std::iter::once(DEFAULT.to_string()).chain(config.profiles.keys().filter(|name| *name != DEFAULT).cloned()).collect()
+}
+
+/// Returns where the repository of the profile `name` lives.
+pub fn repo_path(config: &Config, name: &str) -> io::Result<PathBuf> {
+    if name == DEFAULT {
+        return config::repo_path(config);
+    }
+    let Some(profile) = config.profiles.get(name) else {
+        return Err(errors::error(
+            Code::InvalidArgument,
+            format!("Unknown profile: {}. Add it under [profiles.{}] in {}, or use one of: {}.", name, name, config::config_path()?.display(), names(config).join(", ")),
+        ));
+    };
+    if profile.repo_path.is_empty() {
+        return Err(errors::error(Code::InvalidConfig, format!("The {} profile has no repo_path in {}.", name, config::config_path()?.display())));
+    }
+    let home_dir = repo::home_dir()?;
+    Ok(home_dir.join(repo::expand_home(Path::new(&profile.repo_path), &home_dir)))
+}
+
+/// Returns the remote of the profile `--profile` picked, if it has one.
+pub fn remote(config: &Config) -> Option<String> {
+    let name = env::var(PROFILE_VARIABLE).ok()?;
+    config.profiles.get(&name).map(|profile| profile.remote.clone()).filter(|remote| !remote.is_empty())
+}
+
+/// Runs `command`, given on the command line as `args`, once for each profile, each
+/// in a dfl process of its own so it sees only that profile's repository. Every
+/// profile gets its turn even after one fails, and the failures are summed up at the end.
+/// With `--json`, the profile headers go to standard error, leaving one line of
+/// JSON per profile on standard output.
+pub fn run_all(config: &Config, command: &str, args: &[String]) -> io::Result<()> {
+    if !ACROSS.contains(&command) {
+        return Err(errors::error(Code::InvalidArgument, format!("'--all-profiles' works with {}, not '{}'.", ACROSS.join(", "), command)));
+    }
+    let exe = env::current_exe()?;
+    let names = names(config);
+    let mut failed = Vec::new();
+    let json = args.iter().any(|arg| arg == "--json");
+    for name in &names {
+        let redirect = if json { Some(ui::StdoutToStderr::new()?) } else { None };
+        message_box(&format!("Profile: {}", name), &repo::display(&repo_path(config, name)?));
+        drop(redirect);
+        let mut profile_args = vec!["--profile".to_string(), name.clone()];
+        profile_args.extend(args.iter().cloned());
+        if !cmd(&exe, profile_args).unchecked().run()?.status.success() {
+            failed.push(name.clone());
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(errors::error(Code::ProfileFailed, format!("'{}' failed for {} of {} profiles: {}.", command, failed.len(), names.len(), failed.join(", "))))
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::errors::{self, Code};
+use crate::repo;
+use crate::ui::{self, message_box};
+
+/// Finds symlinks that point into the repository at something that no longer exists,
+/// such as the link to a file deleted from the repository on another machine.
+/// It looks in the home directory and in every directory managed links are deployed
+/// to, without descending further, so it stays fast in a large home directory.
+pub fn find_orphans(dfl_path: &Path, home_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut dirs = BTreeSet::from([home_dir.to_path_buf()]);
+    for entry in repo::managed_entries(dfl_path)? {
+        if let Some(parent) = entry.target.parent() {
+            dirs.insert(parent.to_path_buf());
+        }
+        // Directories deployed file by file hold the links of their children.
+        if fs::symlink_metadata(&entry.target).is_ok_and(|m| m.is_dir()) {
+            dirs.insert(entry.target.clone());
+        }
+    }
+
+    let mut orphans = Vec::new();
+    for dir in dirs {
+        let Ok(children) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for child in children {
+            let path = child?.path();
+            // `exists` follows the link, so it is false exactly when the link dangles.
+            if repo::links_into_repo(&path, dfl_path) && !path.exists() {
+                orphans.push(path);
+            }
+        }
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
+/// Removes the given orphaned links, printing each one.
+pub fn remove_orphans(orphans: &[PathBuf]) -> io::Result<()> {
+    for orphan in orphans {
+        fs::remove_file(orphan)?;
+        ui::say(&format!("🧹 Removed orphaned link '{}'", orphan.display()));
+    }
+    Ok(())
+}
+
+/// Handles the 'prune' command.
+/// It removes symlinks into the repository whose targets no longer exist, or only
+/// lists them with `--dry-run`.
+pub fn handle_prune_command(dry_run: bool) -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let dfl_path = repo::dfl_path()?;
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let orphans = find_orphans(&dfl_path, &home_dir)?;
+    if orphans.is_empty() {
+        println!("✅ No orphaned links found.");
+        return Ok(());
+    }
+    if dry_run {
+        for orphan in &orphans {
+            println!("{} -> {}", orphan.display(), fs::read_link(orphan)?.display());
+        }
+        println!("\n{} orphaned link(s). Run 'dfl prune' to remove them.", orphans.len());
+        return Ok(());
+    }
+
+    remove_orphans(&orphans)?;
+    message_box("Pruned", &format!("Removed {} orphaned link(s).", orphans.len()));
+    Ok(())
+}
@@ -0,0 +1,293 @@
+use std::io;
+use std::path::Path;
+
+use crate::conflict;
+use crate::credentials;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::hints;
+use crate::hooks;
+use crate::machine;
+use crate::manifest;
+use crate::mirror;
+use crate::offline;
+use crate::repo;
+use crate::sparse;
+use crate::ssh;
+use crate::ui::{self, message_box};
+use crate::verify;
+
+/// Handles the new 'remote' command.
+/// It adds a remote URL to the repository. Adding the same URL again does nothing,
+/// and a different URL for an existing remote replaces the old one in place. For
+/// HTTPS remotes it also sets up how git logs in, see `credentials::configure`.
+pub fn handle_remote_command(name: &str, url: &str, credential_helper: Option<&str>, token: bool) -> io::Result<()> {
+    message_box(&format!("Adding Remote '{}'", name), "Connecting your local repository to a remote URL.");
+
+    let dfl_path = repo::dfl_path()?;
+
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' first."));
+    }
+
+    let existing = git::remote_url(&dfl_path, name)?.unwrap_or_default();
+    let existing = existing.as_str();
+    if existing == url {
+        if credential_helper.is_none() && !token {
+            ui::say(&format!("✅ Remote '{}' already points to {}. Nothing to do.", name, url));
+            return Ok(());
+        }
+        return credentials::configure(&dfl_path, url, credential_helper, token);
+    }
+
+    if existing.is_empty() {
+        if let Err(e) = git::add_remote(&dfl_path, name, url) {
+            return Err(errors::error(Code::GitFailed, format!("Error adding remote {}: {}", name, e)));
+        }
+        ui::say(&format!("✅ Remote '{}' added: {}", name, url));
+    } else {
+        if let Err(e) = git::set_remote_url(&dfl_path, name, url) {
+            return Err(errors::error(Code::GitFailed, format!("Error updating remote {}: {}", name, e)));
+        }
+        ui::say(&format!("✅ Remote '{}' changed from {} to {}. Use --name <other> to add a URL alongside instead.", name, existing, url));
+    }
+
+    credentials::configure(&dfl_path, url, credential_helper, token)?;
+    message_box("Remote Added", "Your local repository is now connected to your remote!");
+
+    Ok(())
+}
+
+/// Pushes the current branch right after a command committed, to its upstream or
+/// else to origin. Without a remote there is nothing to do, and a failed push only
+/// warns, since the commit itself succeeded. Returns whether anything was pushed.
+pub fn push_after_commit(dfl_path: &Path) -> io::Result<bool> {
+    let branch = git::branch(dfl_path)?;
+    let (remote, remote_branch, set_upstream) = match git::upstream(dfl_path, &branch)? {
+        Some((remote, remote_branch)) => (remote, remote_branch, false),
+        None if git::remotes(dfl_path)?.iter().any(|remote| remote == "origin") => ("origin".to_string(), branch.clone(), true),
+        None => {
+            ui::say("Not pushing: no remote is configured yet. Add one with 'dfl remote add <url>'.");
+            return Ok(false);
+        }
+    };
+    let pushed = if set_upstream { ssh::preflight(dfl_path, &remote) } else { Ok(()) };
+    if let Err(e) = pushed.and_then(|()| git::push(dfl_path, &branch, &remote, &remote_branch, set_upstream)) {
+        if offline::is_unreachable(dfl_path, &remote) {
+            offline::queue(dfl_path, &branch, &remote)?;
+            return Ok(false);
+        }
+        ui::warn(&format!("Your changes are committed but could not be pushed to {}: {}\nRun 'dfl push' to try again.", remote, e))?;
+        return Ok(false);
+    }
+    offline::dequeue(dfl_path, &branch)?;
+    ui::say(&format!("✅ Pushed to {}.", remote));
+    mirror::publish(dfl_path)?;
+    if let Err(e) = hints::record_use("push") {
+        tracing::warn!("Could not update hint state: {}", e);
+    }
+    Ok(true)
+}
+
+/// Handles the 'remote list' command.
+/// It prints every remote with its URL.
+pub fn handle_remote_list_command() -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' first."));
+    }
+
+    let remotes = git::remotes(&dfl_path)?;
+    if remotes.is_empty() {
+        println!("No remotes configured. Add one with 'dfl remote add <url>'.");
+    }
+    for name in remotes {
+        let url = git::remote_url(&dfl_path, &name)?.unwrap_or_default();
+        match mirror::mirror_of(&dfl_path, &name)? {
+            Some(mirror) => println!("{}\t{} (read-only copy of the mirror {})", name, url, mirror.target()),
+            None => println!("{}\t{}", name, url),
+        }
+    }
+    Ok(())
+}
+
+/// Handles the 'remote set-url' command.
+/// It points an existing remote at a new URL, such as after the repository moved
+/// or to switch between SSH and HTTPS, and sets up the login for HTTPS URLs.
+pub fn handle_remote_set_url_command(name: &str, url: &str, credential_helper: Option<&str>, token: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' first."));
+    }
+
+    let Some(existing) = git::remote_url(&dfl_path, name)? else {
+        return Err(errors::error(Code::NoRemote, format!("There is no remote named '{}'. Add it with 'dfl remote add --name {} {}'.", name, name, url)));
+    };
+    if mirror::is_mirror(&dfl_path, name)? {
+        return Err(errors::error(Code::InvalidArgument, format!("'{}' is a copy of a mirror, which dfl keeps pointed at its download. Add the git host with 'dfl remote add --name <name> {}'.", name, url)));
+    }
+    if existing != url {
+        if let Err(e) = git::set_remote_url(&dfl_path, name, url) {
+            return Err(errors::error(Code::GitFailed, format!("Error updating remote {}: {}", name, e)));
+        }
+        ui::say(&format!("✅ Remote '{}' changed from {} to {}", name, existing, url));
+    } else {
+        ui::say(&format!("✅ Remote '{}' already points to {}.", name, url));
+    }
+    credentials::configure(&dfl_path, url, credential_helper, token)
+}
+
+/// Handles the 'remote remove' command.
+/// It disconnects a remote; nothing on the remote itself is deleted.
+pub fn handle_remote_remove_command(name: &str) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' first."));
+    }
+
+    if !git::remotes(&dfl_path)?.iter().any(|remote| remote == name) {
+        return Err(errors::error(Code::NoRemote, format!("There is no remote named '{}'. Run 'dfl remote list' to see them.", name)));
+    }
+    if let Err(e) = git::remove_remote(&dfl_path, name) {
+        return Err(errors::error(Code::GitFailed, format!("Error removing remote {}: {}", name, e)));
+    }
+    ui::say(&format!("✅ Remote '{}' removed.", name));
+    Ok(())
+}
+
+/// Handles 'push --all-remotes'.
+/// It pushes the current branch, or `branch`, to the branch of the same name on
+/// every remote, and reports each one. One remote failing does not stop the rest.
+pub fn handle_push_all_command(branch: Option<&str>) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => git::branch(&dfl_path).map_err(|e| errors::error(Code::PushFailed, format!("Error finding the current branch: {}", e)))?,
+    };
+    let remotes = git::remotes(&dfl_path)?;
+    if remotes.is_empty() {
+        return Err(errors::error(Code::NoRemote, "No remotes configured. Please run 'dfl remote add <url>' first."));
+    }
+
+    let mut failed = Vec::new();
+    for remote in &remotes {
+        if mirror::is_mirror(&dfl_path, remote)? {
+            continue;
+        }
+        match git::push(&dfl_path, &branch, remote, &branch, false) {
+            Ok(()) => ui::say(&format!("✅ Pushed '{}' to {}", branch, remote)),
+            Err(e) => {
+                ui::warn(&format!("Could not push '{}' to {}: {}", branch, remote, e))?;
+                failed.push(remote.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        return Err(errors::error(Code::PushFailed, format!("Pushing failed for {} of {} remote(s): {}", failed.len(), remotes.len(), failed.join(", "))));
+    }
+    message_box("Success", &format!("Your dotfiles are now synced with all {} remote(s)!", remotes.len()));
+    mirror::publish(&dfl_path)
+}
+
+/// Handles the new 'push' command.
+/// It pushes committed changes to the remote repository: the current branch, or
+/// `branch` when given, to its upstream or else to a branch of the same name on origin.
+/// With `force`, rewritten history replaces the upstream's, as long as the upstream
+/// has not changed since it was last fetched.
+pub fn handle_push_command(branch: Option<&str>, force: bool) -> io::Result<()> {
+    message_box("Pushing to Remote", "Uploading your committed changes to the remote repository.");
+    let dfl_path = repo::dfl_path()?;
+
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    // Push whatever branch is checked out, which may be main, master, or anything else.
+    let branch = match branch {
+        Some(branch) if git::branches(&dfl_path)?.iter().any(|b| b == branch) => branch.to_string(),
+        Some(branch) => return Err(errors::error(Code::InvalidArgument, format!("There is no branch named '{}'. Run 'dfl branch list' to see them.", branch))),
+        None => git::branch(&dfl_path).map_err(|e| errors::error(Code::PushFailed, format!("Error finding the current branch: {}", e)))?,
+    };
+
+    if let Some((remote, remote_branch)) = git::upstream(&dfl_path, &branch)? {
+        if mirror::is_mirror(&dfl_path, &remote)? {
+            return Err(errors::error(Code::PushFailed, format!("'{}' is a read-only copy of a mirror. Add the git host with 'dfl remote add --name <name> <url>' and push there with 'git -C {} push -u <name>'.", remote, repo::display(&dfl_path))));
+        }
+        // Upstream is set, just do a normal push
+        let pushed = if force {
+            let expected = git::upstream_head(&dfl_path, &branch)?;
+            git::force_push(&dfl_path, &branch, &remote, &remote_branch, expected.as_deref())
+        } else {
+            git::push(&dfl_path, &branch, &remote, &remote_branch, false)
+        };
+        if let Err(e) = pushed {
+            if !force && offline::is_unreachable(&dfl_path, &remote) {
+                return offline::queue(&dfl_path, &branch, &remote);
+            }
+            return Err(errors::error(Code::PushFailed, format!("Error pushing to remote: {}", e)));
+        }
+    } else {
+        // Check if a remote named 'origin' exists
+        if !git::remotes(&dfl_path)?.iter().any(|remote| remote == "origin") {
+            return Err(errors::error(Code::NoRemote, "No remote named 'origin' found. Please run 'dfl remote add <url>' first."));
+        }
+        message_box("Initial Push", &format!("No upstream branch found. Pushing '{}' to origin and setting it as upstream.", branch));
+        ssh::preflight(&dfl_path, "origin")?;
+        if let Err(e) = git::push(&dfl_path, &branch, "origin", &branch, true) {
+            if offline::is_unreachable(&dfl_path, "origin") {
+                return offline::queue(&dfl_path, &branch, "origin");
+            }
+            return Err(errors::error(Code::PushFailed, format!("Error performing initial push: {}", e)));
+        }
+    }
+    offline::dequeue(&dfl_path, &branch)?;
+
+    ui::say("✅ Changes pushed successfully!");
+    mirror::publish(&dfl_path)?;
+
+    message_box("Success", "Your dotfiles are now synced with your remote repository!");
+
+    Ok(())
+}
+/// Handles the new 'pull' command.
+/// It pulls changes from the remote repository into the current branch: from its
+/// upstream, or from the remote branch `branch` when given. Uncommitted changes are
+/// first committed, set aside during the pull and put back, or stop the pull, as
+/// `local_changes` says or the user picks.
+pub fn handle_pull_command(branch: Option<&str>, rebase: bool, local_changes: Option<conflict::LocalChanges>) -> io::Result<()> {
+    message_box("Pulling from Remote", "Fetching the latest changes from your remote repository.");
+
+    let home_dir = repo::home_dir()?;
+    let dfl_path = repo::dfl_path()?;
+
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    conflict::settle_local_changes(&dfl_path, local_changes)?;
+    mirror::refresh_upstream(&dfl_path)?;
+    if let Err(e) = git::pull_keeping_changes(&dfl_path, branch, rebase, conflict::merge_resolver()) {
+        let current = git::branch(&dfl_path)?;
+        let remote = git::upstream(&dfl_path, &current)?.map(|(remote, _)| remote).unwrap_or_else(|| "origin".to_string());
+        if offline::is_unreachable(&dfl_path, &remote) {
+            return ui::warn(&format!("You are offline: {} cannot be reached. Your dotfiles stay as they are; pull again once you are back online.", remote));
+        }
+        return Err(errors::error(errors::code_or(&e, Code::PullFailed), format!("Error pulling from remote: {}", e)));
+    }
+    machine::pull_shared(&dfl_path)?;
+    sparse::apply(&dfl_path)?;
+    ui::say("✅ Pulled latest changes successfully!");
+    offline::flush(&dfl_path)?;
+
+    // Linked files change as soon as the pull lands, so their programs reload now.
+    let changed = verify::record_checksums(&dfl_path)?;
+    hooks::run_reloads(&manifest::load(&dfl_path)?, &home_dir, &changed)?;
+
+    message_box("Success", "Your local dotfiles repository is now up-to-date. Run 'dfl sync' to apply the changes.");
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::git;
+use crate::manifest;
+use crate::repo;
+use crate::ui::{self, message_box};
+use crate::verify;
+
+/// Stops managing the entry deployed at `target` in the repository at `dfl_path`.
+/// A symlink is replaced by the stored file or directory; a copy, hard link, or
+/// rendered template already holds the contents and stays. The entry is then
+/// deleted from the repository and from `dfl.toml`, without committing. Returns
+/// the repository-relative path it was stored at.
+pub fn remove(dfl_path: &Path, target: &Path) -> io::Result<PathBuf> {
+    let target = fsutil::normalize(&std::path::absolute(target)?);
+    let Some(entry) = repo::managed_entries(dfl_path)?.into_iter().find(|entry| entry.target == target) else {
+        return Err(errors::error(Code::InvalidArgument, format!("{} is not managed by dfl. Run 'dfl list' to see what is.", repo::display(&target))));
+    };
+    let stored = entry.stored.strip_prefix(dfl_path).map_err(io::Error::other)?.to_path_buf();
+
+    let metadata = fs::symlink_metadata(&entry.target).ok();
+    let is_link = metadata.as_ref().is_some_and(|metadata| metadata.file_type().is_symlink());
+    if entry.mode.is_symlink() && metadata.as_ref().is_some_and(|metadata| metadata.is_dir()) {
+        return Err(errors::error(Code::InvalidArgument, format!("{} is deployed as a directory of links. Run 'dfl fold' on it first.", repo::display(&target))));
+    }
+    if is_link || metadata.is_none() {
+        if is_link {
+            fs::remove_file(&entry.target)?;
+        }
+        fsutil::move_path(&entry.stored, &entry.target).map_err(|e| errors::error(Code::MoveFailed, format!("Error moving {} back: {}", repo::display(&target), e)))?;
+    } else {
+        fsutil::remove_path(&entry.stored)?;
+    }
+
+    let mut manifest = manifest::load(dfl_path)?;
+    manifest.entries.retain(|entry| *entry != stored);
+    manifest.paths.remove(&stored);
+    manifest.modes.remove(&stored);
+    manifest.permissions.remove(&stored);
+    manifest.unfold.retain(|entry| *entry != stored);
+    manifest::save(dfl_path, &manifest)?;
+    Ok(stored)
+}
+
+/// Handles the 'remove' command.
+/// It stops managing each path, leaving the file where it is deployed as a plain
+/// file, and commits the removal unless `no_commit`.
+pub fn handle_remove_command(paths: &[String], message: Option<&str>, no_commit: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    // Every path is checked first, so a typo does not leave the others half done.
+    let home_dir = repo::home_dir()?;
+    let managed: Vec<PathBuf> = repo::managed_entries(&dfl_path)?.into_iter().map(|entry| entry.target).collect();
+    let mut targets = Vec::new();
+    for path in paths {
+        let target = fsutil::normalize(&std::path::absolute(repo::expand_home(Path::new(path), &home_dir))?);
+        if !managed.contains(&target) {
+            return Err(errors::error(Code::InvalidArgument, format!("{} is not managed by dfl. Run 'dfl list' to see what is.", repo::display(&target))));
+        }
+        targets.push(target);
+    }
+    let mut removed = Vec::new();
+    for target in &targets {
+        removed.push(remove(&dfl_path, target)?);
+        ui::say(&format!("✅ {} is no longer managed; it stays in place as a plain file.", repo::display(target)));
+    }
+    verify::record_checksums(&dfl_path)?;
+    if no_commit {
+        ui::say("Skipped committing. Run 'dfl commit' when you are ready.");
+        return Ok(());
+    }
+
+    git::add_all(&dfl_path).map_err(|e| errors::error(Code::GitFailed, format!("Error staging changes: {}", e)))?;
+    let message = match message {
+        Some(message) => message.to_string(),
+        None => {
+            let names: Vec<String> = removed.iter().map(|stored| stored.display().to_string()).collect();
+            git::commit_message("remove", &removed, format!("chore: Stop managing {}", names.join(", ")))?
+        }
+    };
+    git::commit(&dfl_path, &message).map_err(|e| errors::error(Code::GitFailed, format!("Error committing changes: {}", e)))?;
+    ui::say("✅ Changes committed.");
+    if config::load()?.auto_push {
+        crate::remote::push_after_commit(&dfl_path)?;
+    }
+    message_box("Success", &format!("{} dotfile(s) no longer managed.", removed.len()));
+    Ok(())
+}
+
@@ -0,0 +1,481 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::alternate;
+use crate::bare;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::manifest::{self, MANIFEST_FILE};
+use crate::sparse;
+use crate::template;
+
+/// Returns the user's home directory.
+pub fn home_dir() -> io::Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| errors::error(Code::NoHomeDir, "Could not find home directory"))
+}
+
+/// Environment variable naming the repository, ahead of `repo_path` in the user
+/// configuration. The global `--repo` option sets it for the command it is given to.
+pub const DIR_VARIABLE: &str = "DFL_DIR";
+
+/// Where older versions of dfl kept the repository, relative to the home directory.
+pub const LEGACY_DIR: &str = ".dfl";
+
+/// Returns the base directory an XDG variable such as `XDG_DATA_HOME` names, or
+/// `fallback` when it is unset or, as the specification says to ignore it, relative.
+pub fn xdg_dir(variable: &str, fallback: impl FnOnce() -> Option<PathBuf>) -> Option<PathBuf> {
+    std::env::var_os(variable).map(PathBuf::from).filter(|dir| dir.is_absolute()).or_else(fallback)
+}
+
+/// Returns `current`, unless only `legacy` exists: what an older dfl left in its
+/// old place keeps being used there until 'dfl migrate' moves it.
+pub fn current_or_legacy(current: PathBuf, legacy: PathBuf) -> PathBuf {
+    if fs::symlink_metadata(&current).is_err() && fs::symlink_metadata(&legacy).is_ok() { legacy } else { current }
+}
+
+/// Returns where the repository goes by default: `$XDG_DATA_HOME/dfl/repo`, which is
+/// `~/.local/share/dfl/repo` unless the variable is set.
+pub fn xdg_repo_path() -> io::Result<PathBuf> {
+    let home_dir = home_dir()?;
+    let data_dir = xdg_dir("XDG_DATA_HOME", || Some(home_dir.join(".local").join("share"))).unwrap_or_default();
+    Ok(data_dir.join("dfl").join("repo"))
+}
+
+/// Returns the location of the repository when nothing else picks one:
+/// `$XDG_DATA_HOME/dfl/repo`, or `~/.dfl` where an older dfl created it.
+pub fn default_dfl_path() -> io::Result<PathBuf> {
+    Ok(current_or_legacy(xdg_repo_path()?, home_dir()?.join(LEGACY_DIR)))
+}
+
+/// Returns the location of the dfl repository: `$DFL_DIR` when set, then
+/// `repo_path` in the user configuration, then the default location.
+pub fn dfl_path() -> io::Result<PathBuf> {
+    match std::env::var_os(DIR_VARIABLE).filter(|dir| !dir.is_empty()) {
+        Some(dir) => std::path::absolute(expand_home(Path::new(&dir), &home_dir()?)),
+        None => config::repo_path(&config::load()?),
+    }
+}
+
+/// Returns `path` for messages, with the home directory written as `~`.
+pub fn display(path: &Path) -> String {
+    match dirs::home_dir().and_then(|home_dir| path.strip_prefix(home_dir).ok().map(Path::to_path_buf)) {
+        Some(relative) if relative.as_os_str().is_empty() => "~".to_string(),
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Directory at the repository root that stores entries deployed outside `$HOME`.
+pub const PATHS_DIR: &str = ".dfl-paths";
+
+/// File at the repository root listing gitignore-style patterns of files that are never linked.
+pub const IGNORE_FILE: &str = ".dflignore";
+
+/// Directory at the repository root holding scripts run around `sync`, such as `hooks/post-sync`.
+pub const HOOKS_DIR: &str = "hooks";
+
+/// Names at the repository root that belong to dfl or git rather than to the user.
+const RESERVED: &[&str] = &[".git", ".gitattributes", ".gitignore", ".gitmodules", MANIFEST_FILE, PATHS_DIR, IGNORE_FILE, HOOKS_DIR];
+
+/// Returns true if a repository-relative path is one of dfl's own files.
+pub fn is_reserved(stored: &Path) -> bool {
+    RESERVED.iter().any(|name| stored == Path::new(name))
+}
+
+/// Loads the patterns from `.dflignore`, such as `README.md` or `screenshots/`.
+/// A repository without the file ignores nothing.
+pub fn ignore_rules(dfl_path: &Path) -> io::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dfl_path);
+    let path = dfl_path.join(IGNORE_FILE);
+    if path.exists()
+        && let Some(e) = builder.add(&path)
+    {
+        return Err(errors::error(Code::InvalidConfig, format!("Error parsing {}: {}", path.display(), e)));
+    }
+    builder.build().map_err(|e| errors::error(Code::InvalidConfig, format!("Error parsing {}: {}", path.display(), e)))
+}
+
+/// Returns true if `.dflignore` excludes a repository-relative path or one of its parents.
+pub fn is_ignored(rules: &Gitignore, dfl_path: &Path, stored: &Path) -> bool {
+    rules.matched_path_or_any_parents(stored, dfl_path.join(stored).is_dir()).is_ignore()
+}
+
+/// How `sync` deploys an entry, as written in `dfl.toml`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// A symlink pointing into the repository.
+    #[default]
+    Symlink,
+    /// A copy of the stored entry, for tools and filesystems that cannot use symlinks.
+    Copy,
+    /// Hard links to the stored files, for tools that resolve symlinks and write back
+    /// through them badly. The repository and the target must share a filesystem.
+    Hardlink,
+    /// A rendered copy of a `.tmpl` file. Templates are recognized by name, so this
+    /// mode is never written in `dfl.toml`.
+    #[serde(skip)]
+    Template,
+}
+
+impl LinkMode {
+    pub fn is_symlink(&self) -> bool {
+        *self == LinkMode::Symlink
+    }
+}
+
+/// A managed dotfile: where it is stored in the repository and where it is deployed.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Absolute path inside the repository.
+    pub stored: PathBuf,
+    /// Absolute path of the symlink (or copy) in the home directory.
+    pub target: PathBuf,
+    /// How the entry is deployed.
+    pub mode: LinkMode,
+    /// Deploy a directory as a real directory holding a link to each child, so
+    /// files other programs put there stay out of the repository.
+    pub unfold: bool,
+}
+
+/// Lists the repository entries, files and directories alike, that `sync` links into the home directory.
+/// Entries whose conditions, such as `##os.darwin` or those under `[when]` in `dfl.toml`, do not apply to this machine are left out.
+pub fn managed_entries(dfl_path: &Path) -> io::Result<Vec<Entry>> {
+    Ok(pick_alternates(collect_entries(dfl_path, true)?))
+}
+
+/// Lists every repository entry with where it is deployed, including the variants
+/// for other machines, for commands that reorganize the repository.
+pub fn all_entries(dfl_path: &Path) -> io::Result<Vec<Entry>> {
+    collect_entries(dfl_path, false)
+}
+
+fn collect_entries(dfl_path: &Path, applying_only: bool) -> io::Result<Vec<Entry>> {
+    // A bare repository tracks files in place, so nothing is linked.
+    if bare::in_use(dfl_path) {
+        return Ok(Vec::new());
+    }
+    let home_dir = home_dir()?;
+    let manifest = manifest::load(dfl_path)?;
+    let layout = manifest.layout.layout();
+
+    let mut entries = Vec::new();
+    for stored in layout.entries(dfl_path, &manifest)? {
+        if !applying_only || manifest.applies(&stored)? {
+            let mut entry = entry_for(dfl_path.join(&stored), home_dir.join(alternate::strip(&layout.target_path(&stored))), manifest.mode_for(&stored));
+            entry.unfold = manifest.unfold.contains(&stored);
+            entries.push(entry);
+        }
+    }
+    // A sparse checkout leaves out what this machine does not need, which is then not linked either.
+    let sparse = sparse::in_use(dfl_path);
+    for (stored, target) in &manifest.paths {
+        if sparse && !dfl_path.join(stored).exists() {
+            continue;
+        }
+        if !applying_only || manifest.applies(stored)? {
+            let mut entry = entry_for(dfl_path.join(stored), expand_target(target, &home_dir)?, manifest.mode_for(stored));
+            entry.unfold = manifest.unfold.contains(stored);
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Builds the entry for a stored path. Templates are rendered instead of linked,
+/// and deployed without their `.tmpl` extension unless the target is mapped explicitly.
+fn entry_for(stored: PathBuf, target: PathBuf, mode: LinkMode) -> Entry {
+    if !template::is_template(&stored) {
+        return Entry { stored, target, mode, unfold: false };
+    }
+    let target = if target.extension().is_some_and(|extension| extension == template::EXTENSION) { target.with_extension("") } else { target };
+    Entry { stored, target, mode: LinkMode::Template, unfold: false }
+}
+
+/// Keeps one entry per target where several alternates apply, preferring the one
+/// with the most conditions, so `.gitconfig##os.darwin` wins over `.gitconfig`.
+fn pick_alternates(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut picked: Vec<Entry> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match picked.iter_mut().find(|e| e.target == entry.target) {
+            Some(existing) if alternate::specificity(&entry.stored) > alternate::specificity(&existing.stored) => *existing = entry,
+            Some(_) => {}
+            None => picked.push(entry),
+        }
+    }
+    picked
+}
+
+/// Returns true if anything below a stored directory is conditional or a template,
+/// in which case it is deployed file by file so that each alternate can be picked
+/// and each template rendered.
+pub fn deploys_per_file(dir: &Path) -> io::Result<bool> {
+    for child in fs::read_dir(dir)? {
+        let child = child?;
+        if alternate::is_conditional(&child.file_name()) || template::is_template(&child.path()) || (child.file_type()?.is_dir() && deploys_per_file(&child.path())?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Narrows `entries` to those named on the command line. A name may be a package,
+/// a file name such as `.gitconfig`, or a path given relative to the home directory
+/// or the repository, or as `~/...` or an absolute path. Naming a path inside a
+/// directory entry, such as `.config/kitty` when `.config` is managed, selects just
+/// that part of it. Fails if a name matches nothing.
+pub fn select_entries(dfl_path: &Path, entries: Vec<Entry>, names: &[String]) -> io::Result<Vec<Entry>> {
+    let home_dir = home_dir()?;
+    let manifest = manifest::load(dfl_path)?;
+    let layout = manifest.layout.layout();
+
+    let mut selected: Vec<Entry> = Vec::new();
+    for name in names {
+        let path = expand_home(Path::new(name), &home_dir);
+        // A relative name may mean a home path or a repository path; try both.
+        let candidates = if path.is_absolute() { vec![path] } else { vec![home_dir.join(&path), dfl_path.join(&path)] };
+        let mut found = false;
+        for entry in &entries {
+            let stored = entry.stored.strip_prefix(dfl_path).unwrap_or(&entry.stored);
+            let package = if manifest.paths.contains_key(stored) { None } else { layout.package(stored) };
+            let matched = if package.as_deref() == Some(name.as_str()) || entry.target.file_name().is_some_and(|f| f == name.as_str()) {
+                Some(entry.clone())
+            } else {
+                candidates.iter().find_map(|candidate| {
+                    if entry.target.starts_with(candidate) || entry.stored.starts_with(candidate) {
+                        return Some(entry.clone());
+                    }
+                    // A path inside a directory entry selects that part of it.
+                    let rest = candidate.strip_prefix(&entry.target).or_else(|_| candidate.strip_prefix(&entry.stored)).ok()?;
+                    let stored = entry.stored.join(rest);
+                    fs::symlink_metadata(&stored).is_ok().then(|| entry_for(stored, entry.target.join(rest), entry.mode))
+                })
+            };
+            if let Some(entry) = matched {
+                found = true;
+                if !selected.iter().any(|e| e.target == entry.target) {
+                    selected.push(entry);
+                }
+            }
+        }
+        if !found {
+            return Err(errors::error(Code::InvalidArgument, format!("Nothing managed matches '{}'. Run 'dfl list' to see the managed entries.", name)));
+        }
+    }
+    Ok(selected)
+}
+
+/// Expands a path-mapping target: environment variables written as `$NAME`,
+/// `${NAME}`, or `${NAME:-default}` first, then a leading `~`. A variable that is
+/// unset or empty and has no default is an error, since the entry would otherwise
+/// be deployed to the wrong place.
+pub fn expand_target(target: &Path, home_dir: &Path) -> io::Result<PathBuf> {
+    let text = target.to_string_lossy();
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text.as_ref();
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let (name, default, after) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced.find('}').ok_or_else(|| errors::error(Code::InvalidConfig, format!("Unclosed '${{' in the target '{}' in dfl.toml.", text)))?;
+            let (name, default) = match braced[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&braced[..end], None),
+            };
+            (name, default, &braced[end + 1..])
+        } else {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            (&rest[..end], None, &rest[end..])
+        };
+        match std::env::var(name).ok().filter(|value| !value.is_empty()).or(default.map(str::to_string)) {
+            Some(value) => expanded.push_str(&value),
+            None => return Err(errors::error(Code::InvalidConfig, format!("The target '{}' in dfl.toml uses ${}, which is not set. Set it or give a default, as in ${{{}:-default}}.", text, name, name))),
+        }
+        rest = after;
+    }
+    expanded.push_str(rest);
+    Ok(expand_home(Path::new(&expanded), home_dir))
+}
+
+/// Expands a leading `~` in a path-mapping target or repository location.
+pub fn expand_home(path: &Path, home_dir: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => home_dir.join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Returns the repository-relative path and the path-mapping target for an absolute
+/// path that the layout cannot store because it is outside `$HOME`. Targets that are
+/// still spelled below `$HOME` (e.g. behind a symlink to another mount) are stored
+/// under `HOME/` with a `~/` target, so they deploy to the right place on machines
+/// with a different home directory.
+pub fn mapped_paths(absolute: &Path, home_dir: &Path) -> (PathBuf, PathBuf) {
+    match absolute.strip_prefix(home_dir) {
+        Ok(rest) => (Path::new(PATHS_DIR).join("HOME").join(rest), Path::new("~").join(rest)),
+        Err(_) => (Path::new(PATHS_DIR).join(absolute.strip_prefix("/").unwrap_or(absolute)), absolute.to_path_buf()),
+    }
+}
+
+/// Recursively lists every regular file at or below `path`, without following symlinks.
+pub fn files_under(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        let mut children: Vec<PathBuf> = fs::read_dir(path)?.map(|e| e.map(|e| e.path())).collect::<io::Result<_>>()?;
+        children.sort();
+        for child in children {
+            files.extend(files_under(&child)?);
+        }
+    } else if metadata.is_file() {
+        files.push(path.to_path_buf());
+    }
+    Ok(files)
+}
+
+/// Returns the first `.git` directory or file found inside `dir`, if any.
+pub fn find_nested_git_dir(dir: &Path) -> io::Result<Option<PathBuf>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return Ok(Some(path));
+        }
+        if fs::symlink_metadata(&path)?.is_dir()
+            && let Some(found) = find_nested_git_dir(&path)?
+        {
+            return Ok(Some(found));
+        }
+    }
+    Ok(None)
+}
+
+/// Pairs each child of a stored directory with the same name below the entry's target.
+/// Conditional children are deployed without their conditions, or skipped when
+/// they do not apply to this machine.
+pub fn children(entry: &Entry) -> io::Result<Vec<Entry>> {
+    let mut children = Vec::new();
+    for child in fs::read_dir(&entry.stored)? {
+        let name = PathBuf::from(child?.file_name());
+        if alternate::applies(&name)? {
+            children.push(entry_for(entry.stored.join(&name), entry.target.join(alternate::strip(&name)), entry.mode));
+        }
+    }
+    children.sort_by(|a, b| a.stored.cmp(&b.stored));
+    Ok(pick_alternates(children))
+}
+
+/// The state of an entry's deployed symlink.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// The symlink exists and points at the stored entry.
+    Linked,
+    /// Nothing exists at the target path.
+    Missing,
+    /// A regular file or directory occupies the target path.
+    Conflict,
+    /// A symlink exists but points somewhere else.
+    WrongTarget(PathBuf),
+    /// A copied entry whose contents differ from the repository, or a hard-linked
+    /// entry that no longer shares its files with it.
+    Diverged,
+}
+
+/// Inspects the target path of an entry without following symlinks.
+pub fn link_status(entry: &Entry) -> io::Result<LinkStatus> {
+    match entry.mode {
+        LinkMode::Copy => return copy_status(entry),
+        LinkMode::Hardlink => return hardlink_status(entry),
+        LinkMode::Template => return template_status(entry),
+        LinkMode::Symlink => {}
+    }
+    match fs::symlink_metadata(&entry.target) {
+        // A file where a parent directory should be also means nothing is linked yet.
+        Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::NotADirectory) => Ok(LinkStatus::Missing),
+        Err(e) => Err(e),
+        Ok(metadata) if !metadata.file_type().is_symlink() => {
+            // A real directory whose children are all linked is deployed file by file.
+            if metadata.is_dir() && entry.stored.is_dir() {
+                for child in children(entry)? {
+                    if link_status(&child)? != LinkStatus::Linked {
+                        return Ok(LinkStatus::Conflict);
+                    }
+                }
+                return Ok(LinkStatus::Linked);
+            }
+            Ok(LinkStatus::Conflict)
+        }
+        Ok(_) if points_to(&entry.target, &entry.stored) => Ok(LinkStatus::Linked),
+        Ok(_) => Ok(LinkStatus::WrongTarget(fs::read_link(&entry.target)?)),
+    }
+}
+
+/// Inspects the targets of many entries at once, in parallel. The statuses are
+/// returned in the same order as `entries`.
+pub fn link_statuses(entries: &[Entry]) -> io::Result<Vec<LinkStatus>> {
+    entries.par_iter().map(link_status).collect()
+}
+
+/// Returns true if `link` is a symlink to `stored`, whether it was written as an
+/// absolute path or relative to the directory containing the link.
+pub fn points_to(link: &Path, stored: &Path) -> bool {
+    let Ok(target) = fs::read_link(link) else {
+        return false;
+    };
+    if target.is_absolute() {
+        return target == stored;
+    }
+    let resolved = link.parent().map_or(target.clone(), |parent| parent.join(&target));
+    fsutil::normalize(&resolved) == stored || fs::canonicalize(link).is_ok_and(|link| fs::canonicalize(stored).is_ok_and(|stored| link == stored))
+}
+
+/// Returns true if `path` is a symlink that points into the repository, i.e. it is already managed.
+pub fn links_into_repo(path: &Path, dfl_path: &Path) -> bool {
+    let Ok(target) = fs::read_link(path) else {
+        return false;
+    };
+    // Relative link targets resolve against the directory containing the link.
+    let target = path.parent().map_or(target.clone(), |parent| parent.join(&target));
+    target.starts_with(dfl_path) || fs::canonicalize(&target).is_ok_and(|resolved| fs::canonicalize(dfl_path).is_ok_and(|repo| resolved.starts_with(repo)))
+}
+
+/// Compares a copied entry with the repository version.
+fn copy_status(entry: &Entry) -> io::Result<LinkStatus> {
+    match fs::symlink_metadata(&entry.target) {
+        Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::NotADirectory) => Ok(LinkStatus::Missing),
+        Err(e) => Err(e),
+        Ok(metadata) if metadata.file_type().is_symlink() => Ok(LinkStatus::WrongTarget(fs::read_link(&entry.target)?)),
+        Ok(_) if fsutil::same_tree(&entry.stored, &entry.target) => Ok(LinkStatus::Linked),
+        Ok(_) => Ok(LinkStatus::Diverged),
+    }
+}
+
+/// Compares a rendered template with what it renders to now, so both local edits
+/// and changes to the template or its variables show up as diverged.
+fn template_status(entry: &Entry) -> io::Result<LinkStatus> {
+    match fs::symlink_metadata(&entry.target) {
+        Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::NotADirectory) => Ok(LinkStatus::Missing),
+        Err(e) => Err(e),
+        Ok(metadata) if metadata.file_type().is_symlink() => Ok(LinkStatus::WrongTarget(fs::read_link(&entry.target)?)),
+        Ok(metadata) if !metadata.is_file() => Ok(LinkStatus::Conflict),
+        Ok(_) if template::is_rendered(&entry.stored, &entry.target)? => Ok(LinkStatus::Linked),
+        Ok(_) => Ok(LinkStatus::Diverged),
+    }
+}
+
+/// Checks that a hard-linked entry still shares its files with the repository. An
+/// editor that saves by replacing the file breaks the link, which counts as diverged.
+fn hardlink_status(entry: &Entry) -> io::Result<LinkStatus> {
+    match fs::symlink_metadata(&entry.target) {
+        Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::NotADirectory) => Ok(LinkStatus::Missing),
+        Err(e) => Err(e),
+        Ok(metadata) if metadata.file_type().is_symlink() => Ok(LinkStatus::WrongTarget(fs::read_link(&entry.target)?)),
+        Ok(_) if fsutil::same_inodes(&entry.stored, &entry.target) => Ok(LinkStatus::Linked),
+        Ok(_) => Ok(LinkStatus::Diverged),
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::errors::{self, Code};
+use crate::git;
+use crate::repo;
+use crate::ui::message_box;
+
+/// Comment that marks a line as not secret, for values that only look like one.
+const ALLOW_MARKER: &str = "dfl:allow-secret";
+
+/// Files larger than this are not read; dotfiles with secrets in them are small.
+const MAX_SCANNED_BYTES: u64 = 1024 * 1024;
+
+/// Prefixes of access tokens whose format is known, with how many characters
+/// follow at least and what they belong to.
+const TOKEN_PREFIXES: &[(&str, usize, &str)] = &[
+    ("ghp_", 36, "GitHub token"),
+    ("gho_", 36, "GitHub token"),
+    ("ghu_", 36, "GitHub token"),
+    ("ghs_", 36, "GitHub token"),
+    ("ghr_", 36, "GitHub token"),
+    ("github_pat_", 22, "GitHub token"),
+    ("glpat-", 20, "GitLab token"),
+    ("xoxb-", 10, "Slack token"),
+    ("xoxp-", 10, "Slack token"),
+    ("AKIA", 16, "AWS access key ID"),
+    ("ASIA", 16, "AWS access key ID"),
+    ("AIza", 35, "Google API key"),
+    ("sk_live_", 16, "Stripe secret key"),
+    ("sk-", 32, "API key"),
+    ("npm_", 36, "npm token"),
+];
+
+/// Words that make a variable or setting hold a secret when a literal value is assigned to it.
+const SECRET_NAMES: &[&str] = &["token", "secret", "password", "passwd", "api_key", "apikey", "access_key"];
+
+/// Something that looks like a secret, and where it is.
+struct Finding {
+    /// Path as shown to the user, starting with `~/` below the home directory.
+    path: String,
+    /// Line number, from 1.
+    line: usize,
+    /// What it looks like, such as "GitHub token".
+    kind: &'static str,
+}
+
+/// Returns true for the characters tokens are made of.
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Returns true if `value` is a literal worth calling a secret: long enough, and
+/// not a reference to a variable, a command, or a placeholder.
+fn is_literal_secret(value: &str) -> bool {
+    let value = value.trim().trim_matches(['"', '\'']);
+    value.len() >= 12 && !value.contains(['$', '(', '{', '<', ' ']) && value.chars().collect::<HashSet<_>>().len() > 4
+}
+
+/// Returns what `line` of the file at `path` looks like, if it looks like a secret.
+fn check_line(path: &str, line: &str) -> Option<&'static str> {
+    if line.contains(ALLOW_MARKER) {
+        return None;
+    }
+    if line.contains("-----BEGIN") && line.contains("PRIVATE KEY") {
+        return Some("private key");
+    }
+    for token in line.split(|c: char| !is_token_char(c)) {
+        for (prefix, length, kind) in TOKEN_PREFIXES {
+            // AWS key IDs are upper case, which tells them apart from words that happen to start alike.
+            let upper_only = matches!(*prefix, "AKIA" | "ASIA");
+            if let Some(rest) = token.strip_prefix(prefix)
+                && rest.len() >= *length
+                && (!upper_only || rest.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()))
+            {
+                return Some(kind);
+            }
+        }
+    }
+
+    let lower = line.to_ascii_lowercase();
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    if file_name.contains("netrc") {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.windows(2).any(|pair| pair[0] == "password" && is_literal_secret(pair[1])) {
+            return Some(".netrc password");
+        }
+    }
+    if lower.contains("aws_secret_access_key") && lower.split_once(['=', ':']).is_some_and(|(_, value)| is_literal_secret(value)) {
+        return Some("AWS secret access key");
+    }
+    // Assignments such as `export GITHUB_TOKEN=...` or `password = ...` in an ini file.
+    let (name, value) = line.split_once('=').or_else(|| line.split_once(':'))?;
+    let name = name.trim().trim_start_matches("export ").to_ascii_lowercase();
+    if name.contains(char::is_whitespace) {
+        return None;
+    }
+    (SECRET_NAMES.iter().any(|word| name.contains(word)) && is_literal_secret(value)).then_some("secret assigned to a variable")
+}
+
+/// Returns `path` as shown to the user, with `~` for the home directory.
+fn shown(path: &Path, home_dir: &Path) -> String {
+    path.strip_prefix(home_dir).map(|path| format!("~/{}", path.display())).unwrap_or_else(|_| path.display().to_string())
+}
+
+/// Checks every line of `text`, the contents of the file at `path`.
+fn check_text(path: &str, text: &str, findings: &mut Vec<Finding>) {
+    for (index, line) in text.lines().enumerate() {
+        if let Some(kind) = check_line(path, line) {
+            findings.push(Finding { path: path.to_string(), line: index + 1, kind });
+        }
+    }
+}
+
+/// Looks for secrets in what a commit would take in: every file at or below the
+/// `sources` about to be added, and the lines added to managed files since the
+/// last commit. Binary and very large files are skipped.
+fn scan(dfl_path: &Path, home_dir: &Path, sources: &[PathBuf]) -> io::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    for source in sources {
+        // Symlinks are already managed or point elsewhere, so their contents are not
+        // added; missing paths are reported by the add itself.
+        if !fs::symlink_metadata(source).is_ok_and(|metadata| !metadata.file_type().is_symlink()) {
+            continue;
+        }
+        for file in repo::files_under(source)? {
+            if fs::metadata(&file)?.len() > MAX_SCANNED_BYTES {
+                continue;
+            }
+            let contents = fs::read(&file)?;
+            if contents.contains(&0) {
+                continue;
+            }
+            check_text(&shown(&std::path::absolute(&file)?, home_dir), &String::from_utf8_lossy(&contents), &mut findings);
+        }
+    }
+    for (path, line, text) in git::uncommitted_lines(dfl_path)? {
+        if let Some(kind) = check_line(&path, &text) {
+            findings.push(Finding { path: shown(&git::work_tree(dfl_path).join(&path), home_dir), line, kind });
+        }
+    }
+    Ok(findings)
+}
+
+/// Stops a commit if `scan` finds anything that looks like a secret, listing
+/// where without showing the values themselves.
+pub fn check(dfl_path: &Path, home_dir: &Path, sources: &[PathBuf]) -> io::Result<()> {
+    let findings = scan(dfl_path, home_dir, sources)?;
+    if findings.is_empty() {
+        return Ok(());
+    }
+    let lines: Vec<String> = findings.iter().map(|finding| format!("{}:{}  {}", finding.path, finding.line, finding.kind)).collect();
+    message_box("Possible Secrets", &lines.join("\n"));
+    Err(errors::error(
+        Code::SecretDetected,
+        format!("Not committing: {} line(s) look like secrets, which stay in the history once pushed. Move them out of your dotfiles, mark a line that is not secret with the comment '{}', or pass --allow-secrets.", findings.len(), ALLOW_MARKER),
+    ))
+}
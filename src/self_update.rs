@@ -0,0 +1,160 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path};
+
+use duct::cmd;
+use serde::Deserialize;
+
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::ui::{self, message_box};
+use crate::verify;
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/aashish-thapa/dfl/releases/latest";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Returns the Rust target triple release artifacts are named after, e.g. `x86_64-unknown-linux-gnu`.
+fn release_target() -> String {
+    let os = match env::consts::OS {
+        "linux" => "unknown-linux-gnu",
+        "macos" => "apple-darwin",
+        other => other,
+    };
+    format!("{}-{}", env::consts::ARCH, os)
+}
+
+/// Parses a `v1.2.3` or `1.2.3` version into parts that compare the way releases
+/// are ordered. Missing parts count as 0, so `1.2` is `1.2.0`, and a pre-release
+/// such as `1.3.0-rc1` comes before `1.3.0`. Build metadata after `+` is ignored.
+fn parse_version(version: &str) -> (u64, u64, u64, bool, String) {
+    let version = version.trim_start_matches('v');
+    let version = version.split_once('+').map_or(version, |(version, _)| version);
+    let (numbers, pre_release) = version.split_once('-').unwrap_or((version, ""));
+    let mut parts = numbers.split('.').map(|part| part.parse().unwrap_or(0));
+    let mut next = || parts.next().unwrap_or(0);
+    (next(), next(), next(), pre_release.is_empty(), pre_release.to_string())
+}
+
+fn download(url: &str, destination: &Path) -> io::Result<()> {
+    cmd!("curl", "-fsSL", "-o", destination, url)
+        .run()
+        .map_err(|e| errors::error(Code::UpdateFailed, format!("Error downloading {}: {}", url, e)))?;
+    Ok(())
+}
+
+/// Handles the 'self-update' command.
+/// It checks the latest GitHub release, downloads the artifact for this platform,
+/// verifies its checksum, and atomically replaces the running binary.
+pub fn handle_self_update_command(check_only: bool) -> io::Result<()> {
+    let current_version = env!("CARGO_PKG_VERSION");
+
+    let current_exe = env::current_exe()?.canonicalize()?;
+    if current_exe.components().any(|c| c.as_os_str() == ".cargo") {
+        message_box("Installed with Cargo", "This copy of dfl is managed by Cargo. Run 'cargo install dfl' to upgrade it instead.");
+        return Ok(());
+    }
+
+    ui::say("Checking for updates...");
+    let response = cmd!("curl", "-fsSL", "-H", "Accept: application/vnd.github+json", LATEST_RELEASE_URL)
+        .read()
+        .map_err(|e| errors::error(Code::UpdateFailed, format!("Error checking for the latest release: {}", e)))?;
+    let release: Release = serde_json::from_str(&response)
+        .map_err(|e| errors::error(Code::UpdateFailed, format!("Unexpected response from GitHub: {}", e)))?;
+
+    if parse_version(&release.tag_name) <= parse_version(current_version) {
+        ui::say(&format!("✅ dfl {} is already the latest version.", current_version));
+        return Ok(());
+    }
+    ui::say(&format!("A new version is available: {} (installed: {})", release.tag_name, current_version));
+    if check_only {
+        return Ok(());
+    }
+
+    let target = release_target();
+    let archive = release
+        .assets
+        .iter()
+        .find(|a| a.name.contains(&target) && a.name.ends_with(".tar.gz"))
+        .ok_or_else(|| errors::error(Code::UpdateFailed, format!("Release {} has no artifact for {}.", release.tag_name, target)))?;
+    let checksum_name = format!("{}.sha256", archive.name);
+    let checksum = release
+        .assets
+        .iter()
+        .find(|a| a.name == checksum_name)
+        .ok_or_else(|| errors::error(Code::UpdateFailed, format!("Release {} has no checksum file '{}'.", release.tag_name, checksum_name)))?;
+
+    // A fresh directory only this user can write to, so nobody can slip another binary into it.
+    let work_dir = fsutil::PrivateDir::create("dfl-update")?;
+    install_release(archive, checksum, work_dir.path(), &current_exe)?;
+
+    message_box("Updated", &format!("dfl has been updated to {}.", release.tag_name));
+    Ok(())
+}
+
+fn install_release(archive: &Asset, checksum: &Asset, work_dir: &Path, current_exe: &Path) -> io::Result<()> {
+    let archive_path = work_dir.join(&archive.name);
+    let checksum_path = work_dir.join(&checksum.name);
+    ui::say(&format!("Downloading {}...", archive.name));
+    download(&archive.browser_download_url, &archive_path)?;
+    download(&checksum.browser_download_url, &checksum_path)?;
+
+    // The checksum file uses the `sha256sum` format: "<hash>  <file name>".
+    let expected = fs::read_to_string(&checksum_path)?.split_whitespace().next().unwrap_or_default().to_lowercase();
+    let actual = verify::sha256_file(&archive_path)?;
+    if expected != actual {
+        return Err(errors::error(Code::UpdateFailed, format!("Checksum mismatch for {}: expected {}, got {}.", archive.name, expected, actual)));
+    }
+    ui::say("✅ Checksum verified.");
+
+    let member = binary_member(&archive_path)?;
+    cmd!("tar", "-xzf", &archive_path, "-C", work_dir, &member)
+        .run()
+        .map_err(|e| errors::error(Code::UpdateFailed, format!("Error extracting {}: {}", archive.name, e)))?;
+    let new_binary = work_dir.join(&member);
+    if !fs::symlink_metadata(&new_binary).is_ok_and(|metadata| metadata.is_file()) {
+        return Err(errors::error(Code::UpdateFailed, format!("'{}' in {} is not a regular file.", member, archive.name)));
+    }
+
+    // Stage the new binary next to the current one so the final rename stays on one filesystem and is atomic.
+    let install_dir = current_exe.parent().ok_or_else(|| errors::error(Code::UpdateFailed, "Could not determine the install directory"))?;
+    let staged = install_dir.join(".dfl.new");
+    fs::copy(&new_binary, &staged)?;
+    fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    if let Err(e) = fs::rename(&staged, current_exe) {
+        let _ = fs::remove_file(&staged);
+        return Err(errors::error(Code::UpdateFailed, format!("Error replacing {}: {}", current_exe.display(), e)));
+    }
+    ui::say(&format!("✅ Replaced {}", current_exe.display()));
+    Ok(())
+}
+
+/// Returns the name of the `dfl` executable in the release archive, which is at
+/// its top or in the one directory the archive unpacks into. Only that member is
+/// extracted, so nothing else in the archive can be mistaken for it.
+fn binary_member(archive: &Path) -> io::Result<String> {
+    let listing = cmd!("tar", "-tzf", archive).read().map_err(|e| errors::error(Code::UpdateFailed, format!("Error listing {}: {}", archive.display(), e)))?;
+    let mut members = listing.lines().filter(|member| {
+        let path = Path::new(member);
+        let depth = path.components().filter(|component| matches!(component, Component::Normal(_))).count();
+        // Directories are listed with a trailing slash; only plain relative names are taken.
+        !member.ends_with('/') && path.file_name().is_some_and(|name| name == "dfl") && depth <= 2 && path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    });
+    match (members.next(), members.next()) {
+        (Some(member), None) => Ok(member.to_string()),
+        (None, _) => Err(errors::error(Code::UpdateFailed, "The release archive does not contain a 'dfl' binary.")),
+        (Some(_), Some(_)) => Err(errors::error(Code::UpdateFailed, "The release archive contains more than one 'dfl' binary.")),
+    }
+}
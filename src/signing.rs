@@ -0,0 +1,103 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use duct::cmd;
+
+use crate::config;
+use crate::errors::{self, Code};
+
+/// Extension of a key file that holds an SSH public key.
+pub const SSH_PUBLIC_KEY: &str = "pub";
+
+/// How commits are signed.
+pub struct Signing {
+    /// Sign with an SSH key instead of a GPG key.
+    pub ssh: bool,
+    /// GPG key ID, or path to the SSH key; empty for GPG's default key.
+    pub key: String,
+    /// `gpg` or `ssh-keygen`, unless git's configuration names another program.
+    pub program: String,
+}
+
+/// Reads the signing settings: signing is on when `sign_commits` is set in dfl's
+/// config or `commit.gpgsign` in git's, and dfl's `signing_key` wins over git's
+/// `user.signingkey`. Returns `None` when commits are not signed.
+pub fn settings(git_config: &git2::Config) -> io::Result<Option<Signing>> {
+    let config = config::load()?;
+    if !config.sign_commits && !git_config.get_bool("commit.gpgsign").unwrap_or(false) {
+        return Ok(None);
+    }
+    let key = Some(config.signing_key).filter(|key| !key.is_empty()).or_else(|| git_config.get_string("user.signingkey").ok()).unwrap_or_default();
+    let ssh = git_config.get_string("gpg.format").is_ok_and(|format| format == "ssh") || key.starts_with("ssh-") || Path::new(&key).extension().is_some_and(|extension| extension == SSH_PUBLIC_KEY);
+    let program = if ssh { git_config.get_string("gpg.ssh.program").unwrap_or_else(|_| "ssh-keygen".to_string()) } else { git_config.get_string("gpg.program").unwrap_or_else(|_| "gpg".to_string()) };
+    Ok(Some(Signing { ssh, key, program }))
+}
+
+/// Signs a commit's contents, returning the armored signature git stores with it.
+pub fn sign(content: &str, signing: &Signing) -> io::Result<String> {
+    let failed = |e: &dyn std::fmt::Display| errors::error(Code::GitFailed, format!("Could not sign the commit with {}: {}", signing.program, e));
+    let output = if signing.ssh {
+        if signing.key.is_empty() {
+            return Err(errors::error(Code::InvalidConfig, "SSH signing needs a key. Set signing_key in the dfl config to your public key file, e.g. '~/.ssh/id_ed25519.pub'."));
+        }
+        // A key given literally, as git allows, is read from a file by ssh-keygen.
+        let literal = signing.key.starts_with("ssh-");
+        let key_file = if literal {
+            let path = std::env::temp_dir().join(format!("dfl-signing-{}.pub", std::process::id()));
+            fs::write(&path, &signing.key)?;
+            path
+        } else {
+            let home = dirs::home_dir().unwrap_or_default();
+            signing.key.strip_prefix("~/").map_or_else(|| Path::new(&signing.key).to_path_buf(), |rest| home.join(rest))
+        };
+        let output = cmd!(&signing.program, "-Y", "sign", "-n", "git", "-f", &key_file).stdin_bytes(content).stdout_capture().stderr_capture().unchecked().run();
+        if literal {
+            let _ = fs::remove_file(&key_file);
+        }
+        output.map_err(|e| failed(&e))?
+    } else {
+        let mut args = vec!["--status-fd=2", "-bsa"];
+        if !signing.key.is_empty() {
+            args.extend(["-u", signing.key.as_str()]);
+        }
+        cmd(&signing.program, args).stdin_bytes(content).stdout_capture().stderr_capture().unchecked().run().map_err(|e| failed(&e))?
+    };
+    if !output.status.success() {
+        return Err(failed(&String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Signs a sample text to find out whether signing works, returning a description
+/// of the key used, or `None` when commits are not signed.
+pub fn check(dfl_path: &Path) -> io::Result<Option<String>> {
+    let git_config = match git2::Repository::open(dfl_path) {
+        Ok(repo) => repo.config(),
+        Err(_) => git2::Config::open_default(),
+    }
+    .map_err(|e| io::Error::other(e.message().to_string()))?;
+    let Some(signing) = settings(&git_config)? else {
+        return Ok(None);
+    };
+    sign("dfl signing check\n", &signing)?;
+    let kind = if signing.ssh { "SSH" } else { "GPG" };
+    Ok(Some(if signing.key.is_empty() { format!("{} default key", kind) } else { format!("{} key {}", kind, signing.key) }))
+}
+
+/// Returns the `-c` options that make the `git` command sign as dfl's config asks.
+/// Signing set up in git's own configuration needs none.
+pub fn command_options() -> io::Result<Vec<String>> {
+    let config = config::load()?;
+    let mut options = Vec::new();
+    if config.sign_commits {
+        options.extend(["-c".to_string(), "commit.gpgsign=true".to_string()]);
+    }
+    if !config.signing_key.is_empty() {
+        options.extend(["-c".to_string(), format!("user.signingkey={}", config.signing_key)]);
+        if config.signing_key.starts_with("ssh-") || config.signing_key.ends_with(".pub") {
+            options.extend(["-c".to_string(), "gpg.format=ssh".to_string()]);
+        }
+    }
+    Ok(options)
+}
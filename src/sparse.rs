@@ -0,0 +1,128 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::manifest::{self, MANIFEST_FILE, Manifest};
+use crate::repo;
+use crate::ui;
+
+/// File in which git keeps the patterns of a sparse checkout.
+const PATTERNS_FILE: &str = ".git/info/sparse-checkout";
+
+/// dfl's own files at the repository root, which every machine checks out.
+const ALWAYS: &[&str] = &["/dfl.toml", "/.gitignore", "/.gitattributes", "/.gitmodules", "/.dflignore", "/hooks/"];
+
+/// Returns true if only part of the repository is checked out.
+pub fn in_use(dfl_path: &Path) -> bool {
+    dfl_path.join(PATTERNS_FILE).exists()
+}
+
+/// Returns the classes in `sparse` this machine belongs to and the patterns they
+/// check out, or no patterns if it belongs to none and checks out everything.
+fn wanted(manifest: &Manifest) -> io::Result<(Vec<String>, Vec<String>)> {
+    let tags = config::load()?.tags;
+    let mut classes = Vec::new();
+    let mut patterns: Vec<String> = Vec::new();
+    for (class, listed) in manifest.sparse.iter().filter(|(class, _)| tags.contains(class)) {
+        classes.push(class.clone());
+        for pattern in ALWAYS.iter().map(|pattern| pattern.to_string()).chain(listed.iter().cloned()) {
+            if !patterns.contains(&pattern) {
+                patterns.push(pattern);
+            }
+        }
+    }
+    Ok((classes, patterns))
+}
+
+/// Says which classes the checkout is limited to.
+fn report(classes: &[String]) {
+    ui::say(&format!("✅ Checked out only what {} machines need; the rest of the repository stays in its history.", classes.join(", ")));
+}
+
+/// Checks out a fresh clone: only the patterns this machine's classes list in
+/// the cloned dfl.toml, or the whole repository. Without the `git` command, which
+/// a sparse checkout needs, everything is checked out with a warning.
+pub fn check_out(dfl_path: &Path) -> io::Result<()> {
+    if git::head(dfl_path)?.is_none() {
+        return Ok(());
+    }
+    let manifest = match git::file_at(dfl_path, "HEAD", MANIFEST_FILE)? {
+        Some(contents) => manifest::parse(&contents, MANIFEST_FILE)?,
+        None => Manifest::default(),
+    };
+    let (classes, patterns) = wanted(&manifest)?;
+    if !patterns.is_empty() {
+        match git::sparse_checkout(dfl_path, Some(&patterns)) {
+            Ok(()) => {
+                report(&classes);
+                return Ok(());
+            }
+            Err(e) => ui::warn(&format!("Could not check out only what {} machines need, so everything is checked out: {}", classes.join(", "), e))?,
+        }
+    }
+    git::checkout_head(dfl_path).map_err(|e| errors::error(Code::GitFailed, format!("Error checking out the files: {}", e)))
+}
+
+/// Brings the checkout in line with `sparse` in dfl.toml and this machine's tags,
+/// after either changed: files of classes the machine no longer needs are removed
+/// from disk, and files it now needs are checked out.
+pub fn apply(dfl_path: &Path) -> io::Result<()> {
+    let (classes, patterns) = wanted(&manifest::load(dfl_path)?)?;
+    let failed = |e: io::Error| errors::error(Code::GitFailed, format!("Error changing which files are checked out: {}", e));
+    if patterns.is_empty() {
+        if in_use(dfl_path) {
+            git::sparse_checkout(dfl_path, None).map_err(failed)?;
+            fs::remove_file(dfl_path.join(PATTERNS_FILE))?;
+            ui::say("✅ Checked out the whole repository again, as this machine is in none of the classes in [sparse].");
+        }
+        return Ok(());
+    }
+    let current = fs::read_to_string(dfl_path.join(PATTERNS_FILE)).unwrap_or_default();
+    if current.lines().eq(patterns.iter().map(String::as_str)) {
+        return Ok(());
+    }
+    git::sparse_checkout(dfl_path, Some(&patterns)).map_err(failed)?;
+    report(&classes);
+    Ok(())
+}
+
+/// What the 'sparse' command does.
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum SparseCommand {
+    /// Shows which paths this machine checks out (the default).
+    Status,
+    /// Checks out what dfl.toml and this machine's tags call for now.
+    Apply,
+}
+
+/// Handles the 'sparse' command.
+/// It shows which part of the repository this machine checks out, or with `apply`
+/// checks out what `sparse` in dfl.toml and the machine's tags call for now.
+pub fn handle_sparse_command(command: Option<SparseCommand>) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+    match command.unwrap_or(SparseCommand::Status) {
+        SparseCommand::Status => {
+            let (_, patterns) = wanted(&manifest::load(&dfl_path)?)?;
+            let current = fs::read_to_string(dfl_path.join(PATTERNS_FILE)).unwrap_or_default();
+            if in_use(&dfl_path) {
+                println!("Only these paths are checked out:");
+                for pattern in current.lines() {
+                    println!("  {}", pattern);
+                }
+            } else {
+                println!("The whole repository is checked out. List what a class of machines needs under [sparse] in {} to check out less.", MANIFEST_FILE);
+            }
+            if !current.lines().eq(patterns.iter().map(String::as_str)) {
+                println!("{} or this machine's tags changed since; run 'dfl sparse apply' to catch up.", MANIFEST_FILE);
+            }
+            Ok(())
+        }
+        SparseCommand::Apply => apply(&dfl_path),
+    }
+}
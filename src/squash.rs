@@ -0,0 +1,148 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::{self, Code};
+use crate::git;
+use crate::repo;
+use crate::ui::{self, message_box};
+
+/// Parses `2024-05-01` as midnight UTC that day, or `7d` as seven days ago, into
+/// seconds since the epoch.
+fn parse_date(since: &str) -> Option<i64> {
+    if let Some(days) = since.strip_suffix('d').and_then(|days| days.parse::<i64>().ok()) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+        return Some(now - days * 86_400);
+    }
+    let mut parts = since.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day))) = (parts.next(), parts.next(), parts.next()) else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days-from-civil conversion (Howard Hinnant's algorithm).
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some((era * 146_097 + doe - 719_468) * 86_400)
+}
+
+/// Resolves `since` to the commit the squashed commit goes on top of: a commit or
+/// tag itself, or the newest commit made before a date. A shallow clone may not
+/// have that commit yet, so its older history is fetched before giving up.
+fn find_base(dfl_path: &Path, since: &str) -> io::Result<String> {
+    let time = parse_date(since);
+    loop {
+        if let Some(id) = git::find_commit(dfl_path, since)? {
+            return Ok(id);
+        }
+        if let Some(id) = time.map(|time| git::last_commit_before(dfl_path, time)).transpose()?.flatten() {
+            return Ok(id);
+        }
+        if !git::is_shallow(dfl_path)? {
+            break;
+        }
+        ui::say(&format!("Fetching older history: this shallow clone does not reach back to {}.", since));
+        git::unshallow(dfl_path).map_err(|e| errors::error(Code::PullFailed, format!("Error fetching older history: {}", e)))?;
+    }
+    if time.is_none() {
+        return Err(errors::error(Code::InvalidArgument, format!("'{}' is not a commit, a tag, or a date such as 2024-05-01 or 7d.", since)));
+    }
+    Err(errors::error(Code::InvalidArgument, format!("Every commit is newer than {}, so there is nothing to squash them onto.", since)))
+}
+
+/// Builds the message for the squashed commit: how many commits it replaces, then
+/// their subjects, with repeated ones listed once and counted.
+fn summary(dfl_path: &Path, since: &str, base: &str, head: &str, subjects: &[String]) -> io::Result<String> {
+    let mut counted: Vec<(&str, usize)> = Vec::new();
+    for subject in subjects {
+        match counted.iter_mut().find(|(seen, _)| *seen == subject) {
+            Some((_, count)) => *count += 1,
+            None => counted.push((subject, 1)),
+        }
+    }
+    let lines: Vec<String> = counted.iter().map(|(subject, count)| if *count > 1 { format!("- {} ({} times)", subject, count) } else { format!("- {}", subject) }).collect();
+    let default = format!("Squash {} commits since {}\n\n{}", subjects.len(), since, lines.join("\n"));
+
+    // The template names the files instead, from `git diff --name-status` lines.
+    let files: Vec<PathBuf> = git::changed_files(dfl_path, base, head)?.iter().filter_map(|line| line.split_once('\t')).map(|(_, path)| PathBuf::from(path)).collect();
+    git::commit_message("squash", &files, default)
+}
+
+/// Handles the 'squash' command.
+/// It replaces the current branch's commits since a commit, tag, or date with one
+/// commit holding the same files. Commits that were already pushed are only
+/// replaced on the remote with `push`, and only if nobody pushed on top of them.
+pub fn handle_squash_command(since: &str, message: Option<String>, push: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+    if git::has_local_changes(&dfl_path)? {
+        return Err(errors::error(Code::InvalidArgument, "You have uncommitted changes. Commit them with 'dfl add' or discard them before squashing."));
+    }
+
+    let failed = |action: &str, e: io::Error| errors::error(Code::GitFailed, format!("Error {}: {}", action, e));
+    let branch = git::branch(&dfl_path).map_err(|e| failed("finding the current branch", e))?;
+    let Some(head) = git::head(&dfl_path)? else {
+        return Err(errors::error(Code::InvalidArgument, "There are no commits to squash yet."));
+    };
+    let base = find_base(&dfl_path, since)?;
+    if git::count_commits(&dfl_path, Some(&head), &base)? > 0 {
+        return Err(errors::error(Code::InvalidArgument, format!("'{}' is not part of the history of '{}'.", since, branch)));
+    }
+    let subjects = git::subjects(&dfl_path, &base, &head)?;
+    if subjects.len() < 2 {
+        ui::say(&format!("Nothing to squash: there are {} commit(s) since {}.", subjects.len(), since));
+        return Ok(());
+    }
+
+    // Squashing commits the remote has and then force pushing would drop anything
+    // pushed on top of them from another machine, so that has to be pulled first.
+    let upstream = git::upstream(&dfl_path, &branch)?;
+    if upstream.is_some() {
+        if let Err(e) = git::fetch(&dfl_path) {
+            ui::warn(&format!("Could not check the remote for new commits: {}", e))?;
+        }
+        let incoming = git::incoming(&dfl_path)?;
+        if incoming > 0 {
+            return Err(errors::error(Code::PullFailed, format!("The remote has {} commit(s) you have not pulled. Run 'dfl pull' first so squashing does not lose them.", incoming)));
+        }
+    }
+    let published = git::upstream_head(&dfl_path, &branch)?;
+    let rewrites_published = match &published {
+        Some(published) => git::count_commits(&dfl_path, Some(&base), published)? > 0,
+        None => false,
+    };
+
+    let message = match message {
+        Some(message) => message,
+        None => summary(&dfl_path, since, &base, &head, &subjects)?,
+    };
+    git::squash(&dfl_path, &base, &message).map_err(|e| failed("squashing", e))?;
+    ui::say(&format!("✅ Squashed {} commits into one.", subjects.len()));
+    ui::say(&format!("The previous history is still in git; 'git -C {} reset --hard {}' brings it back.", dfl_path.display(), &head[..head.len().min(12)]));
+
+    match upstream {
+        Some((remote, remote_branch)) if rewrites_published && push => {
+            git::force_push(&dfl_path, &branch, &remote, &remote_branch, published.as_deref()).map_err(|e| errors::error(Code::PushFailed, format!("Error replacing the pushed history: {}", e)))?;
+            ui::say(&format!("✅ Replaced the pushed history on {}.", remote));
+        }
+        Some((remote, _)) if rewrites_published => {
+            ui::say(&format!("Some of these commits were already pushed to {}, so a plain 'dfl push' will be rejected.", remote));
+            ui::say("Run 'dfl push --force' to replace them there; it refuses if the remote changed since your last pull.");
+        }
+        _ if push => {
+            crate::remote::push_after_commit(&dfl_path)?;
+        }
+        _ => {}
+    }
+    if rewrites_published {
+        message_box("Squashed", "Other machines that pulled the old commits pick up the squashed history with 'dfl pull'.");
+    }
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use duct::cmd;
+
+use crate::alternate;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::repo;
+use crate::ui::{self, message_box};
+
+/// How often access is checked again while waiting for a new key to be added.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many checks to make before giving up, ten minutes in all.
+const POLL_ATTEMPTS: u32 = 120;
+
+/// Keys tried in order, matching the ones dfl's built-in git logs in with.
+const KEYS: &[&str] = &["id_ed25519", "id_rsa"];
+
+/// Returns the host of an SSH remote URL, `ssh://[user@]host[:port]/path` or
+/// `[user@]host:path`, or `None` for any other transport.
+fn ssh_host(url: &str) -> Option<String> {
+    let authority = match url.split_once("://") {
+        Some(("ssh" | "git+ssh" | "ssh+git", rest)) => rest.split('/').next()?.rsplit('@').next()?.split(':').next()?,
+        Some(_) => return None,
+        None => {
+            let (authority, _) = url.split_once(':')?;
+            if authority.contains('/') {
+                return None;
+            }
+            authority.rsplit('@').next()?
+        }
+    };
+    (!authority.is_empty()).then(|| authority.to_string())
+}
+
+/// Returns true if a failed check was the host refusing the login, as opposed to
+/// the network or a repository that does not exist.
+fn is_auth_failure(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    ["permission denied", "publickey", "authentication", "no ssh key"].iter().any(|sign| message.contains(sign))
+}
+
+/// Returns where a public key is added on well-known hosts.
+fn key_settings(host: &str) -> String {
+    match host {
+        "github.com" => "https://github.com/settings/ssh/new".to_string(),
+        "gitlab.com" => "https://gitlab.com/-/user_settings/ssh_keys".to_string(),
+        "codeberg.org" => "https://codeberg.org/user/settings/keys".to_string(),
+        _ => format!("the SSH key settings of your account on {}", host),
+    }
+}
+
+/// Generates an ed25519 key at `key` with ssh-keygen, which asks for an optional passphrase.
+fn generate_key(key: &Path) -> io::Result<()> {
+    if let Some(ssh_dir) = key.parent() {
+        fs::create_dir_all(ssh_dir)?;
+        fs::set_permissions(ssh_dir, fs::Permissions::from_mode(0o700))?;
+    }
+    let comment = format!("dfl@{}", alternate::hostname());
+    cmd!("ssh-keygen", "-t", "ed25519", "-C", comment, "-f", key).run().map_err(|e| errors::error(Code::SshAuthFailed, format!("Error running ssh-keygen: {}", e)))?;
+    ui::say(&format!("✅ Generated {}. If you gave it a passphrase, add it to ssh-agent with 'ssh-add'.", key.display()));
+    Ok(())
+}
+
+/// Checks that `remote` accepts an SSH login before the first push to it. If it
+/// does not, and dfl runs in a terminal, it offers to generate a key, shows the
+/// public key to add on the host, and waits until the host accepts it. Remotes
+/// reached over other transports, and failures other than a refused login, are
+/// left for the push to report.
+pub fn preflight(dfl_path: &Path, remote: &str) -> io::Result<()> {
+    let Some(url) = git::remote_url(dfl_path, remote)? else {
+        return Ok(());
+    };
+    let Some(host) = ssh_host(&url) else {
+        return Ok(());
+    };
+    let refused = match git::check_access(dfl_path, remote) {
+        // git follows the host's answer with generic advice; the answer is what matters.
+        Err(e) if is_auth_failure(&e.to_string()) => e.to_string().lines().next().unwrap_or_default().to_string(),
+        _ => return Ok(()),
+    };
+
+    let ssh_dir = repo::home_dir()?.join(".ssh");
+    let public_key = KEYS.iter().map(|key| ssh_dir.join(format!("{}.pub", key))).find(|path| path.exists());
+    if !ui::interactive() {
+        let advice = match &public_key {
+            Some(path) => format!("Add {} at {}", path.display(), key_settings(&host)),
+            None => "Create a key with 'ssh-keygen -t ed25519' and add it on the host".to_string(),
+        };
+        return Err(errors::error(Code::SshAuthFailed, format!("{} does not accept your SSH login: {}\n{}, or run 'dfl push' in a terminal to be walked through it.", host, refused, advice)));
+    }
+
+    println!("{} does not accept your SSH login: {}", host, refused);
+    let public_key: PathBuf = match public_key {
+        Some(path) => path,
+        None => {
+            let generate = dialoguer::Confirm::new()
+                .with_prompt("You have no SSH key yet. Generate an ed25519 key now?")
+                .default(true)
+                .interact()
+                .map_err(|e| errors::error(Code::InvalidArgument, format!("The SSH key prompt needs a terminal: {}", e)))?;
+            if !generate {
+                return Err(errors::error(Code::SshAuthFailed, format!("{} needs an SSH key to push to. Create one with 'ssh-keygen -t ed25519', or use an HTTPS remote.", host)));
+            }
+            let key = ssh_dir.join(KEYS[0]);
+            generate_key(&key)?;
+            key.with_extension("pub")
+        }
+    };
+
+    message_box("Your Public SSH Key", fs::read_to_string(&public_key)?.trim());
+    println!("Add this key at {}", key_settings(&host));
+    println!("(or, for this repository only, as a deploy key with write access in its settings).");
+    println!("Waiting until {} accepts it; press Ctrl-C to stop...", host);
+    for _ in 0..POLL_ATTEMPTS {
+        thread::sleep(POLL_INTERVAL);
+        if git::check_access(dfl_path, remote).is_ok() {
+            ui::say(&format!("✅ {} accepts your SSH key.", host));
+            return Ok(());
+        }
+    }
+    Err(errors::error(Code::SshAuthFailed, format!("{} still does not accept {}. Add it on the host and run 'dfl push' again.", host, public_key.display())))
+}
@@ -0,0 +1,128 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::repo;
+
+/// Machine-local bookkeeping that should never be committed to the dotfiles repository.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct State {
+    /// How many times each command has completed successfully on this machine.
+    pub usage: BTreeMap<String, u32>,
+    /// Identifiers of hints that have already been shown.
+    pub hints_shown: BTreeSet<String>,
+    /// What an older dfl kept here for every repository alike, carried along until
+    /// the default repository has state of its own.
+    #[serde(flatten)]
+    legacy: toml::Table,
+}
+
+/// Machine-local bookkeeping about one repository. Each repository keeps its own,
+/// so the repositories of different profiles never see each other's.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RepoState {
+    /// Managed targets restored from a backup. `sync` leaves them alone until forced.
+    pub restored: BTreeSet<PathBuf>,
+    /// Branches whose push failed because the remote could not be reached. The
+    /// next pull or up pushes them.
+    pub queued_pushes: BTreeSet<String>,
+    /// The branch every machine shares, set while this machine commits to its own
+    /// `machine/<hostname>` branch instead.
+    pub shared_branch: Option<String>,
+}
+
+/// Returns the directory for machine-local state by the XDG base directories,
+/// `$XDG_STATE_HOME/dfl`, and where an older dfl kept it, the system's own state
+/// or local data directory. They only differ where the XDG variable overrides the
+/// system's convention, such as on macOS.
+pub fn state_dirs() -> io::Result<(PathBuf, PathBuf)> {
+    let legacy = dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .ok_or_else(|| errors::error(Code::NoHomeDir, "Could not find state directory"))?
+        .join("dfl");
+    let current = repo::xdg_dir("XDG_STATE_HOME", || None).map_or_else(|| legacy.clone(), |dir| dir.join("dfl"));
+    Ok((current, legacy))
+}
+
+/// Returns the directory dfl keeps its machine-local state in.
+pub fn state_dir() -> io::Result<PathBuf> {
+    let (current, legacy) = state_dirs()?;
+    Ok(repo::current_or_legacy(current, legacy))
+}
+
+fn state_path() -> io::Result<PathBuf> {
+    Ok(state_dir()?.join("state.toml"))
+}
+
+/// Returns the directory dfl keeps what it knows about the repository at
+/// `dfl_path` in. It is inside the git directory, so it moves with the repository
+/// and is never committed.
+pub fn repo_dir(dfl_path: &Path) -> PathBuf {
+    git::git_dir(dfl_path).join("dfl")
+}
+
+/// Returns the file `name` in the state of the repository at `dfl_path`. Until it
+/// exists, the default repository reads the file an older dfl shared between all
+/// repositories, if there is one; it is written to its own place from then on.
+pub fn repo_file(dfl_path: &Path, name: &str) -> io::Result<PathBuf> {
+    let path = repo_dir(dfl_path).join(name);
+    if path.exists() || config::repo_path(&config::load()?)? != dfl_path {
+        return Ok(path);
+    }
+    let shared = state_dir()?.join(name);
+    Ok(if shared.exists() { shared } else { path })
+}
+
+/// Returns true if dfl has never recorded any state on this machine.
+pub fn is_first_run() -> io::Result<bool> {
+    Ok(!state_path()?.exists())
+}
+
+/// Loads the state file, returning an empty state when it does not exist yet.
+pub fn load() -> io::Result<State> {
+    let path = state_path()?;
+    if !path.exists() {
+        return Ok(State::default());
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    toml::from_str(&contents).map_err(|e| errors::error(Code::InvalidConfig, format!("Error parsing {}: {}", path.display(), e)))
+}
+
+/// Writes the state file, creating the state directory if needed.
+pub fn save(state: &State) -> io::Result<()> {
+    let path = state_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let contents = toml::to_string(state).map_err(io::Error::other)?;
+    fs::write(&path, contents)
+}
+
+/// Loads the state of the repository at `dfl_path`, empty when it has none yet.
+pub fn load_repo(dfl_path: &Path) -> io::Result<RepoState> {
+    let path = repo_file(dfl_path, "state.toml")?;
+    if !path.exists() {
+        return Ok(RepoState::default());
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    toml::from_str(&contents).map_err(|e| errors::error(Code::InvalidConfig, format!("Error parsing {}: {}", path.display(), e)))
+}
+
+/// Writes the state of the repository at `dfl_path`.
+pub fn save_repo(dfl_path: &Path, state: &RepoState) -> io::Result<()> {
+    let dir = repo_dir(dfl_path);
+    fs::create_dir_all(&dir)?;
+    let contents = toml::to_string(state).map_err(io::Error::other)?;
+    fs::write(dir.join("state.toml"), contents)
+}
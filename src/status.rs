@@ -0,0 +1,83 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::errors::{self, Code};
+use crate::git;
+use crate::repo;
+use crate::ui::{self, Tone};
+
+/// Where a repository stands, as the 'status' command shows it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Status {
+    /// The checked-out branch.
+    pub branch: String,
+    /// The branch it tracks and how far apart they are, or `None` if it is not pushed anywhere yet.
+    pub upstream: Option<Upstream>,
+    /// Absolute paths of the files changed since the last commit.
+    pub changes: Vec<PathBuf>,
+}
+
+/// The remote branch a local branch tracks, compared as of the last fetch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Upstream {
+    pub remote: String,
+    pub branch: String,
+    /// Local commits the remote branch does not have.
+    pub ahead: usize,
+    /// Remote commits the local branch does not have.
+    pub behind: usize,
+}
+
+/// Returns where the repository at `dfl_path` stands.
+pub fn status(dfl_path: &Path) -> io::Result<Status> {
+    let branch = git::branch(dfl_path)?;
+    let head = git::head(dfl_path)?;
+    let upstream = match (git::upstream(dfl_path, &branch)?, git::upstream_head(dfl_path, &branch)?, &head) {
+        (Some((remote, remote_branch)), Some(published), Some(head)) => Some(Upstream {
+            remote,
+            branch: remote_branch,
+            ahead: git::count_commits(dfl_path, Some(&published), head)?,
+            behind: git::count_commits(dfl_path, Some(head), &published)?,
+        }),
+        _ => None,
+    };
+    let work_tree = git::work_tree(dfl_path);
+    let changes = git::local_changes(dfl_path)?.iter().map(|change| work_tree.join(change)).collect();
+    Ok(Status { branch, upstream, changes })
+}
+
+/// Handles the 'status' command.
+/// It shows the current branch, how far it is ahead of or behind the branch it
+/// tracks as of the last fetch, and the files changed since the last commit,
+/// or prints all of that as JSON with `json`.
+pub fn handle_status_command(json: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let status = status(&dfl_path)?;
+    if json {
+        return ui::print_json(&status);
+    }
+    match &status.upstream {
+        Some(upstream) => println!(
+            "On branch {}, {} commit(s) ahead of and {} behind {}/{} as of the last fetch.",
+            status.branch, upstream.ahead, upstream.behind, upstream.remote, upstream.branch
+        ),
+        None => println!("On branch {}, which is not pushed anywhere yet.", status.branch),
+    }
+
+    if status.changes.is_empty() {
+        println!("{} Nothing to commit: your dotfiles match the last commit.", ui::mark(Tone::Ok));
+        return Ok(());
+    }
+    println!("{} file(s) changed since the last commit:", status.changes.len());
+    for path in &status.changes {
+        println!("  {} {}", ui::mark(Tone::Conflict), ui::paint(Tone::Conflict, &repo::display(path)));
+    }
+    println!("Run 'dfl commit' to commit them.");
+    Ok(())
+}
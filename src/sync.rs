@@ -0,0 +1,361 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::backup;
+use crate::config;
+use crate::conflict::{self, Resolution};
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::git;
+use crate::hooks;
+use crate::manifest;
+use crate::perms;
+use crate::plugins;
+use crate::prune;
+use crate::repo;
+use crate::state;
+use crate::template;
+use crate::transaction;
+use crate::ui::{self, Tone, message_box};
+use crate::verify;
+
+/// Options accepted by `dfl sync`.
+#[derive(Debug, Default)]
+pub struct SyncOptions {
+    /// Replace files and symlinks in the way instead of backing them up.
+    pub force: bool,
+    /// Ask how to settle each file that differs from the repository version.
+    pub interactive: bool,
+    /// The answer to apply to all remaining conflicts, once one was given.
+    pub resolution: Cell<Option<Resolution>>,
+    /// Create links relative to their own directory instead of absolute ones.
+    pub relative: bool,
+    /// Keep the changes made before a failure instead of undoing them.
+    pub keep_partial: bool,
+    /// Remove symlinks into the repository whose targets no longer exist.
+    pub prune: bool,
+    /// Names of the files, directories, or packages to deploy; empty for all of them.
+    pub only: Vec<String>,
+    /// The changes made so far, undone if the sync fails.
+    pub journal: RefCell<transaction::Journal>,
+}
+
+impl SyncOptions {
+    /// Options for syncing without asking, with the link style from the user configuration.
+    pub fn from_config() -> io::Result<SyncOptions> {
+        Ok(SyncOptions { relative: config::load()?.relative_links, ..SyncOptions::default() })
+    }
+}
+
+/// Handles the 'sync' command.
+/// It creates symlinks for all files in the dfl repository. With `json`, what it
+/// did is printed as JSON and its progress messages go to standard error.
+pub fn handle_sync_command(options: &SyncOptions, json: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    if json {
+        let redirect = ui::StdoutToStderr::new()?;
+        let tally = sync(&dfl_path, options);
+        drop(redirect);
+        return ui::print_json(&tally?);
+    }
+    ui::say("Syncing dotfiles...");
+    let tally = sync(&dfl_path, options)?;
+    if tally.conflicts > 0 {
+        message_box("Synced with warnings", &format!("{}. See the messages above for the conflicts.", tally));
+        return Ok(());
+    }
+    message_box("Success", &format!("{}.", tally));
+    Ok(())
+}
+
+/// Deploys the entries of the repository at `dfl_path` into the home directory,
+/// with everything around it: submodules, hooks, file modes, orphaned links,
+/// plugins, checksums, and reloads. Returns what was done with each file.
+pub fn sync(dfl_path: &Path, options: &SyncOptions) -> io::Result<Tally> {
+    let home_dir = repo::home_dir()?;
+
+    // Linked directories would be empty without their submodules' files. Being
+    // offline should not stop the sync, though.
+    if let Err(e) = git::update_submodules(dfl_path) {
+        ui::warn(&format!("Could not update submodules: {}", e))?;
+    }
+
+    // The pre-sync hook may refuse the sync before anything is touched.
+    hooks::check(dfl_path, "pre-sync", &[])?;
+
+    // Some SMB servers only emulate symlinks, so failures below may come from the share itself.
+    let home_fs = fsutil::fs_info(&home_dir)?;
+    if home_fs.is_network {
+        ui::say(&format!("Note: your home directory is on a network filesystem ({}). Run 'dfl doctor' if links fail.", home_fs.name));
+    }
+
+    // Git does not keep modes such as 0600, so the recorded ones are restored first.
+    perms::apply(dfl_path, &manifest::load(dfl_path)?)?;
+
+    // Link all files in the repository
+    let mut entries = repo::managed_entries(dfl_path)?;
+    if !options.only.is_empty() {
+        entries = repo::select_entries(dfl_path, entries, &options.only)?;
+    }
+    // Checking every target is independent work, so it runs in parallel; linking
+    // stays sequential because it may ask questions and is recorded for a rollback.
+    let statuses = repo::link_statuses(&entries)?;
+    let tally = transactional(dfl_path, options, || {
+        let mut tally = Tally::default();
+        for (entry, status) in entries.iter().zip(statuses) {
+            tally += deploy_with_status(entry, status, options)?;
+        }
+        Ok(tally)
+    })?;
+
+    // After deploying, so directories such as ~/.ssh created on a new machine are covered.
+    perms::tighten_sensitive(dfl_path, &home_dir)?;
+    // A partial sync leaves everything it was not asked about alone.
+    let orphans = if options.only.is_empty() { prune::find_orphans(dfl_path, &home_dir)? } else { Vec::new() };
+    if options.prune {
+        prune::remove_orphans(&orphans)?;
+    } else if !orphans.is_empty() {
+        ui::say(&format!("Found {} orphaned link(s) to files no longer in the repository. Run 'dfl prune' to remove them.", orphans.len()));
+    }
+
+    plugins::sync_plugins(dfl_path, &home_dir)?;
+    let changed = verify::record_checksums(dfl_path)?;
+    hooks::run_reloads(&manifest::load(dfl_path)?, &home_dir, &changed)?;
+    hooks::run(dfl_path, "post-sync")?;
+
+    Ok(tally)
+}
+
+/// What a sync did with each managed file.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct Tally {
+    /// Files linked (or copied) in this run.
+    pub linked: usize,
+    /// Files that were already linked correctly and left untouched.
+    pub up_to_date: usize,
+    /// Files left alone because something else is in the way.
+    pub conflicts: usize,
+}
+
+impl std::ops::AddAssign for Tally {
+    fn add_assign(&mut self, other: Tally) {
+        self.linked += other.linked;
+        self.up_to_date += other.up_to_date;
+        self.conflicts += other.conflicts;
+    }
+}
+
+impl std::fmt::Display for Tally {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} linked, {} already up to date, {} conflicts", ui::thousands(self.linked), ui::thousands(self.up_to_date), ui::thousands(self.conflicts))
+    }
+}
+
+/// Runs `deploy` as one transaction: when it fails, every change recorded in the
+/// journal is undone, unless `--keep-partial` was passed.
+pub fn transactional<T>(dfl_path: &Path, options: &SyncOptions, deploy: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    options.journal.replace(transaction::Journal::begin(dfl_path)?);
+    let result = deploy();
+    let journal = options.journal.take();
+    match &result {
+        Err(_) if options.keep_partial => {
+            journal.commit()?;
+            ui::say("Kept the changes made before the failure (--keep-partial).");
+        }
+        Err(_) => {
+            ui::say("Sync failed; undoing the changes it made...");
+            if journal.rollback()? == 0 {
+                ui::say(&format!("{} Rolled back. Your files are as they were before the sync.", ui::mark(Tone::Ok)));
+            }
+        }
+        Ok(_) => journal.commit()?,
+    }
+    result
+}
+
+/// Deploys one managed entry. A directory entry whose target is already a real
+/// directory, such as `.config` on a machine that has one, is deployed child by
+/// child so the existing directory and its other contents stay in place.
+pub fn deploy_entry(entry: &repo::Entry, options: &SyncOptions) -> io::Result<Tally> {
+    deploy_with_status(entry, repo::link_status(entry)?, options)
+}
+
+/// Deploys one managed entry whose current status is already known. Targets that
+/// are already correct are not touched.
+pub fn deploy_with_status(entry: &repo::Entry, status: repo::LinkStatus, options: &SyncOptions) -> io::Result<Tally> {
+    let target_is_dir = fs::symlink_metadata(&entry.target).is_ok_and(|m| m.is_dir());
+    // An unfolded directory that is still deployed as one link is turned into a real directory.
+    let folded = entry.unfold && entry.mode.is_symlink() && entry.stored.is_dir() && status == repo::LinkStatus::Linked && !target_is_dir;
+    if status == repo::LinkStatus::Linked && !folded {
+        match fs::read_link(&entry.target) {
+            // A correct link in the other style is recreated in the style asked for.
+            Ok(link) if link.is_relative() != options.relative => {}
+            // The children of a merged directory may still need the same.
+            _ if target_is_dir && entry.mode.is_symlink() => {}
+            _ => return Ok(Tally { up_to_date: 1, ..Tally::default() }),
+        }
+    }
+    // A directory holding alternates or templates is deployed file by file, so it needs a real directory.
+    let is_dir = entry.stored.is_dir();
+    let per_file = is_dir && entry.mode.is_symlink() && status == repo::LinkStatus::Missing && (entry.unfold || repo::deploys_per_file(&entry.stored)?);
+    let merge = is_dir && (target_is_dir || folded || per_file);
+    if !merge {
+        let linked = link_entry(entry, options)?;
+        return Ok(if linked { Tally { linked: 1, ..Tally::default() } } else { Tally { conflicts: 1, ..Tally::default() } });
+    }
+    if folded {
+        unfold_link(entry, options)?;
+    } else if !target_is_dir {
+        let mut created = fsutil::create_parent_dirs(&entry.target)?;
+        fs::create_dir(&entry.target)?;
+        created.push(entry.target.clone());
+        for dir in created {
+            ui::say(&format!("📁 Created directory '{}'", dir.display()));
+            options.journal.borrow_mut().created_dir(&dir);
+        }
+    }
+
+    let mut tally = Tally::default();
+    for child in repo::children(entry)? {
+        tally += deploy_entry(&child, options)?;
+    }
+    Ok(tally)
+}
+
+/// Replaces the single symlink of an unfolded directory with an empty real
+/// directory, which its children are then linked into. The link is kept until the
+/// sync completes so a rollback can put it back.
+fn unfold_link(entry: &repo::Entry, options: &SyncOptions) -> io::Result<()> {
+    let staged = fsutil::staging_path(&entry.target);
+    if fs::symlink_metadata(&staged).is_ok() {
+        fsutil::remove_path(&staged)?;
+    }
+    fs::create_dir(&staged)?;
+    let previous = options.journal.borrow_mut().stash_path(&entry.target)?;
+    if let Err(e) = fsutil::replace_atomically(&staged, &entry.target, Some(&previous)) {
+        let _ = fsutil::remove_path(&staged);
+        return Err(errors::error(Code::SymlinkFailed, format!("Error unfolding '{}': {}", entry.target.display(), e)));
+    }
+    options.journal.borrow_mut().deployed(&entry.target, Some(&previous));
+    ui::say(&format!("📂 Unfolded '{}' into a real directory", entry.target.display()));
+    Ok(())
+}
+
+/// Links one managed entry into place, backing up whatever already occupies its
+/// target, or replacing it with `--force`. Returns whether the link was created;
+/// a link that cannot be created is an error, so the sync is rolled back.
+fn link_entry(entry: &repo::Entry, options: &SyncOptions) -> io::Result<bool> {
+    let file_path_in_repo = &entry.stored;
+    let symlink_path = &entry.target;
+
+    // Nested targets such as ~/.config/kitty/kitty.conf need their directories on a fresh machine.
+    match fsutil::create_parent_dirs(symlink_path) {
+        Ok(created) => {
+            for dir in created {
+                ui::say(&format!("📁 Created directory '{}'", dir.display()));
+                options.journal.borrow_mut().created_dir(&dir);
+            }
+        }
+        Err(e) => {
+            ui::warn(&format!("Cannot link '{}': {}.", symlink_path.display(), e))?;
+            return Ok(false);
+        }
+    }
+
+    if entry.mode == repo::LinkMode::Hardlink && !fsutil::same_device(file_path_in_repo, symlink_path)? {
+        ui::warn(&format!("Cannot hard-link '{}': it is on a different filesystem than '{}'. Use mode = \"copy\" for it instead.", symlink_path.display(), file_path_in_repo.display()))?;
+        return Ok(false);
+    }
+
+    // symlink_metadata also sees dangling symlinks, which `exists` reports as missing.
+    // A link left over from before the entry switched to copy or hardlink mode can simply go.
+    let occupied = fs::symlink_metadata(symlink_path).is_ok();
+    let mut back_up = false;
+    if occupied && !repo::points_to(symlink_path, file_path_in_repo) {
+        let dfl_path = options.journal.borrow().dfl_path().to_path_buf();
+        let mut state = state::load_repo(&dfl_path)?;
+        if state.restored.contains(symlink_path) {
+            if !options.force {
+                ui::warn(&format!("Left '{}' alone because it was restored from a backup. Run 'dfl sync --force' to link it again.", symlink_path.display()))?;
+                return Ok(false);
+            }
+            state.restored.remove(symlink_path);
+            state::save_repo(&dfl_path, &state)?;
+            options.journal.borrow_mut().unrestored(symlink_path);
+        }
+        let both_files = fs::symlink_metadata(symlink_path)?.is_file() && fs::symlink_metadata(file_path_in_repo)?.is_file();
+        let is_template = entry.mode == repo::LinkMode::Template;
+        let identical = both_files && if is_template { template::is_rendered(file_path_in_repo, symlink_path)? } else { fsutil::same_contents(symlink_path, file_path_in_repo)? };
+        if options.force || identical {
+            ui::say(&format!("Replacing existing '{}'.", symlink_path.display()));
+        } else {
+            // Keeping the local version of a template would overwrite the template with its output.
+            let resolution = if both_files && options.interactive && !is_template {
+                conflict::resolve(file_path_in_repo, symlink_path, &options.resolution)?
+            } else {
+                Resolution::UseRepo
+            };
+            match resolution {
+                Resolution::Skip => {
+                    ui::say(&format!("{} Skipped '{}'.", ui::mark(Tone::Conflict), symlink_path.display()));
+                    return Ok(false);
+                }
+                Resolution::KeepLocal => {
+                    let previous = options.journal.borrow_mut().stash_path(file_path_in_repo)?;
+                    fs::copy(file_path_in_repo, &previous)?;
+                    options.journal.borrow_mut().replaced_in_repo(file_path_in_repo, &previous);
+                    fs::copy(symlink_path, file_path_in_repo)?;
+                    ui::say(&format!("Copied '{}' into the repository. Commit it with 'dfl commit'.", symlink_path.display()));
+                }
+                Resolution::UseRepo => {
+                    ui::warn(&format!("'{}' already exists. Backing it up and replacing it.", symlink_path.display()))?;
+                    back_up = true;
+                }
+            }
+        }
+    }
+
+    // Build the new entry next to the target and rename it into place, so an
+    // interrupted sync never leaves the target missing.
+    let staged = fsutil::staging_path(symlink_path);
+    if fs::symlink_metadata(&staged).is_ok() {
+        fsutil::remove_path(&staged)?;
+    }
+    let (built, done) = match entry.mode {
+        repo::LinkMode::Copy => (fsutil::copy_recursive(file_path_in_repo, &staged), "Copied"),
+        repo::LinkMode::Hardlink => (fsutil::hardlink_recursive(file_path_in_repo, &staged), "Hard-linked"),
+        repo::LinkMode::Template => (template::render_to(file_path_in_repo, &staged), "Rendered"),
+        repo::LinkMode::Symlink => (fsutil::symlink_to(file_path_in_repo, &staged, options.relative), "Synced"),
+    };
+    // Whatever is replaced is kept, in the backups or until the sync completes, so it can be put back.
+    let backup_path = if back_up { Some(backup::reserve(symlink_path)?) } else { None };
+    let previous = match &backup_path {
+        Some(backup_path) => Some(backup_path.clone()),
+        None if occupied => Some(options.journal.borrow_mut().stash_path(symlink_path)?),
+        None => None,
+    };
+    if let Err(e) = built.and_then(|()| fsutil::replace_atomically(&staged, symlink_path, previous.as_deref())) {
+        let _ = fsutil::remove_path(&staged);
+        // Errors that already carry a code, such as a template that fails to render, say what went wrong.
+        if errors::code_of(&e).is_some() {
+            return Err(e);
+        }
+        return Err(errors::error(Code::SymlinkFailed, format!("Error deploying '{}' to '{}': {}", file_path_in_repo.display(), symlink_path.display(), e)));
+    }
+    options.journal.borrow_mut().deployed(symlink_path, previous.as_deref());
+    if let Some(backup_path) = backup_path {
+        backup::prune(symlink_path)?;
+        ui::say(&format!("Backed up '{}' to '{}'.", symlink_path.display(), backup_path.display()));
+    }
+    ui::say(&format!("{} {} '{}' to '{}'", ui::mark(Tone::Ok), done, file_path_in_repo.display(), symlink_path.display()));
+    Ok(true)
+}
@@ -0,0 +1,88 @@
+use std::io;
+use std::path::PathBuf;
+
+use crate::config;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::hooks;
+use crate::manifest;
+use crate::repo;
+use crate::ui::{self, message_box};
+use crate::verify;
+
+/// Handles the 'tag' command.
+/// Without arguments it lists the tags; with a name it tags the current commit as
+/// a known-good state to roll back to, and `--delete` removes a tag again.
+pub fn handle_tag_command(name: Option<&str>, message: Option<String>, delete: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    let failed = |action: &str, e: io::Error| errors::error(Code::GitFailed, format!("Error {}: {}", action, e));
+    match (name, delete) {
+        (None, false) => {
+            let tags = git::tags(&dfl_path)?;
+            if tags.is_empty() {
+                println!("No tags yet. Mark the current state with 'dfl tag <name>'.");
+            }
+            for (name, subject) in tags {
+                println!("{}\t{}", name, subject);
+            }
+            Ok(())
+        }
+        (None, true) => Err(errors::error(Code::InvalidArgument, "'tag --delete' needs the name of the tag to delete.")),
+        (Some(name), true) => {
+            git::delete_tag(&dfl_path, name).map_err(|e| failed("deleting the tag", e))?;
+            ui::say(&format!("✅ Deleted tag '{}'. A copy already pushed stays on the remote.", name));
+            Ok(())
+        }
+        (Some(name), false) => {
+            if git::head(&dfl_path)?.is_none() {
+                return Err(errors::error(Code::InvalidArgument, "There are no commits to tag yet. Add a dotfile first."));
+            }
+            let message = message.unwrap_or_else(|| format!("Known-good dotfiles: {}", name));
+            git::create_tag(&dfl_path, name, &message).map_err(|e| failed("creating the tag", e))?;
+            ui::say(&format!("✅ Tagged the current state as '{}'. 'dfl push' publishes it with the branch.", name));
+            Ok(())
+        }
+    }
+}
+
+/// Handles the 'rollback' command.
+/// It puts the managed files back as they were at a tag and commits that, so the
+/// rollback is pushed and undone like any other change. Programs reload as after a pull.
+pub fn handle_rollback_command(tag: &str) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+    if git::has_local_changes(&dfl_path)? {
+        return Err(errors::error(Code::InvalidArgument, "You have uncommitted changes. Commit them with 'dfl add' or discard them before rolling back."));
+    }
+    let Some(target) = git::find_commit(&dfl_path, &format!("refs/tags/{}", tag))? else {
+        return Err(errors::error(Code::InvalidArgument, format!("There is no tag named '{}'. Run 'dfl tag' to see them.", tag)));
+    };
+    let Some(head) = git::head(&dfl_path)? else {
+        return Err(errors::error(Code::InvalidArgument, "There are no commits to roll back yet."));
+    };
+
+    let files: Vec<PathBuf> = git::changed_files(&dfl_path, &head, &target)?.iter().filter_map(|line| line.split_once('\t')).map(|(_, path)| PathBuf::from(path)).collect();
+    if files.is_empty() {
+        ui::say(&format!("Your dotfiles already match '{}'; there is nothing to roll back.", tag));
+        return Ok(());
+    }
+    let message = git::commit_message(&format!("roll back to {}", tag), &files, format!("Roll back to {}", tag))?;
+    if let Err(e) = git::restore(&dfl_path, &target, &message) {
+        return Err(errors::error(Code::GitFailed, format!("Error rolling back to '{}': {}", tag, e)));
+    }
+    ui::say(&format!("✅ Rolled back {} file(s) to '{}'.", files.len(), tag));
+
+    let changed = verify::record_checksums(&dfl_path)?;
+    hooks::run_reloads(&manifest::load(&dfl_path)?, &repo::home_dir()?, &changed)?;
+    if config::load()?.auto_push {
+        crate::remote::push_after_commit(&dfl_path)?;
+    }
+    message_box("Rolled Back", "Run 'dfl sync' to link files the tag has, and 'dfl prune' to remove links to files it does not. The rollback is a commit of its own, so 'git revert' undoes it.");
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use duct::cmd;
+
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderErrorReason};
+use serde_json::{Map, Value};
+
+use crate::alternate;
+use crate::config;
+use crate::errors::{self, Code};
+use crate::manifest;
+use crate::repo;
+use crate::ui;
+
+/// Extension of files that are rendered at sync time: `.gitconfig.tmpl` is
+/// deployed as `.gitconfig`.
+pub const EXTENSION: &str = "tmpl";
+
+/// Returns true if a stored path is a template file.
+pub fn is_template(stored: &Path) -> bool {
+    stored.extension().is_some_and(|extension| extension == EXTENSION) && fs::symlink_metadata(stored).is_ok_and(|m| m.is_file())
+}
+
+/// Returns the name of the user running dfl.
+fn username() -> String {
+    std::env::var("USER").or_else(|_| std::env::var("LOGNAME")).unwrap_or_default()
+}
+
+/// Collects the variables templates can use: the `[values]` table of `dfl.toml`,
+/// overridden by this machine's `values.toml`, then the built-in `hostname`, `os`,
+/// `username`, `home`, `tags`, and `tag.<name>`, which take precedence over both.
+/// `dfl.toml` is read from the repository the template at `stored` belongs to.
+fn variables(stored: &Path) -> io::Result<Value> {
+    let dfl_path = match stored.ancestors().find(|dir| dir.join(".git").is_dir()) {
+        Some(dfl_path) => dfl_path.to_path_buf(),
+        None => repo::dfl_path()?,
+    };
+    let manifest = manifest::load(&dfl_path)?;
+    let mut values = manifest.values;
+    values.extend(config::load_values()?);
+    let mut variables: Map<String, Value> = values.iter().map(|(name, value)| Ok((name.clone(), serde_json::to_value(value)?))).collect::<Result<_, serde_json::Error>>().map_err(io::Error::other)?;
+
+    let tags = config::load()?.tags;
+    variables.insert("hostname".to_string(), Value::from(alternate::hostname()));
+    variables.insert("os".to_string(), Value::from(std::env::consts::OS));
+    variables.insert("username".to_string(), Value::from(username()));
+    variables.insert("home".to_string(), Value::from(repo::home_dir()?.to_string_lossy().into_owned()));
+    variables.insert("tag".to_string(), Value::Object(tags.iter().map(|tag| (tag.clone(), Value::Bool(true))).collect()));
+    variables.insert("tags".to_string(), Value::from(tags));
+    Ok(Value::Object(variables))
+}
+
+/// Writes the value of an environment variable, as in `{{env "XDG_DATA_HOME"}}`.
+/// A second argument is used when the variable is unset or empty:
+/// `{{env "EDITOR" "vim"}}`.
+fn env_helper(helper: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    let name = helper.param(0).and_then(|param| param.value().as_str()).ok_or(RenderErrorReason::ParamNotFoundForIndex("env", 0))?;
+    let fallback = helper.param(1).and_then(|param| param.value().as_str()).unwrap_or_default();
+    let value = std::env::var(name).ok().filter(|value| !value.is_empty()).unwrap_or_else(|| fallback.to_string());
+    out.write(&value)?;
+    Ok(())
+}
+
+/// Secrets already resolved during this run, so each command runs only once.
+static SECRETS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// Resolves a secret from the environment variable of the same name, then from its
+/// command in the `[secrets]` table of the config, then from `secret_command`.
+fn resolve_secret(name: &str) -> io::Result<String> {
+    // Held while a command runs, so parallel renders never prompt for a passphrase twice.
+    let mut secrets = SECRETS.lock().map_err(|_| io::Error::other("secret cache poisoned"))?;
+    let secrets = secrets.get_or_insert_with(HashMap::new);
+    if let Some(value) = secrets.get(name) {
+        return Ok(value.clone());
+    }
+
+    let value = match std::env::var(name).ok().filter(|value| !value.is_empty()) {
+        Some(value) => value,
+        None => {
+            let config = config::load()?;
+            let command = match config.secrets.get(name) {
+                Some(command) => command.clone(),
+                None if !config.secret_command.is_empty() => config.secret_command.replace("{name}", name),
+                None => {
+                    return Err(errors::error(
+                        Code::TemplateFailed,
+                        format!("The secret '{}' is not set. Export it, or set a command that prints it in the [secrets] table or secret_command of {}.", name, config::config_path()?.display()),
+                    ));
+                }
+            };
+            let output = cmd!("sh", "-c", &command).stdout_capture().unchecked().run()?;
+            if !output.status.success() {
+                return Err(errors::error(Code::TemplateFailed, format!("The command for the secret '{}' failed: {}", name, command)));
+            }
+            String::from_utf8_lossy(&output.stdout).trim_end_matches(['\n', '\r']).to_string()
+        }
+    };
+    secrets.insert(name.to_string(), value.clone());
+    Ok(value)
+}
+
+/// A rendered template.
+struct Rendered {
+    text: String,
+    /// Whether the output contains a secret, in which case only the owner may read it.
+    has_secrets: bool,
+}
+
+/// Renders a template with Handlebars. Output is not HTML-escaped, and using a
+/// variable that is not defined is an error rather than an empty string. With
+/// `ask`, an undefined variable is asked for in the terminal and the answer saved
+/// to `values.toml`; without it, `None` is returned instead.
+fn try_render(stored: &Path, ask: bool) -> io::Result<Option<Rendered>> {
+    let source = fs::read_to_string(stored)?;
+    let mut registry = Handlebars::new();
+    registry.set_strict_mode(true);
+    registry.register_escape_fn(handlebars::no_escape);
+    registry.register_helper("env", Box::new(env_helper));
+    let has_secrets = Arc::new(AtomicBool::new(false));
+    let used = Arc::clone(&has_secrets);
+    registry.register_helper(
+        "secret",
+        Box::new(move |helper: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output| -> HelperResult {
+            let name = helper.param(0).and_then(|param| param.value().as_str()).ok_or(RenderErrorReason::ParamNotFoundForIndex("secret", 0))?;
+            let value = resolve_secret(name).map_err(|e| RenderErrorReason::Other(e.to_string()))?;
+            used.store(true, Ordering::Relaxed);
+            out.write(&value)?;
+            Ok(())
+        }),
+    );
+    let name = stored.display().to_string();
+    let failed = |e: &dyn std::fmt::Display| errors::error(Code::TemplateFailed, e.to_string());
+    registry.register_template_string(&name, source).map_err(|e| failed(&e))?;
+
+    let mut asked = Vec::new();
+    loop {
+        let e = match registry.render(&name, &variables(stored)?) {
+            Ok(text) => return Ok(Some(Rendered { text, has_secrets: has_secrets.load(Ordering::Relaxed) })),
+            Err(e) => e,
+        };
+        let RenderErrorReason::MissingVariable(Some(variable)) = e.reason() else {
+            return Err(failed(&e));
+        };
+        if !ask {
+            return Ok(None);
+        }
+        // A name that is still missing after answering cannot be set from values.toml.
+        if !ui::interactive() || asked.contains(variable) {
+            return Err(errors::error(
+                Code::TemplateFailed,
+                format!("'{}' uses '{}', which is not defined. Add it to {} or the [values] table of dfl.toml.", stored.display(), variable, config::values_path()?.display()),
+            ));
+        }
+        ask_value(variable, stored)?;
+        asked.push(variable.clone());
+    }
+}
+
+/// Asks for the value of an undefined variable and saves it to `values.toml`, so
+/// it is only asked once per machine. A dotted name such as `font.size` is saved
+/// as a nested table.
+fn ask_value(variable: &str, stored: &Path) -> io::Result<()> {
+    let answer: String = dialoguer::Input::new()
+        .with_prompt(format!("'{}' uses '{}', which is not set on this machine. Value", stored.display(), variable))
+        .interact_text()
+        .map_err(io::Error::other)?;
+
+    let mut values = config::load_values()?;
+    set_nested(&mut values, variable, toml::Value::String(answer));
+    config::save_values(&values)?;
+    ui::say(&format!("Saved '{}' to {}.", variable, config::values_path()?.display()));
+    Ok(())
+}
+
+/// Sets `value` at a dotted `name` in `table`, creating nested tables as needed.
+fn set_nested(table: &mut toml::Table, name: &str, value: toml::Value) {
+    let Some((first, rest)) = name.split_once('.') else {
+        table.insert(name.to_string(), value);
+        return;
+    };
+    match table.entry(first).or_insert_with(|| toml::Value::Table(toml::Table::new())) {
+        toml::Value::Table(nested) => set_nested(nested, rest, value),
+        other => {
+            let mut nested = toml::Table::new();
+            set_nested(&mut nested, rest, value);
+            *other = toml::Value::Table(nested);
+        }
+    }
+}
+
+/// Renders a template to `destination`, which must not exist yet, asking for any
+/// variable that is not defined yet. The output gets the template's file mode,
+/// restricted to the owner when it contains a secret.
+pub fn render_to(stored: &Path, destination: &Path) -> io::Result<()> {
+    let Some(rendered) = try_render(stored, true)? else {
+        return Ok(());
+    };
+    let mut mode = fs::metadata(stored)?.permissions().mode() & 0o7777;
+    if rendered.has_secrets {
+        mode &= 0o700;
+    }
+    // Created with the mode from the start, so a secret is never readable by others, not even briefly.
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(mode).open(destination)?;
+    file.write_all(rendered.text.as_bytes())?;
+    fs::set_permissions(destination, fs::Permissions::from_mode(mode))
+}
+
+/// Returns true if `target` holds exactly what the template renders to now. It
+/// never asks for values, so a template with undefined variables counts as differing.
+pub fn is_rendered(stored: &Path, target: &Path) -> io::Result<bool> {
+    Ok(try_render(stored, false)?.is_some_and(|rendered| fs::read(target).is_ok_and(|contents| contents == rendered.text.into_bytes())))
+}
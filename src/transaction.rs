@@ -0,0 +1,176 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::backup;
+use crate::errors::{self, Code};
+use crate::fsutil;
+use crate::state;
+
+/// One change made during a sync, recorded so it can be undone.
+#[derive(Debug)]
+enum Action {
+    /// A missing parent directory was created.
+    CreatedDir(PathBuf),
+    /// An entry was deployed at `target`; whatever was there before is kept at `previous`.
+    Deployed { target: PathBuf, previous: Option<PathBuf> },
+    /// A file in the repository was overwritten; its old contents are kept at `previous`.
+    ReplacedInRepo { stored: PathBuf, previous: PathBuf },
+    /// A target restored from a backup was handed back to `sync`.
+    Unrestored(PathBuf),
+}
+
+impl Action {
+    /// Returns the path the change was made to.
+    fn path(&self) -> &Path {
+        match self {
+            Action::CreatedDir(path) | Action::Unrestored(path) => path,
+            Action::Deployed { target, .. } => target,
+            Action::ReplacedInRepo { stored, .. } => stored,
+        }
+    }
+}
+
+/// Every change a sync run made so far. A run that fails part way is rolled back
+/// from here, so the machine is never left half-synced.
+#[derive(Debug, Default)]
+pub struct Journal {
+    /// The repository being synced.
+    dfl_path: PathBuf,
+    /// The directory of this run in the stash, created once a file is kept there.
+    run_dir: PathBuf,
+    actions: Vec<Action>,
+    stashed: usize,
+}
+
+/// File in a run's stash directory that lists where each kept file came from,
+/// one `<name>  <original path>` line per file.
+const MANIFEST_FILE: &str = "manifest";
+
+/// Returns the directory that holds replaced files of syncs of the repository at
+/// `dfl_path`, one directory per run, until the run completes.
+fn stash_dir(dfl_path: &Path) -> PathBuf {
+    state::repo_dir(dfl_path).join("sync-stash")
+}
+
+/// Returns the stash directories of earlier runs that still hold files, removing
+/// those whose files were all put back. Anything else in the stash, such as what
+/// an older dfl kept there without a manifest, counts as holding files.
+fn leftover_runs(dfl_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let Ok(children) = fs::read_dir(stash_dir(dfl_path)) else {
+        return Ok(Vec::new());
+    };
+    let mut leftovers = Vec::new();
+    for child in children {
+        let run_dir = child?.path();
+        let manifest = fs::read_to_string(run_dir.join(MANIFEST_FILE)).unwrap_or_default();
+        let holds_files = !run_dir.is_dir() || manifest.lines().filter_map(|line| line.split_once("  ")).any(|(name, _)| fs::symlink_metadata(run_dir.join(name)).is_ok());
+        if holds_files {
+            leftovers.push(run_dir);
+        } else {
+            fs::remove_dir_all(&run_dir)?;
+        }
+    }
+    leftovers.sort();
+    Ok(leftovers)
+}
+
+impl Journal {
+    /// Starts a new journal for a sync of the repository at `dfl_path`. It refuses
+    /// while a run that failed or was interrupted left files it replaced in the
+    /// stash, since they may be the only copy of them.
+    pub fn begin(dfl_path: &Path) -> io::Result<Journal> {
+        if let Some(run_dir) = leftover_runs(dfl_path)?.first() {
+            return Err(errors::error(
+                Code::StashLeftOver,
+                format!(
+                    "A sync that failed or was interrupted kept files it replaced in {}, and the {} file there lists where each came from. Put back what you need, then delete the directory and sync again.",
+                    run_dir.display(),
+                    MANIFEST_FILE
+                ),
+            ));
+        }
+        let run_dir = stash_dir(dfl_path).join(format!("{}-{}", backup::timestamp(), std::process::id()));
+        Ok(Journal { dfl_path: dfl_path.to_path_buf(), run_dir, ..Journal::default() })
+    }
+
+    /// Returns the repository being synced.
+    pub fn dfl_path(&self) -> &Path {
+        &self.dfl_path
+    }
+
+    /// Returns an unused path to keep the file replaced at `original` at until the
+    /// run completes, and records where it came from in the run's manifest.
+    pub fn stash_path(&mut self, original: &Path) -> io::Result<PathBuf> {
+        if self.run_dir.as_os_str().is_empty() {
+            return Err(io::Error::other("The sync journal was not started."));
+        }
+        fs::create_dir_all(&self.run_dir)?;
+        self.stashed += 1;
+        let name = self.stashed.to_string();
+        let mut manifest = OpenOptions::new().create(true).append(true).open(self.run_dir.join(MANIFEST_FILE))?;
+        writeln!(manifest, "{}  {}", name, original.display())?;
+        Ok(self.run_dir.join(name))
+    }
+
+    pub fn created_dir(&mut self, dir: &Path) {
+        self.actions.push(Action::CreatedDir(dir.to_path_buf()));
+    }
+
+    pub fn deployed(&mut self, target: &Path, previous: Option<&Path>) {
+        self.actions.push(Action::Deployed { target: target.to_path_buf(), previous: previous.map(Path::to_path_buf) });
+    }
+
+    pub fn replaced_in_repo(&mut self, stored: &Path, previous: &Path) {
+        self.actions.push(Action::ReplacedInRepo { stored: stored.to_path_buf(), previous: previous.to_path_buf() });
+    }
+
+    pub fn unrestored(&mut self, target: &Path) {
+        self.actions.push(Action::Unrestored(target.to_path_buf()));
+    }
+
+    /// Keeps every change and drops the replaced files that were only kept for a rollback.
+    pub fn commit(self) -> io::Result<()> {
+        if self.run_dir.exists() {
+            fs::remove_dir_all(&self.run_dir)?;
+        }
+        // Gone once no other run left anything in it.
+        let _ = fs::remove_dir(stash_dir(&self.dfl_path));
+        Ok(())
+    }
+
+    /// Undoes every recorded change, newest first. Steps that fail are reported and
+    /// skipped so the rest can still be undone; returns how many of them failed.
+    pub fn rollback(mut self) -> io::Result<usize> {
+        let mut failed = 0;
+        while let Some(action) = self.actions.pop() {
+            tracing::debug!("undoing {:?}", action);
+            let undone = match &action {
+                // Directories that gained other contents since are left in place.
+                Action::CreatedDir(dir) => {
+                    let _ = fs::remove_dir(dir);
+                    Ok(())
+                }
+                Action::Deployed { target, previous } => fsutil::remove_path(target).and_then(|()| match previous {
+                    Some(previous) => fsutil::move_path(previous, target),
+                    None => Ok(()),
+                }),
+                Action::ReplacedInRepo { stored, previous } => fs::copy(previous, stored).map(|_| ()),
+                Action::Unrestored(target) => state::load_repo(&self.dfl_path).and_then(|mut state| {
+                    state.restored.insert(target.clone());
+                    state::save_repo(&self.dfl_path, &state)
+                }),
+            };
+            if let Err(e) = undone {
+                tracing::error!("Could not undo the change to '{}': {}", action.path().display(), e);
+                failed += 1;
+            }
+        }
+        if failed == 0 {
+            self.commit()?;
+        } else {
+            tracing::warn!("Replaced files that could not be put back are kept in {}; the {} file there lists where each came from.", self.run_dir.display(), MANIFEST_FILE);
+        }
+        Ok(failed)
+    }
+}
@@ -0,0 +1,239 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// Process exit code used when `--strict` turns a warning into a failure.
+pub const EXIT_STRICT: i32 = 3;
+
+/// Target of the progress and result lines commands report, which the logging layer
+/// writes to standard output as they are and leaves out with `--quiet`.
+pub const OUTPUT: &str = "dfl::output";
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(false);
+/// The ANSI color codes of the theme, in the order of `Tone`.
+static PALETTE: [AtomicU8; 3] = [AtomicU8::new(Color::Green as u8), AtomicU8::new(Color::Yellow as u8), AtomicU8::new(Color::Red as u8)];
+
+/// The error produced by `warn` in strict mode, so callers can tell it apart from other failures.
+#[derive(Debug)]
+pub struct StrictViolation(String);
+
+impl fmt::Display for StrictViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (warning treated as an error in strict mode)", self.0)
+    }
+}
+
+impl Error for StrictViolation {}
+
+/// Enables or disables strict mode for the rest of the process.
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Makes every question take the answer dfl gives without a terminal, for the rest of the process.
+pub fn set_assume_yes(assume_yes: bool) {
+    ASSUME_YES.store(assume_yes, Ordering::Relaxed);
+}
+
+/// Returns true if dfl may stop to ask something: standard input is a terminal and
+/// `--yes` was not given. Every prompt checks this and has an answer to fall back on.
+pub fn interactive() -> bool {
+    !ASSUME_YES.load(Ordering::Relaxed) && io::stdin().is_terminal()
+}
+
+/// When to color output, as `--color` or the `color` setting say.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Only on a terminal, and not when `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// A terminal color for the theme, as its ANSI code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    Black = 30,
+    Red = 31,
+    Green = 32,
+    Yellow = 33,
+    Blue = 34,
+    Magenta = 35,
+    Cyan = 36,
+    White = 37,
+}
+
+/// The colors of entries that are in place, in conflict, and broken, set under
+/// `[theme]` in `~/.config/dfl/config.toml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Theme {
+    pub ok: Color,
+    pub conflict: Color,
+    pub broken: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme { ok: Color::Green, conflict: Color::Yellow, broken: Color::Red }
+    }
+}
+
+/// How an entry or a step went, which picks its color and mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    /// In place or done.
+    Ok = 0,
+    /// Needs a look: in conflict, diverged, skipped, or not committed.
+    Conflict = 1,
+    /// Not working: not linked or gone.
+    Broken = 2,
+}
+
+/// Decides for the rest of the process whether output is colored, and in which colors.
+pub fn set_color(choice: ColorChoice, theme: &Theme) {
+    let color = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        // https://no-color.org: a set, non-empty NO_COLOR turns color off unless asked for explicitly.
+        ColorChoice::Auto => env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && env::var_os("TERM").is_none_or(|term| term != "dumb") && io::stdout().is_terminal(),
+    };
+    COLOR.store(color, Ordering::Relaxed);
+    for (slot, color) in PALETTE.iter().zip([theme.ok, theme.conflict, theme.broken]) {
+        slot.store(color as u8, Ordering::Relaxed);
+    }
+}
+
+/// Returns true if the locale says the terminal shows Unicode; without a UTF-8
+/// locale, marks fall back to ASCII.
+fn unicode() -> bool {
+    static UNICODE: OnceLock<bool> = OnceLock::new();
+    *UNICODE.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter().find_map(|var| env::var(var).ok().filter(|value| !value.is_empty())).unwrap_or_default().to_ascii_lowercase();
+        locale.contains("utf-8") || locale.contains("utf8")
+    })
+}
+
+/// Returns `text` in the theme's color for `tone` when output is colored.
+pub fn paint(tone: Tone, text: &str) -> String {
+    if !COLOR.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    format!("\x1b[{}m{}\x1b[0m", PALETTE[tone as usize].load(Ordering::Relaxed), text)
+}
+
+/// Returns the colored mark that starts a line about an entry or a step: a check
+/// mark, an exclamation mark, or a cross, or `+`, `!`, and `x` without Unicode.
+/// Every mark is one column wide, so the lines stay aligned.
+pub fn mark(tone: Tone) -> String {
+    let mark = match (tone, unicode()) {
+        (Tone::Ok, true) => "✓",
+        (Tone::Ok, false) => "+",
+        (Tone::Conflict, _) => "!",
+        (Tone::Broken, true) => "✗",
+        (Tone::Broken, false) => "x",
+    };
+    paint(tone, mark)
+}
+
+/// Returns true if `err` was produced by a warning in strict mode.
+pub fn is_strict_violation(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<StrictViolation>())
+}
+
+/// Formats a count with thousands separators, e.g. `1,420`.
+pub fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Formats a size in bytes, e.g. `1.2 MB`.
+pub fn bytes(n: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+    let mut size = n as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// A friendly and conversational message box function.
+pub fn message_box(title: &str, message: &str) {
+    say(&format!("\n--- {} ---\n{}\n", title, message));
+}
+
+/// Reports what a command is doing or has done, such as a file it linked. Unlike
+/// the data a command prints, such as a listing or JSON, this goes through the
+/// logging layer, so `--quiet` leaves it out and a program using the library
+/// decides where it goes.
+pub fn say(message: &str) {
+    tracing::info!(target: OUTPUT, "{}", message);
+}
+
+/// Reports a warning. In strict mode the warning becomes an error, so callers
+/// should warn before doing the degraded thing and propagate the result with `?`.
+pub fn warn(message: &str) -> io::Result<()> {
+    tracing::warn!("{}", message);
+    if STRICT.load(Ordering::Relaxed) {
+        return Err(io::Error::other(StrictViolation(message.to_string())));
+    }
+    Ok(())
+}
+
+/// Prints `value` as one line of JSON, for the `--json` output of commands that
+/// scripts read. Field names and meanings stay the same across releases.
+pub fn print_json(value: &impl Serialize) -> io::Result<()> {
+    println!("{}", serde_json::to_string(value).map_err(io::Error::other)?);
+    Ok(())
+}
+
+/// Sends what is printed to standard output to standard error until dropped, so
+/// the progress messages of a command run with `--json` stay out of its JSON.
+pub struct StdoutToStderr {
+    saved: libc::c_int,
+}
+
+impl StdoutToStderr {
+    pub fn new() -> io::Result<StdoutToStderr> {
+        io::stdout().flush()?;
+        // SAFETY: dup and dup2 only duplicate file descriptors this process owns.
+        let saved = unsafe { libc::dup(libc::STDOUT_FILENO) };
+        if saved < 0 || unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(StdoutToStderr { saved })
+    }
+}
+
+impl Drop for StdoutToStderr {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        // SAFETY: `saved` is the descriptor duplicated in `new` and is closed only here.
+        unsafe {
+            libc::dup2(self.saved, libc::STDOUT_FILENO);
+            libc::close(self.saved);
+        }
+    }
+}
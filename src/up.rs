@@ -0,0 +1,104 @@
+use std::io;
+
+use crate::config;
+use crate::conflict;
+use crate::errors::{self, Code};
+use crate::git;
+use crate::hooks;
+use crate::machine;
+use crate::manifest;
+use crate::mirror;
+use crate::offline;
+use crate::perms;
+use crate::plugins;
+use crate::sparse;
+use crate::repo::{self, LinkStatus};
+use crate::ui::{self, message_box};
+use crate::verify;
+
+/// Handles the 'up' command.
+/// It fetches and pulls the latest changes, links every entry that is not linked
+/// yet, and prints a short summary. Nothing is linked if the pull fails, so the
+/// home directory never reflects a half-applied update.
+pub fn handle_up_command() -> io::Result<()> {
+    let home_dir = repo::home_dir()?;
+    let dfl_path = repo::dfl_path()?;
+
+    if !git::git_dir(&dfl_path).exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    if git::remotes(&dfl_path)?.is_empty() {
+        return Err(errors::error(Code::NoRemote, "No remote configured. Please run 'dfl remote add <url>' first."));
+    }
+
+    mirror::refresh_upstream(&dfl_path)?;
+    ui::say("Fetching...");
+    let before = git::head(&dfl_path)?;
+    let online = match git::fetch(&dfl_path) {
+        Ok(()) => true,
+        // Offline, the files already in the repository are still linked; only the pull is skipped.
+        Err(_) if git::remotes(&dfl_path)?.iter().any(|remote| offline::is_unreachable(&dfl_path, remote)) => {
+            ui::warn(&format!("You are offline: the remote cannot be reached, so nothing was pulled. Linking what is already in {}.", repo::display(&dfl_path)))?;
+            false
+        }
+        Err(e) => return Err(errors::error(Code::PullFailed, format!("Error fetching from remote: {}", e))),
+    };
+
+    let behind = online && (git::incoming(&dfl_path)? > 0 || before.is_none());
+    if behind && let Err(e) = git::pull_keeping_changes(&dfl_path, None, config::load()?.pull_rebase, conflict::merge_resolver()) {
+        return Err(errors::error(errors::code_or(&e, Code::PullFailed), format!("Error pulling from remote: {}. Nothing was linked.", e)));
+    }
+    if online {
+        machine::pull_shared(&dfl_path)?;
+        offline::flush(&dfl_path)?;
+    }
+    sparse::apply(&dfl_path)?;
+    let after = git::head(&dfl_path)?;
+
+    let mut changes = Vec::new();
+    if let Some(after) = after.as_deref().filter(|after| before.as_deref() != Some(after)) {
+        let commits = git::count_commits(&dfl_path, before.as_deref(), after)?;
+        ui::say(&format!("✅ Pulled {} commit(s).", commits));
+        if let Some(before) = &before {
+            changes = git::changed_files(&dfl_path, before, after)?;
+        }
+    }
+
+    hooks::check(&dfl_path, "pre-sync", &[])?;
+    perms::apply(&dfl_path, &manifest::load(&dfl_path)?)?;
+    let options = crate::sync::SyncOptions::from_config()?;
+    let entries = repo::managed_entries(&dfl_path)?;
+    let statuses = repo::link_statuses(&entries)?;
+    let tally = crate::sync::transactional(&dfl_path, &options, || {
+        let mut tally = crate::sync::Tally::default();
+        for (entry, status) in entries.iter().zip(statuses) {
+            if status != LinkStatus::Linked {
+                tally += crate::sync::deploy_with_status(entry, status, &options)?;
+            }
+        }
+        Ok(tally)
+    })?;
+
+    perms::tighten_sensitive(&dfl_path, &home_dir)?;
+    plugins::sync_plugins(&dfl_path, &home_dir)?;
+    let changed = verify::record_checksums(&dfl_path)?;
+    hooks::run_reloads(&manifest::load(&dfl_path)?, &home_dir, &changed)?;
+    hooks::run(&dfl_path, "post-sync")?;
+
+    let mut summary = Vec::new();
+    if !online {
+        summary.push("Offline: nothing was pulled.".to_string());
+    } else if before == after {
+        summary.push("Already up to date.".to_string());
+    } else if !changes.is_empty() {
+        summary.push(format!("{} file(s) changed:", changes.len()));
+        summary.extend(changes.iter().map(|change| format!("  {}", change.replace('\t', " "))));
+    }
+    summary.push(format!("{} new link(s) created.", tally.linked));
+    if tally.conflicts > 0 {
+        summary.push(format!("{} file(s) could not be linked; see the warnings above.", tally.conflicts));
+    }
+    message_box("Up to date", &summary.join("\n"));
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+use crate::errors::{self, Code};
+use crate::repo::{self, LinkMode, LinkStatus};
+use crate::state;
+use crate::ui;
+
+/// Maps repository-relative file paths to their SHA-256 checksums.
+type Checksums = BTreeMap<String, String>;
+
+fn checksums_path(dfl_path: &Path) -> io::Result<PathBuf> {
+    state::repo_file(dfl_path, "checksums")
+}
+
+/// Returns the hex-encoded SHA-256 digest of a file's contents.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let digest = Sha256::digest(fs::read(path)?);
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Computes checksums for every file below the managed entries of the repository.
+/// Files are hashed in parallel, since large repositories hold thousands of them.
+fn compute_checksums(dfl_path: &Path) -> io::Result<Checksums> {
+    let mut files = Vec::new();
+    for entry in repo::managed_entries(dfl_path)? {
+        files.extend(repo::files_under(&entry.stored)?);
+    }
+    files
+        .par_iter()
+        .map(|file| {
+            let relative = file.strip_prefix(dfl_path).map_err(io::Error::other)?;
+            Ok((relative.to_string_lossy().into_owned(), sha256_file(file)?))
+        })
+        .collect()
+}
+
+/// Reads the checksum manifest, stored in the same `<sha256>  <path>` format as `sha256sum`.
+fn load_checksums(dfl_path: &Path) -> io::Result<Option<Checksums>> {
+    let path = checksums_path(dfl_path)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut checksums = Checksums::new();
+    for line in fs::read_to_string(&path)?.lines() {
+        if let Some((hash, file)) = line.split_once("  ") {
+            checksums.insert(file.to_string(), hash.to_string());
+        }
+    }
+    Ok(Some(checksums))
+}
+
+/// Records the current checksums of all managed files as the trusted baseline.
+/// Called after `add`, `sync`, and `pull`, and by `dfl verify --update`. Returns the
+/// repository-relative paths of files that are new or changed since the previous
+/// baseline; without one, every file counts as changed.
+pub fn record_checksums(dfl_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let previous = load_checksums(dfl_path)?.unwrap_or_default();
+    let checksums = compute_checksums(dfl_path)?;
+    let dir = state::repo_dir(dfl_path);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("checksums");
+
+    let contents: String = checksums.iter().map(|(file, hash)| format!("{}  {}\n", hash, file)).collect();
+    fs::write(&path, contents)?;
+    Ok(checksums.into_iter().filter(|(file, hash)| previous.get(file) != Some(hash)).map(|(file, _)| PathBuf::from(file)).collect())
+}
+
+/// Handles the 'verify' command.
+/// It checks that every managed symlink points into the repository and that
+/// every managed file still matches its recorded checksum.
+pub fn handle_verify_command(update: bool) -> io::Result<()> {
+    let dfl_path = repo::dfl_path()?;
+
+    if !dfl_path.exists() {
+        return Err(errors::error(Code::NotInitialized, "dfl repository not found. Please run 'dfl init' or clone your repository first."));
+    }
+
+    if update {
+        record_checksums(&dfl_path)?;
+        ui::say("✅ Recorded checksums for all managed files.");
+        return Ok(());
+    }
+
+    let mut problems = Vec::new();
+
+    let entries = repo::managed_entries(&dfl_path)?;
+    for (entry, status) in entries.iter().zip(repo::link_statuses(&entries)?) {
+        let link_path = &entry.target;
+        match status {
+            LinkStatus::Linked => {}
+            LinkStatus::Missing => problems.push(format!("'{}' is missing; run 'dfl sync' to link it.", link_path.display())),
+            LinkStatus::Conflict => problems.push(format!("'{}' has been replaced by a regular file or directory.", link_path.display())),
+            LinkStatus::Diverged if entry.mode == LinkMode::Hardlink => {
+                problems.push(format!("'{}' is no longer hard-linked to '{}'; run 'dfl sync' to relink it.", link_path.display(), entry.stored.display()))
+            }
+            LinkStatus::Diverged if entry.mode == LinkMode::Template => {
+                problems.push(format!("'{}' differs from what '{}' renders to; run 'dfl sync' to render it again.", link_path.display(), entry.stored.display()))
+            }
+            LinkStatus::Diverged => problems.push(format!("'{}' is a copy that differs from '{}'; run 'dfl sync' to update it.", link_path.display(), entry.stored.display())),
+            LinkStatus::WrongTarget(target) => {
+                problems.push(format!("'{}' points to '{}' instead of '{}'.", link_path.display(), target.display(), entry.stored.display()))
+            }
+        }
+    }
+
+    let current = compute_checksums(&dfl_path)?;
+    match load_checksums(&dfl_path)? {
+        None => {
+            ui::warn("No checksums recorded yet. Run 'dfl verify --update' to record the current state.")?;
+        }
+        Some(recorded) => {
+            for (file, hash) in &current {
+                match recorded.get(file) {
+                    None => problems.push(format!("'{}' is not in the checksum manifest.", file)),
+                    Some(expected) if expected != hash => problems.push(format!("'{}' has changed since its checksum was recorded.", file)),
+                    Some(_) => {}
+                }
+            }
+            for file in recorded.keys().filter(|f| !current.contains_key(*f)) {
+                problems.push(format!("'{}' is missing from the repository.", file));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        ui::say(&format!("✅ Verified {} links and {} files.", entries.len(), current.len()));
+        return Ok(());
+    }
+
+    for problem in &problems {
+        tracing::warn!("{}", problem);
+    }
+    Err(errors::error(Code::VerificationFailed, format!("Verification failed: {} problem(s) found. If content changes are intentional, run 'dfl verify --update' to accept them.", problems.len())))
+}
@@ -0,0 +1,241 @@
+//! Drives a repository through `DflRepo` in a home directory of its own.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use dfl::DflRepo;
+use dfl::add::AddOptions;
+use dfl::backup;
+use dfl::errors::{self, Code, DflError};
+use dfl::fsutil;
+use dfl::git;
+use dfl::migrate;
+use dfl::repo::LinkStatus;
+use dfl::sync::SyncOptions;
+use dfl::ui;
+
+/// dfl finds the home directory and the repository through the environment, which
+/// every test in this process shares, so they take turns.
+static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+/// A temporary home directory holding a dfl repository, removed when dropped.
+struct Sandbox {
+    home: PathBuf,
+    repo: DflRepo,
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl Sandbox {
+    fn new(name: &str) -> io::Result<Sandbox> {
+        let turn = ENVIRONMENT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let home = env::temp_dir().join(format!("dfl-test-{}-{}", name, std::process::id()));
+        if home.exists() {
+            fs::remove_dir_all(&home)?;
+        }
+        fs::create_dir_all(&home)?;
+        let home = fs::canonicalize(home)?;
+        let repo_path = home.join(".local/share/dfl/repo");
+        // SAFETY: the lock above keeps the other tests from touching the environment meanwhile.
+        unsafe {
+            env::set_var("HOME", &home);
+            env::set_var("DFL_DIR", &repo_path);
+            env::set_var("GIT_CONFIG_NOSYSTEM", "1");
+            for name in ["XDG_CONFIG_HOME", "XDG_DATA_HOME", "XDG_STATE_HOME", "XDG_CACHE_HOME", "DFL_PROFILE", "DFL_LOCK"] {
+                env::remove_var(name);
+            }
+        }
+        let repo = DflRepo::init(&repo_path)?;
+        // libgit2 reads the global configuration of the first home directory it saw, so the identity goes in the repository.
+        git::set_config(repo.path(), "user.name", "dfl")?;
+        git::set_config(repo.path(), "user.email", "dfl@example.com")?;
+        repo.commit("chore: Start the repository")?;
+        Ok(Sandbox { home, repo, _turn: turn })
+    }
+
+    /// Writes a dotfile into the home directory and adds it to the repository.
+    fn add(&self, name: &str, contents: &str) -> io::Result<PathBuf> {
+        let path = self.home.join(name);
+        fs::write(&path, contents)?;
+        self.repo.add(&path, &AddOptions { no_commit: true, ..AddOptions::default() })?;
+        Ok(path)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.home);
+    }
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+#[test]
+fn status_lists_uncommitted_changes() -> io::Result<()> {
+    let sandbox = Sandbox::new("status")?;
+    assert!(sandbox.repo.status()?.changes.is_empty());
+
+    sandbox.add(".bashrc", "export EDITOR=vi\n")?;
+    let status = sandbox.repo.status()?;
+    assert!(status.changes.iter().any(|path| path.ends_with(".bashrc")), "{:?}", status.changes);
+    assert!(status.upstream.is_none());
+
+    assert!(sandbox.repo.commit("feat: Add .bashrc")?);
+    assert!(sandbox.repo.status()?.changes.is_empty());
+    assert!(!sandbox.repo.commit("nothing")?);
+    Ok(())
+}
+
+#[test]
+fn sync_links_missing_entries_and_leaves_linked_ones() -> io::Result<()> {
+    let sandbox = Sandbox::new("sync")?;
+    let bashrc = sandbox.add(".bashrc", "export EDITOR=vi\n")?;
+    sandbox.repo.commit("feat: Add .bashrc")?;
+    assert!(is_symlink(&bashrc));
+
+    fs::remove_file(&bashrc)?;
+    let tally = sandbox.repo.sync(&SyncOptions::default())?;
+    assert_eq!((tally.linked, tally.up_to_date, tally.conflicts), (1, 0, 0));
+    assert!(is_symlink(&bashrc));
+    assert_eq!(fs::read_to_string(&bashrc)?, "export EDITOR=vi\n");
+
+    let tally = sandbox.repo.sync(&SyncOptions::default())?;
+    assert_eq!((tally.linked, tally.up_to_date, tally.conflicts), (0, 1, 0));
+    let links = sandbox.repo.links()?;
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].1, LinkStatus::Linked);
+    Ok(())
+}
+
+#[test]
+fn remove_puts_the_file_back_in_place() -> io::Result<()> {
+    let sandbox = Sandbox::new("remove")?;
+    let bashrc = sandbox.add(".bashrc", "export EDITOR=vi\n")?;
+    let vimrc = sandbox.add(".vimrc", "set number\n")?;
+    sandbox.repo.commit("feat: Add dotfiles")?;
+
+    let stored = sandbox.repo.remove(&bashrc)?;
+    assert_eq!(stored, Path::new(".bashrc"));
+    assert!(!is_symlink(&bashrc));
+    assert_eq!(fs::read_to_string(&bashrc)?, "export EDITOR=vi\n");
+    assert!(!sandbox.repo.path().join(".bashrc").exists());
+
+    let targets: Vec<PathBuf> = sandbox.repo.entries()?.into_iter().map(|entry| entry.target).collect();
+    assert_eq!(targets, vec![vimrc]);
+
+    let err = sandbox.repo.remove(&bashrc).expect_err("an unmanaged file cannot be removed");
+    assert!(matches!(errors::dfl_error(&err), Some(DflError::Usage { .. })), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn open_needs_an_existing_repository() -> io::Result<()> {
+    let sandbox = Sandbox::new("open")?;
+    let err = DflRepo::open(sandbox.home.join("missing")).expect_err("there is no repository there");
+    assert!(matches!(errors::dfl_error(&err), Some(DflError::NotInitialized { .. })), "{}", err);
+    assert_eq!(DflRepo::discover()?.path(), sandbox.repo.path());
+    Ok(())
+}
+
+#[test]
+fn restore_backup_keeps_the_backup_when_the_move_fails() -> io::Result<()> {
+    let sandbox = Sandbox::new("restore")?;
+    let dir = sandbox.home.join(".config/app");
+    fs::create_dir_all(&dir)?;
+    let target = dir.join("app.conf");
+    fs::write(&target, "theme = dark\n")?;
+    let backup = backup::create(&target)?;
+
+    // A file where the target's directory should be makes the final move fail.
+    fs::remove_dir(&dir)?;
+    fs::write(&dir, "in the way\n")?;
+    assert!(backup::handle_restore_backup_command(&target.to_string_lossy(), None).is_err());
+
+    assert_eq!(backup::list(&target)?, vec![backup.clone()]);
+    assert_eq!(fs::read_to_string(&backup)?, "theme = dark\n");
+    let leftovers: Vec<_> = fs::read_dir(backup.parent().unwrap_or(&backup))?.collect::<io::Result<_>>()?;
+    assert_eq!(leftovers.len(), 1);
+    assert_eq!(fs::read_to_string(&dir)?, "in the way\n");
+
+    // Once the way is clear, the same backup restores.
+    fs::remove_file(&dir)?;
+    fs::create_dir(&dir)?;
+    backup::handle_restore_backup_command(&target.to_string_lossy(), None)?;
+    assert_eq!(fs::read_to_string(&target)?, "theme = dark\n");
+    assert!(backup::list(&target)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn failed_sync_puts_the_home_directory_back() -> io::Result<()> {
+    let sandbox = Sandbox::new("rollback")?;
+    let bashrc = sandbox.add(".bashrc", "export EDITOR=vi\n")?;
+    // A template using a value that is not defined anywhere fails to render.
+    fs::write(sandbox.repo.path().join(".zshrc.tmpl"), "export EMAIL={{ email_nowhere }}\n")?;
+    sandbox.repo.commit("feat: Add dotfiles")?;
+    ui::set_assume_yes(true);
+
+    // The local .bashrc is replaced first, then the template makes the sync fail.
+    fs::remove_file(&bashrc)?;
+    fs::write(&bashrc, "local changes\n")?;
+    let err = sandbox.repo.sync(&SyncOptions { force: true, ..SyncOptions::default() }).expect_err("the template cannot be rendered");
+    assert_eq!(errors::code_of(&err), Some(Code::TemplateFailed), "{}", err);
+
+    assert!(!is_symlink(&bashrc));
+    assert_eq!(fs::read_to_string(&bashrc)?, "local changes\n");
+    assert!(fs::symlink_metadata(sandbox.home.join(".zshrc")).is_err());
+    assert!(sandbox.repo.status()?.changes.is_empty());
+
+    // Nothing was left in the stash, so the next sync starts normally.
+    fs::remove_file(sandbox.repo.path().join(".zshrc.tmpl"))?;
+    sandbox.repo.commit("fix: Drop the template")?;
+    let tally = sandbox.repo.sync(&SyncOptions { force: true, ..SyncOptions::default() })?;
+    assert_eq!(tally.linked, 1);
+    assert!(is_symlink(&bashrc));
+    Ok(())
+}
+
+#[test]
+fn failed_migrate_leaves_the_legacy_repository_in_use() -> io::Result<()> {
+    let sandbox = Sandbox::new("migrate")?;
+    let bashrc = sandbox.add(".bashrc", "export EDITOR=vi\n")?;
+    fs::create_dir_all(sandbox.home.join(".config/app"))?;
+    let conf = sandbox.add(".config/app/app.conf", "theme = dark\n")?;
+    sandbox.repo.commit("feat: Add dotfiles")?;
+
+    // Move the repository to where an older dfl kept it, with the links following it.
+    let legacy = sandbox.home.join(".dfl");
+    fs::rename(sandbox.repo.path(), &legacy)?;
+    for (link, stored) in [(&bashrc, ".bashrc"), (&conf, ".config/app/app.conf")] {
+        fs::remove_file(link)?;
+        std::os::unix::fs::symlink(legacy.join(stored), link)?;
+    }
+    // SAFETY: the sandbox still holds the environment lock.
+    unsafe { env::remove_var("DFL_DIR") };
+
+    // A directory where the new link to app.conf is built makes repointing it fail, after .bashrc was repointed.
+    let blocker = fsutil::staging_path(&conf);
+    fs::create_dir(&blocker)?;
+    fs::write(blocker.join("keep"), "")?;
+    let err = migrate::handle_migrate_command(false).expect_err("app.conf cannot be repointed");
+    assert_eq!(errors::code_of(&err), Some(Code::MigrateFailed), "{}", err);
+
+    assert!(!sandbox.home.join(".local/share/dfl/repo").exists());
+    assert_eq!(fs::read_to_string(legacy.join(".bashrc"))?, "export EDITOR=vi\n");
+    assert_eq!(fs::read_link(&bashrc)?, legacy.join(".bashrc"));
+    assert_eq!(fs::read_link(&conf)?, legacy.join(".config/app/app.conf"));
+    assert_eq!(DflRepo::discover()?.path(), legacy);
+
+    // Once the way is clear, the migration goes through.
+    fs::remove_dir_all(&blocker)?;
+    migrate::handle_migrate_command(false)?;
+    let repo = sandbox.home.join(".local/share/dfl/repo");
+    assert!(!legacy.exists());
+    assert_eq!(fs::read_link(&bashrc)?, repo.join(".bashrc"));
+    assert_eq!(fs::read_to_string(&conf)?, "theme = dark\n");
+    Ok(())
+}